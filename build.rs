@@ -0,0 +1,17 @@
+fn main() {
+    // Header of the C ABI, generated in OUT_DIR. The copy committed in include/ is only
+    // rewritten when EBUSTL_UPDATE_HEADER is set, a test checks it is up to date.
+    #[cfg(feature = "ffi")]
+    {
+        let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        let out_dir = std::env::var("OUT_DIR").unwrap();
+        println!("cargo:rerun-if-changed=src/ffi.rs");
+        println!("cargo:rerun-if-changed=cbindgen.toml");
+        println!("cargo:rerun-if-env-changed=EBUSTL_UPDATE_HEADER");
+        let header = cbindgen::generate(&crate_dir).expect("Unable to generate the C header");
+        header.write_to_file(format!("{}/ebustl.h", out_dir));
+        if std::env::var_os("EBUSTL_UPDATE_HEADER").is_some() {
+            header.write_to_file(format!("{}/include/ebustl.h", crate_dir));
+        }
+    }
+}
@@ -1,19 +1,276 @@
 extern crate ebustl;
 
 use ebustl::parse_stl_from_file;
+use ebustl::parser::parse_stl_from_slice;
+use ebustl::{StatsOptions, Stl, StlStats, Time, TranscriptLayout, TranscriptOptions};
+use std::collections::HashSet;
 use std::env;
+use std::io::{self, Read};
 use std::process;
 
 fn print_usage() {
-    println!("dump file.stl\n");
+    println!(
+        "dump [--json | --text [--time-codes]] [--gsi-only | --tti-only] [--range FROM..TO]\n     \
+         [--time FROM..TO] file.stl\n\
+         dump --hex [N] [--gsi-only | --tti-only] file.stl\n\
+         dump --stats [--min-gap FRAMES] [--json] [--range FROM..TO] [--time FROM..TO] file.stl\n\n\
+         Use - to read the STL from stdin.\n\
+         --text prints the text of the subtitles only, a paragraph each, --time-codes starting\n\
+         them with their time code in.\n\
+         --range keeps the subtitles numbered FROM to TO included.\n\
+         --time keeps the subtitles on screen between the time codes HH:MM:SS:FF FROM and TO.\n\
+         --hex prints the bytes of the GSI block and of every TTI block, or of TTI block N\n\
+         only counting from 0, field by field.\n\
+         --stats prints the figures of the subtitles, listing the gaps shorter than --min-gap\n\
+         frames, 2 by default.\n"
+    );
+    #[cfg(not(feature = "serde"))]
+    println!("--json requires the serde feature\n");
+}
+
+#[derive(Default)]
+struct Args {
+    json: bool,
+    text: bool,
+    time_codes: bool,
+    #[doc = "Hex dump, of a single TTI block if given"]
+    hex: Option<Option<usize>>,
+    stats: bool,
+    #[doc = "Gaps shorter than this number of frames are listed with --stats"]
+    min_gap: Option<u32>,
+    gsi_only: bool,
+    tti_only: bool,
+    range: Option<(u16, u16)>,
+    time: Option<(Time, Time)>,
+    file: String,
+}
+
+fn parse_args(args: impl Iterator<Item = String>) -> Option<Args> {
+    let mut parsed = Args::default();
+    let mut files = vec![];
+    let mut args = args.peekable();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => parsed.json = true,
+            "--text" => parsed.text = true,
+            "--time-codes" => parsed.time_codes = true,
+            "--hex" => {
+                let block = args.next_if(|arg| arg.parse::<usize>().is_ok());
+                parsed.hex = Some(block.map(|block| block.parse().unwrap()));
+            }
+            "--stats" => parsed.stats = true,
+            "--min-gap" => parsed.min_gap = Some(args.next()?.parse().ok()?),
+            "--gsi-only" => parsed.gsi_only = true,
+            "--tti-only" => parsed.tti_only = true,
+            "--range" => parsed.range = Some(bounds(&args.next()?, |n| n.parse().ok())?),
+            "--time" => parsed.time = Some(bounds(&args.next()?, time_code)?),
+            _ => files.push(arg),
+        }
+    }
+    let filtered = parsed.range.is_some() || parsed.time.is_some();
+    let only = parsed.gsi_only || parsed.tti_only;
+    if files.len() != 1
+        || (parsed.gsi_only && (parsed.tti_only || filtered))
+        || (parsed.text && (parsed.json || only))
+        || (parsed.time_codes && !parsed.text)
+        || (parsed.hex.is_some() && (parsed.json || parsed.text || filtered))
+        || (parsed.gsi_only && parsed.hex.is_some_and(|block| block.is_some()))
+        || (parsed.stats && (parsed.text || parsed.hex.is_some() || only))
+        || (parsed.min_gap.is_some() && !parsed.stats)
+    {
+        return None;
+    }
+    parsed.file = files.remove(0);
+    Some(parsed)
+}
+
+// Bounds of FROM..TO.
+fn bounds<T>(arg: &str, parse: impl Fn(&str) -> Option<T>) -> Option<(T, T)> {
+    let (from, to) = arg.split_once("..")?;
+    Some((parse(from)?, parse(to)?))
+}
+
+// Time code HH:MM:SS:FF.
+fn time_code(arg: &str) -> Option<Time> {
+    match arg.split(':').count() {
+        4 => Time::from_time_code(&arg.replace(':', "")),
+        _ => None,
+    }
+}
+
+// Blocks of the subtitles selected by number and time, extension blocks included.
+fn filter(stl: &mut Stl, args: &Args) {
+    if let Some((from, to)) = args.range {
+        stl.ttis
+            .retain(|tti| (from..=to).contains(&tti.get_subtitle_number_range()));
+    }
+    if let Some((from, to)) = &args.time {
+        let numbers = stl
+            .cues_between(from, to)
+            .iter()
+            .map(|tti| tti.get_subtitle_number_range())
+            .collect::<HashSet<_>>();
+        stl.ttis
+            .retain(|tti| numbers.contains(&tti.get_subtitle_number_range()));
+    }
+}
+
+fn hex_dump(stl: &Stl, block: Option<usize>, args: &Args) -> Result<String, String> {
+    if let Some(block) = block {
+        return stl
+            .tti_hex_dump(block)
+            .ok_or_else(|| format!("There is no TTI block {}", block));
+    }
+    let mut dumps = vec![];
+    if !args.tti_only {
+        dumps.push(stl.gsi_hex_dump().map_err(|err| err.to_string())?);
+    }
+    if !args.gsi_only {
+        dumps.extend((0..stl.ttis.len()).filter_map(|index| stl.tti_hex_dump(index)));
+    }
+    Ok(dumps.join("\n"))
+}
+
+// Statistics written for reading.
+fn report(stats: &StlStats, options: &StatsOptions, fps: usize) -> String {
+    let pairs = |pairs: &[(u16, u16, i64)]| match pairs {
+        [] => "none".to_string(),
+        pairs => pairs
+            .iter()
+            .map(|(earlier, later, frames)| format!("{}-{} ({} frames)", earlier, later, frames))
+            .collect::<Vec<_>>()
+            .join(", "),
+    };
+    let on_air = Time::from_frames(stats.on_air_frames, fps);
+    let mut report = format!(
+        "Subtitles: {}\n\
+         On air: {} ({} frames)\n\
+         Characters per second: min {:.1}, average {:.1}, max {:.1}\n\
+         Characters per row: max {} of {} declared, average {:.1}\n\
+         Rows per subtitle:\n",
+        stats.subtitle_count,
+        on_air.to_smpte(),
+        stats.on_air_frames,
+        stats.min_chars_per_second,
+        stats.average_chars_per_second,
+        stats.max_chars_per_second,
+        stats.max_chars_per_row,
+        stats.declared_max_chars_per_row,
+        stats.average_chars_per_row,
+    );
+    for (rows, count) in &stats.row_counts {
+        report.push_str(&format!("  {:>2}: {}\n", rows, count));
+    }
+    if let Some((number, frames)) = stats.longest_cue {
+        report.push_str(&format!(
+            "Longest subtitle: {} ({} frames)\n",
+            number, frames
+        ));
+    }
+    report.push_str(&format!(
+        "Gaps under {} frames: {}\nOverlaps: {}\n",
+        options.min_gap_frames,
+        pairs(&stats.short_gaps),
+        pairs(&stats.overlaps)
+    ));
+    report
+}
+
+#[cfg(feature = "serde")]
+fn to_json(stl: &Stl, args: &Args) -> Result<String, String> {
+    let options = ebustl::JsonExportOptions {
+        raw_text_fields: true,
+        pretty: true,
+    };
+    let json = stl
+        .to_json_with_options(&options)
+        .map_err(|err| err.to_string())?;
+    let part = match (args.gsi_only, args.tti_only) {
+        (true, _) => "gsi",
+        (_, true) => "ttis",
+        _ => return Ok(json),
+    };
+    let value = serde_json::from_str::<serde_json::Value>(&json).map_err(|err| err.to_string())?;
+    serde_json::to_string_pretty(&value[part]).map_err(|err| err.to_string())
 }
 
 fn main() {
-    if env::args().count() != 2 {
-        print_usage();
-        process::exit(1);
+    let args = match parse_args(env::args().skip(1)) {
+        Some(args) if !args.json || cfg!(feature = "serde") => args,
+        _ => {
+            print_usage();
+            process::exit(1);
+        }
+    };
+    let stl = match args.file.as_str() {
+        "-" => {
+            let mut bytes = vec![];
+            io::stdin()
+                .read_to_end(&mut bytes)
+                .map_err(|err| err.to_string())
+                .and_then(|_| parse_stl_from_slice(&bytes).map_err(|err| err.to_string()))
+        }
+        file => parse_stl_from_file(file).map_err(|err| err.to_string()),
+    };
+    let mut stl = match stl {
+        Ok(stl) => stl,
+        Err(err) => {
+            println!("Error: {}\n", err);
+            process::exit(1);
+        }
+    };
+    filter(&mut stl, &args);
+    if args.stats {
+        let options = StatsOptions {
+            min_gap_frames: args.min_gap.unwrap_or(2),
+        };
+        let stats = stl.statistics_with_options(&options);
+        if args.json {
+            #[cfg(feature = "serde")]
+            match serde_json::to_string_pretty(&stats) {
+                Ok(json) => println!("{}", json),
+                Err(err) => {
+                    println!("Error: {}\n", err);
+                    process::exit(1);
+                }
+            }
+        } else {
+            print!(
+                "{}",
+                report(&stats, &options, stl.gsi.get_disk_format_code().get_fps())
+            );
+        }
+    } else if let Some(block) = args.hex {
+        match hex_dump(&stl, block, &args) {
+            Ok(dump) => print!("{}", dump),
+            Err(err) => {
+                println!("Error: {}\n", err);
+                process::exit(1);
+            }
+        }
+    } else if args.text {
+        let options = TranscriptOptions {
+            layout: TranscriptLayout::Paragraphs {
+                min_pause_frames: 0,
+            },
+            time_codes: args.time_codes,
+            ..TranscriptOptions::default()
+        };
+        print!("{}", stl.to_transcript(&options));
+    } else if args.json {
+        #[cfg(feature = "serde")]
+        match to_json(&stl, &args) {
+            Ok(json) => println!("{}", json),
+            Err(err) => {
+                println!("Error: {}\n", err);
+                process::exit(1);
+            }
+        }
+    } else if args.gsi_only {
+        println!("{:?}", stl.gsi);
+    } else if args.tti_only {
+        println!("{:?}", stl.ttis);
+    } else {
+        println!("{:?}", stl);
     }
-    let input_filename = env::args().nth(1).unwrap();
-    let stl = parse_stl_from_file(&input_filename).map_err(|err| err.to_string());
-    println!("{:?}", stl);
 }
@@ -0,0 +1,57 @@
+/*
+ * Checks of the C ABI and of its memory ownership rules, run from the crate directory:
+ *
+ *   cargo rustc --features ffi --crate-type staticlib
+ *   cc -Wall -Iinclude ffi/test_ffi.c target/debug/libebustl.a -lpthread -ldl -lm -o target/test_ffi
+ *   target/test_ffi stls/test.stl
+ */
+#include <assert.h>
+#include <stdio.h>
+#include <string.h>
+
+#include "ebustl.h"
+
+static unsigned char input[1 << 20];
+
+int main(int argc, char **argv) {
+    assert(argc == 2);
+    FILE *file = fopen(argv[1], "rb");
+    assert(file != NULL);
+    size_t len = fread(input, 1, sizeof(input), file);
+    fclose(file);
+
+    EbustlHandle *handle = NULL;
+    assert(ebustl_parse(input, len, &handle) == EBUSTL_STATUS_OK);
+    assert(strcmp(ebustl_gsi_field(handle, "OPT"), "TEST") == 0);
+    assert(ebustl_gsi_field(handle, "XYZ") == NULL);
+    assert(strcmp(ebustl_last_error(), "Unknown GSI field XYZ") == 0);
+
+    size_t count = ebustl_subtitle_count(handle);
+    assert(count == 13);
+    for (size_t i = 0; i < count; i++) {
+        EbustlSubtitle subtitle;
+        assert(ebustl_subtitle(handle, i, &subtitle) == EBUSTL_STATUS_OK);
+        assert(subtitle.end_ms >= subtitle.start_ms);
+        printf("%u %llu %llu %s\n", subtitle.number, (unsigned long long)subtitle.start_ms,
+               (unsigned long long)subtitle.end_ms, subtitle.text);
+    }
+    EbustlSubtitle subtitle;
+    assert(ebustl_subtitle(handle, count, &subtitle) == EBUSTL_STATUS_INDEX_OUT_OF_RANGE);
+
+    uint8_t *bytes = NULL;
+    size_t bytes_len = 0;
+    assert(ebustl_serialize(handle, &bytes, &bytes_len) == EBUSTL_STATUS_OK);
+    assert(bytes_len == len && memcmp(bytes, input, len) == 0);
+    ebustl_bytes_free(bytes, bytes_len);
+    ebustl_free(handle);
+
+    handle = NULL;
+    assert(ebustl_parse(input, 100, &handle) == EBUSTL_STATUS_PARSE_ERROR);
+    assert(handle == NULL);
+    assert(strlen(ebustl_last_error()) > 0);
+    assert(ebustl_parse(NULL, 0, &handle) == EBUSTL_STATUS_NULL_POINTER);
+    ebustl_free(NULL);
+
+    puts("ok");
+    return 0;
+}
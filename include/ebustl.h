@@ -0,0 +1,92 @@
+#ifndef EBUSTL_H
+#define EBUSTL_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Result of the calls, details of failures are given by `ebustl_last_error`.
+typedef enum EbustlStatus {
+  EBUSTL_STATUS_OK = 0,
+  EBUSTL_STATUS_NULL_POINTER = 1,
+  EBUSTL_STATUS_PARSE_ERROR = 2,
+  EBUSTL_STATUS_SERIALIZE_ERROR = 3,
+  EBUSTL_STATUS_INDEX_OUT_OF_RANGE = 4,
+  EBUSTL_STATUS_UNKNOWN_FIELD = 5,
+  EBUSTL_STATUS_PANIC = 6,
+} EbustlStatus;
+
+// Parsed document with the strings handed out to the caller.
+typedef struct EbustlHandle EbustlHandle;
+
+// Subtitle of a document, see `ebustl_subtitle`.
+typedef struct EbustlSubtitle {
+  uint16_t number;
+  //Time Code In in milliseconds since midnight
+  uint64_t start_ms;
+  //Time Code Out in milliseconds since midnight
+  uint64_t end_ms;
+  //UTF-8 text, rows separated by new lines, owned by the handle
+  const char *text;
+} EbustlSubtitle;
+
+// Parse the `len` bytes at `data` and store a new handle in `*out`.
+//
+// # Safety
+//
+// `data` must point to `len` readable bytes and `out` to writable memory.
+enum EbustlStatus ebustl_parse(const uint8_t *data, size_t len, struct EbustlHandle **out);
+
+// Release a handle, null is ignored.
+//
+// # Safety
+//
+// `handle` must come from `ebustl_parse` and not have been freed.
+void ebustl_free(struct EbustlHandle *handle);
+
+// Serialize the document, storing a new buffer in `*data` and its length in `*len`.
+//
+// # Safety
+//
+// `handle` must be a live handle, `data` and `len` must point to writable memory.
+enum EbustlStatus ebustl_serialize(const struct EbustlHandle *handle, uint8_t **data, size_t *len);
+
+// Release a buffer returned by `ebustl_serialize`, null is ignored.
+//
+// # Safety
+//
+// `data` and `len` must be as returned by `ebustl_serialize`, not freed before.
+void ebustl_bytes_free(uint8_t *data, size_t len);
+
+// Value of a GSI field named as in the specification ("OPT", "TCP"...), trimmed, or
+// null if the field is unknown. The string is owned by the handle.
+//
+// # Safety
+//
+// `handle` must be a live handle and `name` a NUL-terminated string.
+const char *ebustl_gsi_field(const struct EbustlHandle *handle, const char *name);
+
+// Number of displayable subtitles, 0 for a null handle.
+//
+// # Safety
+//
+// `handle` must be a live handle or null.
+size_t ebustl_subtitle_count(const struct EbustlHandle *handle);
+
+// Store the subtitle at `index`, in file order, in `*out`.
+//
+// # Safety
+//
+// `handle` must be a live handle and `out` must point to writable memory.
+enum EbustlStatus ebustl_subtitle(const struct EbustlHandle *handle,
+                                  size_t index,
+                                  struct EbustlSubtitle *out);
+
+// Message of the last failure on this thread, empty if none.
+const char *ebustl_last_error(void);
+
+#endif  /* EBUSTL_H */
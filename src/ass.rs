@@ -0,0 +1,214 @@
+//! Advanced SubStation Alpha (ASS) export.
+
+use super::*;
+use styled::{Span, COLOURS};
+
+const PLAY_RES_X: u32 = 720;
+const PLAY_RES_Y: u32 = 576;
+
+#[derive(Debug, Clone, Default)]
+pub struct AssExportOptions {
+    #[doc = "Same as SrtExportOptions::rebase_to_tcp"]
+    pub rebase_to_tcp: bool,
+}
+
+impl Stl {
+    /// Advanced SubStation Alpha script of the displayed subtitles, with a single
+    /// default style sized from the display standard. Justification and vertical
+    /// position become `\an` alignments, italics, underline, colours and double height
+    /// become override tags.
+    pub fn to_ass(&self, options: &AssExportOptions) -> String {
+        let fps = self.gsi.dfc.get_fps();
+        let origin = self.time_origin(options.rebase_to_tcp);
+        let font_size = match self.gsi.dsc {
+            DisplayStandardCode::OpenSubtitling => PLAY_RES_Y / 30,
+            _ => PLAY_RES_Y / 24,
+        };
+        // Teletext subtitles are shown in black boxes, open subtitles outlined.
+        let (border_style, outline) = match self.gsi.dsc {
+            DisplayStandardCode::OpenSubtitling => (1, 2),
+            _ => (3, 0),
+        };
+
+        let mut ass = String::from("[Script Info]\n");
+        let title = self.gsi.opt.trim();
+        if !title.is_empty() {
+            ass.push_str(&format!("Title: {}\n", title));
+        }
+        ass.push_str(&format!(
+            "ScriptType: v4.00+\nWrapStyle: 2\nScaledBorderAndShadow: yes\n\
+             PlayResX: {}\nPlayResY: {}\n",
+            PLAY_RES_X, PLAY_RES_Y
+        ));
+        ass.push_str(
+            "\n[V4+ Styles]\n\
+             Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, \
+             BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, \
+             BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding\n",
+        );
+        ass.push_str(&format!(
+            "Style: Default,Arial,{},&H00FFFFFF,&H000000FF,&H00000000,&H00000000,\
+             0,0,0,0,100,100,0,0,{},{},0,2,{},{},{},1\n",
+            font_size,
+            border_style,
+            outline,
+            PLAY_RES_X / 10,
+            PLAY_RES_X / 10,
+            PLAY_RES_Y / 20,
+        ));
+        ass.push_str(
+            "\n[Events]\n\
+             Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n",
+        );
+        for chain in self.cues() {
+            let first = &self.ttis[chain.start];
+            let double_height = self.ttis[chain.clone()]
+                .iter()
+                .any(|tti| tti.tf.contains(&0x0d));
+            let mut text = format!("{{\\an{}", self.alignment(first.jc, first.vp));
+            if double_height {
+                text.push_str(&format!("\\fs{}", font_size * 2));
+            }
+            text.push('}');
+            let mut style = Style::default();
+            for (index, row) in styled::styled_rows(&self.ttis[chain]).iter().enumerate() {
+                if index > 0 {
+                    text.push_str("\\N");
+                }
+                for span in row {
+                    text.push_str(&style.change_to(span));
+                    text.push_str(&ass_text(&span.text));
+                }
+            }
+            ass.push_str(&format!(
+                "Dialogue: 0,{},{},Default,,0,0,0,,{}\n",
+                ass_time(styled::millis(&first.tci, &origin, fps)),
+                ass_time(styled::millis(&first.tco, &origin, fps)),
+                text
+            ));
+        }
+        ass
+    }
+
+    // Numpad alignment: the column from the justification, the row from the third of
+    // the screen the subtitle is in.
+    fn alignment(&self, jc: u8, vp: u8) -> u8 {
+        let column = match Justification::from_jc(jc) {
+            Justification::Left => 1,
+            Justification::Right => 3,
+            _ => 2,
+        };
+        let row = match self.line_percentage(vp) {
+            Some(line) if line < 33 => 6,
+            Some(line) if line < 66 => 3,
+            _ => 0,
+        };
+        row + column
+    }
+}
+
+// Style in effect in a dialogue line, starting from the default style.
+#[derive(Default)]
+struct Style {
+    italic: bool,
+    underline: bool,
+    colour: Option<u8>,
+}
+
+impl Style {
+    // Override tags switching to the style of `span`.
+    fn change_to(&mut self, span: &Span) -> String {
+        let mut tags = String::new();
+        if span.italic != self.italic {
+            tags.push_str(if span.italic { "\\i1" } else { "\\i0" });
+        }
+        if span.underline != self.underline {
+            tags.push_str(if span.underline { "\\u1" } else { "\\u0" });
+        }
+        let colour = (!span.is_white()).then_some(span.colour);
+        if colour != self.colour {
+            match colour {
+                Some(colour) => tags.push_str(&format!("\\c{}", ass_colour(colour))),
+                None => tags.push_str("\\c"),
+            }
+        }
+        (self.italic, self.underline, self.colour) = (span.italic, span.underline, colour);
+        match tags.is_empty() {
+            true => tags,
+            false => format!("{{{}}}", tags),
+        }
+    }
+}
+
+// Teletext colour as &HBBGGRR&.
+fn ass_colour(colour: u8) -> String {
+    let rgb = &COLOURS[colour as usize].1[1..];
+    format!("&H{}{}{}&", &rgb[4..6], &rgb[2..4], &rgb[0..2]).to_uppercase()
+}
+
+// Subtitle text with its braces escaped, so that they are not read as override
+// blocks, and a word joiner after each backslash, so that `\n`, `\N` and `\h` are
+// not read as breaks or hard spaces.
+fn ass_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c == '{' || c == '}' {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+        if c == '\\' {
+            escaped.push('\u{2060}');
+        }
+    }
+    escaped
+}
+
+fn ass_time(millis: u64) -> String {
+    let centis = millis / 10;
+    format!(
+        "{}:{:02}:{:02}.{:02}",
+        centis / 360_000,
+        centis / 6000 % 60,
+        centis / 100 % 60,
+        centis % 100
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_ass() {
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        let mut tf = b"Tom \x80and\x81\x01Jerry\x8aagain".to_vec();
+        tf.resize(112, 0x8f);
+        stl.ttis[2].tf = tf;
+        stl.ttis[2].jc = 1;
+        stl.ttis[2].vp = 1;
+        let ass = stl.to_ass(&AssExportOptions {
+            rebase_to_tcp: true,
+        });
+        assert!(ass.starts_with("[Script Info]\nTitle: TEST\nScriptType: v4.00+\n"));
+        assert!(ass.contains("\nPlayResX: 720\nPlayResY: 576\n"));
+        assert!(ass.contains("\nStyle: Default,Arial,24,"));
+        assert!(ass.contains(
+            "\nDialogue: 0,0:00:06.32,0:00:09.36,Default,,0,0,0,,\
+             {\\an2\\fs48}{\\c&HFFFF00&}-Ellis Island,\\Nîlot de larmes et d'exil,\n"
+        ));
+        assert!(ass.contains(",,{\\an7}Tom {\\i1}and{\\i0} {\\c&H0000FF&}Jerry\\N{\\c}again\n"));
+        assert_eq!(13, ass.matches("\nDialogue: ").count());
+    }
+
+    #[test]
+    fn test_to_ass_escapes_text() {
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        let mut tf = b"{\\i1}a\\Nb}".to_vec();
+        tf.resize(112, 0x8f);
+        stl.ttis[2].tf = tf;
+        stl.ttis[2].jc = 2;
+        stl.ttis[2].vp = 22;
+        let ass = stl.to_ass(&AssExportOptions::default());
+        assert!(ass.contains(",,{\\an2}\\{\\\u{2060}i1\\}a\\\u{2060}Nb\\}\n"));
+    }
+}
@@ -0,0 +1,191 @@
+//! Creating a document from scratch:
+//!
+//! ```
+//! use ebustl::{DiskFormatCode, StlBuilder, Time};
+//!
+//! let stl = StlBuilder::new()
+//!     .frame_rate(DiskFormatCode::STL25_01)
+//!     .teletext()
+//!     .language("0F")
+//!     .title("Ellis Island")
+//!     .cue(Time::from_frames(250, 25), Time::from_frames(300, 25), "Hello")
+//!     .cue(Time::from_frames(310, 25), Time::from_frames(400, 25), "World")
+//!     .build()
+//!     .unwrap();
+//! assert_eq!(2, stl.subtitles().count());
+//! ```
+
+use thiserror::Error;
+
+use super::*;
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum BuildError {
+    #[error("Invalid GSI field: {0}")]
+    Field(#[from] SerializeError),
+    #[error("Invalid cues: {0:?}")]
+    Cues(Vec<CueError>),
+}
+
+/// Document built from a GSI profile, header fields and cues, checked as a whole by
+/// [`StlBuilder::build`].
+#[derive(Debug, Clone)]
+pub struct StlBuilder {
+    profile: GsiProfile,
+    title: Option<String>,
+    episode_title: Option<String>,
+    format: TtiFormat,
+    cues: Vec<Cue>,
+}
+
+impl Default for StlBuilder {
+    fn default() -> StlBuilder {
+        StlBuilder::new()
+    }
+}
+
+impl StlBuilder {
+    /// Level 1 teletext at 25 fps, cues centred on row 20.
+    pub fn new() -> StlBuilder {
+        StlBuilder {
+            profile: GsiProfile::default(),
+            title: None,
+            episode_title: None,
+            format: TtiFormat {
+                jc: 2,
+                vp: 20,
+                dh: false,
+            },
+            cues: vec![],
+        }
+    }
+
+    pub fn profile(mut self, profile: GsiProfile) -> StlBuilder {
+        self.profile = profile;
+        self
+    }
+
+    pub fn frame_rate(mut self, dfc: DiskFormatCode) -> StlBuilder {
+        self.profile.dfc = dfc;
+        self
+    }
+
+    /// Level 1 teletext, 40 characters on 23 rows.
+    pub fn teletext(mut self) -> StlBuilder {
+        self.profile.dsc = DisplayStandardCode::Level1Teletext;
+        self.profile.mnc = 40;
+        self.profile.mnr = 23;
+        self
+    }
+
+    pub fn open_subtitles(mut self) -> StlBuilder {
+        self.profile.dsc = DisplayStandardCode::OpenSubtitling;
+        self
+    }
+
+    pub fn character_code_table(mut self, cct: CharacterCodeTable) -> StlBuilder {
+        self.profile.cct = cct;
+        self
+    }
+
+    /// Language Code, two hexadecimal digits as defined by EBU Tech 3264.
+    pub fn language(mut self, lc: &str) -> StlBuilder {
+        self.profile.lc = lc.to_string();
+        self
+    }
+
+    pub fn max_chars_per_row(mut self, mnc: u16) -> StlBuilder {
+        self.profile.mnc = mnc;
+        self
+    }
+
+    pub fn max_rows(mut self, mnr: u16) -> StlBuilder {
+        self.profile.mnr = mnr;
+        self
+    }
+
+    pub fn title(mut self, title: &str) -> StlBuilder {
+        self.title = Some(title.to_string());
+        self
+    }
+
+    pub fn episode_title(mut self, title: &str) -> StlBuilder {
+        self.episode_title = Some(title.to_string());
+        self
+    }
+
+    /// Format of the cues added after this call.
+    pub fn format(mut self, format: TtiFormat) -> StlBuilder {
+        self.format = format;
+        self
+    }
+
+    pub fn cue(mut self, tci: Time, tco: Time, text: &str) -> StlBuilder {
+        self.cues.push(Cue {
+            start: tci,
+            end: tco,
+            text: text.to_string(),
+            format: self.format,
+        });
+        self
+    }
+
+    /// Check the header fields and the cues, see [`Stl::from_cues`].
+    pub fn build(self) -> Result<Stl, BuildError> {
+        let mut gsi = GsiBlock::from_profile(&self.profile);
+        gsi.set_language_code(&self.profile.lc)?;
+        gsi.set_max_number_of_chars_in_row(self.profile.mnc)?;
+        gsi.set_max_number_of_rows(self.profile.mnr)?;
+        if let Some(title) = &self.title {
+            gsi.set_original_program_title(title)?;
+        }
+        if let Some(title) = &self.episode_title {
+            gsi.set_original_episode_title(title)?;
+        }
+        Stl::from_cues(gsi, self.cues).map_err(BuildError::Cues)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder() {
+        let stl = StlBuilder::new()
+            .frame_rate(DiskFormatCode::STL30_01)
+            .open_subtitles()
+            .title("Ellis Island")
+            .cue(Time::new(0, 0, 5, 0), Time::new(0, 0, 7, 0), "Second")
+            .cue(Time::new(0, 0, 1, 0), Time::new(0, 0, 3, 0), "First")
+            .build()
+            .unwrap();
+        assert_eq!(DiskFormatCode::STL30_01, stl.gsi.dfc);
+        assert_eq!(DisplayStandardCode::OpenSubtitling, stl.gsi.dsc);
+        assert_eq!("Ellis Island", stl.gsi.opt);
+        assert_eq!(2, stl.gsi.tns);
+        assert_eq!(Time::new(0, 0, 1, 0), stl.ttis[0].tci);
+        assert!(!stl.validate().has_errors());
+    }
+
+    #[test]
+    fn test_builder_errors() {
+        let error = StlBuilder::new()
+            .title("A title longer than the 32 bytes of the field")
+            .build()
+            .unwrap_err();
+        assert_eq!(
+            BuildError::Field(SerializeError::FieldOverflow {
+                field: "OPT",
+                width: 32
+            }),
+            error
+        );
+
+        let error = StlBuilder::new()
+            .cue(Time::new(0, 0, 1, 0), Time::new(0, 0, 1, 25), "Frame 25")
+            .build()
+            .unwrap_err();
+        assert_eq!(BuildError::Cues(vec![CueError::InvalidTimeCode(0)]), error);
+    }
+}
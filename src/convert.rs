@@ -0,0 +1,496 @@
+use thiserror::Error;
+
+use super::*;
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum ConvertError {
+    #[error("Characters {chars:?} of subtitle {sn} cannot be represented")]
+    Unmappable { sn: u16, chars: Vec<char> },
+    #[error("Text of subtitle {0} does not fit in the text field once converted")]
+    TextTooLong(u16),
+    #[error("The document would need {blocks} TTI blocks, more than the 65535 of a disk")]
+    CapacityExceeded { blocks: usize },
+}
+
+/// What to do with the characters missing from the target character code table.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnmappablePolicy {
+    #[doc = "Replace them with '?'"]
+    #[default]
+    Replace,
+    #[doc = "Drop them"]
+    Skip,
+    #[doc = "Leave the document unchanged and return an error"]
+    Fail,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ConvertOptions {
+    pub unmappable: UnmappablePolicy,
+}
+
+/// What was lost by [`Stl::convert_character_table`] or
+/// [`Stl::convert_display_standard`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConversionReport {
+    #[doc = "Subtitle numbers with the characters that could not be represented"]
+    pub unmappable: Vec<(u16, Vec<char>)>,
+    #[doc = "Subtitle numbers with the control codes that have no equivalent"]
+    pub dropped_codes: Vec<(u16, Vec<u8>)>,
+}
+
+/// Subtitles left unchanged by [`Stl::map_text`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MapReport {
+    #[doc = "Subtitle numbers of the text changed"]
+    pub changed: Vec<u16>,
+    #[doc = "Subtitle numbers of the text no longer fitting in an extension chain"]
+    pub too_long: Vec<u16>,
+    #[doc = "Subtitle numbers with the characters missing from the character code table"]
+    pub unmappable: Vec<(u16, Vec<char>)>,
+}
+
+impl Stl {
+    /// Apply `f` to the text of every subtitle, chains being decoded as a whole. `f` is
+    /// called on each run of characters between control codes so that boxing, sizes
+    /// and colours are kept. Subtitles growing past a text field are split in extension
+    /// blocks.
+    ///
+    /// A subtitle is left unchanged and reported when its new text does not fit in an
+    /// extension chain or cannot be encoded. Nothing is changed when the document would
+    /// exceed the TTI blocks of a disk.
+    pub fn map_text<F: FnMut(&str) -> String>(
+        &mut self,
+        mut f: F,
+    ) -> Result<MapReport, ConvertError> {
+        let mut report = MapReport::default();
+        let mut ttis = Vec::with_capacity(self.ttis.len());
+        for chain in edit::chains(&self.ttis) {
+            let blocks = &self.ttis[chain];
+            let first = &blocks[0];
+            if !edit::is_subtitle_block(first) {
+                ttis.extend_from_slice(blocks);
+                continue;
+            }
+            let (units, unmappable) = map_chain(blocks, &mut f);
+            if !unmappable.is_empty() {
+                report.unmappable.push((first.sn, unmappable));
+                ttis.extend_from_slice(blocks);
+                continue;
+            }
+            let text_fields = cues::pack_text_fields(units);
+            if text_fields.len() > cues::MAX_CHAIN_LENGTH {
+                report.too_long.push(first.sn);
+                ttis.extend_from_slice(blocks);
+                continue;
+            }
+            if text_fields.len() == blocks.len()
+                && blocks.iter().zip(&text_fields).all(|(tti, tf)| {
+                    tti.tf
+                        .iter()
+                        .take_while(|&&c| c != 0x8f)
+                        .eq(tf.iter().take_while(|&&c| c != 0x8f))
+                })
+            {
+                ttis.extend_from_slice(blocks);
+                continue;
+            }
+            report.changed.push(first.sn);
+            let last = text_fields.len() - 1;
+            for (ebn, tf) in text_fields.into_iter().enumerate() {
+                ttis.push(TtiBlock {
+                    ebn: if ebn == last { 0xff } else { ebn as u8 },
+                    tf,
+                    ..first.clone()
+                });
+            }
+        }
+        if ttis.len() > u16::MAX as usize {
+            return Err(ConvertError::CapacityExceeded { blocks: ttis.len() });
+        }
+        self.ttis = ttis;
+        self.recompute_totals();
+        Ok(report)
+    }
+
+    /// Rewrite the text fields, vertical positions and GSI limits for the `target`
+    /// display standard. Going to open subtitling, the teletext boxing, size and colour
+    /// codes are removed. Going to teletext, rows are boxed and the open subtitling
+    /// italics, underline and boxing codes are removed. Vertical positions are scaled
+    /// between the 23 teletext rows and the 100 open subtitling lines.
+    ///
+    /// The document is left unchanged when an error is returned.
+    pub fn convert_display_standard(
+        &mut self,
+        target: DisplayStandardCode,
+    ) -> Result<ConversionReport, ConvertError> {
+        let mut report = ConversionReport::default();
+        if is_teletext(self.gsi.dsc) == is_teletext(target) {
+            self.gsi.dsc = target;
+            return Ok(report);
+        }
+        let mut ttis = Vec::with_capacity(self.ttis.len());
+        for chain in edit::chains(&self.ttis) {
+            let blocks = &self.ttis[chain];
+            let first = &blocks[0];
+            if !edit::is_subtitle_block(first) {
+                ttis.extend_from_slice(blocks);
+                continue;
+            }
+            let bytes = blocks
+                .iter()
+                .flat_map(|tti| tti.tf.iter().copied().take_while(|&c| c != 0x8f))
+                .collect::<Vec<_>>();
+            let mut dropped = vec![];
+            let rows = bytes
+                .split(|&c| c == 0x8a)
+                .map(|row| match is_teletext(target) {
+                    true => row_to_teletext(row, &mut dropped),
+                    false => row_to_open_subtitling(row, &mut dropped),
+                })
+                .collect::<Vec<_>>();
+            if !dropped.is_empty() {
+                dropped.sort();
+                dropped.dedup();
+                report.dropped_codes.push((first.sn, dropped));
+            }
+            let text_fields = cues::pack_text_fields(text_units(&rows.join(&0x8a), first.cct));
+            if text_fields.len() > cues::MAX_CHAIN_LENGTH {
+                return Err(ConvertError::TextTooLong(first.sn));
+            }
+            let vp = match is_teletext(target) {
+                true => 1 + (first.vp.min(99) as u32 * 22 + 49) / 99,
+                false => (first.vp.clamp(1, 23) as u32 - 1) * 99 / 22,
+            } as u8;
+            let last = text_fields.len() - 1;
+            for (ebn, tf) in text_fields.into_iter().enumerate() {
+                ttis.push(TtiBlock {
+                    ebn: if ebn == last { 0xff } else { ebn as u8 },
+                    vp,
+                    tf,
+                    ..first.clone()
+                });
+            }
+        }
+        if ttis.len() > u16::MAX as usize {
+            return Err(ConvertError::CapacityExceeded { blocks: ttis.len() });
+        }
+        self.ttis = ttis;
+        self.gsi.dsc = target;
+        if is_teletext(target) {
+            self.gsi.mnc = self.gsi.mnc.min(40);
+            self.gsi.mnr = 23;
+        } else {
+            self.gsi.mnr = 99;
+        }
+        self.recompute_totals();
+        Ok(report)
+    }
+
+    /// Re-encode the text of every block with `target`. The document is left unchanged
+    /// when an error is returned.
+    pub fn convert_character_table(
+        &mut self,
+        target: CharacterCodeTable,
+    ) -> Result<ConversionReport, ConvertError> {
+        self.convert_character_table_with_options(target, &ConvertOptions::default())
+    }
+
+    pub fn convert_character_table_with_options(
+        &mut self,
+        target: CharacterCodeTable,
+        options: &ConvertOptions,
+    ) -> Result<ConversionReport, ConvertError> {
+        let mut report = ConversionReport::default();
+        let mut text_fields = Vec::with_capacity(self.ttis.len());
+        for tti in &self.ttis {
+            // User data and reserved blocks do not hold text.
+            if !edit::is_subtitle_block(tti) {
+                text_fields.push(None);
+                continue;
+            }
+            let (tf, unmappable) = convert_text_field(&tti.tf, tti.cct, target, options);
+            if !unmappable.is_empty() {
+                if options.unmappable == UnmappablePolicy::Fail {
+                    return Err(ConvertError::Unmappable {
+                        sn: tti.sn,
+                        chars: unmappable,
+                    });
+                }
+                match report.unmappable.last_mut() {
+                    Some((sn, chars)) if *sn == tti.sn => chars.extend(unmappable),
+                    _ => report.unmappable.push((tti.sn, unmappable)),
+                }
+            }
+            if tf.len() > tti.tf.len() {
+                return Err(ConvertError::TextTooLong(tti.sn));
+            }
+            text_fields.push(Some(tf));
+        }
+
+        for (tti, tf) in self.ttis.iter_mut().zip(text_fields) {
+            if let Some(mut tf) = tf {
+                tf.resize(tti.tf.len(), 0x8f);
+                tti.tf = tf;
+            }
+            tti.cct = target;
+        }
+        self.gsi.cct = target;
+        Ok(report)
+    }
+}
+
+// Text field re-encoded up to the unused space terminator, control codes are kept, and
+// the characters missing from `target`.
+fn convert_text_field(
+    tf: &[u8],
+    source: CharacterCodeTable,
+    target: CharacterCodeTable,
+    options: &ConvertOptions,
+) -> (Vec<u8>, Vec<char>) {
+    let mut result = Vec::with_capacity(tf.len());
+    let mut unmappable = vec![];
+    let end = tf.iter().position(|&c| c == 0x8f).unwrap_or(tf.len());
+    let is_control = |c: u8| c < 0x20 || (0x80..0xa0).contains(&c);
+    let mut rest = &tf[..end];
+    while !rest.is_empty() {
+        let len = rest
+            .iter()
+            .position(|&c| is_control(c) != is_control(rest[0]))
+            .unwrap_or(rest.len());
+        if is_control(rest[0]) {
+            result.extend_from_slice(&rest[..len]);
+        } else {
+            for c in source.decode(&rest[..len]).chars() {
+                match target.encode_char(c) {
+                    Some(bytes) => result.extend(bytes),
+                    None => {
+                        unmappable.push(c);
+                        if options.unmappable == UnmappablePolicy::Replace {
+                            result.push(b'?');
+                        }
+                    }
+                }
+            }
+        }
+        rest = &rest[len..];
+    }
+    (result, unmappable)
+}
+
+fn is_teletext(dsc: DisplayStandardCode) -> bool {
+    matches!(
+        dsc,
+        DisplayStandardCode::Level1Teletext | DisplayStandardCode::Level2Teletext
+    )
+}
+
+// Row without the teletext control codes, the ones changing the appearance of the text
+// are added to `dropped`.
+fn row_to_open_subtitling(row: &[u8], dropped: &mut Vec<u8>) -> Vec<u8> {
+    row.iter()
+        .copied()
+        .filter(|&c| match c {
+            // White text, boxing, normal height, steady and black background are the
+            // defaults.
+            0x07 | 0x09 | 0x0a | 0x0b | 0x0c | 0x1c => false,
+            0x00..=0x1f => {
+                dropped.push(c);
+                false
+            }
+            _ => true,
+        })
+        .collect()
+}
+
+// Row boxed for teletext, without the open subtitling italics, underline and boxing
+// codes. Italics and underline are added to `dropped`.
+fn row_to_teletext(row: &[u8], dropped: &mut Vec<u8>) -> Vec<u8> {
+    let mut text = row
+        .iter()
+        .copied()
+        .filter(|&c| match c {
+            0x80 | 0x82 => {
+                dropped.push(c);
+                false
+            }
+            0x81 | 0x83..=0x85 => false,
+            _ => true,
+        })
+        .collect::<Vec<_>>();
+    if text.iter().all(|&c| c == b' ') {
+        return text;
+    }
+    let mut row = vec![0x0b, 0x0b];
+    row.append(&mut text);
+    row.extend([0x0a, 0x0a]);
+    row
+}
+
+// Units of encoded text, see `cues::pack_text_fields`, a diacritical mark of ISO/IEC
+// 6937 going with the letter following it.
+pub(crate) fn text_units(bytes: &[u8], cct: CharacterCodeTable) -> Vec<Vec<u8>> {
+    let mut units = vec![];
+    let mut index = 0;
+    while index < bytes.len() {
+        let len = match (cct, bytes[index]) {
+            (CharacterCodeTable::Latin, 0xc1..=0xcf) => 2.min(bytes.len() - index),
+            _ => 1,
+        };
+        units.push(bytes[index..index + len].to_vec());
+        index += len;
+    }
+    units
+}
+
+// Units of the text of an extension chain passed through `f`, see
+// `cues::pack_text_fields`, and the characters missing from its character code table.
+fn map_chain<F: FnMut(&str) -> String>(
+    blocks: &[TtiBlock],
+    f: &mut F,
+) -> (Vec<Vec<u8>>, Vec<char>) {
+    let cct = blocks[0].cct;
+    let bytes = blocks
+        .iter()
+        .flat_map(|tti| tti.tf.iter().copied().take_while(|&c| c != 0x8f))
+        .collect::<Vec<_>>();
+    let is_control = |c: u8| c < 0x20 || (0x80..0xa0).contains(&c);
+    let mut units = vec![];
+    let mut unmappable = vec![];
+    let mut rest = &bytes[..];
+    while !rest.is_empty() {
+        let len = rest
+            .iter()
+            .position(|&c| is_control(c) != is_control(rest[0]))
+            .unwrap_or(rest.len());
+        if is_control(rest[0]) {
+            units.push(rest[..len].to_vec());
+        } else {
+            for c in f(&cct.decode(&rest[..len])).chars() {
+                match cct.encode_char(c) {
+                    Some(bytes) => units.push(bytes),
+                    None => unmappable.push(c),
+                }
+            }
+        }
+        rest = &rest[len..];
+    }
+    (units, unmappable)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_character_table() {
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        let original = stl.clone();
+        let report = stl
+            .convert_character_table(CharacterCodeTable::LatinCyrillic)
+            .unwrap();
+        assert_eq!(CharacterCodeTable::LatinCyrillic, stl.gsi.cct);
+        assert!(stl
+            .ttis
+            .iter()
+            .all(|tti| tti.cct == CharacterCodeTable::LatinCyrillic));
+        assert!(report.unmappable.contains(&(2, vec!['î'])));
+        assert!(stl.ttis[1].get_text().contains("?lot de larmes"));
+        assert_eq!(original.ttis[0].get_text(), stl.ttis[0].get_text());
+
+        let mut stl = original.clone();
+        let options = ConvertOptions {
+            unmappable: UnmappablePolicy::Fail,
+        };
+        assert_eq!(
+            Err(ConvertError::Unmappable {
+                sn: 2,
+                chars: vec!['î']
+            }),
+            stl.convert_character_table_with_options(CharacterCodeTable::LatinGreek, &options)
+        );
+        assert_eq!(original, stl);
+
+        // "Привет" in ISO/IEC 8859-5.
+        let mut stl = original.clone();
+        let mut tf = vec![0x0d, 0x0b, 0x0b, 0xbf, 0xe0, 0xd8, 0xd2, 0xd5, 0xe2, 0x8a];
+        tf.resize(112, 0x8f);
+        stl.ttis[0].tf = tf;
+        stl.ttis[0].cct = CharacterCodeTable::LatinCyrillic;
+        assert_eq!("Привет\r\n", stl.ttis[0].get_text());
+        let report = stl
+            .convert_character_table(CharacterCodeTable::Latin)
+            .unwrap();
+        assert_eq!(
+            (1, "Привет".chars().collect::<Vec<_>>()),
+            report.unmappable[0]
+        );
+        assert_eq!("??????\r\n", stl.ttis[0].get_text());
+        assert_eq!(0x0d, stl.ttis[0].tf[0]);
+    }
+
+    #[test]
+    fn test_map_text() {
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        let original = stl.clone();
+        let report = stl.map_text(|text| text.to_uppercase()).unwrap();
+        assert_eq!(12, report.changed.len());
+        assert_eq!(
+            "         -ELLIS ISLAND,\r\n\r\n     ÎLOT DE LARMES ET D'EXIL,\r\n",
+            stl.ttis[1].get_text()
+        );
+        // Control codes are kept in place.
+        let codes = |tti: &TtiBlock| {
+            tti.tf
+                .iter()
+                .copied()
+                .filter(|&c| c < 0x20 || (0x80..0xa0).contains(&c))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(codes(&original.ttis[1]), codes(&stl.ttis[1]));
+
+        // Growing text is split in extension blocks.
+        let report = stl.map_text(|text| text.repeat(4)).unwrap();
+        assert!(report.too_long.is_empty());
+        assert!(stl.ttis.len() > 13);
+        assert_eq!(stl.ttis.len() as u16, stl.gsi.tnb);
+        assert_eq!(13, stl.gsi.tns);
+        let third = stl.subtitles().nth(2).unwrap();
+        assert_eq!(4, third.lines[0].matches("AU PIED DE LA STATUE").count());
+
+        let mut stl = original.clone();
+        let report = stl.map_text(|text| text.replace("Ellis", "Эллис")).unwrap();
+        assert_eq!(3, report.unmappable.len());
+        assert_eq!(original, stl);
+    }
+
+    #[test]
+    fn test_convert_display_standard() {
+        let original = parse_stl_from_file("stls/test.stl").unwrap();
+        let mut stl = original.clone();
+        let report = stl
+            .convert_display_standard(DisplayStandardCode::OpenSubtitling)
+            .unwrap();
+        assert_eq!(DisplayStandardCode::OpenSubtitling, stl.gsi.dsc);
+        assert_eq!(99, stl.gsi.mnr);
+        // Cyan and double height have no equivalent.
+        assert!(report.dropped_codes.contains(&(2, vec![0x06, 0x0d])));
+        assert!(stl.ttis.iter().all(|tti| !tti.tf.iter().any(|&c| c < 0x20)));
+        assert_eq!(85, stl.ttis[1].vp);
+        assert!(!stl.validate().has_errors(), "{}", stl.validate());
+
+        // Italics are lost going back to teletext.
+        stl.ttis[2].tf.insert(0, 0x80);
+        stl.ttis[2].tf.pop();
+        let report = stl
+            .convert_display_standard(DisplayStandardCode::Level1Teletext)
+            .unwrap();
+        assert_eq!(vec![(3, vec![0x80])], report.dropped_codes);
+        assert_eq!((40, 23), (stl.gsi.mnc, stl.gsi.mnr));
+        assert_eq!(20, stl.ttis[1].vp);
+        assert_eq!([0x0b, 0x0b], stl.ttis[1].tf[..2]);
+        let lines = |stl: &Stl| stl.subtitles().map(|sub| sub.lines).collect::<Vec<_>>();
+        assert_eq!(lines(&original), lines(&stl));
+        assert!(!stl.validate().has_errors(), "{}", stl.validate());
+    }
+}
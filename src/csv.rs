@@ -0,0 +1,480 @@
+//! CSV export of the subtitle list, for spreadsheets, and import of spotting lists.
+
+use thiserror::Error;
+
+use super::*;
+use srt::FrameRounding;
+
+/// Layout of the CSV export, see [`Stl::to_csv`].
+#[derive(Debug, Clone)]
+pub struct CsvExportOptions {
+    #[doc = "Field delimiter, a comma by default"]
+    pub delimiter: char,
+    #[doc = "Replaces the row breaks in the text column"]
+    pub row_separator: String,
+    #[doc = "Start with a UTF-8 byte order mark"]
+    pub bom: bool,
+}
+
+impl Default for CsvExportOptions {
+    fn default() -> CsvExportOptions {
+        CsvExportOptions {
+            delimiter: ',',
+            row_separator: " / ".to_string(),
+            bom: false,
+        }
+    }
+}
+
+impl CsvExportOptions {
+    /// Semicolons and a byte order mark, as expected by Excel in most locales.
+    pub fn excel() -> CsvExportOptions {
+        CsvExportOptions {
+            delimiter: ';',
+            bom: true,
+            ..CsvExportOptions::default()
+        }
+    }
+}
+
+const HEADER: [&str; 9] = [
+    "number",
+    "tc_in",
+    "tc_out",
+    "duration",
+    "rows",
+    "characters",
+    "cps",
+    "text",
+    "formatting",
+];
+
+impl Stl {
+    /// Write a header and a CSV record per subtitle: number, time codes, duration in
+    /// seconds, number of rows and of displayed characters, characters per second, text
+    /// and formatting (double height, italic, underline, colour). Records end with
+    /// CR/LF and fields are quoted when needed.
+    pub fn to_csv<W: Write>(&self, w: &mut W, options: &CsvExportOptions) -> io::Result<()> {
+        let fps = self.gsi.dfc.get_fps();
+        if options.bom {
+            w.write_all("\u{feff}".as_bytes())?;
+        }
+        write_record(w, &HEADER.map(String::from), options.delimiter)?;
+        for subtitle in self.subtitles() {
+            let frames = subtitle
+                .end
+                .to_frames(fps)
+                .saturating_sub(subtitle.start.to_frames(fps));
+            let seconds = frames as f64 / fps as f64;
+            let characters = subtitle
+                .lines
+                .iter()
+                .map(|line| line.chars().count())
+                .sum::<usize>();
+            let cps = match frames {
+                0 => String::new(),
+                _ => format!("{:.1}", characters as f64 / seconds),
+            };
+            write_record(
+                w,
+                &[
+                    subtitle.number.to_string(),
+                    subtitle.start.to_smpte(),
+                    subtitle.end.to_smpte(),
+                    format!("{:.2}", seconds),
+                    subtitle.lines.len().to_string(),
+                    characters.to_string(),
+                    cps,
+                    subtitle.lines.join(&options.row_separator),
+                    formatting(subtitle.blocks()).join(" "),
+                ],
+                options.delimiter,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn write_record<W: Write>(w: &mut W, fields: &[String], delimiter: char) -> io::Result<()> {
+    let record = fields
+        .iter()
+        .map(|field| quote(field, delimiter))
+        .collect::<Vec<_>>()
+        .join(&delimiter.to_string());
+    write!(w, "{}\r\n", record)
+}
+
+// Field quoted as in RFC 4180 when it holds a delimiter, a quote, a line break or
+// surrounding spaces that spreadsheets would drop.
+fn quote(field: &str, delimiter: char) -> String {
+    let needs_quotes = field
+        .chars()
+        .any(|c| c == delimiter || c == '"' || c == '\r' || c == '\n')
+        || field.starts_with(' ')
+        || field.ends_with(' ');
+    match needs_quotes {
+        true => format!("\"{}\"", field.replace('"', "\"\"")),
+        false => field.to_string(),
+    }
+}
+
+fn formatting(blocks: &[&TtiBlock]) -> Vec<&'static str> {
+    let codes = blocks
+        .iter()
+        .flat_map(|tti| tti.tf.iter().copied().take_while(|&c| c != 0x8f))
+        .collect::<Vec<_>>();
+    [
+        ("double-height", &[0x0d][..]),
+        ("italic", &[0x80][..]),
+        ("underline", &[0x82][..]),
+        ("colour", &[0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06][..]),
+    ]
+    .into_iter()
+    .filter(|(_, flag_codes)| codes.iter().any(|c| flag_codes.contains(c)))
+    .map(|(flag, _)| flag)
+    .collect()
+}
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum CsvImportError {
+    #[error("Cannot read the CSV input: {0}")]
+    Io(String),
+    #[error("Line {0}: quoted field not closed")]
+    Quote(usize),
+    #[error("Invalid cues: {0:?}")]
+    Cues(Vec<CueError>),
+}
+
+/// Record skipped while importing a CSV spotting list, with the line it starts on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CsvImportWarning {
+    #[doc = "A time code is malformed or not valid at the frame rate, or the out time is not after the in time"]
+    Timing(usize),
+    #[doc = "Fewer fields than the mapped columns"]
+    Columns(usize),
+    #[doc = "The text is empty"]
+    EmptyText(usize),
+}
+
+/// Column mapping and conversion of a CSV spotting list, see [`Stl::from_timed_csv`].
+#[derive(Debug, Clone)]
+pub struct CsvImportOptions {
+    #[doc = "Field delimiter, a comma by default"]
+    pub delimiter: char,
+    #[doc = "Skip the first record, a header"]
+    pub header: bool,
+    #[doc = "Column of the Time Code In, counted from 0"]
+    pub tc_in_column: usize,
+    #[doc = "Column of the Time Code Out, counted from 0"]
+    pub tc_out_column: usize,
+    #[doc = "Column of the text, counted from 0"]
+    pub text_column: usize,
+    #[doc = "Breaks rows in the text besides line breaks, such as \" / \" written by the export"]
+    pub row_separator: Option<String>,
+    #[doc = "GSI block of the document, its frame rate is used for the time codes"]
+    pub gsi: GsiBlock,
+    #[doc = "Justification, vertical position and double height of every subtitle"]
+    pub format: TtiFormat,
+    #[doc = "Rounding of the time codes given in milliseconds"]
+    pub rounding: FrameRounding,
+    #[doc = "Wrap rows longer than the maximum number of characters instead of failing"]
+    pub wrap: bool,
+}
+
+impl Default for CsvImportOptions {
+    fn default() -> CsvImportOptions {
+        CsvImportOptions {
+            delimiter: ',',
+            header: true,
+            tc_in_column: 0,
+            tc_out_column: 1,
+            text_column: 2,
+            row_separator: None,
+            gsi: GsiBlock::new(),
+            format: TtiFormat {
+                jc: 2,
+                vp: 20,
+                dh: false,
+            },
+            rounding: FrameRounding::Nearest,
+            wrap: true,
+        }
+    }
+}
+
+impl CsvImportOptions {
+    /// Semicolons, as written by Excel in most locales.
+    pub fn excel() -> CsvImportOptions {
+        CsvImportOptions {
+            delimiter: ';',
+            ..CsvImportOptions::default()
+        }
+    }
+}
+
+impl Stl {
+    /// Document made of the records of a CSV spotting list holding the in and out time
+    /// codes and the text of each subtitle. Time codes are written HH:MM:SS:FF, or
+    /// HH:MM:SS.mmm and rounded to frames at the frame rate of the GSI block. The input
+    /// is UTF-8, with or without a byte order mark, or else Windows-1252. Records with
+    /// unusable time codes are skipped and reported with their line number.
+    pub fn from_timed_csv<R: Read>(
+        mut r: R,
+        options: &CsvImportOptions,
+    ) -> Result<(Stl, Vec<CsvImportWarning>), CsvImportError> {
+        let mut bytes = vec![];
+        r.read_to_end(&mut bytes)
+            .map_err(|err| CsvImportError::Io(err.to_string()))?;
+        let csv = match str::from_utf8(&bytes) {
+            Ok(csv) => csv.strip_prefix('\u{feff}').unwrap_or(csv).to_string(),
+            Err(_) => encoding_rs::WINDOWS_1252
+                .decode_without_bom_handling(&bytes)
+                .0
+                .into_owned(),
+        };
+        let gsi = options.gsi.clone();
+        let fps = gsi.dfc.get_fps();
+        let mut warnings = vec![];
+        let mut cues = vec![];
+        let records = parse_records(&csv, options.delimiter)?;
+        for (line, fields) in records.into_iter().skip(options.header as usize) {
+            let field = |column: usize| fields.get(column).map(|field| field.trim());
+            let (Some(tc_in), Some(tc_out), Some(text)) = (
+                field(options.tc_in_column),
+                field(options.tc_out_column),
+                field(options.text_column),
+            ) else {
+                warnings.push(CsvImportWarning::Columns(line));
+                continue;
+            };
+            let time = |field| parse_time(field, fps, options.rounding);
+            let (Some(start), Some(end)) = (time(tc_in), time(tc_out)) else {
+                warnings.push(CsvImportWarning::Timing(line));
+                continue;
+            };
+            if end.to_frames(fps) <= start.to_frames(fps) {
+                warnings.push(CsvImportWarning::Timing(line));
+                continue;
+            }
+            let mut text = text.replace("\r\n", "\n");
+            if let Some(separator) = options.row_separator.as_deref().filter(|s| !s.is_empty()) {
+                text = text.replace(separator, "\n");
+            }
+            let text = text
+                .lines()
+                .map(str::trim)
+                .filter(|row| !row.is_empty())
+                .collect::<Vec<_>>()
+                .join("\n");
+            if text.is_empty() {
+                warnings.push(CsvImportWarning::EmptyText(line));
+                continue;
+            }
+            cues.push(Cue {
+                start,
+                end,
+                text,
+                format: options.format,
+            });
+        }
+        if !options.wrap {
+            let errors = cues::long_rows(&cues, gsi.mnc as usize);
+            if !errors.is_empty() {
+                return Err(CsvImportError::Cues(errors));
+            }
+        }
+        let stl = Stl::from_cues(gsi, cues).map_err(CsvImportError::Cues)?;
+        Ok((stl, warnings))
+    }
+}
+
+// Records of a CSV document as in RFC 4180, with the line each starts on. Blank lines
+// are left out, quotes in the middle of an unquoted field are kept as is.
+fn parse_records(csv: &str, delimiter: char) -> Result<Vec<(usize, Vec<String>)>, CsvImportError> {
+    let mut records = vec![];
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut line = 1;
+    let mut start = 1;
+    let mut quoted = false;
+    let mut chars = csv.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted => match chars.peek() {
+                Some('"') => {
+                    field.push('"');
+                    chars.next();
+                }
+                _ => quoted = false,
+            },
+            '"' if field.is_empty() => quoted = true,
+            '\n' if quoted => {
+                field.push(c);
+                line += 1;
+            }
+            _ if quoted => field.push(c),
+            _ if c == delimiter => fields.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => (),
+            '\n' => {
+                fields.push(std::mem::take(&mut field));
+                if fields.len() > 1 || !fields[0].trim().is_empty() {
+                    records.push((start, std::mem::take(&mut fields)));
+                }
+                fields.clear();
+                line += 1;
+                start = line;
+            }
+            _ => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(CsvImportError::Quote(start));
+    }
+    fields.push(field);
+    if fields.len() > 1 || !fields[0].trim().is_empty() {
+        records.push((start, fields));
+    }
+    Ok(records)
+}
+
+// Time of a time code written HH:MM:SS:FF, or HH:MM:SS.mmm (or with a comma) rounded to
+// a frame, `None` if malformed or not a valid time at `fps`.
+fn parse_time(field: &str, fps: usize, rounding: FrameRounding) -> Option<Time> {
+    let number = |digits: &str, max: u64| {
+        (!digits.is_empty() && digits.len() <= 2 && digits.bytes().all(|c| c.is_ascii_digit()))
+            .then(|| digits.parse::<u64>().ok())
+            .flatten()
+            .filter(|&n| n < max)
+    };
+    match field.split(':').collect::<Vec<_>>()[..] {
+        [h, m, s, f] => {
+            let time = Time::new(
+                number(h, 24)? as u8,
+                number(m, 60)? as u8,
+                number(s, 60)? as u8,
+                number(f, fps as u64)? as u8,
+            );
+            Some(time)
+        }
+        [h, m, s] => {
+            let (s, fraction) = s.split_once(['.', ','])?;
+            if fraction.is_empty()
+                || fraction.len() > 3
+                || !fraction.bytes().all(|c| c.is_ascii_digit())
+            {
+                return None;
+            }
+            let millis = fraction.parse::<u64>().ok()? * 10u64.pow(3 - fraction.len() as u32);
+            let seconds = (number(h, 24)? * 60 + number(m, 60)?) * 60 + number(s, 60)?;
+            let frames = rounding.frames(seconds * 1000 + millis, fps);
+            Some(Time::from_frames(frames, fps)).filter(|time| time.is_valid(fps))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote() {
+        assert_eq!("plain", quote("plain", ','));
+        assert_eq!("\"a, b\"", quote("a, b", ','));
+        assert_eq!("a, b", quote("a, b", ';'));
+        assert_eq!("\"say \"\"hi\"\"\"", quote("say \"hi\"", ','));
+        assert_eq!("\" padded\"", quote(" padded", ','));
+    }
+
+    #[test]
+    fn test_to_csv() {
+        let stl = parse_stl_from_file("stls/test.stl").unwrap();
+        let mut csv = vec![];
+        stl.to_csv(&mut csv, &CsvExportOptions::default()).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let records = csv.split_terminator("\r\n").collect::<Vec<_>>();
+        assert_eq!(14, records.len());
+        assert_eq!(
+            "number,tc_in,tc_out,duration,rows,characters,cps,text,formatting",
+            records[0]
+        );
+        assert_eq!(
+            "2,10:00:06:08,10:00:09:09,3.04,2,39,12.8,\
+             \"-Ellis Island, / îlot de larmes et d'exil,\",double-height colour",
+            records[2]
+        );
+
+        let mut csv = vec![];
+        stl.to_csv(&mut csv, &CsvExportOptions::excel()).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert!(csv.starts_with("\u{feff}number;tc_in;"));
+        assert!(csv.contains(";-Ellis Island, / îlot de larmes et d'exil,;"));
+    }
+
+    #[test]
+    fn test_parse_records() {
+        let csv = "a,\"b, \"\"c\"\"\"\r\n\r\n\"two\nrows\";x\"y\nlast,";
+        assert_eq!(
+            vec![
+                (1, vec!["a".to_string(), "b, \"c\"".to_string()]),
+                (3, vec!["two\nrows;x\"y".to_string()]),
+                (5, vec!["last".to_string(), String::new()]),
+            ],
+            parse_records(csv, ',').unwrap()
+        );
+        assert_eq!(
+            Err(CsvImportError::Quote(2)),
+            parse_records("a\n\"b,c\n", ',')
+        );
+    }
+
+    #[test]
+    fn test_parse_time() {
+        let time = |field| parse_time(field, 25, FrameRounding::Nearest);
+        assert_eq!(Some(Time::new(10, 0, 6, 8)), time("10:00:06:08"));
+        assert_eq!(Some(Time::new(10, 0, 6, 8)), time("10:00:06.320"));
+        assert_eq!(Some(Time::new(0, 1, 2, 13)), time("00:01:02,5"));
+        assert_eq!(None, time("10:00:06:25"));
+        assert_eq!(None, time("10:00:06"));
+        assert_eq!(None, time("10:00:06.1234"));
+        assert_eq!(None, time("1O:00:06:08"));
+    }
+
+    #[test]
+    fn test_from_timed_csv() {
+        let csv = "\u{feff}In;Out;Speaker;Text\r\n\
+                   10:00:01:00;10:00:03:12;A;\"Hello; world\"\r\n\
+                   10:00:04.000;bad;B;Skipped\r\n\
+                   10:00:05:00;10:00:07:00;A;\"First row / second row\"\r\n\
+                   10:00:08:00;10:00:09:00\r\n";
+        let options = CsvImportOptions {
+            text_column: 3,
+            row_separator: Some(" / ".to_string()),
+            ..CsvImportOptions::excel()
+        };
+        let (stl, warnings) = Stl::from_timed_csv(csv.as_bytes(), &options).unwrap();
+        assert_eq!(
+            vec![CsvImportWarning::Timing(3), CsvImportWarning::Columns(5)],
+            warnings
+        );
+        let subtitles = stl.subtitles().collect::<Vec<_>>();
+        assert_eq!(2, subtitles.len());
+        assert_eq!(&Time::new(10, 0, 3, 12), subtitles[0].end);
+        assert_eq!(vec!["Hello; world"], subtitles[0].lines);
+        assert_eq!(vec!["First row", "second row"], subtitles[1].lines);
+
+        let latin1 = b"in,out,text\n00:00:01:00,00:00:02:00,Caf\xe9\n";
+        let (stl, _) = Stl::from_timed_csv(&latin1[..], &CsvImportOptions::default()).unwrap();
+        assert_eq!(vec!["Caf\u{e9}"], stl.subtitles().next().unwrap().lines);
+
+        let long = format!("in,out,text\n00:00:01:00,00:00:02:00,{}\n", "a".repeat(50));
+        let options = CsvImportOptions {
+            wrap: false,
+            ..CsvImportOptions::default()
+        };
+        assert_eq!(
+            Err(CsvImportError::Cues(vec![CueError::RowTooLong(0)])),
+            Stl::from_timed_csv(long.as_bytes(), &options).map(|_| ())
+        );
+    }
+}
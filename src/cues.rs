@@ -0,0 +1,615 @@
+use thiserror::Error;
+
+use super::*;
+
+/// A subtitle to be turned into TTI blocks by [`Stl::from_cues`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cue {
+    pub start: Time,
+    pub end: Time,
+    #[doc = "Rows separated by new lines, longer rows are wrapped"]
+    pub text: String,
+    pub format: TtiFormat,
+}
+
+/// Problem found with a cue, identified by its position in the input.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum CueError {
+    #[error("Cue {0} does not end after it starts")]
+    OutNotAfterIn(usize),
+    #[error("Cue {0} has a time code that is not a valid time at the frame rate")]
+    InvalidTimeCode(usize),
+    #[error("Cue {0} starts before the end of cue {1}")]
+    Overlap(usize, usize),
+    #[error("Cue {0} has more rows than the declared maximum")]
+    TooManyRows(usize),
+    #[error("Cue {0} has a row longer than the maximum number of characters")]
+    RowTooLong(usize),
+    #[error("Characters {chars:?} of cue {cue} are missing from the character code table")]
+    Unmappable { cue: usize, chars: Vec<char> },
+    #[error("Text of cue {0} does not fit in an extension chain")]
+    TextTooLong(usize),
+    #[error(
+        "Cue {0} is past the 65535 subtitles or TTI blocks of a disk, see Stl::write_multi_disk"
+    )]
+    CapacityExceeded(usize),
+}
+
+impl CueError {
+    // Position of the cue with the problem.
+    pub(crate) fn cue(&self) -> usize {
+        match *self {
+            CueError::OutNotAfterIn(cue)
+            | CueError::InvalidTimeCode(cue)
+            | CueError::Overlap(cue, _)
+            | CueError::Unmappable { cue, .. }
+            | CueError::TooManyRows(cue)
+            | CueError::RowTooLong(cue)
+            | CueError::TextTooLong(cue)
+            | CueError::CapacityExceeded(cue) => cue,
+        }
+    }
+
+    // Same error with the cues numbered by `number`.
+    pub(crate) fn renumber(self, number: impl Fn(usize) -> usize) -> CueError {
+        match self {
+            CueError::OutNotAfterIn(cue) => CueError::OutNotAfterIn(number(cue)),
+            CueError::InvalidTimeCode(cue) => CueError::InvalidTimeCode(number(cue)),
+            CueError::Overlap(cue, other) => CueError::Overlap(number(cue), number(other)),
+            CueError::TooManyRows(cue) => CueError::TooManyRows(number(cue)),
+            CueError::RowTooLong(cue) => CueError::RowTooLong(number(cue)),
+            CueError::Unmappable { cue, chars } => CueError::Unmappable {
+                cue: number(cue),
+                chars,
+            },
+            CueError::TextTooLong(cue) => CueError::TextTooLong(number(cue)),
+            CueError::CapacityExceeded(cue) => CueError::CapacityExceeded(number(cue)),
+        }
+    }
+}
+
+// Characters of the private use area standing for the open subtitling italics and
+// underline codes (80h-83h) in the text of a cue, written as the code itself.
+pub(crate) const ITALICS_ON: char = '\u{e080}';
+pub(crate) const ITALICS_OFF: char = '\u{e081}';
+pub(crate) const UNDERLINE_ON: char = '\u{e082}';
+pub(crate) const UNDERLINE_OFF: char = '\u{e083}';
+
+// Character standing for a teletext alphanumeric colour code (00h-07h), which unlike
+// the open subtitling codes takes the place of a character on the row.
+pub(crate) fn colour(code: u8) -> char {
+    char::from_u32(0xe000 + (code & 0x07) as u32).unwrap()
+}
+
+fn control_code(c: char) -> Option<u8> {
+    match c {
+        '\u{e000}'..='\u{e007}' => Some(c as u32 as u8),
+        ITALICS_ON => Some(0x80),
+        ITALICS_OFF => Some(0x81),
+        UNDERLINE_ON => Some(0x82),
+        UNDERLINE_OFF => Some(0x83),
+        _ => None,
+    }
+}
+
+// Whether `c` takes no place on the row, combining marks going on the character
+// before them.
+fn is_zero_width(c: char) -> bool {
+    matches!(control_code(c), Some(0x80..=0x83)) || ('\u{300}'..='\u{36f}').contains(&c)
+}
+
+// Non-spacing diacritical marks of ISO 6937 for the combining marks, written before
+// the letter they go on.
+const ISO6937_DIACRITICS: [(char, u8); 13] = [
+    ('\u{300}', 0xc1),
+    ('\u{301}', 0xc2),
+    ('\u{302}', 0xc3),
+    ('\u{303}', 0xc4),
+    ('\u{304}', 0xc5),
+    ('\u{306}', 0xc6),
+    ('\u{307}', 0xc7),
+    ('\u{308}', 0xc8),
+    ('\u{30a}', 0xca),
+    ('\u{327}', 0xcb),
+    ('\u{30b}', 0xcd),
+    ('\u{328}', 0xce),
+    ('\u{30c}', 0xcf),
+];
+
+// Bytes of `letter` followed by the combining `mark`, as the single character they
+// show, `None` if the table has no such character.
+fn encode_combined(cct: CharacterCodeTable, letter: char, mark: char) -> Option<Vec<u8>> {
+    if cct != CharacterCodeTable::Latin || !letter.is_ascii_alphabetic() {
+        return None;
+    }
+    let (_, diacritic) = ISO6937_DIACRITICS.iter().find(|(c, _)| *c == mark)?;
+    let bytes = vec![*diacritic, letter as u8];
+    (iso6937::decode(&bytes) != "#").then_some(bytes)
+}
+
+// Typographic characters missing from some tables with their closest ASCII.
+const LOOKALIKES: [(char, &str); 16] = [
+    ('\u{a0}', " "),
+    ('\u{ab}', "\""),
+    ('\u{bb}', "\""),
+    ('\u{2010}', "-"),
+    ('\u{2011}', "-"),
+    ('\u{2012}', "-"),
+    ('\u{2013}', "-"),
+    ('\u{2014}', "-"),
+    ('\u{2015}', "-"),
+    ('\u{2018}', "'"),
+    ('\u{2019}', "'"),
+    ('\u{201a}', "'"),
+    ('\u{201c}', "\""),
+    ('\u{201d}', "\""),
+    ('\u{201e}', "\""),
+    ('\u{2026}', "..."),
+];
+
+impl CharacterCodeTable {
+    /// Whether `c` can be written with the table.
+    pub fn can_encode(&self, c: char) -> bool {
+        self.encode_char(c).is_some()
+    }
+
+    /// Characters of `text` that cannot be written with the table, once each in order
+    /// of appearance. New lines separate rows, combining marks are accepted on the
+    /// letters they can go on.
+    pub fn unmappable(&self, text: &str) -> Vec<char> {
+        let mut unmappable = vec![];
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            if chars
+                .peek()
+                .is_some_and(|&mark| encode_combined(*self, c, mark).is_some())
+            {
+                chars.next();
+            } else if c != '\n' && !self.can_encode(c) && !unmappable.contains(&c) {
+                unmappable.push(c);
+            }
+        }
+        unmappable
+    }
+
+    /// Closest text to `c` the table can write: `c` itself, ASCII for the typographic
+    /// quotes, dashes and ellipsis, or the letter without its diacritic. Empty when there
+    /// is none.
+    pub fn transliterate(&self, c: char) -> String {
+        if self.can_encode(c) {
+            return c.to_string();
+        }
+        if let Some((_, ascii)) = LOOKALIKES.iter().find(|(lookalike, _)| *lookalike == c) {
+            return ascii.to_string();
+        }
+        // ISO 6937 writes the accented letters as a diacritic followed by the letter.
+        match &*iso6937::encode(&c.to_string()) {
+            [0xc1..=0xcf, letter] if letter.is_ascii_alphabetic() => (*letter as char).to_string(),
+            _ => String::new(),
+        }
+    }
+}
+
+// Extension blocks 00h-EFh followed by the last block.
+pub(crate) const MAX_CHAIN_LENGTH: usize = 0xf0 + 1;
+
+impl Stl {
+    /// Document made of `cues` sorted by time code in, numbered from 1, their rows
+    /// wrapped at the maximum number of characters of `gsi` and their text encoded with
+    /// its character code table, split in extension blocks when longer than a text
+    /// field. The totals of `gsi` are updated.
+    ///
+    /// [`StlBuilder`](crate::StlBuilder) sets up the GSI block as well.
+    ///
+    /// Every problem found in the cues is returned, nothing is built unless there are
+    /// none.
+    pub fn from_cues(
+        gsi: GsiBlock,
+        cues: impl IntoIterator<Item = Cue>,
+    ) -> Result<Stl, Vec<CueError>> {
+        let ttis = blocks(&gsi, cues, 1, 0)?;
+        let mut stl = Stl { gsi, ttis };
+        stl.recompute_totals();
+        Ok(stl)
+    }
+
+    /// Append `cues` converted as by [`Stl::from_cues`] with the GSI block of the
+    /// document, numbered after its last subtitle. They are checked against each other
+    /// but not against the subtitles already there.
+    ///
+    /// Every problem found in the cues is returned, nothing is appended unless there
+    /// are none.
+    pub fn try_extend_cues(
+        &mut self,
+        cues: impl IntoIterator<Item = Cue>,
+    ) -> Result<(), Vec<CueError>> {
+        let last = self
+            .subtitles()
+            .map(|subtitle| subtitle.number)
+            .max()
+            .unwrap_or(0);
+        let ttis = blocks(&self.gsi, cues, last as usize + 1, self.ttis.len())?;
+        self.ttis.extend(ttis);
+        self.recompute_totals();
+        Ok(())
+    }
+}
+
+/// Document made with a default GSI block, see [`Stl::from_cues`].
+impl FromIterator<Cue> for Result<Stl, Vec<CueError>> {
+    fn from_iter<I: IntoIterator<Item = Cue>>(cues: I) -> Result<Stl, Vec<CueError>> {
+        Stl::from_cues(GsiBlock::new(), cues)
+    }
+}
+
+/// Cue with the plain text rows, time codes and format of a subtitle, so that
+/// subtitles can be collected into a new document.
+impl From<Subtitle<'_>> for Cue {
+    fn from(subtitle: Subtitle<'_>) -> Cue {
+        let first = subtitle.blocks()[0];
+        Cue {
+            start: *subtitle.start,
+            end: *subtitle.end,
+            text: subtitle.lines.join("\n"),
+            format: TtiFormat {
+                jc: first.jc,
+                vp: first.vp,
+                dh: subtitle.is_double_height,
+            },
+        }
+    }
+}
+
+// TTI blocks of `cues`, numbered from `first_number`, to follow `block_count` blocks.
+fn blocks(
+    gsi: &GsiBlock,
+    cues: impl IntoIterator<Item = Cue>,
+    first_number: usize,
+    block_count: usize,
+) -> Result<Vec<TtiBlock>, Vec<CueError>> {
+    let mut cues = cues.into_iter().enumerate().collect::<Vec<_>>();
+    cues.sort_by_key(|(_, cue)| cue.start);
+
+    let mut errors = vec![];
+    let mut ttis = vec![];
+    let fps = gsi.dfc.get_fps();
+    let mut previous: Option<(usize, &Cue)> = None;
+    for (position, (index, cue)) in cues.iter().enumerate() {
+        if !cue.start.is_valid(fps) || !cue.end.is_valid(fps) {
+            errors.push(CueError::InvalidTimeCode(*index));
+        }
+        if cue.end <= cue.start {
+            errors.push(CueError::OutNotAfterIn(*index));
+        }
+        if let Some((previous, _)) = previous.filter(|(_, previous)| previous.end > cue.start) {
+            errors.push(CueError::Overlap(*index, previous));
+        }
+        previous = Some((*index, cue));
+
+        let rows = wrap(&cue.text, gsi.mnc as usize);
+        if rows.len() > gsi.mnr as usize {
+            errors.push(CueError::TooManyRows(*index));
+        }
+        let Ok(sn) = u16::try_from(first_number + position) else {
+            errors.push(CueError::CapacityExceeded(*index));
+            continue;
+        };
+        let (text_fields, unmappable) = text_fields(&rows, cue.format.dh, gsi.cct);
+        if !unmappable.is_empty() {
+            errors.push(CueError::Unmappable {
+                cue: *index,
+                chars: unmappable,
+            });
+        }
+        if text_fields.len() > MAX_CHAIN_LENGTH {
+            errors.push(CueError::TextTooLong(*index));
+            continue;
+        }
+        if block_count + ttis.len() + text_fields.len() > u16::MAX as usize {
+            errors.push(CueError::CapacityExceeded(*index));
+            continue;
+        }
+        ttis.extend(chain_blocks(sn, cue, text_fields, gsi.cct));
+    }
+    if !errors.is_empty() {
+        errors.sort_by_key(CueError::cue);
+        return Err(errors);
+    }
+    Ok(ttis)
+}
+
+// Number of characters `row` takes on screen.
+pub(crate) fn row_width(row: &str) -> usize {
+    row.chars().filter(|&c| !is_zero_width(c)).count()
+}
+
+// Errors for the cues having a row wider than `width`, for imports that do not wrap.
+pub(crate) fn long_rows(cues: &[Cue], width: usize) -> Vec<CueError> {
+    cues.iter()
+        .enumerate()
+        .filter(|(_, cue)| cue.text.lines().any(|row| row_width(row) > width))
+        .map(|(index, _)| CueError::RowTooLong(index))
+        .collect()
+}
+
+// Rows of at most `width` characters, broken between words when possible.
+pub(crate) fn wrap(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut rows = vec![];
+    for line in text.lines() {
+        let mut row = String::new();
+        for word in line.split_whitespace() {
+            let mut word = word.chars().collect::<Vec<_>>();
+            let row_len = row_width(&row);
+            let word_len = word.iter().filter(|&&c| !is_zero_width(c)).count();
+            if row_len > 0 && row_len + 1 + word_len > width {
+                rows.push(std::mem::take(&mut row));
+            } else if row_len > 0 {
+                row.push(' ');
+            }
+            while word.len() > width {
+                rows.push(word.drain(..width).collect());
+            }
+            row.extend(word);
+        }
+        rows.push(row);
+    }
+    rows
+}
+
+// Extension chain of the subtitle numbered `sn` showing `cue`, its text encoded with
+// the character code table of `gsi` and wrapped to its row width as in
+// `Stl::from_cues`.
+pub(crate) fn chain(gsi: &GsiBlock, sn: u16, cue: &Cue) -> Result<Vec<TtiBlock>, EditError> {
+    let rows = wrap(&cue.text, gsi.mnc as usize);
+    let (text_fields, unmappable) = text_fields(&rows, cue.format.dh, gsi.cct);
+    if !unmappable.is_empty() {
+        return Err(EditError::Unmappable(unmappable));
+    }
+    if text_fields.len() > MAX_CHAIN_LENGTH {
+        return Err(EditError::TextTooLong);
+    }
+    Ok(chain_blocks(sn, cue, text_fields, gsi.cct))
+}
+
+fn chain_blocks(
+    sn: u16,
+    cue: &Cue,
+    text_fields: Vec<Vec<u8>>,
+    cct: CharacterCodeTable,
+) -> Vec<TtiBlock> {
+    let last = text_fields.len() - 1;
+    text_fields
+        .into_iter()
+        .enumerate()
+        .map(|(ebn, tf)| TtiBlock {
+            sgn: 0,
+            sn,
+            ebn: if ebn == last { 0xff } else { ebn as u8 },
+            cs: CumulativeStatus::NotPartOfASet,
+            tci: cue.start,
+            tco: cue.end,
+            vp: cue.format.vp,
+            jc: cue.format.jc,
+            cf: 0,
+            tf,
+            cct,
+        })
+        .collect()
+}
+
+// Text fields of the extension chain, rows are boxed as in `TtiBlock::encode_text` and
+// separated by CR/LF. Characters are never split across blocks, the ones missing from
+// `cct` are returned.
+fn text_fields(rows: &[String], dh: bool, cct: CharacterCodeTable) -> (Vec<Vec<u8>>, Vec<char>) {
+    let mut unmappable = vec![];
+    let mut units: Vec<Vec<u8>> = vec![];
+    for (index, row) in rows.iter().enumerate() {
+        if index > 0 {
+            units.push(vec![0x8a]);
+        }
+        if dh {
+            units.push(vec![0x0d]);
+        }
+        units.push(vec![0x0b, 0x0b]);
+        let mut chars = row.chars().peekable();
+        while let Some(c) = chars.next() {
+            let combined = chars.peek().and_then(|&mark| encode_combined(cct, c, mark));
+            if let Some(bytes) = combined {
+                chars.next();
+                units.push(bytes);
+                continue;
+            }
+            match control_code(c)
+                .map(|code| vec![code])
+                .or_else(|| cct.encode_char(c))
+            {
+                Some(bytes) => units.push(bytes),
+                None => unmappable.push(c),
+            }
+        }
+        units.push(vec![0x0a, 0x0a]);
+    }
+    (pack_text_fields(units), unmappable)
+}
+
+// Units of encoded text (characters or control codes that go together) laid out in
+// text fields padded with unused space, a unit is never split across fields.
+pub(crate) fn pack_text_fields(units: Vec<Vec<u8>>) -> Vec<Vec<u8>> {
+    const TF_LENGTH: usize = 112;
+    let mut text_fields = vec![vec![]];
+    for unit in units {
+        let tf = text_fields.last_mut().unwrap();
+        if tf.len() + unit.len() > TF_LENGTH {
+            text_fields.push(unit);
+        } else {
+            tf.extend(unit);
+        }
+    }
+    for tf in &mut text_fields {
+        tf.resize(TF_LENGTH, 0x8f);
+    }
+    text_fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cue(start: u8, end: u8, text: &str) -> Cue {
+        Cue {
+            start: Time::new(0, 0, start, 0),
+            end: Time::new(0, 0, end, 0),
+            text: text.to_string(),
+            format: TtiFormat {
+                jc: 2,
+                vp: 20,
+                dh: false,
+            },
+        }
+    }
+
+    #[test]
+    fn test_from_cues() {
+        let long = "Une très longue réplique ".repeat(5);
+        let stl = Stl::from_cues(
+            GsiBlock::new(),
+            vec![
+                cue(5, 7, "Second"),
+                cue(1, 3, "First\nsubtitle"),
+                cue(8, 12, &long),
+            ],
+        )
+        .unwrap();
+        let blocks = stl
+            .ttis
+            .iter()
+            .map(|tti| (tti.sn, tti.ebn))
+            .collect::<Vec<_>>();
+        assert_eq!(vec![(1, 0xff), (2, 0xff), (3, 0), (3, 0xff)], blocks);
+        assert_eq!((4, 3, 1), (stl.gsi.tnb, stl.gsi.tns, stl.gsi.tng));
+        assert_eq!("First\r\nsubtitle", stl.ttis[0].get_text());
+        let subtitles = stl.subtitles().collect::<Vec<_>>();
+        assert_eq!(4, subtitles[2].lines.len());
+        assert_eq!(long.trim(), subtitles[2].lines.join(" "));
+        assert!(!stl.validate().has_errors(), "{}", stl.validate());
+    }
+
+    #[test]
+    fn test_from_cues_errors() {
+        let errors = Stl::from_cues(
+            GsiBlock::new(),
+            vec![
+                cue(1, 3, "First"),
+                cue(2, 4, "Overlapping"),
+                cue(6, 5, "Backwards"),
+                cue(7, 8, &"row\n".repeat(24)),
+                cue(9, 60, "Привет"),
+            ],
+        )
+        .unwrap_err();
+        assert_eq!(
+            vec![
+                CueError::Overlap(1, 0),
+                CueError::OutNotAfterIn(2),
+                CueError::TooManyRows(3),
+                CueError::InvalidTimeCode(4),
+                CueError::Unmappable {
+                    cue: 4,
+                    chars: "Привет".chars().collect()
+                },
+            ],
+            errors
+        );
+    }
+
+    #[test]
+    fn test_combining_marks() {
+        // "Vérité" with combining acute accents takes 6 characters on screen.
+        let text = "Ve\u{301}rite\u{301} ".repeat(6);
+        let mut gsi = GsiBlock::new();
+        gsi.mnc = 13;
+        let stl = Stl::from_cues(gsi, vec![cue(1, 3, &text)]).unwrap();
+        let subtitles = stl.subtitles().collect::<Vec<_>>();
+        assert_eq!(vec!["Vérité Vérité"; 3], subtitles[0].lines);
+        assert_eq!(6, row_width("Ve\u{301}rite\u{301}"));
+
+        assert_eq!(
+            Err(vec![CueError::Unmappable {
+                cue: 0,
+                chars: vec!['\u{301}']
+            }]),
+            Stl::from_cues(GsiBlock::new(), vec![cue(1, 3, "1\u{301}")])
+        );
+    }
+
+    #[test]
+    fn test_unmappable() {
+        let latin = CharacterCodeTable::Latin;
+        assert!(latin.can_encode('é'));
+        assert!(!latin.can_encode('—'));
+        assert_eq!(
+            vec!['—', '\u{301}', '😀'],
+            latin.unmappable("Ve\u{301}rite\u{301} —\n1\u{301} 😀 —")
+        );
+        assert_eq!("-", latin.transliterate('—'));
+        assert_eq!("’", latin.transliterate('’'));
+        assert_eq!("", latin.transliterate('😀'));
+
+        let greek = CharacterCodeTable::LatinGreek;
+        assert_eq!(vec!['é', '…'], greek.unmappable("Café…"));
+        assert_eq!("e", greek.transliterate('é'));
+        assert_eq!("...", greek.transliterate('…'));
+        assert_eq!("", greek.transliterate('\u{301}'));
+    }
+
+    #[test]
+    fn test_collect_cues() {
+        let stl = parse_stl_from_file("stls/test.stl").unwrap();
+        assert_eq!(stl.ttis.len(), (&stl).into_iter().count());
+
+        let collected = stl
+            .subtitles()
+            .filter(|subtitle| subtitle.is_double_height)
+            .map(Cue::from)
+            .collect::<Result<Stl, _>>()
+            .unwrap();
+        let subtitles = collected.subtitles().collect::<Vec<_>>();
+        assert_eq!(
+            stl.subtitles()
+                .filter(|subtitle| subtitle.is_double_height)
+                .count(),
+            subtitles.len()
+        );
+        assert_eq!(1, subtitles[0].number);
+        assert!(subtitles.iter().all(|subtitle| subtitle.is_double_height));
+        assert!(subtitles
+            .iter()
+            .any(|subtitle| subtitle.lines == ["-Ellis Island,", "îlot de larmes et d'exil,"]));
+
+        let mut stl = vec![cue(1, 3, "First")]
+            .into_iter()
+            .collect::<Result<Stl, _>>()
+            .unwrap();
+        stl.try_extend_cues(vec![cue(6, 8, "Third"), cue(4, 5, "Second")])
+            .unwrap();
+        let numbered = stl
+            .subtitles()
+            .map(|subtitle| (subtitle.number, subtitle.lines[0].clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (1, "First".to_string()),
+                (2, "Second".to_string()),
+                (3, "Third".to_string())
+            ],
+            numbered
+        );
+        assert_eq!(3, stl.gsi.tns);
+        assert_eq!(
+            Err(vec![CueError::OutNotAfterIn(0)]),
+            stl.try_extend_cues(vec![cue(9, 9, "Empty")])
+        );
+        assert_eq!(3, stl.ttis.len());
+    }
+}
@@ -0,0 +1,316 @@
+//! XML and annotated hex dumps of the raw structure of a document, field by field,
+//! for inspection and diffing.
+
+use super::*;
+use diff::gsi_fields;
+
+// Offset and length of the GSI fields, in the order of `diff::gsi_fields`.
+const GSI_LAYOUT: [(usize, usize); 30] = [
+    (0, 3),
+    (3, 8),
+    (11, 1),
+    (12, 2),
+    (14, 2),
+    (16, 32),
+    (48, 32),
+    (80, 32),
+    (112, 32),
+    (144, 32),
+    (176, 32),
+    (208, 16),
+    (224, 6),
+    (230, 6),
+    (236, 2),
+    (238, 5),
+    (243, 5),
+    (248, 3),
+    (251, 2),
+    (253, 2),
+    (255, 1),
+    (256, 8),
+    (264, 8),
+    (272, 1),
+    (273, 1),
+    (274, 3),
+    (277, 32),
+    (309, 32),
+    (341, 32),
+    (448, 576),
+];
+const SPARE: (usize, usize) = (373, 75);
+
+// Bytes on a line of the hex dumps.
+const HEX_LINE: usize = 16;
+
+impl Stl {
+    /// XML listing every GSI field and every TTI block as written to file, each field
+    /// with its offset in the block, its bytes in hexadecimal and its decoded value.
+    /// Control codes in the text fields are shown as `[XX]`, the trailing 8Fh padding is
+    /// counted instead. The spare bytes of the GSI block are listed as written, spaces,
+    /// with the bytes read from the file as value when they differ.
+    #[cfg(feature = "xml")]
+    pub fn to_debug_xml(&self) -> Result<String, SerializeError> {
+        let gsi = self.gsi.serialize()?;
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<stl>\n");
+        xml.push_str("  <gsi>\n");
+        for ((name, value), (offset, len)) in gsi_fields(&self.gsi).into_iter().zip(GSI_LAYOUT) {
+            if name == "UDA" {
+                let written = &gsi[SPARE.0..SPARE.0 + SPARE.1];
+                let read = self.gsi.get_spare_bytes();
+                let value = match read == written {
+                    true => String::new(),
+                    false => format!("read as {}", hex(read)),
+                };
+                push_field(&mut xml, "SB", SPARE.0, written, &value);
+            }
+            push_field(&mut xml, name, offset, &gsi[offset..offset + len], &value);
+        }
+        xml.push_str("  </gsi>\n");
+        let mut offset = gsi.len();
+        for (index, tti) in self.ttis.iter().enumerate() {
+            let bytes = tti.serialize();
+            xml.push_str(&format!(
+                "  <tti index=\"{}\" offset=\"{}\">\n",
+                index, offset
+            ));
+            for (name, start, len, value) in tti_fields(tti) {
+                push_field(&mut xml, name, start, &bytes[start..start + len], &value);
+            }
+            let padding = tti.tf.iter().rev().take_while(|&&c| c == 0x8f).count();
+            xml.push_str(&format!(
+                "    <field name=\"TF\" offset=\"16\" hex=\"{}\" padding=\"{}\">{}</field>\n",
+                hex(&tti.tf),
+                padding,
+                ebu_tt::escape(&text_field(&tti.tf[..tti.tf.len() - padding], tti.cct))
+            ));
+            xml.push_str("  </tti>\n");
+            offset += bytes.len();
+        }
+        xml.push_str("</stl>\n");
+        Ok(xml)
+    }
+
+    /// Hex dump of the GSI block as written to file, a field per line labelled with
+    /// its byte range, its bytes and its decoded value. Fields longer than 16 bytes
+    /// take several lines.
+    pub fn gsi_hex_dump(&self) -> Result<String, SerializeError> {
+        let gsi = self.gsi.serialize()?;
+        let mut dump = String::from("GSI block\n");
+        for ((name, value), (offset, len)) in gsi_fields(&self.gsi).into_iter().zip(GSI_LAYOUT) {
+            if name == "UDA" {
+                push_hex_field(
+                    &mut dump,
+                    "SB",
+                    SPARE.0,
+                    &gsi[SPARE.0..SPARE.0 + SPARE.1],
+                    "",
+                );
+            }
+            push_hex_field(&mut dump, name, offset, &gsi[offset..offset + len], &value);
+        }
+        Ok(dump)
+    }
+
+    /// Hex dump of the TTI block at `index` in the file, `None` past the last one.
+    /// The header fields are labelled as in [`Stl::gsi_hex_dump`], the text field is
+    /// listed 16 bytes a line with the printable ASCII characters alongside.
+    pub fn tti_hex_dump(&self, index: usize) -> Option<String> {
+        let tti = self.ttis.get(index)?;
+        let bytes = tti.serialize();
+        // The blocks follow the 1024 bytes of the GSI block.
+        let mut dump = format!(
+            "TTI block {} at offset {}\n",
+            index,
+            1024 + index * bytes.len()
+        );
+        for (name, start, len, value) in tti_fields(tti) {
+            push_hex_field(&mut dump, name, start, &bytes[start..start + len], &value);
+        }
+        dump.push_str(&format!("{:>9}  TF\n", byte_range(16, bytes.len() - 16)));
+        for (line, chunk) in bytes[16..].chunks(HEX_LINE).enumerate() {
+            let printable = chunk
+                .iter()
+                .map(|&b| match b {
+                    0x20..=0x7e => b as char,
+                    _ => '.',
+                })
+                .collect::<String>();
+            dump.push_str(&format!(
+                "{:>9}       {:<width$}  {}\n",
+                16 + line * HEX_LINE,
+                hex(chunk),
+                printable,
+                width = HEX_LINE * 3 - 1
+            ));
+        }
+        Some(dump)
+    }
+}
+
+// Header fields of a TTI block with their offset, length and decoded value.
+fn tti_fields(tti: &TtiBlock) -> [(&'static str, usize, usize, String); 9] {
+    [
+        ("SGN", 0, 1, tti.sgn.to_string()),
+        ("SN", 1, 2, tti.sn.to_string()),
+        ("EBN", 3, 1, format!("{:02X}h", tti.ebn)),
+        ("CS", 4, 1, format!("{:?}", tti.cs)),
+        ("TCI", 5, 4, tti.tci.to_smpte()),
+        ("TCO", 9, 4, tti.tco.to_smpte()),
+        ("VP", 13, 1, tti.vp.to_string()),
+        ("JC", 14, 1, tti.jc.to_string()),
+        ("CF", 15, 1, tti.cf.to_string()),
+    ]
+}
+
+// Bytes from `offset` written as in the field documentation, "1..2" for two bytes.
+fn byte_range(offset: usize, len: usize) -> String {
+    match len {
+        1 => offset.to_string(),
+        _ => format!("{}..{}", offset, offset + len - 1),
+    }
+}
+
+fn push_hex_field(dump: &mut String, name: &str, offset: usize, bytes: &[u8], value: &str) {
+    for (line, chunk) in bytes.chunks(HEX_LINE).enumerate() {
+        let line = match line {
+            0 => format!(
+                "{:>9}  {:<3}  {:<width$}  {}",
+                byte_range(offset, bytes.len()),
+                name,
+                hex(chunk),
+                printable(value),
+                width = HEX_LINE * 3 - 1
+            ),
+            _ => format!("{:>9}       {}", "", hex(chunk)),
+        };
+        dump.push_str(line.trim_end());
+        dump.push('\n');
+    }
+}
+
+#[cfg(feature = "xml")]
+fn push_field(xml: &mut String, name: &str, offset: usize, bytes: &[u8], value: &str) {
+    xml.push_str(&format!(
+        "    <field name=\"{}\" offset=\"{}\" hex=\"{}\">{}</field>\n",
+        name,
+        offset,
+        hex(bytes),
+        ebu_tt::escape(&printable(value))
+    ));
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Text with the characters XML cannot hold written as `[XX]`.
+fn printable(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '\t' | '\n' | '\r' => c.to_string(),
+            c if (c as u32) < 0x20 || c == '\u{7f}' => format!("[{:02X}]", c as u32),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+// Decoded text field, control codes written as `[XX]`.
+#[cfg(feature = "xml")]
+fn text_field(tf: &[u8], cct: CharacterCodeTable) -> String {
+    let mut text = String::new();
+    let mut rest = tf;
+    while !rest.is_empty() {
+        let len = rest
+            .iter()
+            .position(|&c| c < 0x20 || (0x7f..0xa0).contains(&c))
+            .unwrap_or(rest.len());
+        if len == 0 {
+            text.push_str(&format!("[{:02X}]", rest[0]));
+            rest = &rest[1..];
+        } else {
+            text.push_str(&cct.decode(&rest[..len]));
+            rest = &rest[len..];
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gsi_layout() {
+        let (offset, len) = GSI_LAYOUT[GSI_LAYOUT.len() - 1];
+        assert_eq!(1024, offset + len);
+        assert_eq!(SPARE.0 + SPARE.1, offset);
+        assert_eq!(gsi_fields(&GsiBlock::new()).len(), GSI_LAYOUT.len());
+        for ((offset, len), (next, _)) in GSI_LAYOUT.iter().zip(&GSI_LAYOUT[1..]) {
+            assert!(offset + len == *next || *next == SPARE.0 + SPARE.1);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "xml")]
+    fn test_to_debug_xml() {
+        let stl = parse_stl_from_file("stls/test.stl").unwrap();
+        let xml = stl.to_debug_xml().unwrap();
+        roxmltree::Document::parse(&xml).unwrap();
+        assert!(xml.contains(
+            "<field name=\"OPT\" offset=\"16\" hex=\"54 45 53 54 20 20 20 20 20 20 20 20 \
+             20 20 20 20 20 20 20 20 20 20 20 20 20 20 20 20 20 20 20 20\">TEST"
+        ));
+        assert!(xml.contains("<field name=\"CCT\" offset=\"12\" hex=\"30 30\">Latin</field>"));
+        assert_eq!(stl.ttis.len(), xml.matches("<tti ").count());
+        assert!(xml.contains("<tti index=\"1\" offset=\"1152\">"));
+        assert!(xml
+            .contains("<field name=\"TCI\" offset=\"5\" hex=\"0A 00 06 08\">10:00:06:08</field>"));
+        assert!(xml.contains("-Ellis Island,"));
+        assert!(xml.contains("[8A][8A]"));
+
+        let mut stl = stl;
+        stl.gsi.spare[0] = b'x';
+        let xml = stl.to_debug_xml().unwrap();
+        let spaces = vec!["20"; SPARE.1].join(" ");
+        let read = format!("78 {}", vec!["20"; SPARE.1 - 1].join(" "));
+        assert!(xml.contains(&format!(
+            "<field name=\"SB\" offset=\"373\" hex=\"{}\">read as {}</field>",
+            spaces, read
+        )));
+    }
+
+    #[test]
+    fn test_hex_dump() {
+        let stl = parse_stl_from_file("stls/test.stl").unwrap();
+        let gsi = stl.gsi_hex_dump().unwrap();
+        let field =
+            |range, name, hex, value| format!("{:>9}  {}  {:<47}  {}\n", range, name, hex, value);
+        assert!(gsi.starts_with(&format!(
+            "GSI block\n{}",
+            field("0..2", "CPN", "38 35 30", "CPN_850")
+        )));
+        assert!(gsi.contains(&field("12..13", "CCT", "30 30", "Latin")));
+        assert!(gsi.contains("\n448..1023  UDA  20 20"));
+        assert_eq!(
+            SPARE.1.div_ceil(HEX_LINE) + 576 / HEX_LINE,
+            gsi.lines()
+                .skip_while(|line| !line.contains(" SB "))
+                .count()
+        );
+
+        let tti = stl.tti_hex_dump(1).unwrap();
+        assert!(tti.starts_with("TTI block 1 at offset 1152\n"));
+        assert!(tti.contains(&field("1..2", "SN ", "02 00", "2")));
+        assert!(tti.contains(&field("5..8", "TCI", "0A 00 06 08", "10:00:06:08")));
+        assert!(tti.contains(
+            "  16..127  TF\n       16       0D 06 20 20 20 20 20 20 20 20 20 0B 0B 2D 45 6C  \
+             ..         ..-El\n"
+        ));
+        assert_eq!(11 + 112 / HEX_LINE, tti.lines().count());
+        assert_eq!(None, stl.tti_hex_dump(stl.ttis.len()));
+    }
+}
@@ -0,0 +1,458 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use super::*;
+
+/// GSI field with different values, named as in the specification.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GsiChange {
+    pub field: String,
+    pub old: String,
+    pub new: String,
+}
+
+/// Subtitle found in both documents with different contents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SubtitleChange {
+    pub old_group: u8,
+    pub old_number: u16,
+    pub new_group: u8,
+    pub new_number: u16,
+    #[doc = "Frames the time code in moved by"]
+    pub tci_delta: i64,
+    #[doc = "Frames the time code out moved by"]
+    pub tco_delta: i64,
+    pub text_changed: bool,
+    #[doc = "Justification, vertical position or control codes changed"]
+    pub formatting_changed: bool,
+}
+
+/// GSI fields left out of comparisons, named as in the specification ("CD", "UDA"...).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FieldMask {
+    fields: BTreeSet<String>,
+}
+
+impl FieldMask {
+    /// Fields changing whenever a file is written again: creation and revision dates,
+    /// revision number, user-defined area, and the total number of TTI blocks which
+    /// depends on how the text is split in extension blocks.
+    pub fn volatile() -> FieldMask {
+        FieldMask::default()
+            .ignore("CD")
+            .ignore("RD")
+            .ignore("RN")
+            .ignore("UDA")
+            .ignore("TNB")
+    }
+
+    pub fn ignore(mut self, field: &str) -> FieldMask {
+        self.fields.insert(field.to_uppercase());
+        self
+    }
+
+    pub fn contains(&self, field: &str) -> bool {
+        self.fields.contains(&field.to_uppercase())
+    }
+}
+
+/// Differences between two documents, see [`diff`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StlDiff {
+    pub gsi: Vec<GsiChange>,
+    #[doc = "Groups and numbers of the old subtitles not found in the new document"]
+    pub removed: Vec<(u8, u16)>,
+    #[doc = "Groups and numbers of the new subtitles not found in the old document"]
+    pub added: Vec<(u8, u16)>,
+    pub changed: Vec<SubtitleChange>,
+}
+
+impl StlDiff {
+    pub fn is_empty(&self) -> bool {
+        self.gsi.is_empty()
+            && self.removed.is_empty()
+            && self.added.is_empty()
+            && self.changed.is_empty()
+    }
+}
+
+impl fmt::Display for StlDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for change in &self.gsi {
+            writeln!(
+                f,
+                "GSI {}: {:?} -> {:?}",
+                change.field, change.old, change.new
+            )?;
+        }
+        for &(sgn, sn) in &self.removed {
+            writeln!(f, "- {}", label(sgn, sn))?;
+        }
+        for &(sgn, sn) in &self.added {
+            writeln!(f, "+ {}", label(sgn, sn))?;
+        }
+        for change in &self.changed {
+            write!(f, "~ {}", label(change.old_group, change.old_number))?;
+            if (change.new_group, change.new_number) != (change.old_group, change.old_number) {
+                let new = label(change.new_group, change.new_number);
+                write!(f, " (now {})", new.trim_start_matches("subtitle "))?;
+            }
+            if change.tci_delta != 0 || change.tco_delta != 0 {
+                write!(f, " timing {:+}/{:+}", change.tci_delta, change.tco_delta)?;
+            }
+            if change.text_changed {
+                write!(f, " text")?;
+            }
+            if change.formatting_changed {
+                write!(f, " formatting")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+// Subtitle number, with its group when there are several.
+fn label(sgn: u8, sn: u16) -> String {
+    match sgn {
+        0 => format!("subtitle {}", sn),
+        _ => format!("subtitle {} of group {}", sn, sgn),
+    }
+}
+
+/// Compare two documents. Subtitles are matched by group and number when their texts
+/// are identical or when no other subtitle is closer in time, within two seconds. The
+/// others are matched to the closest unmatched subtitle within two seconds, so that
+/// renumbered documents are compared subtitle by subtitle. Frames are counted at the
+/// frame rate of `a`.
+pub fn diff(a: &Stl, b: &Stl) -> StlDiff {
+    diff_with_mask(a, b, &FieldMask::default())
+}
+
+/// [`diff`] leaving out the GSI fields of `mask`.
+pub fn diff_with_mask(a: &Stl, b: &Stl, mask: &FieldMask) -> StlDiff {
+    let fps = a.gsi.dfc.get_fps();
+    let tolerance = 2 * fps as i64;
+    let old = a.subtitles().collect::<Vec<_>>();
+    let new = b.subtitles().collect::<Vec<_>>();
+    let frames = |subtitle: &Subtitle| subtitle.start.to_frames(fps) as i64;
+    let delta = |old: &Subtitle, new: &Subtitle| frames(new) - frames(old);
+
+    // Start of the new subtitles in order, to find the closest ones by bisection.
+    let mut starts = new.iter().map(frames).collect::<Vec<_>>();
+    starts.sort_unstable();
+    let closest = |old: &Subtitle| {
+        let start = frames(old);
+        let index = starts.partition_point(|&other| other < start);
+        [index.checked_sub(1), Some(index)]
+            .into_iter()
+            .flatten()
+            .filter_map(|index| starts.get(index))
+            .map(|other| (other - start).abs())
+            .min()
+            .unwrap_or(0)
+    };
+
+    let mut pairs = vec![];
+    let mut matched = vec![false; new.len()];
+    let by_number = new
+        .iter()
+        .enumerate()
+        .map(|(index, subtitle)| ((subtitle.group, subtitle.number), index))
+        .collect::<BTreeMap<_, _>>();
+    let mut unmatched = vec![];
+    for (index, subtitle) in old.iter().enumerate() {
+        match by_number.get(&(subtitle.group, subtitle.number)) {
+            Some(&other)
+                if !matched[other]
+                    && (subtitle.lines == new[other].lines
+                        || delta(subtitle, &new[other]).abs()
+                            <= closest(subtitle).min(tolerance)) =>
+            {
+                matched[other] = true;
+                pairs.push((index, other));
+            }
+            _ => unmatched.push(index),
+        }
+    }
+    // New subtitles left, by start and position.
+    let mut available = new
+        .iter()
+        .enumerate()
+        .filter(|&(other, _)| !matched[other])
+        .map(|(other, subtitle)| (frames(subtitle), other))
+        .collect::<BTreeSet<_>>();
+    let mut removed = vec![];
+    for index in unmatched {
+        let start = frames(&old[index]);
+        let closest = available
+            .range((start - tolerance, 0)..=(start + tolerance, usize::MAX))
+            .map(|&(other_start, other)| ((other_start - start).abs(), other_start, other))
+            .min_by_key(|&(delta, _, other)| (delta, other));
+        match closest {
+            Some((_, other_start, other)) => {
+                available.remove(&(other_start, other));
+                matched[other] = true;
+                pairs.push((index, other));
+            }
+            None => removed.push((old[index].group, old[index].number)),
+        }
+    }
+    pairs.sort();
+
+    StlDiff {
+        gsi: gsi_fields(&a.gsi)
+            .into_iter()
+            .zip(gsi_fields(&b.gsi))
+            .filter(|((field, old), (_, new))| old != new && !mask.contains(field))
+            .map(|((field, old), (_, new))| GsiChange {
+                field: field.to_string(),
+                old,
+                new,
+            })
+            .collect(),
+        removed,
+        added: new
+            .iter()
+            .zip(&matched)
+            .filter(|(_, &matched)| !matched)
+            .map(|(subtitle, _)| (subtitle.group, subtitle.number))
+            .collect(),
+        changed: pairs
+            .into_iter()
+            .filter_map(|(index, other)| subtitle_change(&old[index], &new[other], fps))
+            .collect(),
+    }
+}
+
+impl Stl {
+    /// Whether both documents display the same subtitles, in the same order, with the
+    /// same timing, text, justification, vertical position and height, and have the same
+    /// GSI fields apart from the [volatile](FieldMask::volatile) ones.
+    pub fn semantically_eq(&self, other: &Stl) -> bool {
+        self.semantically_eq_with_mask(other, &FieldMask::volatile())
+    }
+
+    pub fn semantically_eq_with_mask(&self, other: &Stl, mask: &FieldMask) -> bool {
+        let fields = gsi_fields(&self.gsi)
+            .into_iter()
+            .zip(gsi_fields(&other.gsi))
+            .all(|((field, a), (_, b))| a == b || mask.contains(field));
+        let cues = |stl: &Stl| {
+            stl.subtitles()
+                .map(|sub| {
+                    (
+                        *sub.start,
+                        *sub.end,
+                        sub.lines,
+                        sub.justification,
+                        sub.vertical_position,
+                        sub.is_double_height,
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        fields && cues(self) == cues(other)
+    }
+}
+
+fn subtitle_change(old: &Subtitle, new: &Subtitle, fps: usize) -> Option<SubtitleChange> {
+    let delta = |old: &Time, new: &Time| new.to_frames(fps) as i64 - old.to_frames(fps) as i64;
+    let change = SubtitleChange {
+        old_group: old.group,
+        old_number: old.number,
+        new_group: new.group,
+        new_number: new.number,
+        tci_delta: delta(old.start, new.start),
+        tco_delta: delta(old.end, new.end),
+        text_changed: old.lines != new.lines,
+        formatting_changed: old.justification != new.justification
+            || old.vertical_position != new.vertical_position
+            || control_codes(old) != control_codes(new),
+    };
+    let unchanged = (change.old_group, change.old_number) == (change.new_group, change.new_number)
+        && change.tci_delta == 0
+        && change.tco_delta == 0
+        && !change.text_changed
+        && !change.formatting_changed;
+    (!unchanged).then_some(change)
+}
+
+// Teletext control codes of the text, padding excluded.
+fn control_codes(subtitle: &Subtitle) -> Vec<u8> {
+    subtitle
+        .blocks()
+        .iter()
+        .flat_map(|tti| tti.tf.iter().take_while(|&&c| c != 0x8f))
+        .copied()
+        .filter(|&c| c < 0x20 || (0x80..0xa0).contains(&c))
+        .collect()
+}
+
+pub(crate) fn gsi_fields(gsi: &GsiBlock) -> Vec<(&'static str, String)> {
+    vec![
+        ("CPN", format!("{:?}", gsi.cpn)),
+        ("DFC", format!("{:?}", gsi.dfc)),
+        ("DSC", format!("{:?}", gsi.dsc)),
+        ("CCT", format!("{:?}", gsi.cct)),
+        ("LC", gsi.lc.clone()),
+        ("OPT", gsi.opt.clone()),
+        ("OET", gsi.oet.clone()),
+        ("TPT", gsi.tpt.clone()),
+        ("TET", gsi.tet.clone()),
+        ("TN", gsi.tn.clone()),
+        ("TCD", gsi.tcd.clone()),
+        ("SLR", gsi.slr.clone()),
+        ("CD", gsi.cd.clone()),
+        ("RD", gsi.rd.clone()),
+        ("RN", gsi.rn.clone()),
+        ("TNB", gsi.tnb.to_string()),
+        ("TNS", gsi.tns.to_string()),
+        ("TNG", gsi.tng.to_string()),
+        ("MNC", gsi.mnc.to_string()),
+        ("MNR", gsi.mnr.to_string()),
+        ("TCS", format!("{:?}", gsi.tcs)),
+        ("TCP", gsi.tcp.clone()),
+        ("TCF", gsi.tcf.clone()),
+        ("TND", gsi.tnd.to_string()),
+        ("DSN", gsi.dsn.to_string()),
+        ("CO", gsi.co.clone()),
+        ("PUB", gsi.pub_.clone()),
+        ("EN", gsi.en.clone()),
+        ("ECD", gsi.ecd.clone()),
+        ("UDA", gsi.uda.clone()),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff() {
+        let a = parse_stl_from_file("stls/test.stl").unwrap();
+        assert!(diff(&a, &a).is_empty());
+
+        let mut b = parse_stl_from_file("stls/test.stl").unwrap();
+        b.gsi.opt = "Another title".to_string();
+        b.ttis[1].tco = Time::new(10, 0, 9, 12);
+        b.ttis[2].jc = 1;
+        b.ttis[3].tf[5] = b'x';
+        b.ttis.remove(4);
+        let diff = diff(&a, &b);
+        assert_eq!(1, diff.gsi.len());
+        assert_eq!("OPT", diff.gsi[0].field);
+        assert_eq!(vec![(0, 5)], diff.removed);
+        assert!(diff.added.is_empty());
+        assert_eq!(3, diff.changed.len());
+        assert_eq!((2, 0, 3), {
+            let change = &diff.changed[0];
+            (change.old_number, change.tci_delta, change.tco_delta)
+        });
+        assert!(diff.changed[1].formatting_changed && !diff.changed[1].text_changed);
+        assert!(diff.changed[2].text_changed);
+        assert!(diff.to_string().contains("- subtitle 5"));
+    }
+
+    #[test]
+    fn test_diff_groups() {
+        let mut a = parse_stl_from_file("stls/test.stl").unwrap();
+        // Subtitles 8 to 13 become subtitles 1 to 6 of group 1.
+        for tti in &mut a.ttis[7..] {
+            tti.sgn = 1;
+            tti.sn -= 7;
+        }
+        let mut b = a.clone();
+        b.ttis[7].tf[5] = b'x';
+        b.ttis[8].tci = Time::new(10, 0, 30, 0);
+        let diff = diff(&a, &b);
+        assert!(diff.removed.is_empty() && diff.added.is_empty());
+        let changes = diff
+            .changed
+            .iter()
+            .map(|change| (change.old_number, change.new_number, change.tci_delta))
+            .collect::<Vec<_>>();
+        assert_eq!(vec![(1, 1, 0), (2, 2, 12)], changes);
+        assert!(diff.changed[0].text_changed);
+
+        // Subtitle 1 of each group removed, far from any other subtitle.
+        let mut b = a.clone();
+        b.ttis.retain(|tti| tti.sn != 1);
+        let diff = super::diff(&a, &b);
+        assert_eq!(vec![(0, 1), (1, 1)], diff.removed);
+        let report = diff.to_string();
+        assert!(report.contains("- subtitle 1\n"));
+        assert!(report.contains("- subtitle 1 of group 1\n"));
+
+        let mut b = a.clone();
+        b.ttis[7].sgn = 2;
+        b.ttis[7].sn = 1;
+        let diff = super::diff(&a, &b);
+        assert!(diff.removed.is_empty() && diff.added.is_empty());
+        assert_eq!(
+            (1, 2),
+            (diff.changed[0].old_group, diff.changed[0].new_group)
+        );
+        assert!(diff
+            .to_string()
+            .contains("~ subtitle 1 of group 1 (now 1 of group 2)\n"));
+    }
+
+    #[test]
+    fn test_semantically_eq() {
+        let a = parse_stl_from_file("stls/test.stl").unwrap();
+        let mut b = a.clone();
+        b.gsi.rd = "261016".to_string();
+        b.gsi.rn = "02".to_string();
+        b.gsi.uda = "Converted".to_string();
+        assert!(a.semantically_eq(&b));
+        assert!(!a.semantically_eq_with_mask(&b, &FieldMask::default()));
+        assert_eq!(0, diff_with_mask(&a, &b, &FieldMask::volatile()).gsi.len());
+
+        b.gsi.opt = "Another title".to_string();
+        assert!(!a.semantically_eq(&b));
+        assert!(a.semantically_eq_with_mask(&b, &FieldMask::volatile().ignore("opt")));
+
+        // Same text split in two extension blocks.
+        let mut b = a.clone();
+        let mut tf = b.ttis[2].tf.clone();
+        let split = tf.iter().position(|&c| c == b'l').unwrap();
+        let rest = tf.split_off(split);
+        let mut first = b.ttis[2].clone();
+        first.ebn = 0;
+        first.tf = tf;
+        first.tf.resize(112, 0x8f);
+        b.ttis[2].tf = rest;
+        b.ttis[2].tf.resize(112, 0x8f);
+        b.ttis.insert(2, first);
+        b.recompute_totals();
+        assert!(a.semantically_eq(&b));
+
+        b.ttis[0].vp = 21;
+        assert!(!a.semantically_eq(&b));
+    }
+
+    #[test]
+    fn test_diff_renumbered() {
+        let a = parse_stl_from_file("stls/test.stl").unwrap();
+        let mut b = parse_stl_from_file("stls/test.stl").unwrap();
+        // Subtitle 1 removed and the others renumbered from 1.
+        b.ttis.remove(0);
+        for tti in &mut b.ttis {
+            tti.sn -= 1;
+        }
+        let diff = super::diff(&a, &b);
+        assert_eq!(vec![(0, 1)], diff.removed);
+        assert!(diff.added.is_empty());
+        assert_eq!(12, diff.changed.len());
+        assert!(diff
+            .changed
+            .iter()
+            .all(|change| change.new_number + 1 == change.old_number
+                && change.tci_delta == 0
+                && !change.text_changed));
+    }
+}
@@ -0,0 +1,654 @@
+//! EBU-TT Part 1 (Tech 3350) export, following the STL mapping of Tech 3360.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use thiserror::Error;
+
+use super::*;
+use styled::{Span, COLOURS};
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum EbuTtError {
+    #[error("GSI {0} is not a valid time code")]
+    GsiTimeCode(&'static str),
+    #[error("Time codes of subtitle {0} are not valid at the frame rate of the file")]
+    TimeCode(u16),
+    #[error("Subtitle {0} starts before the Time Code: Start-of-Programme")]
+    BeforeStartOfProgramme(u16),
+}
+
+#[derive(Debug, Clone)]
+pub struct EbuTtDOptions {
+    #[doc = "Count time from the Time Code: Start-of-Programme instead of midnight"]
+    pub rebase_to_tcp: bool,
+}
+
+impl Default for EbuTtDOptions {
+    fn default() -> EbuTtDOptions {
+        EbuTtDOptions {
+            rebase_to_tcp: true,
+        }
+    }
+}
+
+impl Stl {
+    /// EBU-TT document of the displayed subtitles, with SMPTE time codes at the frame
+    /// rate of the file. GSI fields go into the document metadata, vertical positions
+    /// into regions, colours, double height and justification into styles. Teletext
+    /// boxes get a black background.
+    ///
+    /// The XSD of EBU-TT is not available to the tests, they check the structure it
+    /// requires instead: namespaces, SMPTE time base parameters, document metadata,
+    /// region origins and extents, and style and region references.
+    pub fn to_ebu_tt(&self) -> Result<String, EbuTtError> {
+        let fps = self.gsi.dfc.get_fps();
+        let tcp = Time::from_time_code(&self.gsi.tcp)
+            .filter(|tcp| tcp.is_valid(fps))
+            .ok_or(EbuTtError::GsiTimeCode("TCP"))?;
+        let teletext = self.gsi.dsc != DisplayStandardCode::OpenSubtitling;
+
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        let _ = writeln!(
+            xml,
+            "<tt:tt xmlns:tt=\"http://www.w3.org/ns/ttml\" \
+             xmlns:ttp=\"http://www.w3.org/ns/ttml#parameter\" \
+             xmlns:tts=\"http://www.w3.org/ns/ttml#styling\" \
+             xmlns:ebuttm=\"urn:ebu:tt:metadata\" \
+             ttp:timeBase=\"smpte\" ttp:frameRate=\"{}\" ttp:frameRateMultiplier=\"1 1\" \
+             ttp:markerMode=\"discontinuous\" ttp:dropMode=\"nonDrop\" \
+             ttp:cellResolution=\"{}\" xml:lang=\"{}\">",
+            fps,
+            if teletext { "40 24" } else { "50 30" },
+            language_tag(&self.gsi.lc).unwrap_or(""),
+        );
+        xml.push_str("  <tt:head>\n    <tt:metadata>\n      <ebuttm:documentMetadata>\n");
+        for (element, value) in self.document_metadata(&tcp) {
+            let _ = writeln!(
+                xml,
+                "        <ebuttm:{0}>{1}</ebuttm:{0}>",
+                element,
+                escape(&value)
+            );
+        }
+        xml.push_str("      </ebuttm:documentMetadata>\n    </tt:metadata>\n");
+        xml.push_str(&styling(teletext, false));
+        xml.push_str(&self.layout(teletext));
+        xml.push_str("  </tt:head>\n");
+        xml.push_str(&self.body(Time::to_smpte)?);
+        xml.push_str("</tt:tt>\n");
+        Ok(xml)
+    }
+
+    /// EBU-TT-D document of the displayed subtitles, for distribution. Times are media
+    /// times in milliseconds, counted from the Time Code: Start-of-Programme unless
+    /// `options` says otherwise. Positions are percentages of the screen, colours,
+    /// italics, underline, double height and justification are kept. Subtitles starting
+    /// before the origin of the times are reported as an error.
+    ///
+    /// The XSD of EBU-TT-D is not available to the tests, they check the structure it
+    /// requires instead: namespaces, media time base, region origins and extents, and
+    /// style and region references.
+    pub fn to_ebu_tt_d(&self, options: &EbuTtDOptions) -> Result<String, EbuTtError> {
+        let fps = self.gsi.dfc.get_fps();
+        let origin = match options.rebase_to_tcp {
+            true => Time::from_time_code(&self.gsi.tcp)
+                .filter(|tcp| tcp.is_valid(fps))
+                .ok_or(EbuTtError::GsiTimeCode("TCP"))?,
+            false => Time::from_frames(0, fps),
+        };
+        if let Some(early) = self
+            .cues()
+            .into_iter()
+            .map(|chain| &self.ttis[chain.start])
+            .find(|first| first.tci.to_frames(fps) < origin.to_frames(fps))
+        {
+            return Err(EbuTtError::BeforeStartOfProgramme(early.sn));
+        }
+        let teletext = self.gsi.dsc != DisplayStandardCode::OpenSubtitling;
+
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        let _ = writeln!(
+            xml,
+            "<tt:tt xmlns:tt=\"http://www.w3.org/ns/ttml\" \
+             xmlns:ttp=\"http://www.w3.org/ns/ttml#parameter\" \
+             xmlns:tts=\"http://www.w3.org/ns/ttml#styling\" \
+             xmlns:ebutts=\"urn:ebu:tt:style\" \
+             ttp:timeBase=\"media\" ttp:cellResolution=\"{}\" xml:lang=\"{}\">",
+            if teletext { "40 24" } else { "50 30" },
+            language_tag(&self.gsi.lc).unwrap_or(""),
+        );
+        xml.push_str("  <tt:head>\n");
+        xml.push_str(&styling(teletext, true));
+        xml.push_str(&self.layout(teletext));
+        xml.push_str("  </tt:head>\n");
+        xml.push_str(
+            &self.body(|time| styled::clock_time(styled::millis(time, &origin, fps), '.'))?,
+        );
+        xml.push_str("</tt:tt>\n");
+        Ok(xml)
+    }
+
+    // Regions of the vertical positions used by the subtitles.
+    fn layout(&self, teletext: bool) -> String {
+        let mut xml = String::from("    <tt:layout>\n");
+        for (vp, origin) in self.regions(teletext) {
+            let _ = writeln!(
+                xml,
+                "      <tt:region xml:id=\"r{}\" tts:origin=\"10% {}%\" tts:extent=\"80% {}%\" \
+                 tts:displayAlign=\"after\"/>",
+                vp,
+                origin,
+                100 - origin
+            );
+        }
+        xml.push_str("    </tt:layout>\n");
+        xml
+    }
+
+    // A paragraph per displayed subtitle, timed with `time`. Paragraphs are identified
+    // by their position from 1, subtitle numbers being only unique within a group.
+    fn body(&self, time: impl Fn(&Time) -> String) -> Result<String, EbuTtError> {
+        let fps = self.gsi.dfc.get_fps();
+        let mut xml = String::from("  <tt:body>\n    <tt:div>\n");
+        for (index, chain) in self.cues().into_iter().enumerate() {
+            let first = &self.ttis[chain.start];
+            if !first.tci.is_valid(fps) || !first.tco.is_valid(fps) {
+                return Err(EbuTtError::TimeCode(first.sn));
+            }
+            let double_height = self.ttis[chain.clone()]
+                .iter()
+                .any(|tti| tti.tf.contains(&0x0d));
+            let mut style = alignment(first.jc).to_string();
+            if double_height {
+                style.push_str(" doubleHeight");
+            }
+            let _ = write!(
+                xml,
+                "      <tt:p xml:id=\"sub{}\" begin=\"{}\" end=\"{}\" region=\"r{}\" style=\"{}\">",
+                index + 1,
+                time(&first.tci),
+                time(&first.tco),
+                first.vp,
+                style,
+            );
+            for (index, row) in styled::styled_rows(&self.ttis[chain]).iter().enumerate() {
+                if index > 0 {
+                    xml.push_str("<tt:br/>");
+                }
+                for span in row {
+                    let _ = write!(
+                        xml,
+                        "<tt:span style=\"{}\">{}</tt:span>",
+                        span_style(span),
+                        escape(&span.text)
+                    );
+                }
+            }
+            xml.push_str("</tt:p>\n");
+        }
+        xml.push_str("    </tt:div>\n  </tt:body>\n");
+        Ok(xml)
+    }
+
+    // Elements of ebuttm:documentMetadata in the order of the schema, blank fields left
+    // out.
+    fn document_metadata(&self, tcp: &Time) -> Vec<(&'static str, String)> {
+        let gsi = &self.gsi;
+        let date = |date: &str| match date.trim() {
+            date if date.len() == 6 && date.bytes().all(|c| c.is_ascii_digit()) => {
+                // Years 80 to 99 are the last century.
+                let century = if &date[..2] >= "80" { "19" } else { "20" };
+                format!("{}{}-{}-{}", century, &date[..2], &date[2..4], &date[4..])
+            }
+            _ => String::new(),
+        };
+        let (_, tns, _) = totals(&self.ttis);
+        [
+            ("documentEbuttVersion", "v1.0".to_string()),
+            ("documentOriginalProgrammeTitle", gsi.opt.clone()),
+            ("documentOriginalEpisodeTitle", gsi.oet.clone()),
+            ("documentTranslatedProgrammeTitle", gsi.tpt.clone()),
+            ("documentTranslatedEpisodeTitle", gsi.tet.clone()),
+            ("documentTranslatorsName", gsi.tn.clone()),
+            ("documentTranslatorsContactDetails", gsi.tcd.clone()),
+            ("documentSubtitleListReferenceCode", gsi.slr.clone()),
+            ("documentCreationDate", date(&gsi.cd)),
+            ("documentRevisionDate", date(&gsi.rd)),
+            (
+                "documentRevisionNumber",
+                gsi.rn
+                    .trim()
+                    .parse::<u8>()
+                    .map(|rn| rn.to_string())
+                    .unwrap_or_default(),
+            ),
+            ("documentTotalNumberOfSubtitles", tns.to_string()),
+            (
+                "documentMaximumNumberOfDisplayableCharacterInAnyRow",
+                gsi.mnc.to_string(),
+            ),
+            ("documentStartOfProgramme", tcp.to_smpte()),
+            ("documentCountryOfOrigin", gsi.co.to_lowercase()),
+            ("documentPublisher", gsi.pub_.clone()),
+            ("documentEditorsName", gsi.en.clone()),
+            ("documentEditorsContactDetails", gsi.ecd.clone()),
+        ]
+        .into_iter()
+        .map(|(element, value)| (element, value.trim().to_string()))
+        .filter(|(_, value)| !value.is_empty())
+        .collect()
+    }
+
+    // Vertical positions used by the subtitles with the top of their region, in percent
+    // of the height of the screen.
+    fn regions(&self, teletext: bool) -> BTreeMap<u8, u32> {
+        self.cues()
+            .into_iter()
+            .map(|chain| self.ttis[chain.start].vp)
+            .map(|vp| match teletext {
+                true => (vp, (vp.clamp(1, 23) as u32 - 1) * 100 / 24),
+                false => (vp, vp.min(99) as u32),
+            })
+            .collect()
+    }
+}
+
+// Styles of the document: colours on a black box, italics, underline, double height and
+// text alignment, with the line padding and alignment of EBU-TT-D for `distribution`.
+fn styling(teletext: bool, distribution: bool) -> String {
+    let mut xml = String::from("    <tt:styling>\n");
+    for (name, rgb) in COLOURS {
+        let _ = writeln!(
+            xml,
+            "      <tt:style xml:id=\"{}\" tts:color=\"{}\"{}/>",
+            name,
+            rgb,
+            if teletext {
+                " tts:backgroundColor=\"#000000\""
+            } else {
+                ""
+            },
+        );
+    }
+    xml.push_str(
+        "      <tt:style xml:id=\"italic\" tts:fontStyle=\"italic\"/>\n\
+         \x20     <tt:style xml:id=\"underline\" tts:textDecoration=\"underline\"/>\n",
+    );
+    let _ = writeln!(
+        xml,
+        "      <tt:style xml:id=\"doubleHeight\" tts:fontSize=\"{}\"/>",
+        if distribution { "100% 200%" } else { "1c 2c" }
+    );
+    for (id, align) in [("left", "start"), ("center", "center"), ("right", "end")] {
+        let _ = write!(
+            xml,
+            "      <tt:style xml:id=\"{}\" tts:textAlign=\"{}\"",
+            id, id
+        );
+        if distribution {
+            let _ = write!(
+                xml,
+                " ebutts:multiRowAlign=\"{}\" ebutts:linePadding=\"0.5c\"",
+                align
+            );
+        }
+        xml.push_str("/>\n");
+    }
+    xml.push_str("    </tt:styling>\n");
+    xml
+}
+
+fn alignment(jc: u8) -> &'static str {
+    match Justification::from_jc(jc) {
+        Justification::Left => "left",
+        Justification::Right => "right",
+        _ => "center",
+    }
+}
+
+fn span_style(span: &Span) -> String {
+    let mut style = COLOURS[span.colour as usize].0.to_string();
+    if span.italic {
+        style.push_str(" italic");
+    }
+    if span.underline {
+        style.push_str(" underline");
+    }
+    style
+}
+
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Language tag (ISO 639-1) of a Language Code of EBU Tech 3264, for the European
+/// languages (00h-2Bh).
+pub(crate) fn language_tag(lc: &str) -> Option<&'static str> {
+    const TAGS: [&str; 0x2c] = [
+        "", "sq", "br", "ca", "hr", "cy", "cs", "da", "de", "en", "es", "eo", "et", "eu", "fo",
+        "fr", "fy", "ga", "gd", "gl", "is", "it", "se", "la", "lv", "lb", "lt", "hu", "mt", "nl",
+        "no", "oc", "pl", "pt", "ro", "rm", "sr", "sk", "sl", "fi", "sv", "tr", "nl", "wa",
+    ];
+    let code = u8::from_str_radix(lc.trim(), 16).ok()?;
+    TAGS.get(code as usize)
+        .copied()
+        .filter(|tag| !tag.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TT: &str = "http://www.w3.org/ns/ttml";
+
+    #[test]
+    fn test_to_ebu_tt() {
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        stl.gsi.lc = "0F".to_string();
+        let xml = stl.to_ebu_tt().unwrap();
+        let document = roxmltree::Document::parse(&xml).unwrap();
+        let root = document.root_element();
+        assert_eq!(
+            (TT, "tt"),
+            (root.tag_name().namespace().unwrap(), root.tag_name().name())
+        );
+        let parameter = "http://www.w3.org/ns/ttml#parameter";
+        assert_eq!(Some("smpte"), root.attribute((parameter, "timeBase")));
+        assert_eq!(Some("25"), root.attribute((parameter, "frameRate")));
+        assert_eq!(
+            Some("fr"),
+            root.attribute(("http://www.w3.org/XML/1998/namespace", "lang"))
+        );
+
+        let metadata = |name: &str| {
+            document
+                .descendants()
+                .find(|node| node.tag_name().name() == name)
+                .and_then(|node| node.text())
+        };
+        assert_eq!(Some("TEST"), metadata("documentOriginalProgrammeTitle"));
+        assert_eq!(Some("2013-10-10"), metadata("documentCreationDate"));
+        assert_eq!(Some("10:00:00:00"), metadata("documentStartOfProgramme"));
+        assert_eq!(Some("13"), metadata("documentTotalNumberOfSubtitles"));
+
+        let paragraphs = document
+            .descendants()
+            .filter(|node| node.has_tag_name((TT, "p")))
+            .collect::<Vec<_>>();
+        assert_eq!(13, paragraphs.len());
+        let second = paragraphs[1];
+        assert_eq!(Some("10:00:06:08"), second.attribute("begin"));
+        assert_eq!(Some("10:00:09:09"), second.attribute("end"));
+        assert_eq!(Some("center doubleHeight"), second.attribute("style"));
+        let region = second.attribute("region").unwrap();
+        assert!(document
+            .descendants()
+            .any(|node| node.has_tag_name((TT, "region"))
+                && node.attribute(("http://www.w3.org/XML/1998/namespace", "id")) == Some(region)));
+        let spans = second
+            .children()
+            .filter(|node| node.has_tag_name((TT, "span")))
+            .map(|node| (node.attribute("style").unwrap(), node.text().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("cyan", "-Ellis Island,"),
+                ("cyan", "îlot de larmes et d'exil,")
+            ],
+            spans
+        );
+    }
+
+    #[test]
+    fn test_paragraph_ids() {
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        // Subtitles 8 to 13 become subtitles 1 to 6 of group 1.
+        for tti in &mut stl.ttis[7..] {
+            tti.sgn = 1;
+            tti.sn -= 7;
+        }
+        let xml = stl.to_ebu_tt().unwrap();
+        let document = roxmltree::Document::parse(&xml).unwrap();
+        let ids = document
+            .descendants()
+            .filter(|node| node.has_tag_name((TT, "p")))
+            .map(|node| {
+                node.attribute(("http://www.w3.org/XML/1998/namespace", "id"))
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+        let expected = (1..=13).map(|n| format!("sub{}", n)).collect::<Vec<_>>();
+        assert_eq!(expected, ids);
+    }
+
+    #[test]
+    fn test_to_ebu_tt_errors() {
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        stl.ttis[3].tco.frames = 25;
+        assert_eq!(Err(EbuTtError::TimeCode(4)), stl.to_ebu_tt());
+        stl.gsi.tcp = "10000".to_string();
+        assert_eq!(Err(EbuTtError::GsiTimeCode("TCP")), stl.to_ebu_tt());
+    }
+
+    #[test]
+    fn test_to_ebu_tt_d() {
+        let stl = parse_stl_from_file("stls/test.stl").unwrap();
+        let xml = stl.to_ebu_tt_d(&EbuTtDOptions::default()).unwrap();
+        let document = roxmltree::Document::parse(&xml).unwrap();
+        let root = document.root_element();
+        let parameter = "http://www.w3.org/ns/ttml#parameter";
+        assert_eq!(Some("media"), root.attribute((parameter, "timeBase")));
+        assert_eq!(None, root.attribute((parameter, "frameRate")));
+        assert!(!document
+            .descendants()
+            .any(|node| node.tag_name().name() == "metadata"));
+
+        let ebutts = "urn:ebu:tt:style";
+        let center = document
+            .descendants()
+            .find(|node| {
+                node.has_tag_name((TT, "style"))
+                    && node.attribute(("http://www.w3.org/XML/1998/namespace", "id"))
+                        == Some("center")
+            })
+            .unwrap();
+        assert_eq!(Some("center"), center.attribute((ebutts, "multiRowAlign")));
+        assert_eq!(Some("0.5c"), center.attribute((ebutts, "linePadding")));
+        assert!(document
+            .descendants()
+            .filter(|node| node.has_tag_name((TT, "region")))
+            .all(|node| node
+                .attribute(("http://www.w3.org/ns/ttml#styling", "origin"))
+                .is_some_and(|origin| origin.ends_with('%'))));
+
+        let paragraphs = document
+            .descendants()
+            .filter(|node| node.has_tag_name((TT, "p")))
+            .collect::<Vec<_>>();
+        assert_eq!(13, paragraphs.len());
+        assert_eq!(Some("00:00:06.320"), paragraphs[1].attribute("begin"));
+        assert_eq!(Some("00:00:09.360"), paragraphs[1].attribute("end"));
+
+        let options = EbuTtDOptions {
+            rebase_to_tcp: false,
+        };
+        let xml = stl.to_ebu_tt_d(&options).unwrap();
+        assert!(xml.contains("begin=\"10:00:06.320\""));
+
+        // A subtitle shown before the programme starts cannot be rebased.
+        let mut stl = stl;
+        stl.ttis[0].tci = Time::new(9, 59, 59, 0);
+        assert_eq!(
+            Err(EbuTtError::BeforeStartOfProgramme(1)),
+            stl.to_ebu_tt_d(&EbuTtDOptions::default())
+        );
+        assert!(stl.to_ebu_tt_d(&options).is_ok());
+    }
+
+    const XML: &str = "http://www.w3.org/XML/1998/namespace";
+    const TTP: &str = "http://www.w3.org/ns/ttml#parameter";
+    const TTS: &str = "http://www.w3.org/ns/ttml#styling";
+
+    // Structure the EBU-TT schemas require on top of well-formedness: elements and
+    // attributes in the expected namespaces, a language, a head with styling and layout,
+    // regions placed within the screen, references to defined styles and regions,
+    // unique identifiers, and paragraphs with an identifier, a region and times passing
+    // `time`. Returns the root element.
+    fn assert_structure<'a>(
+        document: &'a roxmltree::Document,
+        namespaces: &[&str],
+        head: &[&str],
+        time: impl Fn(&str) -> bool,
+    ) -> roxmltree::Node<'a, 'a> {
+        let elements = document
+            .descendants()
+            .filter(|node| node.is_element())
+            .collect::<Vec<_>>();
+        for node in &elements {
+            let namespace = node.tag_name().namespace().unwrap();
+            assert!(
+                namespace == TT || namespaces.contains(&namespace),
+                "{:?}",
+                node.tag_name()
+            );
+            for attribute in node.attributes() {
+                assert!(
+                    matches!(attribute.namespace(), None | Some(XML | TTP | TTS))
+                        || namespaces.contains(&attribute.namespace().unwrap()),
+                    "{:?}",
+                    attribute
+                );
+            }
+        }
+
+        let root = document.root_element();
+        assert!(root.has_tag_name((TT, "tt")));
+        assert!(root.attribute((XML, "lang")).is_some());
+        let children = |node: roxmltree::Node| {
+            node.children()
+                .filter(|node| node.is_element())
+                .map(|node| node.tag_name().name().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec!["head", "body"], children(root));
+        assert_eq!(head, children(root.first_element_child().unwrap()));
+
+        let percentages = |value: &str| {
+            value
+                .split(' ')
+                .map(|length| length.strip_suffix('%').unwrap().parse::<u32>().unwrap())
+                .collect::<Vec<_>>()
+        };
+        let regions = elements
+            .iter()
+            .filter(|node| node.has_tag_name((TT, "region")))
+            .collect::<Vec<_>>();
+        assert!(!regions.is_empty());
+        for region in &regions {
+            let origin = percentages(region.attribute((TTS, "origin")).unwrap());
+            let extent = percentages(region.attribute((TTS, "extent")).unwrap());
+            assert_eq!((2, 2), (origin.len(), extent.len()));
+            assert!(origin[0] + extent[0] <= 100 && origin[1] + extent[1] <= 100);
+        }
+
+        let ids = |name: &str| {
+            elements
+                .iter()
+                .filter(|node| node.has_tag_name((TT, name)))
+                .map(|node| node.attribute((XML, "id")).unwrap())
+                .collect::<std::collections::BTreeSet<_>>()
+        };
+        let (styles, region_ids) = (ids("style"), ids("region"));
+        for node in &elements {
+            if let Some(region) = node.attribute("region") {
+                assert!(region_ids.contains(region), "region {}", region);
+            }
+            if let Some(style) = node.attribute("style") {
+                assert!(
+                    style.split(' ').all(|style| styles.contains(style)),
+                    "{}",
+                    style
+                );
+            }
+        }
+        let all_ids = elements
+            .iter()
+            .filter_map(|node| node.attribute((XML, "id")))
+            .collect::<Vec<_>>();
+        let unique = all_ids.iter().collect::<std::collections::BTreeSet<_>>();
+        assert_eq!(all_ids.len(), unique.len());
+
+        for p in elements.iter().filter(|node| node.has_tag_name((TT, "p"))) {
+            assert!(p.attribute((XML, "id")).is_some());
+            assert!(p.attribute("region").is_some());
+            let (begin, end) = (p.attribute("begin").unwrap(), p.attribute("end").unwrap());
+            assert!(time(begin) && time(end), "{} {}", begin, end);
+            assert!(begin < end);
+        }
+        root
+    }
+
+    // Whether `time` has the digits and separators of `pattern`, where 0 is any digit.
+    fn has_pattern(time: &str, pattern: &str) -> bool {
+        time.len() == pattern.len()
+            && time.bytes().zip(pattern.bytes()).all(|(c, p)| match p {
+                b'0' => c.is_ascii_digit(),
+                p => c == p,
+            })
+    }
+
+    #[test]
+    fn test_ebu_tt_structure() {
+        let stl = parse_stl_from_file("stls/test.stl").unwrap();
+        let xml = stl.to_ebu_tt().unwrap();
+        let document = roxmltree::Document::parse(&xml).unwrap();
+        let smpte = |time: &str| {
+            has_pattern(time, "00:00:00:00") && time[9..].parse::<usize>().unwrap() < 25
+        };
+        let root = assert_structure(
+            &document,
+            &["urn:ebu:tt:metadata"],
+            &["metadata", "styling", "layout"],
+            smpte,
+        );
+        // SMPTE time base parameters of Tech 3350.
+        for (name, value) in [
+            ("timeBase", "smpte"),
+            ("frameRate", "25"),
+            ("frameRateMultiplier", "1 1"),
+            ("markerMode", "discontinuous"),
+            ("dropMode", "nonDrop"),
+        ] {
+            assert_eq!(Some(value), root.attribute((TTP, name)), "{}", name);
+        }
+        let metadata = document
+            .descendants()
+            .find(|node| node.has_tag_name(("urn:ebu:tt:metadata", "documentMetadata")))
+            .unwrap();
+        let first = metadata.first_element_child().unwrap();
+        assert_eq!("documentEbuttVersion", first.tag_name().name());
+        assert_eq!(Some("v1.0"), first.text());
+    }
+
+    #[test]
+    fn test_ebu_tt_d_structure() {
+        let stl = parse_stl_from_file("stls/test.stl").unwrap();
+        let xml = stl.to_ebu_tt_d(&EbuTtDOptions::default()).unwrap();
+        let document = roxmltree::Document::parse(&xml).unwrap();
+        let media = |time: &str| has_pattern(time, "00:00:00.000");
+        let root = assert_structure(
+            &document,
+            &["urn:ebu:tt:style"],
+            &["styling", "layout"],
+            media,
+        );
+        assert_eq!(Some("media"), root.attribute((TTP, "timeBase")));
+        for name in ["frameRate", "frameRateMultiplier", "markerMode", "dropMode"] {
+            assert_eq!(None, root.attribute((TTP, name)), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,1816 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use thiserror::Error;
+
+use super::*;
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum EditError {
+    #[error("No subtitle numbered {0}")]
+    SubtitleNotFound(u16),
+    #[error("Overlaps subtitle {0}")]
+    Overlap(u16),
+    #[error("Time codes of subtitle {0} out of range")]
+    TimeCodeOutOfRange(u16),
+    #[error("GSI {0} is not a valid time code or is out of range")]
+    GsiTimeCode(&'static str),
+    #[error("No subtitle number or TTI block left, see Stl::write_multi_disk")]
+    CapacityExceeded,
+    #[error("No subtitle at position {0}")]
+    IndexOutOfRange(usize),
+    #[error("Time code out past the end of the day")]
+    DurationOutOfRange,
+    #[error("Characters {0:?} are missing from the character code table")]
+    Unmappable(Vec<char>),
+    #[error("Text longer than an extension chain can hold")]
+    TextTooLong,
+    #[error("Subtitle 0 is not part of the numbering")]
+    SubtitleZero,
+}
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum MergeError {
+    #[error("Part {part} has a different {field} than the first part")]
+    Incompatible { part: usize, field: &'static str },
+    #[error("Part {part} starts before the end of subtitle {sn} of the previous part")]
+    Overlap { part: usize, sn: u16 },
+}
+
+/// Part given to the cues displayed across the split point by [`Stl::split_at_time`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StraddlePolicy {
+    Earlier,
+    Later,
+    #[doc = "Keep the cue in both parts, ending and starting at the split point"]
+    Duplicate,
+}
+
+/// Treatment of the cues not displayed entirely within the window of [`Stl::clip_to`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClipPolicy {
+    #[doc = "Remove them"]
+    Drop,
+    #[doc = "Remove the ones outside the window, trim the ones across its boundaries"]
+    Trim,
+    #[doc = "Leave the document unchanged and return an error"]
+    Fail,
+}
+
+/// Numbering of [`Stl::renumber`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenumberScope {
+    #[doc = "Number all the subtitles in a single sequence"]
+    Global,
+    #[doc = "Number the subtitles of each Subtitle Group Number from 1"]
+    PerGroup,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct InsertOptions {
+    #[doc = "Insert cues overlapping the previous or next subtitle instead of failing"]
+    pub allow_overlap: bool,
+}
+
+/// What [`Stl::strip_metadata`] removes, everything by default.
+#[derive(Debug, Clone)]
+pub struct StripPolicy {
+    #[doc = "Blank the translator's name and contact details (TN, TCD)"]
+    pub translator: bool,
+    #[doc = "Blank the editor's name and contact details (EN, ECD)"]
+    pub editor: bool,
+    #[doc = "Blank the publisher (PUB)"]
+    pub publisher: bool,
+    #[doc = "Blank the user-defined area (UDA)"]
+    pub user_defined_area: bool,
+    #[doc = "Remove the comment blocks"]
+    pub comments: bool,
+    #[doc = "Remove the blocks of subtitle zero"]
+    pub subtitle_zero: bool,
+}
+
+impl Default for StripPolicy {
+    fn default() -> StripPolicy {
+        StripPolicy {
+            translator: true,
+            editor: true,
+            publisher: true,
+            user_defined_area: true,
+            comments: true,
+            subtitle_zero: true,
+        }
+    }
+}
+
+/// What [`Stl::strip_metadata`] removed, for audit logs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StripReport {
+    #[doc = "GSI fields that were not blank, named as in the specification"]
+    pub fields: Vec<&'static str>,
+    pub comment_blocks: usize,
+    pub subtitle_zero_blocks: usize,
+}
+
+/// Cues changed by [`Stl::clip_to`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClipReport {
+    #[doc = "Cues removed for being outside the window, or across its boundaries"]
+    pub dropped: usize,
+    #[doc = "Cues trimmed to the boundaries of the window"]
+    pub trimmed: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FlattenOptions {
+    #[doc = "Replace each set with a single cue spanning it, with the text of the whole set"]
+    pub single_cue: bool,
+}
+
+/// Cumulative set replaced by [`Stl::flatten_cumulative`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlattenedSet {
+    #[doc = "Number of the first subtitle of the set, before renumbering"]
+    pub first: u16,
+    pub subtitles: usize,
+    pub cues: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FlattenReport {
+    pub sets: Vec<FlattenedSet>,
+    #[doc = "First subtitle of the sets left unchanged, their text not fitting in a chain"]
+    pub too_long: Vec<u16>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SplitOptions {
+    #[doc = "Minimum duration of each piece in frames, one second when not set"]
+    pub min_duration: Option<u32>,
+}
+
+/// Cues changed by [`Stl::split_overflowing_rows`], numbered as before the split.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SplitReport {
+    #[doc = "Subtitle numbers with the number of cues they were split into"]
+    pub split: Vec<(u16, usize)>,
+    #[doc = "Subtitles left unchanged, too short to be split or in a cumulative set"]
+    pub unsplittable: Vec<u16>,
+}
+
+impl Stl {
+    /// Blank the GSI fields and remove the blocks selected by `policy`. Blanked fields
+    /// are written padded with spaces and the totals are updated.
+    pub fn strip_metadata(&mut self, policy: &StripPolicy) -> StripReport {
+        let mut report = StripReport::default();
+        let gsi = &mut self.gsi;
+        for (selected, field, value) in [
+            (policy.translator, "TN", &mut gsi.tn),
+            (policy.translator, "TCD", &mut gsi.tcd),
+            (policy.publisher, "PUB", &mut gsi.pub_),
+            (policy.editor, "EN", &mut gsi.en),
+            (policy.editor, "ECD", &mut gsi.ecd),
+            (policy.user_defined_area, "UDA", &mut gsi.uda),
+        ] {
+            if selected && !value.trim().is_empty() {
+                report.fields.push(field);
+            }
+            if selected {
+                value.clear();
+            }
+        }
+        let count = self.ttis.len();
+        if policy.subtitle_zero {
+            self.ttis.retain(|tti| tti.sn != 0);
+            report.subtitle_zero_blocks = count - self.ttis.len();
+        }
+        if policy.comments {
+            report.comment_blocks = self.remove_comments();
+        }
+        if self.ttis.len() != count {
+            self.recompute_totals();
+        }
+        report
+    }
+
+    /// Remove the comment blocks, renumber the following subtitles so that the
+    /// numbering stays continuous and update the totals. Returns the number of blocks
+    /// removed.
+    pub fn remove_comments(&mut self) -> usize {
+        let count = self.ttis.len();
+        let commented = self
+            .ttis
+            .iter()
+            .filter(|tti| tti.cf != 0 && is_subtitle_block(tti))
+            .map(|tti| (tti.sgn, tti.sn))
+            .collect::<BTreeSet<_>>();
+        self.ttis
+            .retain(|tti| tti.cf == 0 || !is_subtitle_block(tti));
+        let removed = count - self.ttis.len();
+        if removed == 0 {
+            return 0;
+        }
+        // Numbers only used by comments are freed.
+        close_numbering_gaps(&mut self.ttis, &commented);
+        self.recompute_totals();
+        removed
+    }
+
+    /// Merge runs of consecutive subtitles with the same text and formatting, when the
+    /// next one starts at most `max_gap_frames` after the previous one ends (or overlaps
+    /// it). The first subtitle of a run is extended to the end of the run, the following
+    /// ones are removed and the numbering closed up. Returns the number of subtitles
+    /// removed.
+    pub fn dedupe_consecutive(&mut self, max_gap_frames: u32) -> usize {
+        let fps = self.gsi.dfc.get_fps();
+        let mut removed = vec![false; self.ttis.len()];
+        let mut numbers = BTreeSet::new();
+        let mut merged = 0;
+        let mut current: Option<std::ops::Range<usize>> = None;
+        for chain in chains(&self.ttis) {
+            let first = &self.ttis[chain.start];
+            if !is_subtitle_block(first) || first.sn == 0 || first.cf != 0 {
+                continue;
+            }
+            if first.cs != CumulativeStatus::NotPartOfASet {
+                current = None;
+                continue;
+            }
+            match &current {
+                Some(kept) if is_duplicate(&self.ttis[kept.clone()], &self.ttis[chain.clone()]) => {
+                    let end = &self.ttis[kept.start].tco;
+                    let gap = first.tci.to_frames(fps) as i64 - end.to_frames(fps) as i64;
+                    if gap > max_gap_frames as i64 {
+                        current = Some(chain);
+                        continue;
+                    }
+                    let tco = self.ttis[chain.clone()]
+                        .iter()
+                        .map(|tti| tti.tco)
+                        .fold(*end, std::cmp::max);
+                    numbers.insert((first.sgn, first.sn));
+                    for tti in &mut self.ttis[kept.clone()] {
+                        tti.tco = tco;
+                    }
+                    removed[chain]
+                        .iter_mut()
+                        .for_each(|removed| *removed = true);
+                    merged += 1;
+                }
+                _ => current = Some(chain),
+            }
+        }
+        if merged == 0 {
+            return 0;
+        }
+        retain_blocks(&mut self.ttis, &removed);
+        close_numbering_gaps(&mut self.ttis, &numbers);
+        self.recompute_totals();
+        merged
+    }
+
+    /// Remove the subtitles whose text is empty once control codes (boxing, sizes,
+    /// colours) and white space are left out, then close up the numbering. Returns the
+    /// number of subtitles removed.
+    ///
+    /// Subtitles that are part of a cumulative set are kept, a blank one is a deliberate
+    /// way of clearing the rows displayed so far. Comments are kept as well.
+    pub fn remove_empty(&mut self) -> usize {
+        let mut removed = vec![false; self.ttis.len()];
+        let mut numbers = BTreeSet::new();
+        let mut count = 0;
+        for chain in chains(&self.ttis) {
+            let blocks = &self.ttis[chain.clone()];
+            let first = &blocks[0];
+            if !is_subtitle_block(first)
+                || first.sn == 0
+                || first.cf != 0
+                || blocks
+                    .iter()
+                    .any(|tti| tti.cs != CumulativeStatus::NotPartOfASet)
+                || blocks.iter().any(|tti| !tti.get_text().trim().is_empty())
+            {
+                continue;
+            }
+            numbers.insert((first.sgn, first.sn));
+            removed[chain]
+                .iter_mut()
+                .for_each(|removed| *removed = true);
+            count += 1;
+        }
+        if count == 0 {
+            return 0;
+        }
+        retain_blocks(&mut self.ttis, &removed);
+        close_numbering_gaps(&mut self.ttis, &numbers);
+        self.recompute_totals();
+        count
+    }
+
+    /// Remove every block of subtitle `sn` of subtitle group `sgn` and renumber the
+    /// following subtitles of the group so that the numbering stays continuous.
+    /// Subtitle zero is not renumbered around, see [`Stl::remove_sub_keep_numbering`].
+    pub fn remove_sub(&mut self, sgn: u8, sn: u16) -> Result<(), EditError> {
+        if sn == 0 {
+            return Err(EditError::SubtitleZero);
+        }
+        self.remove_sub_keep_numbering(sgn, sn)?;
+        for tti in self
+            .ttis
+            .iter_mut()
+            .filter(|tti| tti.sgn == sgn && tti.sn > sn)
+        {
+            tti.sn -= 1;
+        }
+        Ok(())
+    }
+
+    /// Insert a subtitle before the first one starting after `tci`, in its subtitle
+    /// group or in the one of the last subtitle, the following subtitles of the group
+    /// are renumbered. Returns the number given to the new subtitle.
+    ///
+    /// The text is encoded with the character code table of the GSI block and wrapped
+    /// to its row width, as by [`Stl::from_cues`].
+    pub fn insert_sub(
+        &mut self,
+        tci: Time,
+        tco: Time,
+        txt: &str,
+        opt: TtiFormat,
+    ) -> Result<u16, EditError> {
+        self.insert_sub_with_options(tci, tco, txt, opt, &InsertOptions::default())
+    }
+
+    pub fn insert_sub_with_options(
+        &mut self,
+        tci: Time,
+        tco: Time,
+        txt: &str,
+        opt: TtiFormat,
+        options: &InsertOptions,
+    ) -> Result<u16, EditError> {
+        // Subtitles are only inserted between chains, identified by their first block.
+        let chain_starts = chains(&self.ttis)
+            .into_iter()
+            .map(|chain| chain.start)
+            .filter(|&index| is_subtitle_block(&self.ttis[index]))
+            .collect::<Vec<_>>();
+        let next = chain_starts
+            .iter()
+            .position(|&index| self.ttis[index].tci > tci);
+        let previous = match next {
+            Some(next) => next.checked_sub(1),
+            None => chain_starts.len().checked_sub(1),
+        };
+
+        if !options.allow_overlap {
+            if let Some(previous) = previous.map(|i| &self.ttis[chain_starts[i]]) {
+                if previous.tco > tci {
+                    return Err(EditError::Overlap(previous.sn));
+                }
+            }
+            if let Some(next) = next.map(|i| &self.ttis[chain_starts[i]]) {
+                if next.tci < tco {
+                    return Err(EditError::Overlap(next.sn));
+                }
+            }
+        }
+
+        let cue = Cue {
+            start: tci,
+            end: tco,
+            text: txt.to_string(),
+            format: opt,
+        };
+        let blocks = cues::chain(&self.gsi, 0, &cue)?;
+        self.insert_before(next.map(|i| chain_starts[i]), blocks)
+    }
+
+    /// Insert a subtitle at position `index` in the list of subtitles, the following
+    /// ones of its subtitle group being renumbered, and return its number. Timing is not
+    /// checked, the text is encoded as by [`Stl::insert_sub`].
+    pub fn add_sub_at(
+        &mut self,
+        index: usize,
+        tci: Time,
+        tco: Time,
+        txt: &str,
+        opt: TtiFormat,
+    ) -> Result<u16, EditError> {
+        let chain_starts = chains(&self.ttis)
+            .into_iter()
+            .map(|chain| chain.start)
+            .filter(|&index| is_subtitle_block(&self.ttis[index]))
+            .collect::<Vec<_>>();
+        if index > chain_starts.len() {
+            return Err(EditError::IndexOutOfRange(index));
+        }
+        let cue = Cue {
+            start: tci,
+            end: tco,
+            text: txt.to_string(),
+            format: opt,
+        };
+        let blocks = cues::chain(&self.gsi, 0, &cue)?;
+        self.insert_before(chain_starts.get(index).copied(), blocks)
+    }
+
+    // Insert the extension chain `blocks` before the chain starting at block `next`, or
+    // at the end, taking its subtitle group and number. Only the following subtitles of
+    // that group are renumbered, as by `remove_sub`.
+    fn insert_before(
+        &mut self,
+        next: Option<usize>,
+        mut blocks: Vec<TtiBlock>,
+    ) -> Result<u16, EditError> {
+        let sgn = match next {
+            Some(index) => self.ttis[index].sgn,
+            None => self
+                .ttis
+                .iter()
+                .rfind(|tti| is_subtitle_block(tti))
+                .map_or(0, |tti| tti.sgn),
+        };
+        let last_sn = self
+            .ttis
+            .iter()
+            .filter(|tti| tti.sgn == sgn)
+            .map(|tti| tti.sn)
+            .max();
+        if last_sn == Some(u16::MAX) || self.ttis.len() + blocks.len() > u16::MAX as usize {
+            return Err(EditError::CapacityExceeded);
+        }
+        let (index, sn) = match next {
+            Some(index) => (index, self.ttis[index].sn),
+            None => (self.ttis.len(), last_sn.map_or(1, |sn| sn + 1)),
+        };
+        for tti in self
+            .ttis
+            .iter_mut()
+            .filter(|tti| tti.sgn == sgn && tti.sn >= sn)
+        {
+            tti.sn += 1;
+        }
+        for block in &mut blocks {
+            block.sgn = sgn;
+            block.sn = sn;
+        }
+        self.ttis.splice(index..index, blocks);
+        self.recompute_totals();
+        Ok(sn)
+    }
+
+    /// Whether the subtitles are in chronological order.
+    pub fn is_sorted_by_time(&self) -> bool {
+        let keys = chain_sort_keys(&self.ttis, &chains(&self.ttis));
+        keys.windows(2).all(|w| w[0] <= w[1])
+    }
+
+    /// Sort the extension chains by time code in, keeping the order of equal ones and
+    /// of the blocks inside a chain. Subtitles are then numbered from 1 and the totals
+    /// updated.
+    pub fn sort_by_time(&mut self) {
+        self.renumber(RenumberScope::Global);
+    }
+
+    /// Sort the subtitles as [`Stl::sort_by_time`] does and number them from 1, within
+    /// each subtitle group or for the whole document. The blocks of an extension chain
+    /// share the same number, user data keep the one of the subtitle they follow and
+    /// subtitle zero is left as is. The totals are updated.
+    ///
+    /// Returns the new number of each subtitle, by group and old number.
+    pub fn renumber(&mut self, scope: RenumberScope) -> BTreeMap<(u8, u16), u16> {
+        self.sort_chains();
+        let numbers = renumber(&mut self.ttis, scope);
+        self.recompute_totals();
+        numbers
+    }
+
+    fn sort_chains(&mut self) {
+        let ranges = chains(&self.ttis);
+        let keys = chain_sort_keys(&self.ttis, &ranges)
+            .into_iter()
+            .map(|key| key.map(|tci| (tci.hours, tci.minutes, tci.seconds, tci.frames)))
+            .collect::<Vec<_>>();
+        let mut ttis = std::mem::take(&mut self.ttis).into_iter();
+        let mut chains = ranges
+            .iter()
+            .zip(keys)
+            .map(|(range, key)| (key, ttis.by_ref().take(range.len()).collect::<Vec<_>>()))
+            .collect::<Vec<_>>();
+        chains.sort_by_key(|(key, _)| *key);
+
+        self.ttis = chains.into_iter().flat_map(|(_, chain)| chain).collect();
+    }
+
+    /// Concatenate documents, the subtitles are numbered from 1 and the metadata taken
+    /// from the first part. All the parts must share the disk format, display standard
+    /// and character code table, and follow each other in time.
+    pub fn merge(parts: &[Stl]) -> Result<Stl, MergeError> {
+        let Some(first) = parts.first() else {
+            return Ok(Stl::new());
+        };
+        for (part, stl) in parts.iter().enumerate().skip(1) {
+            let incompatible = |field| MergeError::Incompatible { part, field };
+            if stl.gsi.dfc != first.gsi.dfc {
+                return Err(incompatible("DFC"));
+            }
+            if stl.gsi.dsc != first.gsi.dsc {
+                return Err(incompatible("DSC"));
+            }
+            if stl.gsi.cct != first.gsi.cct {
+                return Err(incompatible("CCT"));
+            }
+            let previous = parts[part - 1]
+                .ttis
+                .iter()
+                .rfind(|tti| is_subtitle_block(tti));
+            let next = stl.ttis.iter().find(|tti| is_subtitle_block(tti));
+            if let (Some(previous), Some(next)) = (previous, next) {
+                if previous.tco > next.tci {
+                    return Err(MergeError::Overlap {
+                        part,
+                        sn: previous.sn,
+                    });
+                }
+            }
+        }
+
+        let mut stl = Stl {
+            gsi: first.gsi.clone(),
+            ttis: parts
+                .iter()
+                .flat_map(|part| part.ttis.iter().cloned())
+                .collect(),
+        };
+        renumber(&mut stl.ttis, RenumberScope::Global);
+        stl.recompute_totals();
+        Ok(stl)
+    }
+
+    /// Split the document before the subtitles starting at `t` or later. Extension
+    /// chains and cumulative sets are kept whole, in the earlier part when they start
+    /// before `t` unless `policy` says otherwise for the ones still displayed at `t`.
+    /// When both parts have blocks, they are disks 1 and 2 of a set of 2.
+    pub fn split_at_time(&self, t: &Time, policy: StraddlePolicy) -> (Stl, Stl) {
+        let mut earlier = vec![];
+        let mut later = vec![];
+        let mut start = None;
+        for unit in units(&self.ttis) {
+            let blocks = &self.ttis[unit];
+            // Blocks without text follow the subtitle preceding them.
+            if let Some(first) = blocks.iter().find(|tti| is_subtitle_block(tti)) {
+                start = Some(first.tci);
+            }
+            let end = blocks
+                .iter()
+                .filter(|tti| is_subtitle_block(tti))
+                .map(|tti| tti.tco)
+                .max();
+            match (start, end) {
+                (Some(start), _) if start >= *t => later.extend(blocks.iter().cloned()),
+                (Some(_), Some(end)) if end > *t => match policy {
+                    StraddlePolicy::Earlier => earlier.extend(blocks.iter().cloned()),
+                    StraddlePolicy::Later => later.extend(blocks.iter().cloned()),
+                    // Members of a cumulative set displayed on one side of `t` only
+                    // are not duplicated.
+                    StraddlePolicy::Duplicate => {
+                        for tti in blocks {
+                            let subtitle = is_subtitle_block(tti);
+                            if !subtitle || tti.tci < *t {
+                                let mut before = tti.clone();
+                                before.tco = before.tco.min(*t);
+                                earlier.push(before);
+                            }
+                            if !subtitle || tti.tco > *t {
+                                let mut after = tti.clone();
+                                after.tci = after.tci.max(*t);
+                                later.push(after);
+                            }
+                        }
+                    }
+                },
+                _ => earlier.extend(blocks.iter().cloned()),
+            }
+        }
+        // A document left whole keeps its numbering in the set of disks.
+        if earlier.is_empty() || later.is_empty() {
+            let (tnd, dsn) = (self.gsi.tnd, self.gsi.dsn);
+            return (self.part(earlier, tnd, dsn), self.part(later, tnd, dsn));
+        }
+        (self.part(earlier, 2, 1), self.part(later, 2, 2))
+    }
+
+    /// Keep the subtitles displayed between `start` and `end`, the ones displayed out
+    /// of this window in part or entirely are handled according to `policy`. The
+    /// numbering is closed up and the totals updated.
+    pub fn clip_to(
+        &mut self,
+        start: Time,
+        end: Time,
+        policy: ClipPolicy,
+    ) -> Result<ClipReport, EditError> {
+        let mut report = ClipReport::default();
+        let mut removed = vec![false; self.ttis.len()];
+        let mut trimmed = vec![];
+        let mut numbers = BTreeSet::new();
+        for chain in chains(&self.ttis) {
+            let first = &self.ttis[chain.start];
+            if !is_subtitle_block(first) || first.sn == 0 {
+                continue;
+            }
+            if first.tci >= start && first.tco <= end {
+                continue;
+            }
+            let outside = first.tco <= start || first.tci >= end;
+            match policy {
+                ClipPolicy::Fail => return Err(EditError::TimeCodeOutOfRange(first.sn)),
+                ClipPolicy::Trim if !outside => {
+                    trimmed.push(chain);
+                    report.trimmed += 1;
+                }
+                _ => {
+                    numbers.insert((first.sgn, first.sn));
+                    removed[chain]
+                        .iter_mut()
+                        .for_each(|removed| *removed = true);
+                    report.dropped += 1;
+                }
+            }
+        }
+        for chain in trimmed {
+            for tti in &mut self.ttis[chain] {
+                tti.tci = tti.tci.max(start);
+                tti.tco = tti.tco.min(end);
+            }
+        }
+        retain_blocks(&mut self.ttis, &removed);
+        close_numbering_gaps(&mut self.ttis, &numbers);
+        self.recompute_totals();
+        Ok(report)
+    }
+
+    /// Replace each cumulative set with conventional cues, one per subtitle of the set
+    /// showing the rows accumulated so far until the next one starts. The rows keep the
+    /// vertical position of the first subtitle of the set.
+    pub fn flatten_cumulative(&mut self) -> FlattenReport {
+        self.flatten_cumulative_with_options(&FlattenOptions::default())
+    }
+
+    pub fn flatten_cumulative_with_options(&mut self, options: &FlattenOptions) -> FlattenReport {
+        let mut report = FlattenReport::default();
+        let mut ttis = Vec::with_capacity(self.ttis.len());
+        let mut numbers = BTreeSet::new();
+        for unit in units(&self.ttis) {
+            let blocks = &self.ttis[unit.clone()];
+            let steps = chains(blocks)
+                .into_iter()
+                .filter(|chain| {
+                    let first = &blocks[chain.start];
+                    is_subtitle_block(first) && first.cf == 0
+                })
+                .collect::<Vec<_>>();
+            if steps.is_empty()
+                || steps
+                    .iter()
+                    .all(|chain| blocks[chain.start].cs == CumulativeStatus::NotPartOfASet)
+            {
+                ttis.extend_from_slice(blocks);
+                continue;
+            }
+            let first = &blocks[steps[0].start];
+            let end = steps.iter().map(|chain| blocks[chain.start].tco).max();
+            let mut text = vec![];
+            let mut pieces = vec![];
+            for (index, chain) in steps.iter().enumerate() {
+                if !text.is_empty() && text.last() != Some(&0x8a) {
+                    text.push(0x8a);
+                }
+                text.extend(
+                    blocks[chain.clone()]
+                        .iter()
+                        .flat_map(|tti| tti.tf.iter().copied().take_while(|&c| c != 0x8f)),
+                );
+                let step = &blocks[chain.start];
+                let tco = match steps.get(index + 1) {
+                    Some(next) => blocks[next.start].tci,
+                    None => end.unwrap_or(step.tco),
+                };
+                if !options.single_cue || index + 1 == steps.len() {
+                    pieces.push((step, tco, text.clone()));
+                }
+            }
+            let mut flattened = vec![];
+            for (step, tco, text) in pieces {
+                let text_fields = cues::pack_text_fields(convert::text_units(&text, first.cct));
+                if text_fields.len() > cues::MAX_CHAIN_LENGTH {
+                    break;
+                }
+                let (sn, tci) = match options.single_cue {
+                    true => (first.sn, first.tci),
+                    false => (step.sn, step.tci),
+                };
+                let last = text_fields.len() - 1;
+                for (ebn, tf) in text_fields.into_iter().enumerate() {
+                    flattened.push(TtiBlock {
+                        sn,
+                        ebn: if ebn == last { 0xff } else { ebn as u8 },
+                        cs: CumulativeStatus::NotPartOfASet,
+                        tci,
+                        tco,
+                        vp: first.vp,
+                        tf,
+                        ..step.clone()
+                    });
+                }
+            }
+            let expected = if options.single_cue { 1 } else { steps.len() };
+            if flattened.iter().filter(|tti| tti.ebn == 0xff).count() != expected {
+                report.too_long.push(first.sn);
+                ttis.extend_from_slice(blocks);
+                continue;
+            }
+            report.sets.push(FlattenedSet {
+                first: first.sn,
+                subtitles: steps.len(),
+                cues: expected,
+            });
+            numbers.extend(
+                steps
+                    .iter()
+                    .map(|chain| (blocks[chain.start].sgn, blocks[chain.start].sn)),
+            );
+            ttis.extend(flattened);
+            // Blocks without text are kept after the cues.
+            ttis.extend(
+                blocks
+                    .iter()
+                    .filter(|tti| !is_subtitle_block(tti) || tti.cf != 0)
+                    .cloned(),
+            );
+        }
+        self.ttis = ttis;
+        close_numbering_gaps(&mut self.ttis, &numbers);
+        self.recompute_totals();
+        report
+    }
+
+    /// Split the subtitles of more than `max_rows` rows in consecutive cues of at most
+    /// `max_rows` rows. Their duration is shared in proportion to the number of
+    /// characters of each cue, the following subtitles are renumbered.
+    pub fn split_overflowing_rows(&mut self, max_rows: u16) -> SplitReport {
+        self.split_overflowing_rows_with_options(max_rows, &SplitOptions::default())
+    }
+
+    pub fn split_overflowing_rows_with_options(
+        &mut self,
+        max_rows: u16,
+        options: &SplitOptions,
+    ) -> SplitReport {
+        let fps = self.gsi.dfc.get_fps();
+        let min_duration = options.min_duration.unwrap_or(fps as u32);
+        let mut report = SplitReport::default();
+        let mut ttis = Vec::with_capacity(self.ttis.len());
+        // Subtitles added so far in each group.
+        let mut added = BTreeMap::<u8, u16>::new();
+        for chain in chains(&self.ttis) {
+            let blocks = &self.ttis[chain];
+            let first = &blocks[0];
+            let offset = *added.get(&first.sgn).unwrap_or(&0);
+            let shifted = |sn: u16| {
+                if sn == 0 {
+                    0
+                } else {
+                    sn.saturating_add(offset)
+                }
+            };
+            let rows = split_rows(blocks);
+            let row_count = rows
+                .iter()
+                .filter(|row| !is_blank_row(row, first.cct))
+                .count();
+            if !is_subtitle_block(first)
+                || first.sn == 0
+                || first.cf != 0
+                || max_rows == 0
+                || row_count <= max_rows as usize
+            {
+                ttis.extend(blocks.iter().map(|tti| TtiBlock {
+                    sn: shifted(tti.sn),
+                    ..tti.clone()
+                }));
+                continue;
+            }
+            let pieces = group_rows(&rows, max_rows as usize, first.cct);
+            let start = first.tci.to_frames(fps);
+            let duration = first.tco.to_frames(fps).saturating_sub(start);
+            let spare = duration.checked_sub(min_duration * pieces.len() as u32);
+            let text_fields = pieces
+                .iter()
+                .map(|rows| {
+                    cues::pack_text_fields(convert::text_units(&rows.join(&0x8a), first.cct))
+                })
+                .collect::<Vec<_>>();
+            let fits = text_fields
+                .iter()
+                .all(|tf| tf.len() <= cues::MAX_CHAIN_LENGTH);
+            let spare = match spare {
+                Some(spare) if fits && first.cs == CumulativeStatus::NotPartOfASet => spare,
+                _ => {
+                    report.unsplittable.push(first.sn);
+                    ttis.extend(blocks.iter().map(|tti| TtiBlock {
+                        sn: shifted(tti.sn),
+                        ..tti.clone()
+                    }));
+                    continue;
+                }
+            };
+
+            let chars = pieces
+                .iter()
+                .map(|rows| {
+                    rows.iter()
+                        .map(|row| row_text(row, first.cct).chars().count() as u32)
+                        .sum::<u32>()
+                })
+                .collect::<Vec<_>>();
+            let total_chars = chars.iter().sum::<u32>().max(1);
+            let mut tci = start;
+            for (index, text_fields) in text_fields.into_iter().enumerate() {
+                let tco = match index + 1 == pieces.len() {
+                    true => start + duration,
+                    false => tci + min_duration + spare * chars[index] / total_chars,
+                };
+                let last = text_fields.len() - 1;
+                for (ebn, tf) in text_fields.into_iter().enumerate() {
+                    ttis.push(TtiBlock {
+                        sn: shifted(first.sn).saturating_add(index as u16),
+                        ebn: if ebn == last { 0xff } else { ebn as u8 },
+                        tci: Time::from_frames(tci, fps),
+                        tco: Time::from_frames(tco, fps),
+                        tf,
+                        ..first.clone()
+                    });
+                }
+                tci = tco;
+            }
+            report.split.push((first.sn, pieces.len()));
+            added.insert(first.sgn, offset + pieces.len() as u16 - 1);
+        }
+        self.ttis = ttis;
+        self.recompute_totals();
+        report
+    }
+
+    /// Split the document in parts of at most `n` subtitles. Extension chains and
+    /// cumulative sets are kept whole, a set of more than `n` subtitles gets a part of
+    /// its own.
+    pub fn split_every(&self, n: usize) -> Vec<Stl> {
+        let parts = split_by_count(&self.ttis, n);
+        let tnd = u8::try_from(parts.len()).unwrap_or(u8::MAX);
+        parts
+            .into_iter()
+            .enumerate()
+            .map(|(index, part)| {
+                let dsn = u8::try_from(index + 1).unwrap_or(u8::MAX);
+                self.part(self.ttis[part].to_vec(), tnd, dsn)
+            })
+            .collect()
+    }
+
+    /// Blocks of Subtitle Group Number `sgn`, in file order.
+    pub fn group(&self, sgn: u8) -> impl Iterator<Item = &TtiBlock> {
+        self.ttis.iter().filter(move |tti| tti.sgn == sgn)
+    }
+
+    /// Standalone document made of the blocks of Subtitle Group Number `sgn`, numbered
+    /// from 1, with the GSI block of this document and its totals updated.
+    pub fn extract_group(&self, sgn: u8) -> Stl {
+        let mut ttis = self.group(sgn).cloned().collect::<Vec<_>>();
+        renumber(&mut ttis, RenumberScope::Global);
+        self.part(ttis, self.gsi.tnd, self.gsi.dsn)
+    }
+
+    fn part(&self, ttis: Vec<TtiBlock>, tnd: u8, dsn: u8) -> Stl {
+        let mut part = Stl {
+            gsi: self.gsi.clone(),
+            ttis,
+        };
+        part.gsi.tnd = tnd;
+        part.gsi.dsn = dsn;
+        part.recompute_totals();
+        part
+    }
+
+    /// Remove every block of subtitle `sn` of subtitle group `sgn`, leaving a gap in
+    /// the numbering. The totals are updated.
+    pub fn remove_sub_keep_numbering(&mut self, sgn: u8, sn: u16) -> Result<(), EditError> {
+        let count = self.ttis.len();
+        self.ttis.retain(|tti| tti.sgn != sgn || tti.sn != sn);
+        if self.ttis.len() == count {
+            return Err(EditError::SubtitleNotFound(sn));
+        }
+        self.recompute_totals();
+        Ok(())
+    }
+}
+
+pub(crate) fn is_subtitle_block(tti: &TtiBlock) -> bool {
+    matches!(
+        tti.get_extension_block_kind(),
+        ExtensionBlockKind::Extension(_) | ExtensionBlockKind::Last
+    )
+}
+
+// Rows of the text of an extension chain, control codes included.
+fn split_rows(blocks: &[TtiBlock]) -> Vec<Vec<u8>> {
+    let bytes = blocks
+        .iter()
+        .flat_map(|tti| tti.tf.iter().copied().take_while(|&c| c != 0x8f))
+        .collect::<Vec<_>>();
+    bytes.split(|&c| c == 0x8a).map(<[u8]>::to_vec).collect()
+}
+
+// Text of a row without control codes and surrounding spaces.
+fn row_text(row: &[u8], cct: CharacterCodeTable) -> String {
+    let text = row
+        .iter()
+        .copied()
+        .filter(|&c| !(c < 0x20 || (0x80..0xa0).contains(&c)))
+        .collect::<Vec<_>>();
+    cct.decode(&text).trim().to_string()
+}
+
+fn is_blank_row(row: &[u8], cct: CharacterCodeTable) -> bool {
+    row_text(row, cct).is_empty()
+}
+
+// Rows grouped by `max` rows with text, blank rows going with the following row with
+// text, or with the last group when at the end.
+fn group_rows(rows: &[Vec<u8>], max: usize, cct: CharacterCodeTable) -> Vec<Vec<Vec<u8>>> {
+    let mut groups: Vec<Vec<Vec<u8>>> = vec![];
+    let mut pending = vec![];
+    let mut count = 0;
+    for row in rows {
+        pending.push(row.clone());
+        if is_blank_row(row, cct) {
+            continue;
+        }
+        if count % max == 0 {
+            groups.push(vec![]);
+        }
+        count += 1;
+        groups.last_mut().unwrap().append(&mut pending);
+    }
+    if let Some(group) = groups.last_mut() {
+        group.append(&mut pending);
+    }
+    groups
+}
+
+// Remove the blocks flagged in `removed`.
+fn retain_blocks(ttis: &mut Vec<TtiBlock>, removed: &[bool]) {
+    let mut index = 0;
+    ttis.retain(|_| {
+        index += 1;
+        !removed[index - 1]
+    });
+}
+
+// Lower the subtitle numbers past the ones of `candidates`, given with their subtitle
+// group, that are no longer used in that group.
+fn close_numbering_gaps(ttis: &mut [TtiBlock], candidates: &BTreeSet<(u8, u16)>) {
+    let remaining = ttis
+        .iter()
+        .map(|tti| (tti.sgn, tti.sn))
+        .collect::<BTreeSet<_>>();
+    let freed = candidates
+        .difference(&remaining)
+        .copied()
+        .filter(|&(_, sn)| sn != 0)
+        .collect::<BTreeSet<_>>();
+    for tti in ttis {
+        tti.sn -= freed.range((tti.sgn, 0)..(tti.sgn, tti.sn)).count() as u16;
+    }
+}
+
+// Whether two extension chains display the same text with the same formatting.
+fn is_duplicate(a: &[TtiBlock], b: &[TtiBlock]) -> bool {
+    a.len() == b.len()
+        && a.iter().zip(b).all(|(a, b)| {
+            a.sgn == b.sgn && a.jc == b.jc && a.vp == b.vp && a.cct == b.cct && a.tf == b.tf
+        })
+}
+
+// Ranges of the blocks making the extension chains, blocks that are not part of a chain
+// (user data for instance) are on their own.
+pub(crate) fn chains(ttis: &[TtiBlock]) -> Vec<std::ops::Range<usize>> {
+    let mut chains: Vec<std::ops::Range<usize>> = vec![];
+    for (index, tti) in ttis.iter().enumerate() {
+        let continued = index > 0 && {
+            let previous = &ttis[index - 1];
+            previous.sn == tti.sn
+                && matches!(
+                    previous.get_extension_block_kind(),
+                    ExtensionBlockKind::Extension(_)
+                )
+        };
+        match chains.last_mut() {
+            Some(chain) if continued => chain.end = index + 1,
+            _ => chains.push(index..index + 1),
+        }
+    }
+    chains
+}
+
+// Ranges of the blocks that cannot be separated, extension chains being merged into the
+// cumulative set they belong to.
+pub(crate) fn units(ttis: &[TtiBlock]) -> Vec<std::ops::Range<usize>> {
+    let mut units: Vec<std::ops::Range<usize>> = vec![];
+    let mut in_set = false;
+    for chain in chains(ttis) {
+        let continues_set = in_set;
+        in_set = ttis[chain.clone()].iter().any(|tti| {
+            matches!(
+                tti.cs,
+                CumulativeStatus::FirstInSet | CumulativeStatus::IntermediateInSet
+            )
+        });
+        match units.last_mut() {
+            Some(unit) if continues_set => unit.end = chain.end,
+            _ => units.push(chain),
+        }
+    }
+    units
+}
+
+// Consecutive units grouped in parts of at most `max` subtitles, or a single unit when
+// it holds more.
+pub(crate) fn split_by_count(ttis: &[TtiBlock], max: usize) -> Vec<std::ops::Range<usize>> {
+    let mut parts: Vec<std::ops::Range<usize>> = vec![];
+    let mut subtitles = 0;
+    for unit in units(ttis) {
+        let count = count_subtitles(&ttis[unit.clone()]) as usize;
+        match parts.last_mut() {
+            Some(part) if subtitles + count <= max => {
+                part.end = unit.end;
+                subtitles += count;
+            }
+            _ => {
+                parts.push(unit);
+                subtitles = count;
+            }
+        }
+    }
+    if parts.is_empty() {
+        parts.push(0..0);
+    }
+    parts
+}
+
+// Number the chains from 1 in file order, subtitle zero is left as is. Chains without
+// text keep the number of the preceding chain they shared their number with. Returns the
+// new number of every (group, old number).
+fn renumber(ttis: &mut [TtiBlock], scope: RenumberScope) -> BTreeMap<(u8, u16), u16> {
+    let mut numbers = BTreeMap::new();
+    let mut last = BTreeMap::new();
+    let mut previous: Option<(u8, u16, u16)> = None;
+    for chain in chains(ttis) {
+        let first = &ttis[chain.start];
+        let (sgn, old) = (first.sgn, first.sn);
+        if old == 0 {
+            previous = None;
+            continue;
+        }
+        let new = match previous {
+            Some((previous_sgn, previous_old, new))
+                if !is_subtitle_block(first) && (previous_sgn, previous_old) == (sgn, old) =>
+            {
+                new
+            }
+            _ => {
+                let counter = match scope {
+                    RenumberScope::Global => last.entry(None).or_insert(0),
+                    RenumberScope::PerGroup => last.entry(Some(sgn)).or_insert(0),
+                };
+                *counter += 1;
+                *counter
+            }
+        };
+        numbers.insert((sgn, old), new);
+        previous = Some((sgn, old, new));
+        for tti in &mut ttis[chain] {
+            tti.sn = new;
+        }
+    }
+    numbers
+}
+
+// Chains are ordered by the time code in of their first block, the ones without text
+// follow the subtitle preceding them.
+fn chain_sort_keys<'a>(
+    ttis: &'a [TtiBlock],
+    chains: &[std::ops::Range<usize>],
+) -> Vec<Option<&'a Time>> {
+    let mut key = None;
+    chains
+        .iter()
+        .map(|chain| {
+            let first = &ttis[chain.start];
+            if is_subtitle_block(first) {
+                key = Some(&first.tci);
+            }
+            key
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remove_sub() {
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        stl.ttis[3].ebn = 0;
+        for tti in &mut stl.ttis[4..] {
+            tti.sn -= 1;
+        }
+        stl.recompute_totals();
+        stl.remove_sub(0, 4).unwrap();
+        assert_eq!(11, stl.ttis.len());
+        let numbers = stl.ttis.iter().map(|tti| tti.sn).collect::<Vec<_>>();
+        assert_eq!((1..=11).collect::<Vec<_>>(), numbers);
+        assert_eq!((11, 11, 1), (stl.gsi.tnb, stl.gsi.tns, stl.gsi.tng));
+        assert_eq!(ValidationReport::default(), stl.validate());
+
+        stl.remove_sub_keep_numbering(0, 1).unwrap();
+        assert_eq!(2, stl.ttis[0].sn);
+        assert_eq!((10, 10, 1), (stl.gsi.tnb, stl.gsi.tns, stl.gsi.tng));
+        assert_eq!(
+            Err(EditError::SubtitleNotFound(1)),
+            stl.remove_sub_keep_numbering(0, 1)
+        );
+        assert_eq!(Err(EditError::SubtitleZero), stl.remove_sub(0, 0));
+
+        // Subtitles 8 to 13 numbered again from 1 in group 1 are left alone when
+        // subtitle 3 of group 0 goes.
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        for tti in &mut stl.ttis[7..] {
+            tti.sgn = 1;
+            tti.sn -= 7;
+        }
+        stl.recompute_totals();
+        stl.remove_sub(0, 3).unwrap();
+        let numbers = stl
+            .ttis
+            .iter()
+            .map(|tti| (tti.sgn, tti.sn))
+            .collect::<Vec<_>>();
+        let expected = (1..=6)
+            .map(|sn| (0, sn))
+            .chain((1..=6).map(|sn| (1, sn)))
+            .collect::<Vec<_>>();
+        assert_eq!(expected, numbers);
+        assert_eq!((12, 12, 2), (stl.gsi.tnb, stl.gsi.tns, stl.gsi.tng));
+
+        stl.remove_sub_keep_numbering(1, 2).unwrap();
+        assert_eq!(11, stl.ttis.len());
+        assert!(stl.ttis.iter().any(|tti| (tti.sgn, tti.sn) == (0, 2)));
+        assert_eq!((11, 11, 2), (stl.gsi.tnb, stl.gsi.tns, stl.gsi.tng));
+    }
+
+    #[test]
+    fn test_insert_sub() {
+        let format = || TtiFormat {
+            jc: 2,
+            vp: 20,
+            dh: false,
+        };
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        assert_eq!(
+            Err(EditError::Overlap(2)),
+            stl.insert_sub(
+                Time::new(10, 0, 5, 0),
+                Time::new(10, 0, 7, 0),
+                "Disclaimer",
+                format()
+            )
+        );
+        let sn = stl
+            .insert_sub(
+                Time::new(10, 0, 27, 0),
+                Time::new(10, 0, 29, 0),
+                "Disclaimer",
+                format(),
+            )
+            .unwrap();
+        assert_eq!(9, sn);
+        assert_eq!("Disclaimer", stl.ttis[8].get_text().trim());
+        let numbers = stl.ttis.iter().map(|tti| tti.sn).collect::<Vec<_>>();
+        assert_eq!((1..=14).collect::<Vec<_>>(), numbers);
+        assert_eq!(ValidationReport::default(), stl.validate());
+
+        let options = InsertOptions {
+            allow_overlap: true,
+        };
+        let sn = stl
+            .insert_sub_with_options(
+                Time::new(10, 0, 48, 0),
+                Time::new(10, 0, 50, 0),
+                "End",
+                format(),
+                &options,
+            )
+            .unwrap();
+        assert_eq!(15, sn);
+        assert_eq!(15, stl.ttis[14].sn);
+
+        let mut stl = Stl::new();
+        stl.gsi.cct = CharacterCodeTable::LatinCyrillic;
+        stl.insert_sub(
+            Time::new(0, 0, 1, 0),
+            Time::new(0, 0, 2, 0),
+            "Привет",
+            format(),
+        )
+        .unwrap();
+        assert_eq!(CharacterCodeTable::LatinCyrillic, stl.ttis[0].cct);
+        assert_eq!("Привет", stl.ttis[0].get_text().trim());
+        assert_eq!(
+            Err(EditError::Unmappable(vec!['é'])),
+            stl.insert_sub(
+                Time::new(0, 0, 3, 0),
+                Time::new(0, 0, 4, 0),
+                "Café",
+                format()
+            )
+        );
+    }
+
+    #[test]
+    fn test_insert_sub_groups() {
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        // Subtitles 8 to 13 numbered again from 1 in group 1.
+        for tti in &mut stl.ttis[7..] {
+            tti.sgn = 1;
+            tti.sn -= 7;
+        }
+        stl.recompute_totals();
+        let original = stl.clone();
+        let format = TtiFormat {
+            jc: 2,
+            vp: 20,
+            dh: false,
+        };
+        let sn = stl
+            .insert_sub(
+                Time::new(10, 0, 27, 0),
+                Time::new(10, 0, 29, 0),
+                "Disclaimer",
+                format,
+            )
+            .unwrap();
+        assert_eq!(2, sn);
+        assert_eq!((1, 2), (stl.ttis[8].sgn, stl.ttis[8].sn));
+        let numbers = stl
+            .ttis
+            .iter()
+            .map(|tti| (tti.sgn, tti.sn))
+            .collect::<Vec<_>>();
+        let expected = (1..=7)
+            .map(|sn| (0, sn))
+            .chain((1..=7).map(|sn| (1, sn)))
+            .collect::<Vec<_>>();
+        assert_eq!(expected, numbers);
+
+        stl.remove_sub(1, sn).unwrap();
+        assert_eq!(original, stl);
+    }
+
+    #[test]
+    fn test_sort_by_time() {
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        assert!(stl.is_sorted_by_time());
+
+        // Subtitle 3 continued in an extension block, moved after subtitle 6 along with
+        // a user data block keeping its number.
+        stl.ttis[2].ebn = 0;
+        stl.ttis[3].sn = 3;
+        stl.ttis[3].tci = Time::new(10, 0, 9, 14);
+        stl.ttis[4].ebn = 0xfe;
+        let mut chain = stl.ttis.drain(2..5).collect::<Vec<_>>();
+        for tti in &mut chain {
+            tti.sn = 20;
+        }
+        stl.ttis.splice(4..4, chain);
+        assert!(!stl.is_sorted_by_time());
+
+        stl.sort_by_time();
+        assert!(stl.is_sorted_by_time());
+        let texts = stl
+            .ttis
+            .iter()
+            .take(6)
+            .map(|tti| (tti.sn, tti.ebn, tti.get_text().trim().to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (1, 0xff, "".to_string()),
+                (
+                    2,
+                    0xff,
+                    "-Ellis Island,\r\n\r\n     îlot de larmes et d'exil,".to_string()
+                ),
+                (3, 0x00, "au pied de la statue de la Liberté.".to_string()),
+                (3, 0xff, "La porte de l'Amérique.".to_string()),
+                (3, 0xfe, "Pendant près de 60 ans,".to_string()),
+                (
+                    4,
+                    0xff,
+                    "Ellis Island\r\n\r\n     a été le point de passage".to_string()
+                ),
+            ],
+            texts
+        );
+        assert_eq!((13, 11, 1), (stl.gsi.tnb, stl.gsi.tns, stl.gsi.tng));
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut first = parse_stl_from_file("stls/test.stl").unwrap();
+        let mut second = parse_stl_from_file("stls/test.stl").unwrap();
+        first.ttis.truncate(6);
+        second.ttis.drain(..6);
+        second.gsi.set_original_episode_title("PART 2").unwrap();
+
+        let stl = Stl::merge(&[first, second]).unwrap();
+        assert_eq!(
+            parse_stl_from_file("stls/test.stl").unwrap().to_bytes(),
+            stl.to_bytes()
+        );
+
+        let parts = [
+            parse_stl_from_file("stls/test.stl").unwrap(),
+            parse_stl_from_file("stls/test.stl").unwrap(),
+        ];
+        assert_eq!(
+            Some(MergeError::Overlap { part: 1, sn: 13 }),
+            Stl::merge(&parts).err()
+        );
+        let [first, mut second] = parts;
+        second.gsi.dsc = DisplayStandardCode::OpenSubtitling;
+        assert_eq!(
+            Some(MergeError::Incompatible {
+                part: 1,
+                field: "DSC"
+            }),
+            Stl::merge(&[first, second]).err()
+        );
+    }
+
+    #[test]
+    fn test_split_every() {
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        stl.ttis[4].cs = CumulativeStatus::FirstInSet;
+        stl.ttis[5].cs = CumulativeStatus::IntermediateInSet;
+        stl.ttis[6].cs = CumulativeStatus::LastInSet;
+        let parts = stl.split_every(2);
+        let sizes = parts.iter().map(|part| part.ttis.len()).collect::<Vec<_>>();
+        assert_eq!(vec![2, 2, 3, 2, 2, 2], sizes);
+        for (index, part) in parts.iter().enumerate() {
+            assert_eq!((6, index as u8 + 1), (part.gsi.tnd, part.gsi.dsn));
+            assert_eq!(part.ttis.len() as u16, part.gsi.tns);
+        }
+    }
+
+    #[test]
+    fn test_split_at_time() {
+        let stl = parse_stl_from_file("stls/test.stl").unwrap();
+        // Subtitle 5 is displayed from 10:00:15:20 to 10:00:17:13.
+        let t = Time::new(10, 0, 16, 0);
+        let (earlier, later) = stl.split_at_time(&t, StraddlePolicy::Earlier);
+        assert_eq!((5, 8), (earlier.ttis.len(), later.ttis.len()));
+        assert_eq!((2, 1, 2), (earlier.gsi.tnd, earlier.gsi.dsn, later.gsi.dsn));
+        assert_eq!((5, 8), (earlier.gsi.tns, later.gsi.tns));
+
+        let (earlier, later) = stl.split_at_time(&t, StraddlePolicy::Later);
+        assert_eq!((4, 9), (earlier.ttis.len(), later.ttis.len()));
+
+        let (earlier, later) = stl.split_at_time(&t, StraddlePolicy::Duplicate);
+        assert_eq!((5, 9), (earlier.ttis.len(), later.ttis.len()));
+        assert_eq!(t, earlier.ttis[4].tco);
+        assert_eq!(t, later.ttis[0].tci);
+        assert_eq!(Time::new(10, 0, 17, 13), later.ttis[0].tco);
+
+        // Subtitles 5 to 7 in a cumulative set, subtitle 5 ending and subtitle 7
+        // starting on either side of 10:00:18:00.
+        let mut cumulative = stl.clone();
+        cumulative.ttis[4].cs = CumulativeStatus::FirstInSet;
+        cumulative.ttis[5].cs = CumulativeStatus::IntermediateInSet;
+        cumulative.ttis[6].cs = CumulativeStatus::LastInSet;
+        let t = Time::new(10, 0, 18, 0);
+        let (earlier, later) = cumulative.split_at_time(&t, StraddlePolicy::Duplicate);
+        let numbers = |stl: &Stl| stl.ttis.iter().map(|tti| tti.sn).collect::<Vec<_>>();
+        assert_eq!(vec![1, 2, 3, 4, 5, 6], numbers(&earlier));
+        assert_eq!(vec![6, 7, 8, 9, 10, 11, 12, 13], numbers(&later));
+        assert!(earlier
+            .ttis
+            .iter()
+            .chain(&later.ttis)
+            .all(|tti| tti.tci < tti.tco));
+
+        let (earlier, later) = stl.split_at_time(&Time::new(9, 0, 0, 0), StraddlePolicy::Earlier);
+        assert!(earlier.ttis.is_empty());
+        assert_eq!(13, later.ttis.len());
+        assert_eq!((1, 1), (later.gsi.tnd, later.gsi.dsn));
+    }
+
+    #[test]
+    fn test_strip_metadata() {
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        stl.gsi.tn = "Jane Doe".to_string();
+        stl.gsi.uda = "jane@example.com".to_string();
+        stl.ttis[0].sn = 0;
+        stl.ttis[4].cf = 1;
+        let report = stl.strip_metadata(&StripPolicy {
+            user_defined_area: false,
+            ..StripPolicy::default()
+        });
+        assert_eq!(vec!["TN", "PUB", "EN"], report.fields);
+        assert_eq!(1, report.comment_blocks);
+        assert_eq!(1, report.subtitle_zero_blocks);
+        assert_eq!("", stl.gsi.tn);
+        assert_eq!("jane@example.com", stl.gsi.uda);
+        assert_eq!(11, stl.ttis.len());
+        assert_eq!(11, stl.gsi.tnb);
+        assert!(!stl.validate().has_errors());
+    }
+
+    #[test]
+    fn test_remove_comments() {
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        assert_eq!(0, stl.remove_comments());
+
+        stl.ttis[4].cf = 1;
+        stl.ttis[7].cf = 1;
+        assert_eq!(2, stl.remove_comments());
+        assert_eq!(11, stl.ttis.len());
+        let numbers = stl.ttis.iter().map(|tti| tti.sn).collect::<Vec<_>>();
+        assert_eq!((1..=11).collect::<Vec<_>>(), numbers);
+        assert_eq!(ValidationReport::default(), stl.validate());
+
+        // Subtitles 8 to 13 numbered again from 1 in group 1, the numbering is closed up
+        // in each group.
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        for tti in &mut stl.ttis[7..] {
+            tti.sgn = 1;
+            tti.sn -= 7;
+        }
+        stl.ttis[1].cf = 1;
+        stl.ttis[9].cf = 1;
+        assert_eq!(2, stl.remove_comments());
+        let numbers = stl
+            .ttis
+            .iter()
+            .map(|tti| (tti.sgn, tti.sn))
+            .collect::<Vec<_>>();
+        let expected = (1..=6)
+            .map(|sn| (0, sn))
+            .chain((1..=5).map(|sn| (1, sn)))
+            .collect::<Vec<_>>();
+        assert_eq!(expected, numbers);
+    }
+
+    #[test]
+    fn test_renumber() {
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        // Subtitle 6 starting before subtitle 5, subtitle 7 continued in an extension
+        // block, and user data following subtitle 10. Subtitles from 11 on belong to
+        // group 1.
+        stl.ttis[5].tci = Time::new(10, 0, 15, 0);
+        stl.ttis[6].ebn = 0;
+        stl.ttis[7].sn = 7;
+        stl.ttis[10].ebn = 0xfe;
+        stl.ttis[10].sn = 10;
+        for tti in &mut stl.ttis[11..] {
+            tti.sgn = 1;
+            tti.sn += 10;
+        }
+        let numbers = stl.renumber(RenumberScope::PerGroup);
+        let blocks = stl
+            .ttis
+            .iter()
+            .map(|tti| (tti.sgn, tti.sn))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (0, 1),
+                (0, 2),
+                (0, 3),
+                (0, 4),
+                (0, 5),
+                (0, 6),
+                (0, 7),
+                (0, 7),
+                (0, 8),
+                (0, 9),
+                (0, 9),
+                (1, 1),
+                (1, 2)
+            ],
+            blocks
+        );
+        assert_eq!(Some(&6), numbers.get(&(0, 5)));
+        assert_eq!(Some(&5), numbers.get(&(0, 6)));
+        assert_eq!(Some(&8), numbers.get(&(0, 9)));
+        assert_eq!(Some(&2), numbers.get(&(1, 23)));
+        assert_eq!((13, 11, 2), (stl.gsi.tnb, stl.gsi.tns, stl.gsi.tng));
+
+        stl.renumber(RenumberScope::Global);
+        assert_eq!(11, stl.ttis[12].sn);
+    }
+
+    #[test]
+    fn test_capacity_exceeded() {
+        let format = || TtiFormat {
+            jc: 2,
+            vp: 20,
+            dh: false,
+        };
+        let mut stl = Stl::new();
+        stl.gsi.tnb = u16::MAX - 1;
+        stl.gsi.tns = u16::MAX - 1;
+        let (tci, tco) = (Time::new(0, 0, 1, 0), Time::new(0, 0, 2, 0));
+        assert_eq!(Ok(u16::MAX), stl.try_add_sub(tci, tco, "Last", format()));
+        assert_eq!(
+            Err(EditError::CapacityExceeded),
+            stl.try_add_sub(tci, tco, "Too many", format())
+        );
+        assert_eq!(
+            Err(EditError::CapacityExceeded),
+            stl.insert_sub(Time::new(0, 0, 0, 0), tci, "Too many", format())
+        );
+    }
+
+    #[test]
+    fn test_dedupe_consecutive() {
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        assert_eq!(0, stl.dedupe_consecutive(100));
+
+        // Subtitle 3 repeated as 4 and 5, 5 frames then 29 frames apart.
+        for index in [3, 4] {
+            stl.ttis[index].tf = stl.ttis[2].tf.clone();
+        }
+        assert_eq!(0, stl.clone().dedupe_consecutive(4));
+        assert_eq!(1, stl.clone().dedupe_consecutive(5));
+        assert_eq!(2, stl.dedupe_consecutive(29));
+        assert_eq!(11, stl.ttis.len());
+        assert_eq!(Time::new(10, 0, 17, 13), stl.ttis[2].tco);
+        let numbers = stl.ttis.iter().map(|tti| tti.sn).collect::<Vec<_>>();
+        assert_eq!((1..=11).collect::<Vec<_>>(), numbers);
+        assert_eq!((11, 11), (stl.gsi.tnb, stl.gsi.tns));
+    }
+
+    #[test]
+    fn test_remove_empty() {
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        let third = stl.ttis[2].get_text();
+        // Subtitle 1 only holds a new line.
+        assert_eq!(1, stl.clone().remove_empty());
+
+        let mut blank = vec![0x0d, 0x0b, 0x0b, b' ', b' ', 0x0a, 0x0a, 0x8a];
+        blank.resize(112, 0x8f);
+        stl.ttis[1].tf = blank.clone();
+        stl.ttis[5].tf = vec![0x8f; 112];
+        // A blank subtitle clearing a cumulative set is kept.
+        stl.ttis[8].tf = blank;
+        stl.ttis[7].cs = CumulativeStatus::FirstInSet;
+        stl.ttis[8].cs = CumulativeStatus::LastInSet;
+
+        assert_eq!(3, stl.remove_empty());
+        assert_eq!(10, stl.ttis.len());
+        assert_eq!(third, stl.ttis[0].get_text());
+        let numbers = stl.ttis.iter().map(|tti| tti.sn).collect::<Vec<_>>();
+        assert_eq!((1..=10).collect::<Vec<_>>(), numbers);
+        assert_eq!((10, 10), (stl.gsi.tnb, stl.gsi.tns));
+        assert_eq!("", stl.ttis[5].get_text().trim());
+    }
+
+    #[test]
+    fn test_add_sub_at() {
+        let format = TtiFormat {
+            jc: 2,
+            vp: 20,
+            dh: false,
+        };
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        let slate = |stl: &mut Stl, index| {
+            stl.add_sub_at(
+                index,
+                Time::new(9, 59, 50, 0),
+                Time::new(9, 59, 55, 0),
+                "Slate",
+                format,
+            )
+        };
+        assert_eq!(Err(EditError::IndexOutOfRange(14)), slate(&mut stl, 14));
+        assert_eq!(Ok(1), slate(&mut stl, 0));
+        assert_eq!("Slate", stl.ttis[0].get_text().trim());
+        assert_eq!(Ok(15), slate(&mut stl, 14));
+        let numbers = stl.ttis.iter().map(|tti| tti.sn).collect::<Vec<_>>();
+        assert_eq!((1..=15).collect::<Vec<_>>(), numbers);
+        assert_eq!((15, 15), (stl.gsi.tnb, stl.gsi.tns));
+
+        let mut stl = Stl::new();
+        stl.gsi.cct = CharacterCodeTable::LatinGreek;
+        let greek = |stl: &mut Stl, txt| {
+            stl.add_sub_at(0, Time::new(0, 0, 1, 0), Time::new(0, 0, 2, 0), txt, format)
+        };
+        assert_eq!(Ok(1), greek(&mut stl, "Καλημέρα"));
+        assert_eq!(CharacterCodeTable::LatinGreek, stl.ttis[0].cct);
+        assert_eq!("Καλημέρα", stl.ttis[0].get_text().trim());
+        assert_eq!(
+            Err(EditError::Unmappable(vec!['Ł', 'ó', 'ź'])),
+            greek(&mut stl, "Łódź")
+        );
+        assert_eq!(1, stl.ttis.len());
+    }
+
+    #[test]
+    fn test_add_sub_with_duration() {
+        let format = TtiFormat {
+            jc: 2,
+            vp: 20,
+            dh: false,
+        };
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        let sn = stl
+            .add_sub_with_duration(Time::new(10, 0, 59, 20), 30, "Slate", format)
+            .unwrap();
+        assert_eq!(14, sn);
+        assert_eq!(Time::new(10, 1, 1, 0), stl.ttis[13].tco);
+        assert_eq!(
+            Err(EditError::DurationOutOfRange),
+            stl.add_sub_with_duration(Time::new(23, 59, 59, 0), 25, "Late", format)
+        );
+        assert_eq!(14, stl.gsi.tns);
+    }
+
+    #[test]
+    fn test_extract_group() {
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        // Subtitles 2, 4... in group 1, renumbered from 1 as well.
+        for tti in stl.ttis.iter_mut().filter(|tti| tti.sn % 2 == 0) {
+            tti.sgn = 1;
+            tti.sn /= 2;
+        }
+        stl.ttis.sort_by_key(|tti| (tti.sgn, tti.sn));
+        stl.recompute_totals();
+        assert_eq!(6, stl.group(1).count());
+
+        let group = stl.extract_group(1);
+        assert_eq!((6, 6, 1), (group.gsi.tnb, group.gsi.tns, group.gsi.tng));
+        assert_eq!(stl.gsi.opt, group.gsi.opt);
+        assert!(group.ttis.iter().all(|tti| tti.sgn == 1));
+        let numbers = group.ttis.iter().map(|tti| tti.sn).collect::<Vec<_>>();
+        assert_eq!((1..=6).collect::<Vec<_>>(), numbers);
+        assert_eq!(7, stl.extract_group(0).gsi.tns);
+        assert!(stl.extract_group(2).ttis.is_empty());
+    }
+
+    #[test]
+    fn test_clip_to() {
+        let original = parse_stl_from_file("stls/test.stl").unwrap();
+        // Subtitle 2 from 06:08 to 09:09, subtitle 12 from 40:07 to 42:11.
+        let (start, end) = (Time::new(10, 0, 8, 0), Time::new(10, 0, 41, 0));
+
+        let mut stl = original.clone();
+        let report = stl.clip_to(start, end, ClipPolicy::Trim).unwrap();
+        assert_eq!(
+            ClipReport {
+                dropped: 2,
+                trimmed: 2
+            },
+            report
+        );
+        assert_eq!(11, stl.gsi.tns);
+        assert_eq!(start, stl.ttis[0].tci);
+        assert_eq!(end, stl.ttis[10].tco);
+        assert_eq!(original.ttis[1].tco, stl.ttis[0].tco);
+        let numbers = stl.ttis.iter().map(|tti| tti.sn).collect::<Vec<_>>();
+        assert_eq!((1..=11).collect::<Vec<_>>(), numbers);
+
+        let mut stl = original.clone();
+        let report = stl.clip_to(start, end, ClipPolicy::Drop).unwrap();
+        assert_eq!(
+            ClipReport {
+                dropped: 4,
+                trimmed: 0
+            },
+            report
+        );
+        assert_eq!(original.ttis[2], {
+            let mut tti = stl.ttis[0].clone();
+            tti.sn = 3;
+            tti
+        });
+
+        let mut stl = original.clone();
+        assert_eq!(
+            Err(EditError::TimeCodeOutOfRange(1)),
+            stl.clip_to(start, end, ClipPolicy::Fail)
+        );
+        assert_eq!(original, stl);
+    }
+
+    #[test]
+    fn test_flatten_cumulative() {
+        let mut original = parse_stl_from_file("stls/test.stl").unwrap();
+        // Subtitles 6 to 8 make a set.
+        original.ttis[5].cs = CumulativeStatus::FirstInSet;
+        original.ttis[6].cs = CumulativeStatus::IntermediateInSet;
+        original.ttis[7].cs = CumulativeStatus::LastInSet;
+        let text = |stl: &Stl, index: usize| stl.subtitles().nth(index).unwrap().lines;
+
+        let mut stl = original.clone();
+        let report = stl.flatten_cumulative();
+        assert_eq!(
+            vec![FlattenedSet {
+                first: 6,
+                subtitles: 3,
+                cues: 3
+            }],
+            report.sets
+        );
+        assert!(stl
+            .ttis
+            .iter()
+            .all(|tti| tti.cs == CumulativeStatus::NotPartOfASet));
+        assert_eq!(13, stl.gsi.tns);
+        let subtitles = stl.subtitles().collect::<Vec<_>>();
+        assert_eq!(original.ttis[6].tci, *subtitles[5].end);
+        assert_eq!(original.ttis[7].tci, *subtitles[6].end);
+        assert_eq!(
+            [text(&original, 5), text(&original, 6), text(&original, 7)].concat(),
+            subtitles[7].lines
+        );
+        assert_eq!(original.ttis[5].vp, subtitles[7].vertical_position);
+        assert!(!stl.validate().has_errors(), "{}", stl.validate());
+
+        let mut stl = original.clone();
+        let options = FlattenOptions { single_cue: true };
+        let report = stl.flatten_cumulative_with_options(&options);
+        assert_eq!(1, report.sets[0].cues);
+        assert_eq!(11, stl.gsi.tns);
+        let sixth = stl.subtitles().nth(5).unwrap();
+        assert_eq!((6, original.ttis[5].tci), (sixth.number, *sixth.start));
+        assert_eq!(original.ttis[7].tco, *sixth.end);
+        assert_eq!(text(&original, 8), text(&stl, 6));
+    }
+
+    #[test]
+    fn test_split_overflowing_rows() {
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        assert_eq!(SplitReport::default(), stl.split_overflowing_rows(2));
+
+        // Subtitle 3 on four rows.
+        let mut tf = b"\x0b\x0bOne\x0a\x0a\x8a\x0b\x0bTwo\x0a\x0a\x8a\x0b\x0bThree\x0a\x0a\x8a\x0b\x0bFour\x0a\x0a".to_vec();
+        tf.resize(112, 0x8f);
+        stl.ttis[2].tf = tf;
+        let original = stl.clone();
+        let report = stl.split_overflowing_rows_with_options(
+            2,
+            &SplitOptions {
+                min_duration: Some(25),
+            },
+        );
+        assert_eq!(vec![(3, 2)], report.split);
+        assert_eq!(14, stl.gsi.tns);
+        let third = stl.subtitles().nth(2).unwrap();
+        let fourth = stl.subtitles().nth(3).unwrap();
+        assert_eq!(vec!["One", "Two"], third.lines);
+        assert_eq!(vec!["Three", "Four"], fourth.lines);
+        // 25 frames each, the rest shared 6 to 9 characters.
+        let (start, end) = (original.ttis[2].tci, original.ttis[2].tco);
+        let spare = end.to_frames(25) - start.to_frames(25) - 50;
+        assert_eq!(
+            start.to_frames(25) + 25 + spare * 6 / 15,
+            third.end.to_frames(25)
+        );
+        assert_eq!(third.end, fourth.start);
+        assert_eq!(original.ttis[2].tco, *fourth.end);
+        let numbers = stl.ttis.iter().map(|tti| tti.sn).collect::<Vec<_>>();
+        assert_eq!((1..=14).collect::<Vec<_>>(), numbers);
+
+        // No subtitle lasts long enough for two cues of 3 seconds.
+        let mut stl = original.clone();
+        let report = stl.split_overflowing_rows_with_options(
+            1,
+            &SplitOptions {
+                min_duration: Some(75),
+            },
+        );
+        assert!(report.split.is_empty());
+        assert!(report.unsplittable.contains(&3));
+        assert_eq!(original, stl);
+    }
+}
@@ -0,0 +1,332 @@
+//! C ABI over parsing, reading and serializing documents, behind the `ffi` feature.
+//!
+//! Memory ownership:
+//!
+//! - A handle returned by `ebustl_parse` belongs to the caller and is released with
+//!   `ebustl_free`.
+//! - Strings returned by the accessors and the subtitle texts belong to the handle,
+//!   they stay valid until the handle is freed.
+//! - Buffers returned by `ebustl_serialize` belong to the caller and are released with
+//!   `ebustl_bytes_free`, giving back the length returned with them.
+//! - The string returned by `ebustl_last_error` belongs to the library, it stays valid
+//!   until the next call failing on the same thread.
+//!
+//! Panics are caught and reported as `EBUSTL_STATUS_PANIC`, they never unwind into the
+//! caller.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+
+use super::*;
+
+/// Result of the calls, details of failures are given by `ebustl_last_error`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EbustlStatus {
+    Ok = 0,
+    NullPointer = 1,
+    ParseError = 2,
+    SerializeError = 3,
+    IndexOutOfRange = 4,
+    UnknownField = 5,
+    Panic = 6,
+}
+
+/// Subtitle of a document, see `ebustl_subtitle`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct EbustlSubtitle {
+    pub number: u16,
+    #[doc = "Time Code In in milliseconds since midnight"]
+    pub start_ms: u64,
+    #[doc = "Time Code Out in milliseconds since midnight"]
+    pub end_ms: u64,
+    #[doc = "UTF-8 text, rows separated by new lines, owned by the handle"]
+    pub text: *const c_char,
+}
+
+/// Parsed document with the strings handed out to the caller.
+pub struct EbustlHandle {
+    stl: Stl,
+    gsi_fields: Vec<(&'static str, CString)>,
+    subtitles: Vec<(u16, u64, u64, CString)>,
+}
+
+impl EbustlHandle {
+    fn new(stl: Stl) -> EbustlHandle {
+        let fps = stl.gsi.dfc.get_fps();
+        let gsi_fields = diff::gsi_fields(&stl.gsi)
+            .into_iter()
+            .map(|(name, value)| (name, c_string(value.trim())))
+            .collect();
+        let subtitles = stl
+            .subtitles()
+            .map(|subtitle| {
+                (
+                    subtitle.number,
+                    subtitle.start.to_millis(fps),
+                    subtitle.end.to_millis(fps),
+                    c_string(&subtitle.lines.join("\n")),
+                )
+            })
+            .collect();
+        EbustlHandle {
+            stl,
+            gsi_fields,
+            subtitles,
+        }
+    }
+}
+
+// Text without the NUL characters C strings cannot hold.
+fn c_string(text: &str) -> CString {
+    CString::new(text.replace('\0', "")).unwrap_or_default()
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+// Runs `f`, recording the message of a failure or a panic for `ebustl_last_error`.
+fn guard(f: impl FnOnce() -> Result<(), (EbustlStatus, String)>) -> EbustlStatus {
+    let (status, message) = match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => return EbustlStatus::Ok,
+        Ok(Err(error)) => error,
+        Err(_) => (EbustlStatus::Panic, "Panic in ebustl".to_string()),
+    };
+    LAST_ERROR.with(|last| *last.borrow_mut() = c_string(&message));
+    status
+}
+
+fn null_pointer(name: &str) -> (EbustlStatus, String) {
+    (
+        EbustlStatus::NullPointer,
+        format!("{} is a null pointer", name),
+    )
+}
+
+/// Parse the `len` bytes at `data` and store a new handle in `*out`.
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes and `out` to writable memory.
+#[no_mangle]
+pub unsafe extern "C" fn ebustl_parse(
+    data: *const u8,
+    len: usize,
+    out: *mut *mut EbustlHandle,
+) -> EbustlStatus {
+    guard(|| {
+        if data.is_null() {
+            return Err(null_pointer("data"));
+        }
+        if out.is_null() {
+            return Err(null_pointer("out"));
+        }
+        let input = std::slice::from_raw_parts(data, len);
+        let stl = parser::parse_stl_from_slice(input)
+            .map_err(|err| (EbustlStatus::ParseError, err.to_string()))?;
+        *out = Box::into_raw(Box::new(EbustlHandle::new(stl)));
+        Ok(())
+    })
+}
+
+/// Release a handle, null is ignored.
+///
+/// # Safety
+///
+/// `handle` must come from `ebustl_parse` and not have been freed.
+#[no_mangle]
+pub unsafe extern "C" fn ebustl_free(handle: *mut EbustlHandle) {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle));
+    }
+}
+
+/// Serialize the document, storing a new buffer in `*data` and its length in `*len`.
+///
+/// # Safety
+///
+/// `handle` must be a live handle, `data` and `len` must point to writable memory.
+#[no_mangle]
+pub unsafe extern "C" fn ebustl_serialize(
+    handle: *const EbustlHandle,
+    data: *mut *mut u8,
+    len: *mut usize,
+) -> EbustlStatus {
+    guard(|| {
+        let handle = handle.as_ref().ok_or_else(|| null_pointer("handle"))?;
+        if data.is_null() || len.is_null() {
+            return Err(null_pointer("data or len"));
+        }
+        let bytes = handle
+            .stl
+            .to_bytes()
+            .map_err(|err| (EbustlStatus::SerializeError, err.to_string()))?
+            .into_boxed_slice();
+        *len = bytes.len();
+        *data = Box::into_raw(bytes) as *mut u8;
+        Ok(())
+    })
+}
+
+/// Release a buffer returned by `ebustl_serialize`, null is ignored.
+///
+/// # Safety
+///
+/// `data` and `len` must be as returned by `ebustl_serialize`, not freed before.
+#[no_mangle]
+pub unsafe extern "C" fn ebustl_bytes_free(data: *mut u8, len: usize) {
+    if !data.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(data, len)));
+    }
+}
+
+/// Value of a GSI field named as in the specification ("OPT", "TCP"...), trimmed, or
+/// null if the field is unknown. The string is owned by the handle.
+///
+/// # Safety
+///
+/// `handle` must be a live handle and `name` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn ebustl_gsi_field(
+    handle: *const EbustlHandle,
+    name: *const c_char,
+) -> *const c_char {
+    let mut value = ptr::null();
+    guard(|| {
+        let handle = handle.as_ref().ok_or_else(|| null_pointer("handle"))?;
+        if name.is_null() {
+            return Err(null_pointer("name"));
+        }
+        let name = CStr::from_ptr(name).to_string_lossy();
+        let (_, field) = handle
+            .gsi_fields
+            .iter()
+            .find(|(field, _)| field.eq_ignore_ascii_case(&name))
+            .ok_or_else(|| {
+                (
+                    EbustlStatus::UnknownField,
+                    format!("Unknown GSI field {}", name),
+                )
+            })?;
+        value = field.as_ptr();
+        Ok(())
+    });
+    value
+}
+
+/// Number of displayable subtitles, 0 for a null handle.
+///
+/// # Safety
+///
+/// `handle` must be a live handle or null.
+#[no_mangle]
+pub unsafe extern "C" fn ebustl_subtitle_count(handle: *const EbustlHandle) -> usize {
+    handle.as_ref().map_or(0, |handle| handle.subtitles.len())
+}
+
+/// Store the subtitle at `index`, in file order, in `*out`.
+///
+/// # Safety
+///
+/// `handle` must be a live handle and `out` must point to writable memory.
+#[no_mangle]
+pub unsafe extern "C" fn ebustl_subtitle(
+    handle: *const EbustlHandle,
+    index: usize,
+    out: *mut EbustlSubtitle,
+) -> EbustlStatus {
+    guard(|| {
+        let handle = handle.as_ref().ok_or_else(|| null_pointer("handle"))?;
+        let out = out.as_mut().ok_or_else(|| null_pointer("out"))?;
+        let (number, start_ms, end_ms, text) = handle.subtitles.get(index).ok_or_else(|| {
+            (
+                EbustlStatus::IndexOutOfRange,
+                format!("No subtitle at index {}", index),
+            )
+        })?;
+        *out = EbustlSubtitle {
+            number: *number,
+            start_ms: *start_ms,
+            end_ms: *end_ms,
+            text: text.as_ptr(),
+        };
+        Ok(())
+    })
+}
+
+/// Message of the last failure on this thread, empty if none.
+#[no_mangle]
+pub extern "C" fn ebustl_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ptr())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ffi() {
+        let data = std::fs::read("stls/test.stl").unwrap();
+        unsafe {
+            let mut handle = ptr::null_mut();
+            assert_eq!(
+                EbustlStatus::Ok,
+                ebustl_parse(data.as_ptr(), data.len(), &mut handle)
+            );
+            let title = ebustl_gsi_field(handle, c"opt".as_ptr());
+            assert_eq!(c"TEST", CStr::from_ptr(title));
+            assert!(ebustl_gsi_field(handle, c"XYZ".as_ptr()).is_null());
+            assert_eq!(
+                c"Unknown GSI field XYZ",
+                CStr::from_ptr(ebustl_last_error())
+            );
+
+            assert_eq!(13, ebustl_subtitle_count(handle));
+            let mut subtitle = std::mem::zeroed::<EbustlSubtitle>();
+            assert_eq!(EbustlStatus::Ok, ebustl_subtitle(handle, 1, &mut subtitle));
+            assert_eq!((2, 36_006_320), (subtitle.number, subtitle.start_ms));
+            assert_eq!(
+                c"-Ellis Island,\nîlot de larmes et d'exil,",
+                CStr::from_ptr(subtitle.text)
+            );
+            assert_eq!(
+                EbustlStatus::IndexOutOfRange,
+                ebustl_subtitle(handle, 13, &mut subtitle)
+            );
+
+            let mut bytes = ptr::null_mut();
+            let mut len = 0;
+            assert_eq!(
+                EbustlStatus::Ok,
+                ebustl_serialize(handle, &mut bytes, &mut len)
+            );
+            assert_eq!(&data[..], std::slice::from_raw_parts(bytes, len));
+            ebustl_bytes_free(bytes, len);
+            ebustl_free(handle);
+
+            let mut handle = ptr::null_mut();
+            assert_eq!(
+                EbustlStatus::ParseError,
+                ebustl_parse(data.as_ptr(), 100, &mut handle)
+            );
+            assert!(handle.is_null());
+            assert_eq!(
+                EbustlStatus::NullPointer,
+                ebustl_parse(ptr::null(), 0, &mut handle)
+            );
+        }
+    }
+
+    #[test]
+    fn test_header_up_to_date() {
+        assert_eq!(
+            include_str!(concat!(env!("OUT_DIR"), "/ebustl.h")),
+            std::fs::read_to_string("include/ebustl.h").unwrap(),
+            "include/ebustl.h is out of date, build with EBUSTL_UPDATE_HEADER=1 to update it"
+        );
+    }
+}
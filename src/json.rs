@@ -0,0 +1,124 @@
+//! JSON export for analysis, with decoded text and times in milliseconds.
+
+use super::*;
+use serde_json::{Map, Value};
+
+#[derive(Debug, Clone, Default)]
+pub struct JsonExportOptions {
+    #[doc = "Include the raw Text Field bytes of the TTI blocks, base64 encoded"]
+    pub raw_text_fields: bool,
+    #[doc = "Indent the document"]
+    pub pretty: bool,
+}
+
+impl Stl {
+    /// JSON document of the GSI block, text fields trimmed, and of the TTI blocks with
+    /// their decoded text and their time codes in milliseconds. Unlike the `Serialize`
+    /// implementation, the document cannot be read back.
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        self.to_json_with_options(&JsonExportOptions::default())
+    }
+
+    /// Same as [`Stl::to_json`], including raw text fields or indenting as set in
+    /// `options`.
+    pub fn to_json_with_options(
+        &self,
+        options: &JsonExportOptions,
+    ) -> Result<String, serde_json::Error> {
+        let fps = self.gsi.dfc.get_fps();
+        let mut gsi = serde_json::to_value(&self.gsi)?;
+        if let Value::Object(fields) = &mut gsi {
+            fields.remove("spare");
+            for value in fields.values_mut() {
+                if let Value::String(text) = value {
+                    *text = text.trim().to_string();
+                }
+            }
+        }
+        let ttis = self
+            .ttis
+            .iter()
+            .map(|tti| {
+                let mut fields = match serde_json::to_value(tti)? {
+                    Value::Object(fields) => fields,
+                    _ => Map::new(),
+                };
+                fields.remove("cct");
+                fields.remove("tf");
+                for (name, time) in [("tci", &tti.tci), ("tco", &tti.tco)] {
+                    if let Some(Value::Object(time_fields)) = fields.get_mut(name) {
+                        let millis = time.to_millis(fps);
+                        time_fields.insert("millis".to_string(), millis.into());
+                    }
+                }
+                fields.insert("text".to_string(), tti.get_text().into());
+                if options.raw_text_fields {
+                    fields.insert("tf".to_string(), base64(&tti.tf).into());
+                }
+                Ok(Value::Object(fields))
+            })
+            .collect::<Result<Vec<_>, serde_json::Error>>()?;
+        let document = serde_json::json!({ "gsi": gsi, "ttis": ttis });
+        match options.pretty {
+            true => serde_json::to_string_pretty(&document),
+            false => serde_json::to_string(&document),
+        }
+    }
+}
+
+// Standard base64 with padding.
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |group, (index, &byte)| {
+                group | (byte as u32) << (16 - 8 * index)
+            });
+        for index in 0..4 {
+            match index <= chunk.len() {
+                true => encoded.push(ALPHABET[(group >> (18 - 6 * index) & 0x3f) as usize] as char),
+                false => encoded.push('='),
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64() {
+        assert_eq!("", base64(b""));
+        assert_eq!("Zg==", base64(b"f"));
+        assert_eq!("Zm8=", base64(b"fo"));
+        assert_eq!("Zm9v", base64(b"foo"));
+        assert_eq!("Zm9vYmFy", base64(b"foobar"));
+    }
+
+    #[test]
+    fn test_to_json() {
+        let stl = parse_stl_from_file("stls/test.stl").unwrap();
+        let json: Value = serde_json::from_str(&stl.to_json().unwrap()).unwrap();
+        assert_eq!("TEST", json["gsi"]["opt"]);
+        assert!(json["gsi"].get("spare").is_none());
+        let second = &json["ttis"][1];
+        assert_eq!(2, second["sn"]);
+        assert_eq!(10, second["tci"]["hours"]);
+        assert_eq!(36_006_320, second["tci"]["millis"]);
+        assert_eq!(stl.ttis[1].get_text(), second["text"]);
+        assert!(second.get("tf").is_none());
+
+        let options = JsonExportOptions {
+            raw_text_fields: true,
+            pretty: true,
+        };
+        let json: Value =
+            serde_json::from_str(&stl.to_json_with_options(&options).unwrap()).unwrap();
+        assert_eq!(152, json["ttis"][1]["tf"].as_str().unwrap().len());
+    }
+}
@@ -1,21 +1,89 @@
+#[cfg(feature = "chrono")]
 extern crate chrono;
 extern crate iso6937;
 extern crate nom;
 
 use std::fmt;
+#[cfg(any(feature = "fs", test))]
 use std::fs::File;
 use std::io;
 use std::io::prelude::*;
+#[cfg(any(feature = "fs", test))]
+use std::path::Path;
 use std::str;
 
 use codepage_strings::Coding;
+pub mod ass;
+pub mod builder;
+pub mod convert;
+pub mod csv;
+pub mod cues;
+mod debug_xml;
+pub mod diff;
+#[cfg(feature = "xml")]
+pub mod ebu_tt;
+pub mod edit;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "serde")]
+pub mod json;
 pub mod parser;
-use crate::parser::parse_stl_from_slice;
-pub use crate::parser::ParseError;
+pub mod search;
+#[cfg(feature = "serde")]
+mod serde_impl;
+pub mod srt;
+pub mod stats;
+mod styled;
+pub mod subtitles;
+pub mod timing;
+pub mod transcript;
+#[cfg(feature = "xml")]
+pub mod ttml;
+pub mod validate;
+pub mod vtt;
+pub mod writer;
+pub use crate::ass::AssExportOptions;
+pub use crate::builder::{BuildError, StlBuilder};
+pub use crate::convert::{
+    ConversionReport, ConvertError, ConvertOptions, MapReport, UnmappablePolicy,
+};
+pub use crate::csv::{CsvExportOptions, CsvImportError, CsvImportOptions, CsvImportWarning};
+pub use crate::cues::{Cue, CueError};
+pub use crate::diff::{diff, diff_with_mask, FieldMask, StlDiff};
+#[cfg(feature = "xml")]
+pub use crate::ebu_tt::{EbuTtDOptions, EbuTtError};
+pub use crate::edit::{
+    ClipPolicy, ClipReport, EditError, FlattenOptions, FlattenReport, FlattenedSet, InsertOptions,
+    MergeError, RenumberScope, StraddlePolicy, StripPolicy, StripReport,
+};
+#[cfg(feature = "serde")]
+pub use crate::json::JsonExportOptions;
+#[cfg(any(feature = "fs", test))]
+use crate::parser::{parse_stl_from_slice, parse_stl_with_options};
+pub use crate::parser::{Endianness, ParseError, ParseOptions, ParseWarning};
+pub use crate::search::SearchOptions;
+#[cfg(feature = "serde")]
+use crate::serde_impl::{number, text, text_field};
+pub use crate::srt::{
+    parse_srt, FrameRounding, SrtCue, SrtError, SrtExportOptions, SrtImportOptions, SrtSplitText,
+    SrtStyles, SrtUnderflow,
+};
+pub use crate::stats::{StatsOptions, StlStats};
+pub use crate::subtitles::{Chain, ChainDefect, Comment, Subtitle};
+pub use crate::timing::{OverlapReport, OverlapStrategy, TimingIssue, TimingReport};
+pub use crate::transcript::{TranscriptLayout, TranscriptOptions};
+#[cfg(feature = "xml")]
+pub use crate::ttml::{TtmlError, TtmlWarning};
+pub use crate::validate::{Severity, ValidationIssue, ValidationReport};
+pub use crate::vtt::{VttError, VttExportOptions, VttWarning};
+#[cfg(feature = "fs")]
+pub use crate::writer::patch_gsi_in_file;
+pub use crate::writer::{write_stl, SerializeError};
 
 // STL File
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Stl {
     pub gsi: GsiBlock,
     pub ttis: Vec<TtiBlock>,
@@ -27,6 +95,7 @@ impl fmt::Display for Stl {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct TtiFormat {
     #[doc = "Justification Code"]
     pub jc: u8,
@@ -44,41 +113,139 @@ impl Stl {
         }
     }
 
-    pub fn write_to_file(&self, filename: &str) -> Result<(), io::Error> {
-        let mut f = File::create(filename)?;
-        f.write_all(&self.gsi.serialize())?;
-        for tti in self.ttis.iter() {
-            f.write_all(&tti.serialize())?;
-        }
-        Ok(())
+    /// Update the GSI block counters (tnb, tns, tng) from the TTI blocks.
+    pub fn recompute_totals(&mut self) {
+        self.gsi.set_totals(self.totals());
+    }
+
+    fn totals(&self) -> (u16, u16, u16) {
+        totals(&self.ttis)
     }
 
+    /// Blocks using the reserved Extension Block Numbers F0h-FDh, kept for round-tripping.
+    pub fn reserved_blocks(&self) -> impl Iterator<Item = &TtiBlock> {
+        self.ttis.iter().filter(|tti| tti.is_reserved())
+    }
+
+    /// Append a subtitle numbered after the counters of the GSI block, nothing is done
+    /// past the 65535 blocks of a disk: use [`Stl::try_add_sub`] to get the error.
+    #[deprecated(note = "drops the subtitle when the disk is full, use Stl::try_add_sub")]
     pub fn add_sub(&mut self, tci: Time, tco: Time, txt: &str, opt: TtiFormat) {
-        if txt.len() > 112 {
-            //TODO: if txt.len() > 112 split in multiple
-            println!("Warning: sub text is too long!");
+        let _ = self.try_add_sub(tci, tco, txt, opt);
+    }
+
+    /// Append a subtitle numbered after the counters of the GSI block and return its
+    /// number.
+    pub fn try_add_sub(
+        &mut self,
+        tci: Time,
+        tco: Time,
+        txt: &str,
+        opt: TtiFormat,
+    ) -> Result<u16, EditError> {
+        if self.gsi.tnb == u16::MAX || self.gsi.tns == u16::MAX {
+            return Err(EditError::CapacityExceeded);
         }
         self.gsi.tnb += 1; // First TTI has sn=1
         let tti = TtiBlock::new(self.gsi.tnb, tci, tco, txt, opt);
         self.gsi.tns += 1;
         self.ttis.push(tti);
+        Ok(self.gsi.tnb)
+    }
+
+    /// [`Stl::try_add_sub`] ending `duration_frames` after `tci`.
+    pub fn add_sub_with_duration(
+        &mut self,
+        tci: Time,
+        duration_frames: u32,
+        txt: &str,
+        opt: TtiFormat,
+    ) -> Result<u16, EditError> {
+        let fps = self.gsi.dfc.get_fps();
+        let tco = tci
+            .to_frames(fps)
+            .checked_add(duration_frames)
+            .filter(|&frames| frames < 24 * 3600 * fps as u32)
+            .ok_or(EditError::DurationOutOfRange)?;
+        self.try_add_sub(tci, Time::from_frames(tco, fps), txt, opt)
     }
 }
 
+// Values of tnb, tns and tng for the given TTI blocks.
+fn totals(ttis: &[TtiBlock]) -> (u16, u16, u16) {
+    (
+        ttis.len().min(u16::MAX as usize) as u16,
+        count_subtitles(ttis),
+        count_subtitle_groups(ttis),
+    )
+}
+
+// Subtitles are counted once per group and number, whatever the number of blocks in
+// their extension chain. User data and reserved blocks are not subtitles.
+fn count_subtitles(ttis: &[TtiBlock]) -> u16 {
+    let numbers = ttis
+        .iter()
+        .filter(|tti| {
+            matches!(
+                tti.get_extension_block_kind(),
+                ExtensionBlockKind::Extension(_) | ExtensionBlockKind::Last
+            )
+        })
+        .map(|tti| (tti.sgn, tti.sn))
+        .collect::<std::collections::BTreeSet<_>>();
+    numbers.len().min(u16::MAX as usize) as u16
+}
+
+fn count_subtitle_groups(ttis: &[TtiBlock]) -> u16 {
+    let groups = ttis
+        .iter()
+        .map(|tti| tti.sgn)
+        .collect::<std::collections::BTreeSet<_>>();
+    groups.len().max(1) as u16
+}
+
 impl Default for Stl {
     fn default() -> Self {
         Self::new()
     }
 }
 
-pub fn parse_stl_from_file(filename: &str) -> Result<Stl, ParseError> {
-    let mut f = File::open(filename)?;
-    let mut buffer = vec![];
-    f.read_to_end(&mut buffer)?;
+/// TTI blocks in file order, see [`Stl::subtitles`] for the displayed subtitles.
+impl<'a> IntoIterator for &'a Stl {
+    type Item = &'a TtiBlock;
+    type IntoIter = std::slice::Iter<'a, TtiBlock>;
+
+    fn into_iter(self) -> std::slice::Iter<'a, TtiBlock> {
+        self.ttis.iter()
+    }
+}
 
+#[cfg(any(feature = "fs", test))]
+pub fn parse_stl_from_file(filename: impl AsRef<Path>) -> Result<Stl, ParseError> {
+    let buffer = read_file(filename.as_ref())?;
     parse_stl_from_slice(&buffer)
 }
 
+#[cfg(any(feature = "fs", test))]
+pub fn parse_stl_from_file_with_options(
+    filename: impl AsRef<Path>,
+    options: &ParseOptions,
+) -> Result<(Stl, Vec<ParseWarning>), ParseError> {
+    let buffer = read_file(filename.as_ref())?;
+    parse_stl_with_options(&buffer, options)
+}
+
+#[cfg(any(feature = "fs", test))]
+fn read_file(filename: &Path) -> Result<Vec<u8>, ParseError> {
+    let read = || -> Result<Vec<u8>, io::Error> {
+        let mut f = File::open(filename)?;
+        let mut buffer = vec![];
+        f.read_to_end(&mut buffer)?;
+        Ok(buffer)
+    };
+    read().map_err(|err| ParseError::IoError(format!("{}: {}", filename.display(), err)))
+}
+
 struct CodePageDecoder {
     coding: Coding,
 }
@@ -93,11 +260,33 @@ impl CodePageDecoder {
     fn parse(&self, data: &[u8]) -> Result<String, ParseError> {
         Ok(self.coding.decode_lossy(data).to_string())
     }
+
+    // Characters missing from the code page, see `encode`.
+    fn unmappable(&self, s: &str) -> Vec<char> {
+        let mut buf = [0u8; 4];
+        s.chars()
+            .filter(|c| self.coding.encode(&*c.encode_utf8(&mut buf)).is_err())
+            .collect()
+    }
+
+    // Characters missing from the code page are replaced with '?'.
+    fn encode(&self, s: &str) -> Vec<u8> {
+        let mut res = Vec::with_capacity(s.len());
+        let mut buf = [0u8; 4];
+        for c in s.chars() {
+            match self.coding.encode(&*c.encode_utf8(&mut buf)) {
+                Ok(bytes) => res.extend(bytes),
+                Err(_) => res.push(b'?'),
+            }
+        }
+        res
+    }
 }
 
 // GSI Block
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[allow(non_camel_case_types)]
 pub enum CodePageNumber {
     CPN_437,
@@ -118,6 +307,16 @@ impl CodePageNumber {
         }
     }
 
+    pub fn to_u16(&self) -> u16 {
+        match *self {
+            CodePageNumber::CPN_437 => 437,
+            CodePageNumber::CPN_850 => 850,
+            CodePageNumber::CPN_860 => 860,
+            CodePageNumber::CPN_863 => 863,
+            CodePageNumber::CPN_865 => 865,
+        }
+    }
+
     pub(crate) fn from_u16(codepage: u16) -> Result<CodePageNumber, ParseError> {
         match codepage {
             437 => Ok(CodePageNumber::CPN_437),
@@ -130,7 +329,8 @@ impl CodePageNumber {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum DisplayStandardCode {
     Blank,
     OpenSubtitling,
@@ -159,7 +359,8 @@ impl DisplayStandardCode {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TimeCodeStatus {
     NotIntendedForUse,
     IntendedForUse,
@@ -182,7 +383,8 @@ impl TimeCodeStatus {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum CharacterCodeTable {
     Latin,
     LatinCyrillic,
@@ -209,6 +411,36 @@ impl CharacterCodeTable {
         }
     }
 
+    fn decode(&self, data: &[u8]) -> String {
+        let encoding = match *self {
+            CharacterCodeTable::Latin => return iso6937::decode(data),
+            CharacterCodeTable::LatinCyrillic => encoding_rs::ISO_8859_5,
+            CharacterCodeTable::LatinArabic => encoding_rs::ISO_8859_6,
+            CharacterCodeTable::LatinGreek => encoding_rs::ISO_8859_7,
+            CharacterCodeTable::LatinHebrew => encoding_rs::ISO_8859_8,
+        };
+        encoding.decode_without_bom_handling(data).0.into_owned()
+    }
+
+    // Bytes of a single character, `None` if missing from the table.
+    fn encode_char(&self, c: char) -> Option<Vec<u8>> {
+        let mut buf = [0u8; 4];
+        let s = &*c.encode_utf8(&mut buf);
+        let bytes = match *self {
+            // Non-spacing diacritical marks alone cannot be decoded.
+            CharacterCodeTable::Latin => match &*iso6937::encode(s) {
+                [0xc1..=0xcf] => return None,
+                bytes => bytes.to_vec(),
+            },
+            CharacterCodeTable::LatinCyrillic => encoding_rs::ISO_8859_5.encode(s).0.into_owned(),
+            CharacterCodeTable::LatinArabic => encoding_rs::ISO_8859_6.encode(s).0.into_owned(),
+            CharacterCodeTable::LatinGreek => encoding_rs::ISO_8859_7.encode(s).0.into_owned(),
+            CharacterCodeTable::LatinHebrew => encoding_rs::ISO_8859_8.encode(s).0.into_owned(),
+        };
+        // Missing characters are replaced by the encoders, they do not decode back.
+        (self.decode(&bytes) == s).then_some(bytes)
+    }
+
     fn serialize(&self) -> Vec<u8> {
         match *self {
             CharacterCodeTable::Latin => vec![0x30, 0x30],
@@ -220,7 +452,8 @@ impl CharacterCodeTable {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[allow(non_camel_case_types)]
 pub enum DiskFormatCode {
     STL25_01,
@@ -253,7 +486,8 @@ impl DiskFormatCode {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GsiBlock {
     #[doc = "0..2 Code Page Number"]
     cpn: CodePageNumber,
@@ -264,61 +498,148 @@ pub struct GsiBlock {
     #[doc = "12..13 Character Code Table Number"]
     cct: CharacterCodeTable,
     #[doc = "14..15 Language Code"]
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "text::<_, 2>"))]
     lc: String,
     #[doc = "16..47 Original Program Title"]
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "text::<_, 32>"))]
     opt: String,
     #[doc = "48..79 Original Episode Title"]
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "text::<_, 32>"))]
     oet: String,
     #[doc = "80..111 Translated Program Title"]
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "text::<_, 32>"))]
     tpt: String,
     #[doc = "112..143 Translated Episode Title"]
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "text::<_, 32>"))]
     tet: String,
     #[doc = "144..175 Translator's Name"]
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "text::<_, 32>"))]
     tn: String,
     #[doc = "176..207 Translator's Contact Details"]
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "text::<_, 32>"))]
     tcd: String,
     #[doc = "208..223 Subtitle List Reference Code"]
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "text::<_, 16>"))]
     slr: String,
     #[doc = "224..229 Creation Date"]
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "text::<_, 6>"))]
     cd: String,
     #[doc = "230..235 Revision Date"]
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "text::<_, 6>"))]
     rd: String,
     #[doc = "236..237 Revision Number"]
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "text::<_, 2>"))]
     rn: String,
     #[doc = "238..242 Total Number of Text and Timing Blocks"]
     tnb: u16,
     #[doc = "243..247 Total Number of Subtitles"]
     tns: u16,
     #[doc = "248..250 Total Number of Subtitle Groups"]
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "number::<_, _, 3>"))]
     tng: u16,
     #[doc = "251..252 Maximum Number of Displayable Characters in a Text Row"]
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "number::<_, _, 2>"))]
     mnc: u16,
     #[doc = "253..254 Maximum Number of Displayable Rows"]
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "number::<_, _, 2>"))]
     mnr: u16,
     #[doc = "255 Time Code Status"]
     tcs: TimeCodeStatus,
     #[doc = "256..263 Time Code: Start of Programme (format: HHMMSSFF)"]
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "text::<_, 8>"))]
     tcp: String,
     #[doc = "264..271 Time Code: First-in-Cue (format: HHMMSSFF)"]
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "text::<_, 8>"))]
     tcf: String,
     #[doc = "272 Total Number of Disks"]
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "number::<_, _, 1>"))]
     tnd: u8,
     #[doc = "273 Disk Sequence Number"]
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "number::<_, _, 1>"))]
     dsn: u8,
     #[doc = "274..276 Country of Origin"]
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "text::<_, 3>"))]
     co: String, // TODO Type with country definitions
     #[doc = "277..308 Publisher"]
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "text::<_, 32>"))]
     pub_: String,
     #[doc = "309..340 Editor's Name"]
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "text::<_, 32>"))]
     en: String,
     #[doc = "341..372 Editor's Contact Details"]
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "text::<_, 32>"))]
     ecd: String,
-    #[doc = "373..447 Spare Bytes"]
-    _spare: String,
+    #[doc = "373..447 Spare Bytes, as read from the file, always written as spaces"]
+    #[cfg_attr(feature = "serde", serde(with = "serde_bytes"))]
+    spare: Vec<u8>,
     #[doc = "448..1023 User-Defined Area"]
+    #[cfg_attr(feature = "serde", serde(deserialize_with = "text::<_, 576>"))]
     uda: String,
 }
 
+/// Defaults of the GSI blocks created from scratch, see [`GsiBlock::from_profile`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GsiProfile {
+    pub cpn: CodePageNumber,
+    pub dfc: DiskFormatCode,
+    pub dsc: DisplayStandardCode,
+    pub cct: CharacterCodeTable,
+    #[doc = "Language Code"]
+    pub lc: String,
+    #[doc = "Maximum Number of Displayable Characters in a Text Row"]
+    pub mnc: u16,
+    #[doc = "Maximum Number of Displayable Rows"]
+    pub mnr: u16,
+    #[doc = "Country of Origin"]
+    pub co: String,
+}
+
+impl GsiProfile {
+    /// Level 1 teletext at 25 fps, the defaults of [`GsiBlock::new`].
+    pub fn teletext_25() -> GsiProfile {
+        GsiProfile {
+            cpn: CodePageNumber::CPN_850,
+            dfc: DiskFormatCode::STL25_01,
+            dsc: DisplayStandardCode::Level1Teletext,
+            cct: CharacterCodeTable::Latin,
+            lc: "0F".to_string(),
+            mnc: 40,
+            mnr: 23,
+            co: "".to_string(),
+        }
+    }
+
+    pub fn teletext_level2_25() -> GsiProfile {
+        GsiProfile {
+            dsc: DisplayStandardCode::Level2Teletext,
+            ..GsiProfile::teletext_25()
+        }
+    }
+
+    pub fn open_subtitles_25() -> GsiProfile {
+        GsiProfile {
+            dsc: DisplayStandardCode::OpenSubtitling,
+            ..GsiProfile::teletext_25()
+        }
+    }
+
+    pub fn open_subtitles_30() -> GsiProfile {
+        GsiProfile {
+            dfc: DiskFormatCode::STL30_01,
+            dsc: DisplayStandardCode::OpenSubtitling,
+            mnc: 37,
+            ..GsiProfile::teletext_25()
+        }
+    }
+}
+
+impl Default for GsiProfile {
+    fn default() -> Self {
+        GsiProfile::teletext_25()
+    }
+}
+
 impl GsiBlock {
     pub fn get_code_page_number(&self) -> &CodePageNumber {
         &self.cpn
@@ -407,28 +728,227 @@ impl GsiBlock {
     pub fn get_editors_contact_details(&self) -> &str {
         &self.ecd
     }
+    /// Spare bytes as read from the file, they are written back as spaces.
+    pub fn get_spare_bytes(&self) -> &[u8] {
+        &self.spare
+    }
     pub fn get_user_defined_area(&self) -> &str {
         &self.uda
     }
+
+    // Setters check that the values fit in their field, strings are written with one
+    // byte per character.
+
+    pub fn set_code_page_number(&mut self, cpn: CodePageNumber) {
+        self.cpn = cpn;
+    }
+    pub fn set_disk_format_code(&mut self, dfc: DiskFormatCode) {
+        self.dfc = dfc;
+    }
+    pub fn set_character_code_table(&mut self, cct: CharacterCodeTable) {
+        self.cct = cct;
+    }
+    pub fn set_display_standard_code(&mut self, dsc: DisplayStandardCode) {
+        self.dsc = dsc;
+    }
+    pub fn set_timecode_status(&mut self, tcs: TimeCodeStatus) {
+        self.tcs = tcs;
+    }
+    pub fn set_max_number_of_chars_in_row(&mut self, mnc: u16) -> Result<(), SerializeError> {
+        self.mnc = checked_number("MNC", mnc, 2)?;
+        Ok(())
+    }
+    pub fn set_max_number_of_rows(&mut self, mnr: u16) -> Result<(), SerializeError> {
+        self.mnr = checked_number("MNR", mnr, 2)?;
+        Ok(())
+    }
+    pub fn set_language_code(&mut self, value: &str) -> Result<(), SerializeError> {
+        self.lc = checked_string("LC", value, 2)?;
+        Ok(())
+    }
+    pub fn set_original_program_title(&mut self, value: &str) -> Result<(), SerializeError> {
+        self.opt = checked_string("OPT", value, 32)?;
+        Ok(())
+    }
+    pub fn set_original_episode_title(&mut self, value: &str) -> Result<(), SerializeError> {
+        self.oet = checked_string("OET", value, 32)?;
+        Ok(())
+    }
+    pub fn set_translated_program_title(&mut self, value: &str) -> Result<(), SerializeError> {
+        self.tpt = checked_string("TPT", value, 32)?;
+        Ok(())
+    }
+    pub fn set_translated_episode_title(&mut self, value: &str) -> Result<(), SerializeError> {
+        self.tet = checked_string("TET", value, 32)?;
+        Ok(())
+    }
+    pub fn set_translators_name(&mut self, value: &str) -> Result<(), SerializeError> {
+        self.tn = checked_string("TN", value, 32)?;
+        Ok(())
+    }
+    pub fn set_translators_contact_details(&mut self, value: &str) -> Result<(), SerializeError> {
+        self.tcd = checked_string("TCD", value, 32)?;
+        Ok(())
+    }
+    pub fn set_subtitle_list_reference_code(&mut self, value: &str) -> Result<(), SerializeError> {
+        self.slr = checked_string("SLR", value, 16)?;
+        Ok(())
+    }
+    pub fn set_creation_date(&mut self, value: &str) -> Result<(), SerializeError> {
+        self.cd = checked_string("CD", value, 6)?;
+        Ok(())
+    }
+    pub fn set_revision_date(&mut self, value: &str) -> Result<(), SerializeError> {
+        self.rd = checked_string("RD", value, 6)?;
+        Ok(())
+    }
+    pub fn set_revision_number(&mut self, value: &str) -> Result<(), SerializeError> {
+        self.rn = checked_string("RN", value, 2)?;
+        Ok(())
+    }
+    pub fn set_timecode_start_of_program(&mut self, value: &str) -> Result<(), SerializeError> {
+        self.tcp = checked_string("TCP", value, 8)?;
+        Ok(())
+    }
+    pub fn set_timecode_first_in_cue(&mut self, value: &str) -> Result<(), SerializeError> {
+        self.tcf = checked_string("TCF", value, 8)?;
+        Ok(())
+    }
+    pub fn set_country_of_origin(&mut self, value: &str) -> Result<(), SerializeError> {
+        self.co = checked_string("CO", value, 3)?;
+        Ok(())
+    }
+    pub fn set_publisher(&mut self, value: &str) -> Result<(), SerializeError> {
+        self.pub_ = checked_string("PUB", value, 32)?;
+        Ok(())
+    }
+    pub fn set_editors_name(&mut self, value: &str) -> Result<(), SerializeError> {
+        self.en = checked_string("EN", value, 32)?;
+        Ok(())
+    }
+    pub fn set_editors_contact_details(&mut self, value: &str) -> Result<(), SerializeError> {
+        self.ecd = checked_string("ECD", value, 32)?;
+        Ok(())
+    }
+    pub fn set_user_defined_area(&mut self, value: &str) -> Result<(), SerializeError> {
+        self.uda = checked_string("UDA", value, 576)?;
+        Ok(())
+    }
 }
 
-fn push_string(v: &mut Vec<u8>, s: &str, len: usize) {
-    let addendum = s.to_owned().into_bytes();
+fn push_string(
+    v: &mut Vec<u8>,
+    coding: &CodePageDecoder,
+    field: &'static str,
+    s: &str,
+    len: usize,
+) -> Result<(), SerializeError> {
+    let addendum = coding.encode(s);
+    if addendum.len() > len {
+        return Err(SerializeError::FieldOverflow { field, width: len });
+    }
     let padding = len - addendum.len();
     v.extend(addendum.iter().cloned());
     v.extend(vec![0x20u8; padding]);
+    Ok(())
+}
+
+fn checked_string(field: &'static str, s: &str, len: usize) -> Result<String, SerializeError> {
+    if s.chars().count() > len {
+        return Err(SerializeError::FieldOverflow { field, width: len });
+    }
+    Ok(s.to_string())
+}
+
+fn checked_number(field: &'static str, n: u16, len: usize) -> Result<u16, SerializeError> {
+    if n as u32 >= 10u32.pow(len as u32) {
+        return Err(SerializeError::FieldOverflow { field, width: len });
+    }
+    Ok(n)
+}
+
+fn push_number(
+    v: &mut Vec<u8>,
+    field: &'static str,
+    n: u32,
+    len: usize,
+) -> Result<(), SerializeError> {
+    let addendum = format!("{:0len$}", n, len = len);
+    if addendum.len() > len {
+        return Err(SerializeError::FieldOverflow { field, width: len });
+    }
+    v.extend(addendum.into_bytes());
+    Ok(())
+}
+
+// Date of today in the local time zone, the default creation and revision date.
+#[cfg(feature = "chrono")]
+pub(crate) fn today() -> chrono::NaiveDate {
+    chrono::Local::now().date_naive()
 }
 
 impl GsiBlock {
+    fn set_totals(&mut self, (tnb, tns, tng): (u16, u16, u16)) {
+        self.tnb = tnb;
+        self.tns = tns;
+        self.tng = tng;
+    }
+
+    /// Default GSI block created and revised today, or with blank dates without the
+    /// `chrono` feature.
     pub fn new() -> GsiBlock {
-        let date = chrono::Local::now();
-        let now = date.format("%y%m%d").to_string();
+        GsiBlock::from_profile(&GsiProfile::default())
+    }
+
+    /// Default GSI block with the given creation and revision dates, for reproducible
+    /// output.
+    #[cfg(feature = "chrono")]
+    pub fn new_with_dates(creation: chrono::NaiveDate, revision: chrono::NaiveDate) -> GsiBlock {
+        GsiBlock::from_profile_with_dates(&GsiProfile::default(), creation, revision)
+    }
+
+    /// Default GSI block with the given creation and revision dates written YYMMDD, or
+    /// blank.
+    pub fn new_with_date_codes(creation: &str, revision: &str) -> Result<GsiBlock, SerializeError> {
+        let mut gsi = GsiBlock::from_profile_undated(&GsiProfile::default());
+        gsi.set_creation_date(creation)?;
+        gsi.set_revision_date(revision)?;
+        Ok(gsi)
+    }
+
+    /// GSI block with the defaults of the given profile, created and revised today.
+    #[cfg(feature = "chrono")]
+    pub fn from_profile(profile: &GsiProfile) -> GsiBlock {
+        let today = today();
+        GsiBlock::from_profile_with_dates(profile, today, today)
+    }
+
+    /// GSI block with the defaults of the given profile and blank dates.
+    #[cfg(not(feature = "chrono"))]
+    pub fn from_profile(profile: &GsiProfile) -> GsiBlock {
+        GsiBlock::from_profile_undated(profile)
+    }
+
+    #[cfg(feature = "chrono")]
+    pub fn from_profile_with_dates(
+        profile: &GsiProfile,
+        creation: chrono::NaiveDate,
+        revision: chrono::NaiveDate,
+    ) -> GsiBlock {
         GsiBlock {
-            cpn: CodePageNumber::CPN_850,
-            dfc: DiskFormatCode::STL25_01,
-            dsc: DisplayStandardCode::Level1Teletext,
-            cct: CharacterCodeTable::Latin,
-            lc: "0F".to_string(), // FIXME: ok for default?
+            cd: creation.format("%y%m%d").to_string(),
+            rd: revision.format("%y%m%d").to_string(),
+            ..GsiBlock::from_profile_undated(profile)
+        }
+    }
+
+    fn from_profile_undated(profile: &GsiProfile) -> GsiBlock {
+        GsiBlock {
+            cpn: profile.cpn,
+            dfc: profile.dfc,
+            dsc: profile.dsc,
+            cct: profile.cct,
+            lc: profile.lc.clone(),
             opt: "".to_string(),
             oet: "".to_string(),
             tpt: "".to_string(),
@@ -436,66 +956,106 @@ impl GsiBlock {
             tn: "".to_string(),
             tcd: "".to_string(),
             slr: "".to_string(),
-            cd: now.clone(),
-            rd: now,
+            cd: "".to_string(),
+            rd: "".to_string(),
             rn: "00".to_string(),
             tnb: 0,
             tns: 0,
-            tng: 1,  // At least one group?
-            mnc: 40, // FIXME: ok for default?
-            mnr: 23, // FIXME: ok for default?
+            tng: 1, // At least one group?
+            mnc: profile.mnc,
+            mnr: profile.mnr,
             tcs: TimeCodeStatus::IntendedForUse,
             tcp: "00000000".to_string(),
             tcf: "00000000".to_string(),
             tnd: 1,
             dsn: 1,
-            co: "".to_string(),
+            co: profile.co.clone(),
             pub_: "".to_string(),
             en: "".to_string(),
             ecd: "".to_string(),
-            _spare: "".to_string(),
+            spare: vec![0x20; 447 - 373 + 1],
             uda: "".to_string(),
         }
     }
 
-    fn serialize(&self) -> Vec<u8> {
+    /// Decode a GSI block, see also [`parser::parse_gsi_block`].
+    pub fn from_bytes(data: &[u8; 1024]) -> Result<GsiBlock, ParseError> {
+        parser::parse_gsi_block(data)
+    }
+
+    /// The 1024 bytes written to file for this block, the encoding is stable across
+    /// versions.
+    pub fn to_bytes(&self) -> Result<[u8; 1024], SerializeError> {
+        self.serialize()?
+            .try_into()
+            .map_err(|block: Vec<u8>| SerializeError::BlockSize {
+                index: 0,
+                actual: block.len(),
+            })
+    }
+
+    /// Encoded block as written to file, 1024 bytes long.
+    ///
+    /// A parsed block is written back as read, except for:
+    /// - the spare bytes, always written as spaces, see [`GsiBlock::get_spare_bytes`];
+    /// - the text fields padded with NUL bytes, turned to spaces while parsing;
+    /// - the characters missing from the code page, written as `?`.
+    ///
+    /// [`Stl::validate`] reports the spare bytes and the missing characters.
+    pub fn serialize(&self) -> Result<Vec<u8>, SerializeError> {
+        // All the code pages of CodePageNumber are supported by the decoder.
+        let coding = CodePageDecoder::new(self.cpn.to_u16()).expect("supported code page");
         let mut res = Vec::with_capacity(1024);
         res.extend(self.cpn.serialize());
         res.extend(self.dfc.serialize().iter().cloned());
         res.push(self.dsc.serialize());
         res.extend(self.cct.serialize());
         // be careful for the length of following: must force padding
-        push_string(&mut res, &self.lc, 15 - 14 + 1);
-        push_string(&mut res, &self.opt, 47 - 16 + 1);
-        push_string(&mut res, &self.oet, 79 - 48 + 1);
-        push_string(&mut res, &self.tpt, 111 - 80 + 1);
-        push_string(&mut res, &self.tet, 143 - 112 + 1);
-        push_string(&mut res, &self.tn, 175 - 144 + 1);
-        push_string(&mut res, &self.tcd, 207 - 176 + 1);
-        push_string(&mut res, &self.slr, 223 - 208 + 1);
-        push_string(&mut res, &self.cd, 229 - 224 + 1);
-        push_string(&mut res, &self.rd, 235 - 230 + 1);
-        push_string(&mut res, &self.rn, 237 - 236 + 1);
-
-        push_string(&mut res, &format!("{:05}", self.tnb), 242 - 238 + 1);
-        push_string(&mut res, &format!("{:05}", self.tns), 247 - 243 + 1);
-        push_string(&mut res, &format!("{:03}", self.tng), 250 - 248 + 1);
-        push_string(&mut res, &format!("{:02}", self.mnc), 252 - 251 + 1);
-        push_string(&mut res, &format!("{:02}", self.mnr), 254 - 253 + 1);
+        push_string(&mut res, &coding, "LC", &self.lc, 15 - 14 + 1)?;
+        push_string(&mut res, &coding, "OPT", &self.opt, 47 - 16 + 1)?;
+        push_string(&mut res, &coding, "OET", &self.oet, 79 - 48 + 1)?;
+        push_string(&mut res, &coding, "TPT", &self.tpt, 111 - 80 + 1)?;
+        push_string(&mut res, &coding, "TET", &self.tet, 143 - 112 + 1)?;
+        push_string(&mut res, &coding, "TN", &self.tn, 175 - 144 + 1)?;
+        push_string(&mut res, &coding, "TCD", &self.tcd, 207 - 176 + 1)?;
+        push_string(&mut res, &coding, "SLR", &self.slr, 223 - 208 + 1)?;
+        push_string(&mut res, &coding, "CD", &self.cd, 229 - 224 + 1)?;
+        push_string(&mut res, &coding, "RD", &self.rd, 235 - 230 + 1)?;
+        push_string(&mut res, &coding, "RN", &self.rn, 237 - 236 + 1)?;
+
+        push_number(&mut res, "TNB", self.tnb.into(), 242 - 238 + 1)?;
+        push_number(&mut res, "TNS", self.tns.into(), 247 - 243 + 1)?;
+        push_number(&mut res, "TNG", self.tng.into(), 250 - 248 + 1)?;
+        push_number(&mut res, "MNC", self.mnc.into(), 252 - 251 + 1)?;
+        push_number(&mut res, "MNR", self.mnr.into(), 254 - 253 + 1)?;
 
         res.push(self.tcs.serialize());
-        push_string(&mut res, &self.tcp, 263 - 256 + 1);
-        push_string(&mut res, &self.tcf, 271 - 264 + 1);
-        push_string(&mut res, &format!("{:1}", self.tnd), 1);
-        push_string(&mut res, &format!("{:1}", self.dsn), 1);
-        push_string(&mut res, &self.co, 276 - 274 + 1);
-        push_string(&mut res, &self.pub_, 308 - 277 + 1);
-        push_string(&mut res, &self.en, 340 - 309 + 1);
-        push_string(&mut res, &self.ecd, 372 - 341 + 1);
-        push_string(&mut res, &self._spare, 447 - 373 + 1);
-        push_string(&mut res, &self.uda, 1023 - 448 + 1);
+        push_string(&mut res, &coding, "TCP", &self.tcp, 263 - 256 + 1)?;
+        push_string(&mut res, &coding, "TCF", &self.tcf, 271 - 264 + 1)?;
+        push_number(&mut res, "TND", self.tnd.into(), 1)?;
+        push_number(&mut res, "DSN", self.dsn.into(), 1)?;
+        push_string(&mut res, &coding, "CO", &self.co, 276 - 274 + 1)?;
+        push_string(&mut res, &coding, "PUB", &self.pub_, 308 - 277 + 1)?;
+        push_string(&mut res, &coding, "EN", &self.en, 340 - 309 + 1)?;
+        push_string(&mut res, &coding, "ECD", &self.ecd, 372 - 341 + 1)?;
+        res.extend(std::iter::repeat_n(0x20, 447 - 373 + 1));
+        push_string(&mut res, &coding, "UDA", &self.uda, 1023 - 448 + 1)?;
 
-        res
+        Ok(res)
+    }
+}
+
+impl GsiBlock {
+    // Fields with characters missing from the code page, written as '?'.
+    pub(crate) fn unmappable_fields(&self) -> Vec<(&'static str, Vec<char>)> {
+        let Ok(coding) = CodePageDecoder::new(self.cpn.to_u16()) else {
+            return vec![];
+        };
+        diff::gsi_fields(self)
+            .into_iter()
+            .map(|(field, value)| (field, coding.unmappable(&value)))
+            .filter(|(_, chars)| !chars.is_empty())
+            .collect()
     }
 }
 
@@ -517,7 +1077,8 @@ impl fmt::Display for GsiBlock {
 
 // TTI Block
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum CumulativeStatus {
     NotPartOfASet,
     FirstInSet,
@@ -546,6 +1107,32 @@ impl CumulativeStatus {
     }
 }
 
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ExtensionBlockKind {
+    #[doc = "00h-EFh Extension block, more blocks follow for the same subtitle"]
+    Extension(u8),
+    #[doc = "F0h-FDh Reserved for future use"]
+    Reserved(u8),
+    #[doc = "FEh User data"]
+    UserData,
+    #[doc = "FFh Last (or only) block of the subtitle"]
+    Last,
+}
+
+impl ExtensionBlockKind {
+    fn from_ebn(ebn: u8) -> ExtensionBlockKind {
+        match ebn {
+            0x00..=0xef => ExtensionBlockKind::Extension(ebn),
+            0xf0..=0xfd => ExtensionBlockKind::Reserved(ebn),
+            0xfe => ExtensionBlockKind::UserData,
+            0xff => ExtensionBlockKind::Last,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Justification {
     Unchanged,
     Left,
@@ -553,7 +1140,20 @@ pub enum Justification {
     Right,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+impl Justification {
+    /// Justification of a Justification Code, undefined codes leave it unchanged.
+    pub fn from_jc(jc: u8) -> Justification {
+        match jc {
+            1 => Justification::Left,
+            2 => Justification::Centered,
+            3 => Justification::Right,
+            _ => Justification::Unchanged,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Time {
     pub hours: u8,
     pub minutes: u8,
@@ -571,6 +1171,67 @@ impl Time {
         }
     }
 
+    /// Number of frames since 00:00:00:00.
+    pub fn to_frames(&self, fps: usize) -> u32 {
+        let seconds = self.hours as u32 * 3600 + self.minutes as u32 * 60 + self.seconds as u32;
+        seconds * fps as u32 + self.frames as u32
+    }
+
+    /// Time code of the given number of frames, hours wrap around after 255.
+    pub fn from_frames(frames: u32, fps: usize) -> Time {
+        let fps = fps as u32;
+        let seconds = frames / fps;
+        Time::new(
+            (seconds / 3600) as u8,
+            (seconds / 60 % 60) as u8,
+            (seconds % 60) as u8,
+            (frames % fps) as u8,
+        )
+    }
+
+    /// Milliseconds since 00:00:00:00 at `fps`, rounded down.
+    pub fn to_millis(&self, fps: usize) -> u64 {
+        self.to_frames(fps) as u64 * 1000 / fps as u64
+    }
+
+    /// Time code of the given number of milliseconds, rounded to the nearest frame at
+    /// `fps`.
+    pub fn from_millis(millis: u64, fps: usize) -> Time {
+        let fps = fps as u64;
+        Time::from_frames(((millis * fps + 500) / 1000) as u32, fps as usize)
+    }
+
+    /// Whether this is a time of day, with fewer frames than `fps`.
+    pub fn is_valid(&self, fps: usize) -> bool {
+        self.hours < 24 && self.minutes < 60 && self.seconds < 60 && (self.frames as usize) < fps
+    }
+
+    /// Time of a GSI time code written HHMMSSFF, `None` if not made of 8 digits.
+    pub fn from_time_code(time_code: &str) -> Option<Time> {
+        if time_code.len() != 8 || !time_code.bytes().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        let field = |i: usize| time_code[i..i + 2].parse().ok();
+        Some(Time::new(field(0)?, field(2)?, field(4)?, field(6)?))
+    }
+
+    /// GSI time code written HHMMSSFF.
+    pub fn to_time_code(&self) -> String {
+        self.format_with("")
+    }
+
+    /// Time code written HH:MM:SS:FF, as in SMPTE time expressions.
+    pub fn to_smpte(&self) -> String {
+        self.format_with(":")
+    }
+
+    fn format_with(&self, separator: &str) -> String {
+        format!(
+            "{:02}{4}{:02}{4}{:02}{4}{:02}",
+            self.hours, self.minutes, self.seconds, self.frames, separator
+        )
+    }
+
     pub fn format_fps(&self, fps: usize) -> String {
         format!(
             "{}:{}:{},{}",
@@ -595,6 +1256,8 @@ impl fmt::Display for Time {
     }
 }
 
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TtiBlock {
     #[doc = "0 Subtitle Group Number. 00h-FFh"]
     sgn: u8,
@@ -615,7 +1278,14 @@ pub struct TtiBlock {
     #[doc = "15 Comment Flag"]
     cf: u8,
     #[doc = "16..127 Text Field"]
+    #[cfg_attr(
+        feature = "serde",
+        serde(serialize_with = "serde_bytes::serialize"),
+        serde(deserialize_with = "text_field")
+    )]
     tf: Vec<u8>,
+    #[doc = "Character Code Table the Text Field is encoded with, copied from the GSI"]
+    cct: CharacterCodeTable,
 }
 
 impl TtiBlock {
@@ -628,6 +1298,15 @@ impl TtiBlock {
     pub fn get_extension_block_number(&self) -> u8 {
         self.ebn
     }
+    pub fn get_extension_block_kind(&self) -> ExtensionBlockKind {
+        ExtensionBlockKind::from_ebn(self.ebn)
+    }
+    pub fn is_reserved(&self) -> bool {
+        matches!(
+            self.get_extension_block_kind(),
+            ExtensionBlockKind::Reserved(_)
+        )
+    }
     pub fn get_cumulative_status(&self) -> &CumulativeStatus {
         &self.cs
     }
@@ -646,6 +1325,12 @@ impl TtiBlock {
     pub fn get_comment_flag(&self) -> u8 {
         self.cf
     }
+    pub fn get_text_field(&self) -> &[u8] {
+        &self.tf
+    }
+    pub fn get_character_code_table(&self) -> CharacterCodeTable {
+        self.cct
+    }
 }
 
 impl TtiBlock {
@@ -661,9 +1346,28 @@ impl TtiBlock {
             jc: opt.jc,
             cf: 0,
             tf: TtiBlock::encode_text(txt, opt.dh),
+            cct: CharacterCodeTable::Latin,
         }
     }
 
+    /// Decode a single TTI block whose text is encoded with the given table.
+    pub fn from_bytes(data: &[u8; 128], cct: CharacterCodeTable) -> Result<TtiBlock, ParseError> {
+        let mut tti = parser::parse_tti_block(data)?;
+        tti.cct = cct;
+        Ok(tti)
+    }
+
+    /// The 128 bytes written to file for this block, the encoding is stable across
+    /// versions.
+    ///
+    /// Panics if the text field is not 112 bytes long, use [`TtiBlock::serialize`] to
+    /// get the bytes unchecked.
+    pub fn to_bytes(&self) -> [u8; 128] {
+        let mut bytes = [0x8f; 128];
+        bytes.copy_from_slice(&self.serialize());
+        bytes
+    }
+
     fn encode_text(txt: &str, dh: bool) -> Vec<u8> {
         const TF_LENGTH: usize = 112;
         let text = iso6937::encode(txt);
@@ -689,33 +1393,59 @@ impl TtiBlock {
         res
     }
 
+    /// Decoded text of the block, empty for reserved blocks whose content is undefined.
+    /// Rows end with CR/LF. Teletext spacing attributes (00h-1Fh) between the first
+    /// and the last characters of a row are shown as the space they take on screen,
+    /// the other control codes are left out.
     pub fn get_text(&self) -> String {
-        let mut result = String::from("");
-        let mut first = 0;
-        for i in 0..self.tf.len() {
-            let c = self.tf[i];
-            if match c {
-                0x0..=0x1f => true, //TODO: decode teletext control codes
-                0x20..=0x7f => false,
-                0x80..=0x9f => true, // TODO: decode codes
-                0xa0..=0xff => false,
-            } {
-                if first != i {
-                    result.push_str(&iso6937::decode(&self.tf[first..i]));
-                }
-                if c == 0x8f {
-                    break;
-                } else if c == 0x8a {
-                    result.push_str("\r\n");
+        if self.is_reserved() {
+            return String::new();
+        }
+        let end = self
+            .tf
+            .iter()
+            .position(|&c| c == 0x8f)
+            .unwrap_or(self.tf.len());
+        // Text filling the field up to its end is continued in the next extension block.
+        self.tf[..end]
+            .split(|&c| c == 0x8a)
+            .map(|row| self.row_text(row))
+            .collect::<Vec<_>>()
+            .join("\r\n")
+    }
+
+    fn row_text(&self, row: &[u8]) -> String {
+        let is_control = |c: u8| c < 0x20 || (0x80..0xa0).contains(&c);
+        let visible = |c: &u8| !is_control(*c) && *c != 0x20;
+        let first = row.iter().position(visible).unwrap_or(row.len());
+        let last = row.iter().rposition(visible).unwrap_or(0);
+        let mut text = String::new();
+        let mut rest = row;
+        let mut offset = 0;
+        while !rest.is_empty() {
+            let len = rest
+                .iter()
+                .position(|&c| is_control(c))
+                .unwrap_or(rest.len());
+            if len == 0 {
+                if rest[0] < 0x20 && (first..last).contains(&offset) {
+                    text.push(' ');
                 }
-                first = i + 1;
+                rest = &rest[1..];
+                offset += 1;
+                continue;
             }
+            text.push_str(&self.cct.decode(&rest[..len]));
+            rest = &rest[len..];
+            offset += len;
         }
-        result
+        text
     }
 
+    /// Encoded block as written to file, 128 bytes long unless the text field was
+    /// resized.
     #[allow(clippy::vec_init_then_push)]
-    fn serialize(&self) -> Vec<u8> {
+    pub fn serialize(&self) -> Vec<u8> {
         let mut res = vec![];
         res.push(self.sgn);
         res.push((self.sn & 0xff) as u8);
@@ -3,7 +3,7 @@ use std::str::FromStr;
 use nom::{
     self,
     bytes::streaming::take,
-    combinator::map_res,
+    combinator::{map, map_res},
     error::{ErrorKind, FromExternalError},
     multi::many1,
     number::streaming::{be_u8, le_u16},
@@ -14,7 +14,7 @@ use thiserror::Error;
 
 use super::*;
 
-#[derive(Debug, Error, PartialEq, Eq)]
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
 pub enum ParseError {
     #[error("IoError: {0}")]
     IoError(String),
@@ -32,6 +32,8 @@ pub enum ParseError {
     CharacterCodeTable,
     #[error("Error parsing Cumulative Status")]
     CumulativeStatus,
+    #[error("Unexpected data at offset {offset}, expected a GSI block")]
+    UnexpectedData { offset: usize },
     #[error("Parse error: {message}")]
     NomParsingError { message: String },
     #[error("Unknown error")]
@@ -89,18 +91,342 @@ where
     }
 }
 
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum ParseWarning {
+    #[error("GSI field {field} is padded with NUL bytes instead of spaces")]
+    NulPadding { field: &'static str },
+    #[error("GSI field {field} contains NUL bytes")]
+    InteriorNul { field: &'static str },
+    #[error("Subtitle numbers look big-endian, they have been byte-swapped")]
+    BigEndianSubtitleNumbers,
+    #[error("TTI block {index} uses reserved Extension Block Number {ebn:#04X}")]
+    ReservedExtensionBlock { index: usize, ebn: u8 },
+    #[error("{count} time codes have frames out of range for {declared} fps, the file is probably {suggested} fps")]
+    FrameRateMismatch {
+        declared: usize,
+        count: usize,
+        suggested: usize,
+    },
+    #[error("TTI block {index} skipped: {error}")]
+    SkippedBlock { index: usize, error: ParseError },
+    #[error("Invalid Code Page Number {found:?}, decoded with code page {used}")]
+    CodePageFallback { found: Vec<u8>, used: u16 },
+    #[error("Subtitle numbers were rewritten sequentially ({} subtitles changed)", .changes.len())]
+    Renumbered {
+        #[doc = "Old and new number of every renumbered subtitle, in file order"]
+        changes: Vec<(u16, u16)>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+/// Controls how tolerant the parser is of files that do not follow the specification.
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    #[doc = "Accept recoverable deviations from the specification and report them as warnings"]
+    pub lenient: bool,
+    #[doc = "Byte order of the Subtitle Number field, guessed in lenient mode when unset"]
+    pub sn_endianness: Option<Endianness>,
+    #[doc = "In lenient mode, skip TTI blocks that fail to parse instead of failing"]
+    pub skip_bad_blocks: bool,
+    #[doc = "Code page used in lenient mode when the Code Page Number is invalid, guessed when unset"]
+    pub fallback_code_page: Option<u16>,
+    #[doc = "In lenient mode, rewrite subtitle numbers sequentially in file order"]
+    pub renumber: bool,
+}
+
+struct Warnings {
+    lenient: bool,
+    list: Vec<ParseWarning>,
+}
+
+impl Warnings {
+    fn new(options: &ParseOptions) -> Self {
+        Self {
+            lenient: options.lenient,
+            list: vec![],
+        }
+    }
+
+    fn push(&mut self, warning: ParseWarning) {
+        if self.lenient {
+            self.list.push(warning);
+        }
+    }
+}
+
 pub type IResult<I, O> = nom::IResult<I, O, ParseError>;
 
-fn parse_stl(input: &[u8]) -> IResult<&[u8], Stl> {
-    let (input, (gsi, ttis)) = tuple((parse_gsi_block, many1(parse_tti_block)))(input)?;
+fn parse_stl<'a>(
+    input: &'a [u8],
+    options: &ParseOptions,
+    warnings: &mut Warnings,
+) -> IResult<&'a [u8], Stl> {
+    let (input, gsi) = lenient_gsi_block(input, options, warnings)?;
+    let (input, ttis) = many1(tti_block)(input)?;
+    Ok((input, Stl { gsi, ttis }))
+}
+
+// Blocks have a fixed size, so a corrupt one can be skipped and parsing resumed at
+// the next block boundary.
+fn parse_stl_skipping_bad_blocks<'a>(
+    input: &'a [u8],
+    options: &ParseOptions,
+    warnings: &mut Warnings,
+) -> IResult<&'a [u8], Stl> {
+    let (mut input, gsi) = lenient_gsi_block(input, options, warnings)?;
+    let mut ttis = vec![];
+    let mut index = 0;
+    while !input.is_empty() && !looks_like_gsi(input) {
+        let (block, rest) = input.split_at(input.len().min(128));
+        match tti_block(block) {
+            Ok((_, tti)) => ttis.push(tti),
+            Err(err) => warnings.push(ParseWarning::SkippedBlock {
+                index,
+                error: err.into(),
+            }),
+        }
+        input = rest;
+        index += 1;
+    }
     Ok((input, Stl { gsi, ttis }))
 }
 
 pub fn parse_stl_from_slice(input: &[u8]) -> Result<Stl, ParseError> {
-    let (_, stl) = parse_stl(input)?;
+    let (stl, _) = parse_stl_with_options(input, &ParseOptions::default())?;
     Ok(stl)
 }
 
+/// Parse an STL document, returning the warnings collected in lenient mode.
+pub fn parse_stl_with_options(
+    input: &[u8],
+    options: &ParseOptions,
+) -> Result<(Stl, Vec<ParseWarning>), ParseError> {
+    let mut warnings = Warnings::new(options);
+    let (_, stl) = parse_document(input, options, &mut warnings)?;
+    Ok((stl, warnings.list))
+}
+
+/// Parse a GSI block from the first 1024 bytes of `input`.
+pub fn parse_gsi_block(input: &[u8]) -> Result<GsiBlock, ParseError> {
+    let options = ParseOptions::default();
+    let (_, mut gsi) = gsi_block(input)?;
+    normalize_gsi_padding(&mut gsi, &mut Warnings::new(&options));
+    Ok(gsi)
+}
+
+/// Parse a TTI block from the first 128 bytes of `input`.
+pub fn parse_tti_block(input: &[u8]) -> Result<TtiBlock, ParseError> {
+    if input.len() < 128 {
+        return Err(ParseError::Incomplete);
+    }
+    let (_, tti) = tti_block(input)?;
+    Ok(tti)
+}
+
+/// Parse one STL document from the start of `input`, also returning the bytes
+/// following it. Parsing stops at the first block that is not a valid TTI block,
+/// such as another GSI block, trailing data or a partial block.
+pub fn parse_stl_from_slice_partial(input: &[u8]) -> Result<(Stl, &[u8]), ParseError> {
+    let options = ParseOptions::default();
+    let mut warnings = Warnings::new(&options);
+    // Only whole blocks can belong to the document, a shorter tail is left unconsumed.
+    let whole_blocks = input.len().saturating_sub(1024) / 128;
+    let len = input.len().min(1024 + whole_blocks * 128);
+    let (rest, stl) = parse_document(&input[..len], &options, &mut warnings)?;
+    Ok((stl, &input[len - rest.len()..]))
+}
+
+/// Parse several complete STL documents concatenated in one buffer, each one
+/// starting with its own GSI block whose Total Number of TTI Blocks (TNB) gives where
+/// it ends. Bytes following a document that are not a whole number of blocks starting
+/// with another GSI block are reported as unexpected data.
+pub fn parse_stl_multi(input: &[u8]) -> Result<Vec<Stl>, ParseError> {
+    let options = ParseOptions::default();
+    let mut warnings = Warnings::new(&options);
+    let mut documents = vec![];
+    let mut offset = 0;
+    loop {
+        let remaining = &input[offset..];
+        if offset > 0 && (!remaining.len().is_multiple_of(128) || !looks_like_gsi(remaining)) {
+            return Err(ParseError::UnexpectedData { offset });
+        }
+        let tnb = parse_gsi_block(remaining)?.tnb as usize;
+        let len = remaining.len().min(1024 + tnb * 128);
+        let (rest, stl) = parse_document(&remaining[..len], &options, &mut warnings)?;
+        if !rest.is_empty() {
+            return Err(ParseError::UnexpectedData {
+                offset: offset + len - rest.len(),
+            });
+        }
+        documents.push(stl);
+        offset += len;
+        if offset == input.len() {
+            return Ok(documents);
+        }
+    }
+}
+
+fn parse_document<'a>(
+    input: &'a [u8],
+    options: &ParseOptions,
+    warnings: &mut Warnings,
+) -> Result<(&'a [u8], Stl), ParseError> {
+    let (input, mut stl) = if options.lenient && options.skip_bad_blocks {
+        parse_stl_skipping_bad_blocks(input, options, warnings)?
+    } else {
+        parse_stl(input, options, warnings)?
+    };
+    for tti in stl.ttis.iter_mut() {
+        tti.cct = stl.gsi.cct;
+    }
+    normalize_gsi_padding(&mut stl.gsi, warnings);
+    fix_sn_endianness(&mut stl.ttis, options, warnings);
+    for (index, tti) in stl.ttis.iter().enumerate() {
+        if tti.is_reserved() {
+            warnings.push(ParseWarning::ReservedExtensionBlock {
+                index,
+                ebn: tti.ebn,
+            });
+        }
+    }
+    if warnings.lenient {
+        check_frame_rate(&stl, warnings);
+    }
+    if options.lenient && options.renumber {
+        let changes = renumber_in_file_order(&mut stl.ttis);
+        if !changes.is_empty() {
+            warnings.push(ParseWarning::Renumbered { changes });
+        }
+    }
+    Ok((input, stl))
+}
+
+// Some encoders restart numbering for each subtitle group or leave duplicates after
+// edits. Blocks of an extension chain keep sharing the same, new, number.
+fn renumber_in_file_order(ttis: &mut [TtiBlock]) -> Vec<(u16, u16)> {
+    let mut changes = vec![];
+    let mut next = match ttis.first() {
+        Some(tti) if tti.sn == 0 => 0,
+        _ => 1,
+    };
+    let mut previous: Option<(u16, u8)> = None;
+    for tti in ttis.iter_mut() {
+        let continues_chain = matches!(previous, Some((sn, ebn)) if sn == tti.sn && ebn < 0xf0);
+        previous = Some((tti.sn, tti.ebn));
+        if !continues_chain {
+            if tti.sn != next {
+                changes.push((tti.sn, next));
+            }
+            next = next.wrapping_add(1);
+        }
+        tti.sn = next.wrapping_sub(1);
+    }
+    changes
+}
+
+// Frames at or above the declared rate usually mean the Disk Format Code is wrong.
+fn check_frame_rate(stl: &Stl, warnings: &mut Warnings) {
+    let declared = stl.gsi.dfc.get_fps();
+    let frames = stl
+        .ttis
+        .iter()
+        .flat_map(|tti| [tti.tci.frames, tti.tco.frames])
+        .filter(|frames| *frames as usize >= declared);
+    let count = frames.clone().count();
+    if let Some(max) = frames.max() {
+        let suggested = [25, 30, 50, 60]
+            .into_iter()
+            .find(|fps| *fps > max as usize)
+            .unwrap_or(max as usize + 1);
+        warnings.push(ParseWarning::FrameRateMismatch {
+            declared,
+            count,
+            suggested,
+        });
+    }
+}
+
+// A GSI block is recognised by its Code Page Number digits followed by the Disk
+// Format Code signature.
+fn looks_like_gsi(input: &[u8]) -> bool {
+    input.len() >= 11
+        && input[..3].iter().all(u8::is_ascii_digit)
+        && (&input[3..11] == b"STL25.01" || &input[3..11] == b"STL30.01")
+}
+
+// The Subtitle Number is little-endian, but some legacy tools write it big-endian,
+// giving 256, 512, 768... instead of 1, 2, 3.
+fn fix_sn_endianness(ttis: &mut [TtiBlock], options: &ParseOptions, warnings: &mut Warnings) {
+    let endianness = match options.sn_endianness {
+        Some(endianness) => endianness,
+        None if options.lenient && looks_big_endian(ttis) => {
+            warnings.push(ParseWarning::BigEndianSubtitleNumbers);
+            Endianness::Big
+        }
+        None => Endianness::Little,
+    };
+    if endianness == Endianness::Big {
+        for tti in ttis.iter_mut() {
+            tti.sn = tti.sn.swap_bytes();
+        }
+    }
+}
+
+fn looks_big_endian(ttis: &[TtiBlock]) -> bool {
+    let numbered = ttis.iter().filter(|tti| tti.sn != 0).count();
+    let low_byte_zero = ttis
+        .iter()
+        .filter(|tti| tti.sn != 0 && tti.sn & 0xff == 0)
+        .count();
+    let monotonic_when_swapped = ttis
+        .windows(2)
+        .all(|w| w[0].sn.swap_bytes() <= w[1].sn.swap_bytes());
+    numbered > 0 && low_byte_zero * 2 > numbered && monotonic_when_swapped
+}
+
+// Some encoders pad GSI strings with NUL bytes instead of spaces. Trailing NULs are
+// turned into spaces so they neither leak into the decoded values nor into files
+// written back from them, interior NULs are kept as-is but reported.
+fn normalize_gsi_padding(gsi: &mut GsiBlock, warnings: &mut Warnings) {
+    let fields = [
+        ("LC", &mut gsi.lc),
+        ("OPT", &mut gsi.opt),
+        ("OET", &mut gsi.oet),
+        ("TPT", &mut gsi.tpt),
+        ("TET", &mut gsi.tet),
+        ("TN", &mut gsi.tn),
+        ("TCD", &mut gsi.tcd),
+        ("SLR", &mut gsi.slr),
+        ("CD", &mut gsi.cd),
+        ("RD", &mut gsi.rd),
+        ("RN", &mut gsi.rn),
+        ("TCP", &mut gsi.tcp),
+        ("TCF", &mut gsi.tcf),
+        ("CO", &mut gsi.co),
+        ("PUB", &mut gsi.pub_),
+        ("EN", &mut gsi.en),
+        ("ECD", &mut gsi.ecd),
+        ("UDA", &mut gsi.uda),
+    ];
+    for (field, value) in fields {
+        let content_len = value.trim_end_matches(['\0', ' ']).len();
+        if value[content_len..].contains('\0') {
+            let padding = value[content_len..].chars().count();
+            value.truncate(content_len);
+            value.extend(std::iter::repeat_n(' ', padding));
+            warnings.push(ParseWarning::NulPadding { field });
+        }
+        if value.contains('\0') {
+            warnings.push(ParseWarning::InteriorNul { field });
+        }
+    }
+}
+
 pub fn take_str<'a, C: nom::ToUsize, Error: nom::error::ParseError<&'a [u8]>>(
     count: C,
 ) -> impl Fn(&'a [u8]) -> nom::IResult<&'a [u8], &'a str, Error> {
@@ -119,9 +445,66 @@ pub fn take_str<'a, C: nom::ToUsize, Error: nom::error::ParseError<&'a [u8]>>(
     }
 }
 
-fn parse_gsi_block(input: &[u8]) -> IResult<&[u8], GsiBlock> {
-    let (input, (codepage, dfc, dsc, cct)) = tuple((
-        map_res(take_str(3_u16), u16::from_str),
+fn gsi_block(input: &[u8]) -> IResult<&[u8], GsiBlock> {
+    let (input, codepage) = map_res(take_str(3_u16), u16::from_str)(input)?;
+    gsi_fields(input, codepage)
+}
+
+// In lenient mode a corrupt Code Page Number does not prevent reading the rest of
+// the block, a fallback code page is used to decode the text fields instead.
+fn lenient_gsi_block<'a>(
+    input: &'a [u8],
+    options: &ParseOptions,
+    warnings: &mut Warnings,
+) -> IResult<&'a [u8], GsiBlock> {
+    if !options.lenient {
+        return gsi_block(input);
+    }
+    let (input, cpn) = take(3_u16)(input)?;
+    let codepage = str::from_utf8(cpn)
+        .ok()
+        .and_then(|cpn| u16::from_str(cpn).ok())
+        .filter(|codepage| CodePageNumber::from_u16(*codepage).is_ok());
+    match codepage {
+        Some(codepage) => gsi_fields(input, codepage),
+        None => {
+            let codepage = options
+                .fallback_code_page
+                .unwrap_or_else(|| guess_code_page(input));
+            warnings.push(ParseWarning::CodePageFallback {
+                found: cpn.to_vec(),
+                used: codepage,
+            });
+            gsi_fields(input, codepage)
+        }
+    }
+}
+
+// OEM code pages map every byte, so pick the one where the titles contain the
+// fewest characters that are unlikely to appear in text (box drawing, symbols...).
+fn guess_code_page(input: &[u8]) -> u16 {
+    // Titles span bytes 16..143 of the GSI, the Code Page Number is already consumed.
+    let titles = &input[13.min(input.len())..140.min(input.len())];
+    let unlikely = |codepage: u16| {
+        CodePageDecoder::new(codepage)
+            .and_then(|coding| coding.parse(titles))
+            .map(|text| {
+                text.chars()
+                    .filter(|c| {
+                        !(c.is_alphanumeric() || c.is_whitespace() || c.is_ascii_punctuation())
+                    })
+                    .count()
+            })
+            .unwrap_or(usize::MAX)
+    };
+    [850, 437, 860, 863, 865]
+        .into_iter()
+        .min_by_key(|codepage| unlikely(*codepage))
+        .unwrap_or(850)
+}
+
+fn gsi_fields(input: &[u8], codepage: u16) -> IResult<&[u8], GsiBlock> {
+    let (input, (dfc, dsc, cct)) = tuple((
         map_res(take_str(10 - 3 + 1_u16), DiskFormatCode::parse),
         map_res(be_u8, DisplayStandardCode::parse),
         map_res(take(13 - 12 + 1_u16), CharacterCodeTable::parse),
@@ -150,7 +533,7 @@ fn parse_gsi_block(input: &[u8]) -> IResult<&[u8], GsiBlock> {
             map_res(be_u8, TimeCodeStatus::parse),
         ))(input)?;
 
-    let (input, (tcp, tcf, tnd, dsn, co, pub_, en, ecd, _spare, uda)) = tuple((
+    let (input, (tcp, tcf, tnd, dsn, co, pub_, en, ecd, spare, uda)) = tuple((
         map_res(take(263 - 256 + 1_u16), |data| coding.parse(data)),
         map_res(take(271 - 264 + 1_u16), |data| coding.parse(data)),
         map_res(take_str(1_u16), u8::from_str),
@@ -159,7 +542,7 @@ fn parse_gsi_block(input: &[u8]) -> IResult<&[u8], GsiBlock> {
         map_res(take(308 - 277 + 1_u16), |data| coding.parse(data)),
         map_res(take(340 - 309 + 1_u16), |data| coding.parse(data)),
         map_res(take(372 - 341 + 1_u16), |data| coding.parse(data)),
-        map_res(take(447 - 373 + 1_u16), |data| coding.parse(data)),
+        map(take(447 - 373 + 1_u16), |data: &[u8]| data.to_vec()),
         map_res(take(1023 - 448 + 1_u16), |data| coding.parse(data)),
     ))(input)?;
     Ok((
@@ -194,7 +577,7 @@ fn parse_gsi_block(input: &[u8]) -> IResult<&[u8], GsiBlock> {
             pub_,
             en,
             ecd,
-            _spare,
+            spare,
             uda,
         },
     ))
@@ -205,9 +588,9 @@ fn parse_time(input: &[u8]) -> IResult<&[u8], Time> {
     Ok((input, Time::new(h, m, s, f)))
 }
 
-fn parse_tti_block(input: &[u8]) -> IResult<&[u8], TtiBlock> {
+fn tti_block(input: &[u8]) -> IResult<&[u8], TtiBlock> {
     //Needed to handle the many1 operator, that expects an error when done.
-    if input.is_empty() {
+    if input.is_empty() || looks_like_gsi(input) {
         return Err(nom::Err::Error(nom::error::ParseError::from_error_kind(
             input,
             nom::error::ErrorKind::Eof,
@@ -238,11 +621,13 @@ fn parse_tti_block(input: &[u8]) -> IResult<&[u8], TtiBlock> {
             jc,
             cf,
             tf: tf.to_vec(),
+            cct: CharacterCodeTable::Latin,
         },
     ))
 }
 
 #[cfg(test)]
+#[allow(clippy::useless_vec)]
 mod tests {
     use nom::Needed;
 
@@ -289,11 +674,413 @@ mod tests {
             stl.ttis.get(11).unwrap().get_text()
         );
     }
-    /* TODO
+
     #[test]
-    fn test_parse_tti() {
+    fn test_parse_nul_padded_gsi() {
+        let mut data = std::fs::read("stls/test.stl").unwrap();
+        // Original Program Title "TEST" followed by NULs, with one interior NUL
+        // in the Original Episode Title.
+        data[20..48].fill(0);
+        data[52] = 0;
+
+        let lenient = ParseOptions {
+            lenient: true,
+            ..Default::default()
+        };
+        let (stl, warnings) = parse_stl_with_options(&data, &lenient).expect("Parse stl");
+        assert_eq!(format!("TEST{}", " ".repeat(28)), stl.gsi.opt);
+        assert_eq!(
+            vec![
+                ParseWarning::NulPadding { field: "OPT" },
+                ParseWarning::InteriorNul { field: "OET" }
+            ],
+            warnings
+        );
+        assert_eq!(
+            b"TEST                            ",
+            &stl.gsi.serialize().unwrap()[16..48]
+        );
+
+        let (_, warnings) = parse_stl_with_options(&data, &ParseOptions::default()).unwrap();
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn test_parse_big_endian_sn() {
+        let mut data = std::fs::read("stls/test.stl").unwrap();
+        for block in data[1024..].chunks_mut(128) {
+            block.swap(1, 2);
+        }
+        let numbers = |stl: &Stl| stl.ttis.iter().map(|tti| tti.sn).collect::<Vec<_>>();
+
+        let stl = parse_stl_from_slice(&data).unwrap();
+        assert_eq!(256, stl.ttis[0].sn);
+
+        let lenient = ParseOptions {
+            lenient: true,
+            ..Default::default()
+        };
+        let (stl, warnings) = parse_stl_with_options(&data, &lenient).unwrap();
+        assert_eq!((1..=13).collect::<Vec<u16>>(), numbers(&stl));
+        assert_eq!(vec![ParseWarning::BigEndianSubtitleNumbers], warnings);
+
+        let explicit = ParseOptions {
+            sn_endianness: Some(Endianness::Big),
+            ..Default::default()
+        };
+        let (stl, warnings) = parse_stl_with_options(&data, &explicit).unwrap();
+        assert_eq!((1..=13).collect::<Vec<u16>>(), numbers(&stl));
+        assert!(warnings.is_empty());
+
+        // A conforming file must not be swapped by the heuristic.
+        let data = std::fs::read("stls/test.stl").unwrap();
+        let (stl, warnings) = parse_stl_with_options(&data, &lenient).unwrap();
+        assert_eq!((1..=13).collect::<Vec<u16>>(), numbers(&stl));
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn test_parse_reserved_extension_block() {
+        let mut data = std::fs::read("stls/test.stl").unwrap();
+        data[1024 + 11 * 128 + 3] = 0xf0;
+        let lenient = ParseOptions {
+            lenient: true,
+            ..Default::default()
+        };
+        let (stl, warnings) = parse_stl_with_options(&data, &lenient).unwrap();
+        assert_eq!(13, stl.ttis.len());
+        assert_eq!(
+            vec![ParseWarning::ReservedExtensionBlock {
+                index: 11,
+                ebn: 0xf0
+            }],
+            warnings
+        );
+        let reserved = stl.reserved_blocks().collect::<Vec<_>>();
+        assert_eq!(1, reserved.len());
+        assert_eq!(
+            ExtensionBlockKind::Reserved(0xf0),
+            reserved[0].get_extension_block_kind()
+        );
+        assert_eq!("", reserved[0].get_text());
     }
+
+    #[test]
+    fn test_parse_multi() {
+        let data = std::fs::read("stls/test.stl").unwrap();
+        assert_eq!(1, parse_stl_multi(&data).unwrap().len());
+
+        let concatenated = [data.as_slice(), data.as_slice()].concat();
+        let documents = parse_stl_multi(&concatenated).unwrap();
+        assert_eq!(2, documents.len());
+        assert!(documents.iter().all(|stl| stl.ttis.len() == 13));
+
+        let garbage = [data.as_slice(), &[0xaa; 5], data.as_slice()].concat();
+        assert_eq!(
+            Some(ParseError::UnexpectedData { offset: data.len() }),
+            parse_stl_multi(&garbage).err()
+        );
+        let truncated = [data.as_slice(), &data[..1000]].concat();
+        assert_eq!(
+            Some(ParseError::UnexpectedData { offset: data.len() }),
+            parse_stl_multi(&truncated).err()
+        );
+
+        // With a TNB of 12 the last TTI block is left over, whole but not a GSI block.
+        let mut short = data.clone();
+        short[238..243].copy_from_slice(b"00012");
+        assert_eq!(
+            Some(ParseError::UnexpectedData {
+                offset: 1024 + 12 * 128
+            }),
+            parse_stl_multi(&short).err()
+        );
+        let documents = parse_stl_multi(&[&short[..1024 + 12 * 128], &data].concat()).unwrap();
+        assert_eq!(
+            vec![12, 13],
+            documents
+                .iter()
+                .map(|stl| stl.ttis.len())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_parse_partial() {
+        let data = std::fs::read("stls/test.stl").unwrap();
+        let (stl, rest) = parse_stl_from_slice_partial(&data).unwrap();
+        assert_eq!(13, stl.ttis.len());
+        assert!(rest.is_empty());
+
+        let trailing = [data.as_slice(), &[0xaa; 50]].concat();
+        let (stl, rest) = parse_stl_from_slice_partial(&trailing).unwrap();
+        assert_eq!(13, stl.ttis.len());
+        assert_eq!(&[0xaa; 50], rest);
+
+        let concatenated = [data.as_slice(), data.as_slice()].concat();
+        let (_, rest) = parse_stl_from_slice_partial(&concatenated).unwrap();
+        assert_eq!(data.as_slice(), rest);
+    }
+
+    #[test]
     fn test_parse_gsi() {
+        let data = std::fs::read("stls/test.stl").unwrap();
+        let gsi = parse_gsi_block(&data[..1024]).unwrap();
+        assert_eq!("STL25.01", String::from_utf8(gsi.dfc.serialize()).unwrap());
+        assert_eq!(13, gsi.tnb);
+        assert_eq!(
+            Err(ParseError::Incomplete),
+            parse_gsi_block(&data[..512]).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn test_parse_tti() {
+        let data = std::fs::read("stls/test.stl").unwrap();
+        let tti = parse_tti_block(&data[1024 + 11 * 128..1024 + 12 * 128]).unwrap();
+        assert_eq!(12, tti.sn);
+        assert_eq!("    dans la baie de New York.\r\n", tti.get_text());
+        assert_eq!(
+            Err(ParseError::Incomplete),
+            parse_tti_block(&data[1024..1100]).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn test_tti_bytes_round_trip() {
+        let data = std::fs::read("stls/test.stl").unwrap();
+        for block in data[1024..].chunks(128) {
+            let block: &[u8; 128] = block.try_into().unwrap();
+            let tti = TtiBlock::from_bytes(block, CharacterCodeTable::Latin).unwrap();
+            assert_eq!(block, &tti.to_bytes());
+        }
+
+        // Cyrillic text field: "ДА" in ISO 8859-5
+        let mut block = [0x8f; 128];
+        block[..16].copy_from_slice(&[0, 1, 0, 0xff, 0, 0, 0, 1, 0, 0, 0, 2, 0, 20, 2, 0]);
+        block[16..18].copy_from_slice(&[0xb4, 0xb0]);
+        let tti = TtiBlock::from_bytes(&block, CharacterCodeTable::LatinCyrillic).unwrap();
+        assert_eq!("ДА", tti.get_text());
+        assert_eq!(block, tti.to_bytes());
+    }
+
+    #[test]
+    fn test_frame_rate_mismatch() {
+        let mut data = std::fs::read("stls/test.stl").unwrap();
+        data[1024 + 8] = 27;
+        data[1024 + 128 + 12] = 29;
+        let lenient = ParseOptions {
+            lenient: true,
+            ..Default::default()
+        };
+        let (_, warnings) = parse_stl_with_options(&data, &lenient).unwrap();
+        assert_eq!(
+            vec![ParseWarning::FrameRateMismatch {
+                declared: 25,
+                count: 2,
+                suggested: 30
+            }],
+            warnings
+        );
+
+        let (_, warnings) = parse_stl_with_options(&data, &ParseOptions::default()).unwrap();
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn test_skip_bad_blocks() {
+        let mut data = std::fs::read("stls/test.stl").unwrap();
+        data[1024 + 5 * 128 + 4] = 0x07;
+        // Without skipping, everything after the corrupt block is lost.
+        assert_eq!(5, parse_stl_from_slice(&data).unwrap().ttis.len());
+
+        let options = ParseOptions {
+            lenient: true,
+            skip_bad_blocks: true,
+            ..Default::default()
+        };
+        let (stl, warnings) = parse_stl_with_options(&data, &options).unwrap();
+        assert_eq!(12, stl.ttis.len());
+        assert!(stl.ttis.iter().all(|tti| tti.sn != 6));
+        assert!(matches!(
+            warnings.as_slice(),
+            [ParseWarning::SkippedBlock { index: 5, .. }]
+        ));
+        assert!(warnings[0].to_string().contains("Cumulative Status"));
+    }
+
+    #[test]
+    fn test_code_page_fallback() {
+        let mut data = std::fs::read("stls/test.stl").unwrap();
+        data[..3].fill(0);
+        assert!(parse_stl_from_slice(&data).is_err());
+
+        let lenient = ParseOptions {
+            lenient: true,
+            ..Default::default()
+        };
+        let (stl, warnings) = parse_stl_with_options(&data, &lenient).unwrap();
+        assert_eq!(13, stl.ttis.len());
+        assert!(stl.gsi.opt.starts_with("TEST "));
+        assert_eq!(
+            vec![ParseWarning::CodePageFallback {
+                found: vec![0, 0, 0],
+                used: 850
+            }],
+            warnings
+        );
+
+        let explicit = ParseOptions {
+            lenient: true,
+            fallback_code_page: Some(437),
+            ..Default::default()
+        };
+        let (stl, _) = parse_stl_with_options(&data, &explicit).unwrap();
+        assert!(matches!(stl.gsi.cpn, CodePageNumber::CPN_437));
+    }
+
+    #[test]
+    fn test_renumber() {
+        let mut data = std::fs::read("stls/test.stl").unwrap();
+        // Restart numbering at block 6, and chain blocks 9 and 10 as an extension.
+        for (index, block) in data[1024..].chunks_mut(128).enumerate().skip(6) {
+            block[1] = index as u8 - 5;
+        }
+        data[1024 + 9 * 128 + 1] = 4;
+        data[1024 + 9 * 128 + 3] = 0;
+        data[1024 + 10 * 128 + 1] = 4;
+        let options = ParseOptions {
+            lenient: true,
+            renumber: true,
+            ..Default::default()
+        };
+        let (stl, warnings) = parse_stl_with_options(&data, &options).unwrap();
+        let numbers = stl.ttis.iter().map(|tti| tti.sn).collect::<Vec<_>>();
+        assert_eq!(vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 10, 11, 12], numbers);
+        assert_eq!(
+            vec![ParseWarning::Renumbered {
+                changes: vec![(1, 7), (2, 8), (3, 9), (4, 10), (6, 11), (7, 12)]
+            }],
+            warnings
+        );
+    }
+
+    #[test]
+    fn test_gsi_bytes_round_trip() {
+        let data = std::fs::read("stls/test.stl").unwrap();
+        let gsi = GsiBlock::from_bytes(data[..1024].try_into().unwrap()).unwrap();
+        assert_eq!(&data[..1024], &gsi.to_bytes().unwrap()[..]);
+        assert_eq!(&data[..1024], &gsi.serialize().unwrap()[..]);
+    }
+
+    #[test]
+    #[cfg(feature = "chrono")]
+    fn test_gsi_new_with_dates() {
+        let gsi = GsiBlock::new_with_dates(
+            chrono::NaiveDate::from_ymd_opt(2013, 10, 10).unwrap(),
+            chrono::NaiveDate::from_ymd_opt(2013, 10, 17).unwrap(),
+        );
+        let bytes = gsi.to_bytes().unwrap();
+        assert_eq!(b"131010131017", &bytes[224..236]);
+    }
+
+    #[test]
+    fn test_gsi_new_with_date_codes() {
+        let gsi = GsiBlock::new_with_date_codes("131010", "131017").unwrap();
+        assert_eq!(b"131010131017", &gsi.to_bytes().unwrap()[224..236]);
+        let gsi = GsiBlock::new_with_date_codes("", "").unwrap();
+        assert_eq!(b"            ", &gsi.to_bytes().unwrap()[224..236]);
+        assert!(GsiBlock::new_with_date_codes("2013-10-10", "").is_err());
+    }
+
+    #[test]
+    #[cfg(not(feature = "chrono"))]
+    fn test_gsi_new_without_chrono() {
+        assert_eq!(
+            GsiBlock::new_with_date_codes("", "")
+                .unwrap()
+                .to_bytes()
+                .unwrap(),
+            GsiBlock::new().to_bytes().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_spare_bytes_written_as_spaces() {
+        let mut data = std::fs::read("stls/test.stl").unwrap();
+        data[380..383].copy_from_slice(b"\0ab");
+        let stl = parse_stl_from_slice(&data).unwrap();
+        assert_eq!(b"\0ab", &stl.gsi.get_spare_bytes()[7..10]);
+        assert_eq!([0x20; 75], stl.to_bytes().unwrap()[373..448]);
+    }
+
+    #[test]
+    fn test_gsi_from_profile() {
+        #[cfg(feature = "chrono")]
+        {
+            let date = chrono::NaiveDate::from_ymd_opt(2013, 10, 10).unwrap();
+            assert_eq!(
+                GsiBlock::new_with_dates(date, date).to_bytes().unwrap(),
+                GsiBlock::from_profile_with_dates(&GsiProfile::teletext_25(), date, date)
+                    .to_bytes()
+                    .unwrap()
+            );
+        }
+
+        let profile = GsiProfile {
+            cct: CharacterCodeTable::LatinCyrillic,
+            co: "BGR".to_string(),
+            ..GsiProfile::teletext_level2_25()
+        };
+        let gsi = GsiBlock::from_profile(&profile);
+        assert_eq!(DisplayStandardCode::Level2Teletext, gsi.dsc);
+        assert_eq!(CharacterCodeTable::LatinCyrillic, gsi.cct);
+        assert_eq!("BGR", gsi.co);
+
+        let gsi = GsiBlock::from_profile(&GsiProfile::open_subtitles_30());
+        let bytes = gsi.to_bytes().unwrap();
+        assert_eq!(b"STL30.010", &bytes[3..12]);
+        assert_eq!(b"3723", &bytes[251..255]);
+    }
+
+    #[test]
+    fn test_time_frames() {
+        let time = Time::new(10, 2, 3, 4);
+        assert_eq!(((10 * 60 + 2) * 60 + 3) * 25 + 4, time.to_frames(25));
+        assert_eq!(time, Time::from_frames(time.to_frames(25), 25));
+        assert_eq!(time, Time::from_frames(time.to_frames(30), 30));
+    }
+
+    #[test]
+    fn test_time_millis() {
+        let time = Time::new(0, 0, 6, 8);
+        assert_eq!(6320, time.to_millis(25));
+        assert_eq!(time, Time::from_millis(6320, 25));
+        assert_eq!(time, Time::from_millis(6339, 25));
+        assert_eq!(Time::new(0, 0, 6, 9), Time::from_millis(6341, 25));
+        assert_eq!(Time::new(0, 0, 6, 8), Time::from_millis(6267, 30));
+    }
+
+    #[test]
+    fn test_time_codes() {
+        let time = Time::new(10, 0, 6, 8);
+        assert_eq!("10000608", time.to_time_code());
+        assert_eq!("10:00:06:08", time.to_smpte());
+        assert_eq!(Some(time), Time::from_time_code(&time.to_time_code()));
+    }
+
+    #[test]
+    fn test_clone_eq() {
+        let stl = parse_stl_from_file("stls/test.stl").unwrap();
+        let mut copy = stl.clone();
+        assert_eq!(stl, copy);
+
+        copy.ttis[3].tf[5] = b'x';
+        assert_ne!(stl.ttis[3], copy.ttis[3]);
+        assert_ne!(stl, copy);
+
+        let mut copy = stl.clone();
+        copy.gsi.spare[0] = b'x';
+        assert_ne!(stl.gsi, copy.gsi);
     }
-    */
 }
@@ -0,0 +1,104 @@
+use super::*;
+
+#[derive(Debug, Clone, Default)]
+pub struct SearchOptions {
+    #[doc = "Match letters regardless of their accents, 'e' matching 'é' for instance"]
+    pub ignore_diacritics: bool,
+}
+
+impl Stl {
+    /// Numbers of the displayed subtitles containing `query`, ignoring case. Rows are
+    /// joined with a space.
+    pub fn find(&self, query: &str) -> Vec<u16> {
+        self.find_with_options(query, &SearchOptions::default())
+    }
+
+    pub fn find_with_options(&self, query: &str, options: &SearchOptions) -> Vec<u16> {
+        let normalize = |text: &str| {
+            let text = text.to_lowercase();
+            if options.ignore_diacritics {
+                text.chars().map(remove_diacritic).collect()
+            } else {
+                text
+            }
+        };
+        let query = normalize(query);
+        self.subtitles()
+            .filter(|sub| normalize(&sub.lines.join(" ")).contains(&query))
+            .map(|sub| sub.number)
+            .collect()
+    }
+
+    /// First block of the displayed subtitles on screen at some point between `from`
+    /// and `to` (excluded), in file order. The subtitles starting after the range are
+    /// skipped with a binary search when the document is sorted by time.
+    pub fn cues_between(&self, from: &Time, to: &Time) -> Vec<&TtiBlock> {
+        let mut cues = self
+            .cues()
+            .into_iter()
+            .map(|chain| &self.ttis[chain.start])
+            .collect::<Vec<_>>();
+        if self.is_sorted_by_time() {
+            let end = cues.partition_point(|tti| tti.tci < *to);
+            cues.truncate(end);
+        }
+        cues.retain(|tti| tti.tci < *to && tti.tco > *from);
+        cues
+    }
+}
+
+// Base letter of `c` when ISO/IEC 6937 encodes it as a diacritical mark followed by a
+// letter.
+fn remove_diacritic(c: char) -> char {
+    match CharacterCodeTable::Latin.encode_char(c).as_deref() {
+        Some(&[0xc1..=0xcf, base]) if base.is_ascii_alphabetic() => base as char,
+        _ => c,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find() {
+        let stl = parse_stl_from_file("stls/test.stl").unwrap();
+        assert_eq!(vec![2, 6, 13], stl.find("ellis island"));
+        assert_eq!(vec![3], stl.find("statue de la liberté"));
+        assert!(stl.find("liberte").is_empty());
+        let options = SearchOptions {
+            ignore_diacritics: true,
+        };
+        assert_eq!(vec![3], stl.find_with_options("LIBERTE", &options));
+        // Rows are joined.
+        assert_eq!(vec![2], stl.find("island, îlot"));
+    }
+
+    #[test]
+    fn test_cues_between() {
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        let numbers = |stl: &Stl, from: Time, to: Time| {
+            stl.cues_between(&from, &to)
+                .iter()
+                .map(|tti| tti.sn)
+                .collect::<Vec<_>>()
+        };
+        // Subtitle 3 ends at 12:08 and subtitle 5 starts at 15:20.
+        assert_eq!(
+            vec![4],
+            numbers(&stl, Time::new(10, 0, 12, 8), Time::new(10, 0, 15, 20))
+        );
+        assert_eq!(
+            vec![3, 4, 5],
+            numbers(&stl, Time::new(10, 0, 12, 7), Time::new(10, 0, 15, 21))
+        );
+        assert!(numbers(&stl, Time::new(11, 0, 0, 0), Time::new(12, 0, 0, 0)).is_empty());
+
+        // Unsorted documents are searched through.
+        stl.ttis.swap(0, 12);
+        assert_eq!(
+            vec![13],
+            numbers(&stl, Time::new(10, 0, 46, 0), Time::new(10, 0, 47, 0))
+        );
+    }
+}
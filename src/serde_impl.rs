@@ -0,0 +1,82 @@
+//! Deserialization checks that the blocks can be written back, so that a document
+//! edited by hand cannot fail later when serialized to STL.
+
+use serde::de::Error;
+use serde::{Deserialize, Deserializer};
+
+// GSI text field written on `N` bytes, every character is encoded as one byte.
+pub(crate) fn text<'de, D: Deserializer<'de>, const N: usize>(
+    deserializer: D,
+) -> Result<String, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    if text.chars().count() > N {
+        return Err(D::Error::invalid_length(
+            text.chars().count(),
+            &format!("at most {} characters", N).as_str(),
+        ));
+    }
+    Ok(text)
+}
+
+// GSI number written with `N` digits.
+pub(crate) fn number<'de, D, T, const N: usize>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + Into<u32> + Copy,
+{
+    let number = T::deserialize(deserializer)?;
+    if number.into() >= 10u32.pow(N as u32) {
+        return Err(D::Error::custom(format!(
+            "{} does not fit in {} digits",
+            number.into(),
+            N
+        )));
+    }
+    Ok(number)
+}
+
+pub(crate) fn text_field<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let tf: Vec<u8> = serde_bytes::deserialize(deserializer)?;
+    if tf.len() != 112 {
+        return Err(D::Error::invalid_length(
+            tf.len(),
+            &"a text field of 112 bytes",
+        ));
+    }
+    Ok(tf)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse_stl_from_slice;
+    use crate::{parse_stl_from_file, Stl};
+
+    #[test]
+    fn test_json_round_trip() {
+        let data = std::fs::read("stls/test.stl").unwrap();
+        let stl = parse_stl_from_slice(&data).unwrap();
+        let json = serde_json::to_string(&stl).unwrap();
+        let stl: Stl = serde_json::from_str(&json).unwrap();
+        assert_eq!(data, stl.to_bytes().unwrap());
+    }
+
+    #[test]
+    fn test_json_invalid() {
+        let stl = parse_stl_from_file("stls/test.stl").unwrap();
+        let mut json = serde_json::to_value(&stl).unwrap();
+        json["gsi"]["tng"] = 1000.into();
+        assert!(serde_json::from_value::<Stl>(json).is_err());
+
+        let mut json = serde_json::to_value(&stl).unwrap();
+        json["gsi"]["opt"] = "A title longer than the 32 bytes of the field".into();
+        assert!(serde_json::from_value::<Stl>(json).is_err());
+
+        let mut json = serde_json::to_value(&stl).unwrap();
+        json["ttis"][0]["tf"].as_array_mut().unwrap().pop();
+        assert!(serde_json::from_value::<Stl>(json).is_err());
+
+        let mut json = serde_json::to_value(&stl).unwrap();
+        json["gsi"]["dsc"] = "Level3Teletext".into();
+        assert!(serde_json::from_value::<Stl>(json).is_err());
+    }
+}
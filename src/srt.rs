@@ -0,0 +1,796 @@
+//! SubRip (SRT) import and export.
+
+use thiserror::Error;
+
+use super::*;
+use styled::{Span, COLOURS};
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum SrtError {
+    #[error("Line {0}: expected a timing line such as 00:00:01,000 --> 00:00:02,500")]
+    Timing(usize),
+    #[error("Invalid cues: {0:?}")]
+    Cues(Vec<CueError>),
+    #[error("Cues {0:?} start before 00:00:00:00 once offset")]
+    Underflow(Vec<usize>),
+}
+
+/// Cue of a SubRip document, times in milliseconds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SrtCue {
+    pub start: u64,
+    pub end: u64,
+    #[doc = "Lines separated by new lines, markup included"]
+    pub text: String,
+}
+
+impl Cue {
+    /// Cue of a SubRip cue at `fps`, its times rounded to the nearest frame and its
+    /// text kept as is.
+    pub fn from_srt_cue(cue: &SrtCue, fps: usize, format: TtiFormat) -> Cue {
+        Cue {
+            start: Time::from_millis(cue.start, fps),
+            end: Time::from_millis(cue.end, fps),
+            text: cue.text.clone(),
+            format,
+        }
+    }
+
+    /// SubRip cue of this cue at `fps`, the format left out.
+    pub fn to_srt_cue(&self, fps: usize) -> SrtCue {
+        SrtCue {
+            start: self.start.to_millis(fps),
+            end: self.end.to_millis(fps),
+            text: self.text.clone(),
+        }
+    }
+}
+
+/// Cues of a SubRip document. A byte order mark, CR/LF line endings, missing or
+/// out of sequence cue numbers and a missing blank line at the end are accepted.
+pub fn parse_srt(srt: &str) -> Result<Vec<SrtCue>, SrtError> {
+    let srt = srt.strip_prefix('\u{feff}').unwrap_or(srt);
+    let mut cues = vec![];
+    let mut lines = srt
+        .lines()
+        .map(|line| line.trim_end_matches('\r'))
+        .enumerate();
+    while let Some((mut number, mut line)) = lines.next() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if !line.contains("-->") && line.trim().bytes().all(|c| c.is_ascii_digit()) {
+            (number, line) = lines.next().ok_or(SrtError::Timing(number + 2))?;
+        }
+        let (start, end) = parse_timing(line).ok_or(SrtError::Timing(number + 1))?;
+        let text = lines
+            .by_ref()
+            .map(|(_, line)| line)
+            .take_while(|line| !line.trim().is_empty())
+            .collect::<Vec<_>>()
+            .join("\n");
+        cues.push(SrtCue { start, end, text });
+    }
+    Ok(cues)
+}
+
+fn parse_timing(line: &str) -> Option<(u64, u64)> {
+    let (start, end) = line.split_once("-->")?;
+    // Position settings may follow the time code out.
+    let end = end.split_whitespace().next()?;
+    Some((parse_time(start.trim())?, parse_time(end)?))
+}
+
+// Milliseconds of HH:MM:SS,mmm, a dot being accepted in place of the comma.
+fn parse_time(time: &str) -> Option<u64> {
+    let (hms, millis) = time.split_once([',', '.'])?;
+    let mut fields = hms.split(':').map(|field| field.trim().parse::<u64>().ok());
+    let (h, m, s) = (fields.next()??, fields.next()??, fields.next()??);
+    if fields.next().is_some() || m >= 60 || s >= 60 {
+        return None;
+    }
+    let millis = format!("{:0<3}", millis.trim());
+    let millis = millis.get(..3)?.parse::<u64>().ok()?;
+    Some(((h * 60 + m) * 60 + s) * 1000 + millis)
+}
+
+/// How the markup of the cues is converted, see [`SrtImportOptions`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SrtStyles {
+    #[doc = "Tags written as text"]
+    Keep,
+    #[doc = "Tags removed"]
+    Strip,
+    #[doc = "Italics and underline kept for open subtitling, font colours for teletext as the closest teletext colour, other tags removed"]
+    #[default]
+    Map,
+}
+
+// Text of a cue with its markup converted as set by `styles`. Classes and
+// annotations of the tags (<i.loud>, <v Name>) are ignored, unbalanced tags are
+// removed without affecting the rest of the text.
+pub(crate) fn cue_text(text: &str, styles: SrtStyles, open_subtitling: bool) -> String {
+    if styles == SrtStyles::Keep {
+        return text.to_string();
+    }
+    let map = styles == SrtStyles::Map;
+    let mut result = String::new();
+    // Colours of the open font tags, and the colour in effect on the current row.
+    let mut colours: Vec<u8> = vec![];
+    let mut colour = WHITE;
+    let mut rest = text;
+    loop {
+        let (run, tag) = match rest.find('<') {
+            Some(start) => match rest[start..].find('>') {
+                Some(len) => (&rest[..start], Some(&rest[start + 1..start + len])),
+                None => (rest, None),
+            },
+            None => (rest, None),
+        };
+        for c in run.chars() {
+            if c == '\n' {
+                colour = WHITE;
+            } else {
+                let wanted = colours.last().copied().unwrap_or(WHITE);
+                if wanted != colour {
+                    // The colour code takes the place of a space next to it.
+                    colour = wanted;
+                    if c == ' ' {
+                        result.push(cues::colour(wanted));
+                        continue;
+                    }
+                    if result.ends_with(' ') {
+                        result.pop();
+                    }
+                    result.push(cues::colour(wanted));
+                }
+            }
+            result.push(c);
+        }
+        let Some(tag) = tag else {
+            break;
+        };
+        rest = &rest[run.len() + tag.len() + 2..];
+        if !map {
+            continue;
+        }
+        let tag = tag.trim().to_lowercase();
+        let name = tag.split(['.', ' ']).next().unwrap_or_default();
+        let code = match name {
+            "i" => Some(cues::ITALICS_ON),
+            "/i" => Some(cues::ITALICS_OFF),
+            "u" => Some(cues::UNDERLINE_ON),
+            "/u" => Some(cues::UNDERLINE_OFF),
+            "font" if !open_subtitling => {
+                // Unknown colours keep the colour in effect, to be closed by </font>.
+                let current = colours.last().copied().unwrap_or(WHITE);
+                colours.push(font_colour(&tag).unwrap_or(current));
+                None
+            }
+            "/font" => {
+                colours.pop();
+                None
+            }
+            _ => None,
+        };
+        if let Some(code) = code.filter(|_| open_subtitling) {
+            result.push(code);
+        }
+    }
+    result
+}
+
+const WHITE: u8 = 7;
+
+// Teletext colour of the color attribute of a font tag.
+fn font_colour(tag: &str) -> Option<u8> {
+    let value = tag
+        .split_once("color")?
+        .1
+        .trim_start()
+        .strip_prefix('=')?
+        .trim_start();
+    let value = match value.strip_prefix(['"', '\'']) {
+        Some(quoted) => quoted.split(['"', '\'']).next()?,
+        None => value.split_whitespace().next()?,
+    };
+    styled::teletext_colour(value)
+}
+
+/// How times in milliseconds are rounded to frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FrameRounding {
+    #[default]
+    Nearest,
+    Down,
+    Up,
+}
+
+impl FrameRounding {
+    pub(crate) fn frames(self, millis: u64, fps: usize) -> u32 {
+        let fps = fps as u64;
+        let frames = match self {
+            FrameRounding::Nearest => (millis * fps + 500) / 1000,
+            FrameRounding::Down => millis * fps / 1000,
+            FrameRounding::Up => (millis * fps).div_ceil(1000),
+        };
+        frames as u32
+    }
+}
+
+/// What [`Stl::from_srt_cues`] does with the cues starting before 00:00:00:00 once
+/// moved by a negative offset. Cues ending before it are dropped unless failing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SrtUnderflow {
+    #[default]
+    Fail,
+    Drop,
+    #[doc = "Start them at 00:00:00:00"]
+    Clamp,
+}
+
+/// Text of the parts of a cue split at the maximum duration, see [`SrtImportOptions`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SrtSplitText {
+    #[doc = "Rows shared between the parts in order, a row repeated when there are fewer rows than parts"]
+    #[default]
+    Lines,
+    #[doc = "Whole text in every part"]
+    Repeat,
+}
+
+/// Conversion of SubRip cues, see [`Stl::from_srt_cues`].
+#[derive(Debug, Clone)]
+pub struct SrtImportOptions {
+    #[doc = "GSI block of the document, its frame rate is used for the time codes"]
+    pub gsi: GsiBlock,
+    #[doc = "Justification, vertical position and double height of every subtitle"]
+    pub format: TtiFormat,
+    pub rounding: FrameRounding,
+    #[doc = "Wrap rows longer than the maximum number of characters instead of failing"]
+    pub wrap: bool,
+    pub styles: SrtStyles,
+    #[doc = "Frames added to every time code, after rounding"]
+    pub offset: i64,
+    pub on_underflow: SrtUnderflow,
+    #[doc = "Split cues having more rows than the maximum of the GSI block in time instead of failing"]
+    pub split_rows: bool,
+    #[doc = "Split cues displayed for more frames than this in consecutive parts"]
+    pub max_duration: Option<u32>,
+    pub split_text: SrtSplitText,
+    #[doc = "Frames between the parts of a cue split at the maximum duration"]
+    pub split_gap: u32,
+}
+
+impl SrtImportOptions {
+    /// Time codes in and out of `cue` in frames once rounded and moved by the offset,
+    /// negative before 00:00:00:00.
+    pub fn frames(&self, cue: &SrtCue) -> (i64, i64) {
+        let fps = self.gsi.dfc.get_fps();
+        let frames =
+            |millis| (self.rounding.frames(millis, fps) as i64).saturating_add(self.offset);
+        (frames(cue.start), frames(cue.end))
+    }
+}
+
+impl Default for SrtImportOptions {
+    fn default() -> SrtImportOptions {
+        SrtImportOptions {
+            gsi: GsiBlock::new(),
+            format: TtiFormat {
+                jc: 2,
+                vp: 20,
+                dh: false,
+            },
+            rounding: FrameRounding::Nearest,
+            wrap: true,
+            styles: SrtStyles::Map,
+            offset: 0,
+            on_underflow: SrtUnderflow::Fail,
+            split_rows: false,
+            max_duration: None,
+            split_text: SrtSplitText::Lines,
+            split_gap: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SrtExportOptions {
+    #[doc = "Count time from the Time Code: Start-of-Programme instead of midnight"]
+    pub rebase_to_tcp: bool,
+    #[doc = "Write italics, underline and colours as <i>, <u> and <font> tags"]
+    pub styling: bool,
+}
+
+impl Stl {
+    /// Document made of the cues of a SubRip document, see [`parse_srt`] and
+    /// [`Stl::from_srt_cues`]. Times are rounded to the nearest frame at the frame rate
+    /// of `gsi`.
+    pub fn from_srt(srt: &str, gsi: GsiBlock, format: TtiFormat) -> Result<Stl, SrtError> {
+        let options = SrtImportOptions {
+            gsi,
+            format,
+            ..SrtImportOptions::default()
+        };
+        Stl::from_srt_cues(parse_srt(srt)?, &options)
+    }
+
+    /// Document made of SubRip cues, see [`Stl::from_cues`]. Times are rounded to
+    /// frames at the frame rate of the GSI block as set in `options`, then moved by
+    /// its offset. Italics and underline are kept for open subtitling, other markup is
+    /// removed.
+    ///
+    /// Cues are numbered in the errors by their position in `cues`, dropped ones
+    /// included.
+    pub fn from_srt_cues(
+        cues: impl IntoIterator<Item = SrtCue>,
+        options: &SrtImportOptions,
+    ) -> Result<Stl, SrtError> {
+        let gsi = options.gsi.clone();
+        let fps = gsi.dfc.get_fps();
+        let open_subtitling = gsi.dsc == DisplayStandardCode::OpenSubtitling;
+        // Time codes past the day are left for `from_cues` to report, within the 255
+        // hours of a time code.
+        let last = 256 * 3600 * fps as i64 - 1;
+        let time = |frames: i64| Time::from_frames(frames.clamp(0, last) as u32, fps);
+        let mut positions = vec![];
+        let mut underflows = vec![];
+        let mut converted = vec![];
+        for (position, cue) in cues.into_iter().enumerate() {
+            let (mut start, end) = options.frames(&cue);
+            if start < 0 {
+                underflows.push(position);
+                match options.on_underflow {
+                    SrtUnderflow::Clamp if end > 0 => start = 0,
+                    _ => continue,
+                }
+            }
+            let text = cue_text(&cue.text, options.styles, open_subtitling);
+            let parts = split_duration(text, start, end, &gsi, options)
+                .into_iter()
+                .flat_map(|(text, start, end)| split_rows(text, start, end, &gsi, options));
+            for (text, start, end) in parts {
+                positions.push(position);
+                converted.push(Cue {
+                    start: time(start),
+                    end: time(end),
+                    text,
+                    format: options.format,
+                });
+            }
+        }
+        if options.on_underflow == SrtUnderflow::Fail && !underflows.is_empty() {
+            return Err(SrtError::Underflow(underflows));
+        }
+        let renumber = |errors: Vec<CueError>| {
+            SrtError::Cues(
+                errors
+                    .into_iter()
+                    .map(|error| error.renumber(|cue| positions[cue]))
+                    .collect(),
+            )
+        };
+        if !options.wrap {
+            let errors = cues::long_rows(&converted, gsi.mnc as usize);
+            if !errors.is_empty() {
+                return Err(renumber(errors));
+            }
+        }
+        Stl::from_cues(gsi, converted).map_err(renumber)
+    }
+
+    /// SubRip document of the displayed subtitles, numbered from 1. Comments, subtitle
+    /// zero and subtitles without text are left out.
+    pub fn to_srt(&self, options: &SrtExportOptions) -> String {
+        let fps = self.gsi.dfc.get_fps();
+        let origin = self.time_origin(options.rebase_to_tcp);
+        let mut srt = String::new();
+        let mut number = 0;
+        for chain in self.cues() {
+            let first = &self.ttis[chain.start];
+            let rows = styled::styled_rows(&self.ttis[chain]);
+            if rows.is_empty() {
+                continue;
+            }
+            if number > 0 {
+                srt.push('\n');
+            }
+            number += 1;
+            srt.push_str(&format!(
+                "{}\n{} --> {}\n",
+                number,
+                styled::clock_time(styled::millis(&first.tci, &origin, fps), ','),
+                styled::clock_time(styled::millis(&first.tco, &origin, fps), ','),
+            ));
+            for row in rows {
+                let line = row
+                    .iter()
+                    .map(|span| match options.styling {
+                        true => styled_text(span),
+                        false => span.text.clone(),
+                    })
+                    .collect::<String>();
+                srt.push_str(&line);
+                srt.push('\n');
+            }
+        }
+        srt
+    }
+}
+
+fn styled_text(span: &Span) -> String {
+    let mut text = span.text.clone();
+    if span.underline {
+        text = format!("<u>{}</u>", text);
+    }
+    if span.italic {
+        text = format!("<i>{}</i>", text);
+    }
+    if !span.is_white() {
+        text = format!(
+            "<font color=\"{}\">{}</font>",
+            COLOURS[span.colour as usize].1, text
+        );
+    }
+    text
+}
+
+// Rows `text` is shown on.
+fn rows(text: &str, gsi: &GsiBlock, options: &SrtImportOptions) -> Vec<String> {
+    match options.wrap {
+        true => cues::wrap(text, gsi.mnc as usize),
+        false => text.lines().map(String::from).collect(),
+    }
+}
+
+// Text and time codes of the cues `text` is shown in, split when longer than the
+// maximum duration in parts of equal duration separated by the gap.
+fn split_duration(
+    text: String,
+    start: i64,
+    end: i64,
+    gsi: &GsiBlock,
+    options: &SrtImportOptions,
+) -> Vec<(String, i64, i64)> {
+    let duration = end - start;
+    let Some(max) = options
+        .max_duration
+        .filter(|&max| max > 0 && duration > max as i64)
+    else {
+        return vec![(text, start, end)];
+    };
+    let (max, gap) = (max as i64, options.split_gap as i64);
+    // Fewest parts of at most `max` frames, with their gaps.
+    let parts = (duration + gap + max + gap - 1) / (max + gap);
+    let rows = rows(&text, gsi, options);
+    (0..parts)
+        .map(|part| {
+            let text = match options.split_text {
+                SrtSplitText::Repeat => text.clone(),
+                SrtSplitText::Lines => {
+                    let first = (part * rows.len() as i64 / parts) as usize;
+                    let last = ((part + 1) * rows.len() as i64 / parts) as usize;
+                    rows[first..last.max(first + 1).min(rows.len())].join("\n")
+                }
+            };
+            let at = |part: i64| start + part * (duration + gap) / parts;
+            (text, at(part), at(part + 1) - gap)
+        })
+        .collect()
+}
+
+// Text and time codes of the cues `text` is shown in, split when asked to in parts of
+// at most the maximum number of rows sharing the duration by their number of rows.
+fn split_rows(
+    text: String,
+    start: i64,
+    end: i64,
+    gsi: &GsiBlock,
+    options: &SrtImportOptions,
+) -> Vec<(String, i64, i64)> {
+    let rows = rows(&text, gsi, options);
+    let max_rows = (gsi.mnr as usize).max(1);
+    if !options.split_rows || rows.len() <= max_rows {
+        return vec![(text, start, end)];
+    }
+    let duration = end - start;
+    let at = |row: usize| start + duration * row as i64 / rows.len() as i64;
+    rows.chunks(max_rows)
+        .enumerate()
+        .map(|(part, chunk)| {
+            let first = part * max_rows;
+            (chunk.join("\n"), at(first), at(first + chunk.len()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_srt() {
+        let srt = "\u{feff}1\r\n00:00:01,000 --> 00:00:02,500\r\nHello\r\nworld\r\n\r\n\
+                   7\n00:00:03.25 --> 00:00:04,000 X1:10\n<i>Bye</i>";
+        assert_eq!(
+            vec![
+                SrtCue {
+                    start: 1000,
+                    end: 2500,
+                    text: "Hello\nworld".to_string()
+                },
+                SrtCue {
+                    start: 3250,
+                    end: 4000,
+                    text: "<i>Bye</i>".to_string()
+                },
+            ],
+            parse_srt(srt).unwrap()
+        );
+        assert_eq!(
+            Err(SrtError::Timing(2)),
+            parse_srt("1\n00:00:01 --> 00:00:02,000\nText\n")
+        );
+    }
+
+    #[test]
+    fn test_from_srt() {
+        let format = TtiFormat {
+            jc: 2,
+            vp: 20,
+            dh: false,
+        };
+        let srt = "1\n00:00:01,000 --> 00:00:02,500\n<i>Hello</i> world\n\n\
+                   2\n00:00:03,000 --> 00:00:04,000\nBye\n";
+        let stl = Stl::from_srt(srt, GsiBlock::new(), format).unwrap();
+        assert_eq!(2, stl.gsi.tns);
+        assert_eq!(Time::new(0, 0, 2, 13), stl.ttis[0].tco);
+        assert_eq!("Hello world", stl.ttis[0].get_text());
+
+        let mut gsi = GsiBlock::new();
+        gsi.dsc = DisplayStandardCode::OpenSubtitling;
+        let stl = Stl::from_srt(srt, gsi, format).unwrap();
+        let rows = styled::styled_rows(&stl.ttis[..1]);
+        assert_eq!(2, rows[0].len());
+        assert!(rows[0][0].italic && !rows[0][1].italic);
+
+        let error = Stl::from_srt(
+            &srt.replace("00:00:03", "00:00:02"),
+            GsiBlock::new(),
+            format,
+        );
+        assert_eq!(Err(SrtError::Cues(vec![CueError::Overlap(1, 0)])), error);
+    }
+
+    #[test]
+    fn test_cue_text() {
+        let text = "<i>Hi</i> <font color=\"#00ffff\">you\nand <font color='red'>me</font> \
+                    too</font> <b>all";
+        assert_eq!(text, cue_text(text, SrtStyles::Keep, false));
+        assert_eq!(
+            "Hi you\nand me too all",
+            cue_text(text, SrtStyles::Strip, true)
+        );
+        let (cyan, red, white) = (cues::colour(6), cues::colour(1), cues::colour(7));
+        assert_eq!(
+            format!("Hi{cyan}you\n{cyan}and{red}me{cyan}too{white}all"),
+            cue_text(text, SrtStyles::Map, false)
+        );
+        assert_eq!(
+            format!(
+                "{}Hi{} you\nand me too all",
+                cues::ITALICS_ON,
+                cues::ITALICS_OFF
+            ),
+            cue_text(text, SrtStyles::Map, true)
+        );
+        assert_eq!(
+            format!("a{red}b{white}</i c"),
+            cue_text("a <font color=red>b</font></i c", SrtStyles::Map, false)
+        );
+    }
+
+    #[test]
+    fn test_from_srt_cues() {
+        let cue = |start, end, text: &str| SrtCue {
+            start,
+            end,
+            text: text.to_string(),
+        };
+        let mut options = SrtImportOptions {
+            rounding: FrameRounding::Down,
+            ..SrtImportOptions::default()
+        };
+        let stl = Stl::from_srt_cues(vec![cue(1000, 2599, "Hello")], &options).unwrap();
+        assert_eq!(Time::new(0, 0, 2, 14), stl.ttis[0].tco);
+        options.rounding = FrameRounding::Up;
+        let stl = Stl::from_srt_cues(vec![cue(1001, 2500, "Hello")], &options).unwrap();
+        assert_eq!(Time::new(0, 0, 1, 1), stl.ttis[0].tci);
+
+        let long = "A row far too long to be shown on a single teletext row";
+        let stl = Stl::from_srt_cues(vec![cue(0, 1000, long)], &options).unwrap();
+        assert_eq!(2, stl.subtitles().next().unwrap().lines.len());
+        options.wrap = false;
+        assert_eq!(
+            Err(SrtError::Cues(vec![CueError::RowTooLong(1)])),
+            Stl::from_srt_cues(vec![cue(0, 1000, "Short"), cue(1000, 2000, long)], &options)
+        );
+    }
+
+    #[test]
+    fn test_from_srt_cues_offset() {
+        let cue = |start, end, text: &str| SrtCue {
+            start,
+            end,
+            text: text.to_string(),
+        };
+        let cues = vec![
+            cue(0, 1000, "Gone"),
+            cue(1000, 3000, "Cut"),
+            cue(4000, 5000, "Kept"),
+        ];
+        let mut options = SrtImportOptions {
+            offset: -50,
+            ..SrtImportOptions::default()
+        };
+        assert_eq!((-50, -25), options.frames(&cues[0]));
+        assert_eq!(
+            Err(SrtError::Underflow(vec![0, 1])),
+            Stl::from_srt_cues(cues.clone(), &options)
+        );
+        options.on_underflow = SrtUnderflow::Drop;
+        let stl = Stl::from_srt_cues(cues.clone(), &options).unwrap();
+        assert_eq!(1, stl.ttis.len());
+        assert_eq!(Time::new(0, 0, 2, 0), stl.ttis[0].tci);
+        options.on_underflow = SrtUnderflow::Clamp;
+        let stl = Stl::from_srt_cues(cues.clone(), &options).unwrap();
+        assert_eq!(2, stl.ttis.len());
+        assert_eq!(Time::new(0, 0, 0, 0), stl.ttis[0].tci);
+        assert_eq!(Time::new(0, 0, 1, 0), stl.ttis[0].tco);
+
+        // Errors are numbered by position in the SubRip cues, dropped ones included.
+        let cues = [&cues[..], &[cue(4500, 6000, "Overlap")]].concat();
+        options.on_underflow = SrtUnderflow::Drop;
+        assert_eq!(
+            Err(SrtError::Cues(vec![CueError::Overlap(3, 2)])),
+            Stl::from_srt_cues(cues.clone(), &options)
+        );
+
+        options.offset = 10 * 3600 * 25;
+        let stl = Stl::from_srt_cues(cues[..3].to_vec(), &options).unwrap();
+        assert_eq!(Time::new(10, 0, 0, 0), stl.ttis[0].tci);
+        options.offset = 24 * 3600 * 25;
+        assert_eq!(
+            Err(SrtError::Cues(vec![
+                CueError::InvalidTimeCode(0),
+                CueError::InvalidTimeCode(1),
+                CueError::InvalidTimeCode(2)
+            ])),
+            Stl::from_srt_cues(cues[..3].to_vec(), &options)
+        );
+    }
+
+    #[test]
+    fn test_from_srt_cues_split_rows() {
+        let cue = SrtCue {
+            start: 0,
+            end: 3000,
+            text: "One\nTwo\nThree".to_string(),
+        };
+        let mut options = SrtImportOptions::default();
+        options.gsi.mnr = 2;
+        assert_eq!(
+            Err(SrtError::Cues(vec![CueError::TooManyRows(0)])),
+            Stl::from_srt_cues(vec![cue.clone()], &options)
+        );
+        options.split_rows = true;
+        let stl = Stl::from_srt_cues(vec![cue], &options).unwrap();
+        let subtitles = stl.subtitles().collect::<Vec<_>>();
+        assert_eq!(vec!["One", "Two"], subtitles[0].lines);
+        assert_eq!(vec!["Three"], subtitles[1].lines);
+        assert_eq!(Time::new(0, 0, 2, 0), stl.ttis[0].tco);
+        assert_eq!(Time::new(0, 0, 2, 0), stl.ttis[1].tci);
+        assert_eq!(Time::new(0, 0, 3, 0), stl.ttis[1].tco);
+    }
+
+    #[test]
+    fn test_from_srt_cues_max_duration() {
+        let cue = SrtCue {
+            start: 0,
+            end: 20000,
+            text: "One\nTwo\nThree\nFour".to_string(),
+        };
+        let mut options = SrtImportOptions {
+            max_duration: Some(8 * 25),
+            split_gap: 2,
+            ..SrtImportOptions::default()
+        };
+        let stl = Stl::from_srt_cues(vec![cue.clone()], &options).unwrap();
+        let parts = stl
+            .subtitles()
+            .map(|subtitle| subtitle.lines.join("/"))
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["One", "Two", "Three/Four"], parts);
+        let times = stl
+            .ttis
+            .iter()
+            .map(|tti| (tti.tci.to_frames(25), tti.tco.to_frames(25)))
+            .collect::<Vec<_>>();
+        assert_eq!(vec![(0, 165), (167, 332), (334, 500)], times);
+
+        options.split_text = SrtSplitText::Repeat;
+        options.max_duration = Some(3 * 25);
+        let stl = Stl::from_srt_cues(vec![cue.clone()], &options).unwrap();
+        let subtitles = stl.subtitles().collect::<Vec<_>>();
+        assert_eq!(7, subtitles.len());
+        assert!(subtitles.iter().all(|subtitle| subtitle.lines.len() == 4));
+
+        options.split_text = SrtSplitText::Lines;
+        let stl = Stl::from_srt_cues(vec![cue], &options).unwrap();
+        let parts = stl
+            .subtitles()
+            .map(|subtitle| subtitle.lines.join("/"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec!["One", "One", "Two", "Two", "Three", "Three", "Four"],
+            parts
+        );
+        assert!(stl
+            .ttis
+            .iter()
+            .all(|tti| tti.tco.to_frames(25) - tti.tci.to_frames(25) <= 75));
+    }
+
+    #[test]
+    fn test_srt_cue_conversions() {
+        let format = TtiFormat {
+            jc: 2,
+            vp: 20,
+            dh: false,
+        };
+        let srt = SrtCue {
+            start: 6330,
+            end: 9360,
+            text: "-Ellis Island,\nîlot de larmes".to_string(),
+        };
+        let cue = Cue::from_srt_cue(&srt, 25, format);
+        assert_eq!(Time::new(0, 0, 6, 8), cue.start);
+        assert_eq!(Time::new(0, 0, 9, 9), cue.end);
+        assert_eq!(srt.text, cue.text);
+        assert_eq!(
+            SrtCue {
+                start: 6320,
+                ..srt.clone()
+            },
+            cue.to_srt_cue(25)
+        );
+        assert_eq!(cue, Cue::from_srt_cue(&cue.to_srt_cue(25), 25, format));
+    }
+
+    #[test]
+    fn test_to_srt() {
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        let srt = stl.to_srt(&SrtExportOptions::default());
+        // Subtitle 1 has no text.
+        assert!(srt.starts_with(
+            "1\n10:00:06,320 --> 10:00:09,360\n-Ellis Island,\nîlot de larmes et d'exil,\n\n\
+             2\n10:00:09,560 --> "
+        ));
+        assert_eq!(12, srt.matches(" --> ").count());
+
+        stl.ttis[1].cf = 1;
+        let mut tf = b"\x0d\x0b\x0bHi\x06you\x07there\x0a\x0a".to_vec();
+        tf.resize(112, 0x8f);
+        stl.ttis[2].tf = tf;
+        let options = SrtExportOptions {
+            rebase_to_tcp: true,
+            styling: true,
+        };
+        let srt = stl.to_srt(&options);
+        assert!(srt.starts_with(
+            "1\n00:00:09,560 --> 00:00:12,320\nHi <font color=\"#00ffff\">you </font>there\n"
+        ));
+        assert_eq!(11, srt.matches(" --> ").count());
+    }
+}
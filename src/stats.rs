@@ -0,0 +1,157 @@
+use std::collections::BTreeMap;
+
+use super::*;
+
+/// Figures for the editorial review of a document, see [`Stl::statistics`].
+///
+/// Characters are counted as displayed, after decoding and without control codes.
+/// Durations are in frames at the frame rate of the document.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StlStats {
+    pub subtitle_count: usize,
+    #[doc = "Sum of the subtitle durations"]
+    pub on_air_frames: u32,
+    pub min_chars_per_second: f64,
+    pub average_chars_per_second: f64,
+    pub max_chars_per_second: f64,
+    pub average_chars_per_row: f64,
+    pub max_chars_per_row: usize,
+    #[doc = "Maximum Number of Displayable Characters in any text row of the GSI block"]
+    pub declared_max_chars_per_row: u16,
+    #[doc = "Number of subtitles by number of rows"]
+    pub row_counts: BTreeMap<usize, usize>,
+    #[doc = "Subtitle number and duration of the longest subtitle"]
+    pub longest_cue: Option<(u16, u32)>,
+    #[doc = "Subtitle numbers and frames between the closest subtitles, negative for overlaps"]
+    pub shortest_gap: Option<(u16, u16, i64)>,
+    #[doc = "Subtitle numbers and frames between the subtitles closer than the minimum gap"]
+    pub short_gaps: Vec<(u16, u16, i64)>,
+    #[doc = "Overlapping subtitles as reported by [`Stl::find_overlaps`]"]
+    pub overlaps: Vec<(u16, u16, i64)>,
+    #[doc = "Number of subtitles using each teletext alphanumeric colour code (00h-07h)"]
+    pub colours: [usize; 8],
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct StatsOptions {
+    #[doc = "Gaps between subtitles shorter than this number of frames are listed"]
+    pub min_gap_frames: u32,
+}
+
+impl Stl {
+    pub fn statistics(&self) -> StlStats {
+        self.statistics_with_options(&StatsOptions::default())
+    }
+
+    pub fn statistics_with_options(&self, options: &StatsOptions) -> StlStats {
+        let fps = self.gsi.dfc.get_fps();
+        let mut stats = StlStats {
+            declared_max_chars_per_row: self.gsi.mnc,
+            ..StlStats::default()
+        };
+        let mut min_chars_per_second: Option<f64> = None;
+        let mut chars = 0;
+        let mut timed_chars = 0;
+        let mut rows = 0;
+        let mut seconds = 0.0;
+        for subtitle in self.subtitles() {
+            let duration = subtitle
+                .end
+                .to_frames(fps)
+                .saturating_sub(subtitle.start.to_frames(fps));
+            let row_chars = subtitle
+                .lines
+                .iter()
+                .map(|line| line.chars().count())
+                .collect::<Vec<_>>();
+            let subtitle_chars = row_chars.iter().sum::<usize>();
+
+            stats.subtitle_count += 1;
+            stats.on_air_frames += duration;
+            *stats.row_counts.entry(row_chars.len()).or_default() += 1;
+            stats.max_chars_per_row = row_chars
+                .iter()
+                .copied()
+                .fold(stats.max_chars_per_row, usize::max);
+            if stats
+                .longest_cue
+                .is_none_or(|(_, longest)| duration > longest)
+            {
+                stats.longest_cue = Some((subtitle.number, duration));
+            }
+            if duration > 0 {
+                let cps = subtitle_chars as f64 * fps as f64 / duration as f64;
+                stats.max_chars_per_second = stats.max_chars_per_second.max(cps);
+                min_chars_per_second = Some(min_chars_per_second.map_or(cps, |min| min.min(cps)));
+                timed_chars += subtitle_chars;
+                seconds += duration as f64 / fps as f64;
+            }
+            chars += subtitle_chars;
+            rows += row_chars.len();
+            for (code, count) in stats.colours.iter_mut().enumerate() {
+                let code = code as u8;
+                if subtitle.blocks().iter().any(|tti| tti.tf.contains(&code)) {
+                    *count += 1;
+                }
+            }
+        }
+        stats.min_chars_per_second = min_chars_per_second.unwrap_or_default();
+        if seconds > 0.0 {
+            stats.average_chars_per_second = timed_chars as f64 / seconds;
+        }
+        if rows > 0 {
+            stats.average_chars_per_row = chars as f64 / rows as f64;
+        }
+        let gaps = self
+            .cue_pairs()
+            .into_iter()
+            .map(|(earlier, later)| {
+                let earlier = &self.ttis[earlier.start];
+                let later = &self.ttis[later.start];
+                let gap = later.tci.to_frames(fps) as i64 - earlier.tco.to_frames(fps) as i64;
+                (earlier.sn, later.sn, gap)
+            })
+            .collect::<Vec<_>>();
+        stats.shortest_gap = gaps.iter().copied().min_by_key(|&(_, _, gap)| gap);
+        stats.short_gaps = gaps
+            .into_iter()
+            .filter(|&(_, _, gap)| (0..options.min_gap_frames as i64).contains(&gap))
+            .collect();
+        stats.overlaps = self.find_overlaps();
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_statistics() {
+        let stl = parse_stl_from_file("stls/test.stl").unwrap();
+        let stats = stl.statistics();
+        assert_eq!(13, stats.subtitle_count);
+        assert_eq!(Some((8, 93)), stats.longest_cue);
+        assert_eq!(Some((2, 3, 5)), stats.shortest_gap);
+        // "au pied de la statue de la Liberté." is 35 characters long, 36 bytes in UTF-8.
+        assert_eq!(35, stats.max_chars_per_row);
+        assert_eq!(13, stats.row_counts.values().sum::<usize>());
+        assert_eq!(817, stats.on_air_frames);
+        // 408 characters in 18 rows shown for 817 frames, subtitle 5 showing 23 in 43.
+        let close = |expected: f64, actual: f64| (expected - actual).abs() < 1e-9;
+        assert!(close(408.0 * 25.0 / 817.0, stats.average_chars_per_second));
+        assert!(close(23.0 * 25.0 / 43.0, stats.max_chars_per_second));
+        assert!(close(408.0 / 18.0, stats.average_chars_per_row));
+        // Subtitle 1 has no text.
+        assert_eq!(0.0, stats.min_chars_per_second);
+        assert_eq!(40, stats.declared_max_chars_per_row);
+        assert!(stats.short_gaps.is_empty());
+        assert!(stats.overlaps.is_empty());
+
+        let options = StatsOptions { min_gap_frames: 6 };
+        let stats = stl.statistics_with_options(&options);
+        assert_eq!(7, stats.short_gaps.len());
+        assert_eq!(Some(&(2, 3, 5)), stats.short_gaps.first());
+    }
+}
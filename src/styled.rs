@@ -0,0 +1,253 @@
+use super::*;
+
+/// Teletext alphanumeric colours, indexed by their control code (00h-07h).
+pub(crate) const COLOURS: [(&str, &str); 8] = [
+    ("black", "#000000"),
+    ("red", "#ff0000"),
+    ("lime", "#00ff00"),
+    ("yellow", "#ffff00"),
+    ("blue", "#0000ff"),
+    ("magenta", "#ff00ff"),
+    ("cyan", "#00ffff"),
+    ("white", "#ffffff"),
+];
+
+const WHITE: u8 = 7;
+
+/// Text sharing the same style within a row.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Span {
+    pub text: String,
+    pub italic: bool,
+    pub underline: bool,
+    #[doc = "Teletext colour code, 07h (white) unless set"]
+    pub colour: u8,
+}
+
+impl Span {
+    pub fn is_white(&self) -> bool {
+        self.colour == WHITE
+    }
+}
+
+// Rows of an extension chain with their style, blank rows left out and spaces around
+// the rows trimmed. Teletext spacing attributes (00h-1Fh) take a space cell in the
+// style before them. Colours are reset at the start of each row as in teletext,
+// italics and underline last until turned off.
+pub(crate) fn styled_rows(blocks: &[TtiBlock]) -> Vec<Vec<Span>> {
+    let Some(first) = blocks.first() else {
+        return vec![];
+    };
+    let bytes = blocks
+        .iter()
+        .flat_map(|tti| tti.tf.iter().copied().take_while(|&c| c != 0x8f))
+        .collect::<Vec<_>>();
+    let mut italic = false;
+    let mut underline = false;
+    let mut rows = vec![];
+    for row in bytes.split(|&c| c == 0x8a) {
+        let mut colour = WHITE;
+        let mut spans: Vec<Span> = vec![];
+        let mut rest = row;
+        while !rest.is_empty() {
+            let len = rest
+                .iter()
+                .position(|&c| c < 0x20 || (0x80..0xa0).contains(&c))
+                .unwrap_or(rest.len());
+            if len == 0 {
+                if rest[0] < 0x20 {
+                    push_text(&mut spans, " ", italic, underline, colour);
+                }
+                match rest[0] {
+                    code @ 0x00..=0x07 => colour = code,
+                    0x80 => italic = true,
+                    0x81 => italic = false,
+                    0x82 => underline = true,
+                    0x83 => underline = false,
+                    _ => {}
+                }
+                rest = &rest[1..];
+                continue;
+            }
+            let text = first.cct.decode(&rest[..len]);
+            push_text(&mut spans, &text, italic, underline, colour);
+            rest = &rest[len..];
+        }
+        trim_row(&mut spans);
+        if !spans.is_empty() {
+            rows.push(spans);
+        }
+    }
+    rows
+}
+
+fn push_text(spans: &mut Vec<Span>, text: &str, italic: bool, underline: bool, colour: u8) {
+    match spans.last_mut() {
+        Some(span) if (span.italic, span.underline, span.colour) == (italic, underline, colour) => {
+            span.text.push_str(text)
+        }
+        _ => spans.push(Span {
+            text: text.to_string(),
+            italic,
+            underline,
+            colour,
+        }),
+    }
+}
+
+fn trim_row(spans: &mut Vec<Span>) {
+    while spans
+        .first()
+        .is_some_and(|span| span.text.trim().is_empty())
+    {
+        spans.remove(0);
+    }
+    while spans.last().is_some_and(|span| span.text.trim().is_empty()) {
+        spans.pop();
+    }
+    if let Some(span) = spans.first_mut() {
+        span.text = span.text.trim_start().to_string();
+    }
+    if let Some(span) = spans.last_mut() {
+        span.text = span.text.trim_end().to_string();
+    }
+}
+
+// Teletext colour code closest to a CSS colour as found in TTML and SRT, `None` for
+// transparent or unknown colours.
+pub(crate) fn teletext_colour(colour: &str) -> Option<u8> {
+    let colour = colour.trim();
+    let (r, g, b, a) = if let Some(hex) = colour.strip_prefix('#') {
+        let channel = |index: usize| u8::from_str_radix(hex.get(index..index + 2)?, 16).ok();
+        match hex.len() {
+            6 => (channel(0)?, channel(2)?, channel(4)?, 255),
+            8 => (channel(0)?, channel(2)?, channel(4)?, channel(6)?),
+            _ => return None,
+        }
+    } else if let Some(arguments) = colour
+        .strip_prefix("rgba(")
+        .or_else(|| colour.strip_prefix("rgb("))
+    {
+        let channels = arguments
+            .strip_suffix(')')?
+            .split(',')
+            .map(|channel| channel.trim().parse::<u8>().ok())
+            .collect::<Option<Vec<_>>>()?;
+        match channels[..] {
+            [r, g, b] => (r, g, b, 255),
+            [r, g, b, a] => (r, g, b, a),
+            _ => return None,
+        }
+    } else {
+        match colour {
+            "black" | "navy" | "maroon" | "green" | "purple" | "teal" | "olive" => (0, 0, 0, 255),
+            "red" => (255, 0, 0, 255),
+            "lime" => (0, 255, 0, 255),
+            "yellow" => (255, 255, 0, 255),
+            "blue" => (0, 0, 255, 255),
+            "magenta" | "fuchsia" => (255, 0, 255, 255),
+            "cyan" | "aqua" => (0, 255, 255, 255),
+            "white" | "silver" | "gray" => (255, 255, 255, 255),
+            _ => return None,
+        }
+    };
+    if a == 0 {
+        return None;
+    }
+    Some((r >= 128) as u8 | ((g >= 128) as u8) << 1 | ((b >= 128) as u8) << 2)
+}
+
+impl Stl {
+    // Time the exported times count from: the Time Code: Start-of-Programme when
+    // `rebase_to_tcp` is set and the TCP can be read, midnight otherwise.
+    pub(crate) fn time_origin(&self, rebase_to_tcp: bool) -> Time {
+        let fps = self.gsi.dfc.get_fps();
+        match rebase_to_tcp {
+            true => Time::from_time_code(&self.gsi.tcp).unwrap_or(Time::from_frames(0, fps)),
+            false => Time::from_frames(0, fps),
+        }
+    }
+}
+
+// Milliseconds of a time code at `fps`, counted from `origin`.
+pub(crate) fn millis(time: &Time, origin: &Time, fps: usize) -> u64 {
+    let frames = time.to_frames(fps).saturating_sub(origin.to_frames(fps));
+    frames as u64 * 1000 / fps as u64
+}
+
+// Clock time HH:MM:SS followed by the milliseconds after `separator`, as written by
+// SubRip, WebVTT and the media times of EBU-TT-D.
+pub(crate) fn clock_time(millis: u64, separator: char) -> String {
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        separator,
+        millis % 1000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_styled_rows() {
+        let stl = parse_stl_from_file("stls/test.stl").unwrap();
+        let rows = styled_rows(&stl.ttis[1..2]);
+        assert_eq!(2, rows.len());
+        assert_eq!(
+            vec![Span {
+                text: "-Ellis Island,".to_string(),
+                italic: false,
+                underline: false,
+                colour: 6,
+            }],
+            rows[0]
+        );
+
+        let mut tti = stl.ttis[1].clone();
+        let mut tf = b"Plain \x80italic\x8a\x01red\x81 plain".to_vec();
+        tf.resize(112, 0x8f);
+        tti.tf = tf;
+        let rows = styled_rows(&[tti]);
+        let styles = rows
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|span| (span.text.as_str(), span.italic, span.colour))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                vec![("Plain ", false, 7), ("italic", true, 7)],
+                vec![("red", true, 1), (" plain", false, 1)],
+            ],
+            styles
+        );
+
+        // Words separated by colour changes only.
+        let mut tti = stl.ttis[1].clone();
+        let mut tf = b"\x0d\x0b\x0bHi\x06you\x07there\x0a\x0a".to_vec();
+        tf.resize(112, 0x8f);
+        tti.tf = tf;
+        let rows = styled_rows(&[tti]);
+        let texts = rows[0]
+            .iter()
+            .map(|span| (span.text.as_str(), span.colour))
+            .collect::<Vec<_>>();
+        assert_eq!(vec![("Hi ", 7), ("you ", 6), ("there", 7)], texts);
+    }
+
+    #[test]
+    fn test_teletext_colour() {
+        assert_eq!(Some(3), teletext_colour("#FFFF00"));
+        assert_eq!(Some(6), teletext_colour("cyan"));
+        assert_eq!(Some(1), teletext_colour("rgb(200, 10, 10)"));
+        assert_eq!(Some(7), teletext_colour("#ffffffff"));
+        assert_eq!(None, teletext_colour("#ffffff00"));
+        assert_eq!(None, teletext_colour("transparent"));
+    }
+}
@@ -0,0 +1,351 @@
+use super::*;
+
+/// A subtitle as displayed, made of the TTI blocks of its extension chain.
+#[derive(Debug)]
+pub struct Subtitle<'a> {
+    #[doc = "Subtitle Number"]
+    pub number: u16,
+    #[doc = "Subtitle Group Number"]
+    pub group: u8,
+    pub start: &'a Time,
+    pub end: &'a Time,
+    #[doc = "Text rows, without control codes and surrounding spaces"]
+    pub lines: Vec<String>,
+    pub justification: Justification,
+    pub vertical_position: u8,
+    pub is_double_height: bool,
+    blocks: Vec<&'a TtiBlock>,
+    reserved_blocks: Vec<&'a TtiBlock>,
+}
+
+impl<'a> Subtitle<'a> {
+    fn new(blocks: Vec<&'a TtiBlock>, reserved_blocks: Vec<&'a TtiBlock>) -> Subtitle<'a> {
+        let first = blocks[0];
+        let text = blocks.iter().map(|tti| tti.get_text()).collect::<String>();
+        Subtitle {
+            number: first.sn,
+            group: first.sgn,
+            start: &first.tci,
+            end: &first.tco,
+            lines: text
+                .split("\r\n")
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(String::from)
+                .collect(),
+            justification: Justification::from_jc(first.jc),
+            vertical_position: first.vp,
+            is_double_height: blocks.iter().any(|tti| tti.tf.contains(&0x0d)),
+            blocks,
+            reserved_blocks,
+        }
+    }
+
+    /// Blocks of the extension chain, in file order.
+    pub fn blocks(&self) -> &[&'a TtiBlock] {
+        &self.blocks
+    }
+
+    /// Blocks using a reserved Extension Block Number found in the chain, they hold no
+    /// text.
+    pub fn reserved_blocks(&self) -> &[&'a TtiBlock] {
+        &self.reserved_blocks
+    }
+}
+
+/// Extension chain as found in the file, see [`Stl::chains`].
+#[derive(Debug, Clone, Copy)]
+pub struct Chain<'a> {
+    #[doc = "Position of the first block in the TTI blocks of the document"]
+    pub index: usize,
+    blocks: &'a [TtiBlock],
+}
+
+/// Reason for an extension chain not to be well formed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChainDefect {
+    #[doc = "Extension Block Number out of sequence"]
+    ExtensionOrder { expected: u8, actual: u8 },
+    #[doc = "Last block of the chain not numbered FFh"]
+    Unterminated,
+}
+
+impl<'a> Chain<'a> {
+    /// Blocks of the chain, in file order.
+    pub fn blocks(&self) -> &'a [TtiBlock] {
+        self.blocks
+    }
+
+    /// Subtitle Number of the first block.
+    pub fn number(&self) -> u16 {
+        self.blocks[0].sn
+    }
+
+    /// Text of the whole chain, rows separated by CR/LF.
+    pub fn text(&self) -> String {
+        self.blocks.iter().map(TtiBlock::get_text).collect()
+    }
+
+    /// Defects found, with the position of the block in the chain.
+    pub fn defects(&self) -> Vec<(usize, ChainDefect)> {
+        let mut defects = vec![];
+        let mut expected = 0;
+        for (index, tti) in self.blocks.iter().enumerate() {
+            match tti.get_extension_block_kind() {
+                ExtensionBlockKind::Extension(ebn) if ebn != expected => {
+                    defects.push((
+                        index,
+                        ChainDefect::ExtensionOrder {
+                            expected,
+                            actual: ebn,
+                        },
+                    ));
+                }
+                _ => {}
+            }
+            if index + 1 == self.blocks.len()
+                && tti.get_extension_block_kind() != ExtensionBlockKind::Last
+            {
+                defects.push((index, ChainDefect::Unterminated));
+            }
+            expected = tti.ebn.wrapping_add(1);
+        }
+        defects
+    }
+
+    /// Numbered from 00h without gaps and terminated by a block numbered FFh.
+    pub fn is_well_formed(&self) -> bool {
+        self.defects().is_empty()
+    }
+}
+
+// Extension chains of subtitles and comments, user data and reserved blocks are left
+// out.
+pub(crate) fn chains(ttis: &[TtiBlock]) -> Vec<Chain<'_>> {
+    edit::chains(ttis)
+        .into_iter()
+        .filter(|chain| edit::is_subtitle_block(&ttis[chain.start]))
+        .map(|chain| Chain {
+            index: chain.start,
+            blocks: &ttis[chain],
+        })
+        .collect()
+}
+
+/// Comment block (Comment Flag set) left by an editor, see [`Stl::comments`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comment {
+    #[doc = "Subtitle Number"]
+    pub number: u16,
+    pub start: Time,
+    pub end: Time,
+    #[doc = "Text of the whole extension chain, rows separated by CR/LF"]
+    pub text: String,
+}
+
+/// Iterator returned by [`Stl::subtitles`].
+#[derive(Debug, Clone)]
+pub struct Subtitles<'a> {
+    ttis: &'a [TtiBlock],
+}
+
+impl<'a> Iterator for Subtitles<'a> {
+    type Item = Subtitle<'a>;
+
+    fn next(&mut self) -> Option<Subtitle<'a>> {
+        while !self.ttis.is_empty() {
+            let sn = self.ttis[0].sn;
+            let mut len = 0;
+            let mut blocks = vec![];
+            let mut reserved_blocks = vec![];
+            for tti in self.ttis.iter().take_while(|tti| tti.sn == sn) {
+                len += 1;
+                match tti.get_extension_block_kind() {
+                    ExtensionBlockKind::Extension(_) => blocks.push(tti),
+                    ExtensionBlockKind::Reserved(_) => reserved_blocks.push(tti),
+                    ExtensionBlockKind::UserData => {
+                        // User data is never part of a chain, it is skipped on its own.
+                        if len > 1 {
+                            len -= 1;
+                        }
+                        break;
+                    }
+                    ExtensionBlockKind::Last => {
+                        blocks.push(tti);
+                        break;
+                    }
+                }
+            }
+            self.ttis = &self.ttis[len..];
+            // Comments and subtitle zero are not displayed.
+            if !blocks.is_empty() && sn != 0 && blocks[0].cf == 0 {
+                return Some(Subtitle::new(blocks, reserved_blocks));
+            }
+        }
+        None
+    }
+}
+
+impl std::iter::FusedIterator for Subtitles<'_> {}
+
+impl Stl {
+    /// Displayable subtitles in file order, comments, user data and subtitle zero
+    /// are skipped.
+    pub fn subtitles(&self) -> Subtitles<'_> {
+        Subtitles { ttis: &self.ttis }
+    }
+
+    /// Extension chains of the subtitles, comments and subtitle zero in file order,
+    /// whether well formed or not. Consecutive blocks are chained as long as they share
+    /// a Subtitle Number and are not numbered FFh.
+    pub fn chains(&self) -> Vec<Chain<'_>> {
+        chains(&self.ttis)
+    }
+
+    /// Comments in file order, their extension chains joined.
+    pub fn comments(&self) -> Vec<Comment> {
+        edit::chains(&self.ttis)
+            .into_iter()
+            .filter(|chain| {
+                let first = &self.ttis[chain.start];
+                first.cf != 0 && edit::is_subtitle_block(first)
+            })
+            .map(|chain| {
+                let first = &self.ttis[chain.start];
+                Comment {
+                    number: first.sn,
+                    start: first.tci,
+                    end: first.tco,
+                    text: self.ttis[chain].iter().map(TtiBlock::get_text).collect(),
+                }
+            })
+            .collect()
+    }
+
+    /// Time code in of the earliest subtitle, `None` without subtitles.
+    pub fn first_cue_time(&self) -> Option<&Time> {
+        self.subtitles().map(|sub| sub.start).min()
+    }
+
+    /// Time code out of the latest subtitle, `None` without subtitles.
+    pub fn last_cue_out_time(&self) -> Option<&Time> {
+        self.subtitles().map(|sub| sub.end).max()
+    }
+
+    /// Frames from the first time code in to the last time code out.
+    pub fn duration_frames(&self) -> Option<u32> {
+        let fps = self.gsi.dfc.get_fps();
+        let first = self.first_cue_time()?.to_frames(fps);
+        let last = self.last_cue_out_time()?.to_frames(fps);
+        Some(last.saturating_sub(first))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subtitles() {
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        let subtitles = stl.subtitles().collect::<Vec<_>>();
+        assert_eq!(13, subtitles.len());
+        let subtitle = &subtitles[1];
+        assert_eq!(2, subtitle.number);
+        assert_eq!(&Time::new(10, 0, 6, 8), subtitle.start);
+        assert_eq!(&Time::new(10, 0, 9, 9), subtitle.end);
+        assert_eq!(
+            vec!["-Ellis Island,", "îlot de larmes et d'exil,"],
+            subtitle.lines
+        );
+        assert_eq!(Justification::Centered, subtitle.justification);
+        assert_eq!(20, subtitle.vertical_position);
+        assert!(subtitle.is_double_height);
+
+        // Subtitle 3 continued in an extension block holding the text of subtitle 4,
+        // subtitle 5 turned into a comment, user data and a reserved block in the chain
+        // of subtitle 7.
+        stl.ttis[2].ebn = 0;
+        stl.ttis[3].sn = 3;
+        stl.ttis[4].cf = 1;
+        stl.ttis[5].ebn = 0xfe;
+        stl.ttis[6].ebn = 0;
+        stl.ttis[7].ebn = 0xf0;
+        stl.ttis[7].sn = 7;
+        stl.ttis[0].sn = 0;
+        let subtitles = stl.subtitles().collect::<Vec<_>>();
+        let numbers = subtitles.iter().map(|sub| sub.number).collect::<Vec<_>>();
+        assert_eq!(vec![2, 3, 7, 9, 10, 11, 12, 13], numbers);
+        assert_eq!(
+            vec![
+                "au pied de la statue de la Liberté.",
+                "La porte de l'Amérique."
+            ],
+            subtitles[1].lines
+        );
+        assert_eq!(2, subtitles[1].blocks().len());
+        assert_eq!(1, subtitles[2].blocks().len());
+        assert_eq!(1, subtitles[2].reserved_blocks().len());
+    }
+
+    #[test]
+    fn test_duration() {
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        assert_eq!(Some(&Time::new(10, 0, 0, 0)), stl.first_cue_time());
+        assert_eq!(Some(&Time::new(10, 0, 48, 2)), stl.last_cue_out_time());
+        assert_eq!(Some(48 * 25 + 2), stl.duration_frames());
+
+        // Subtitle zero and comments are not displayed.
+        stl.ttis[0].sn = 0;
+        stl.ttis[12].cf = 1;
+        assert_eq!(Some(&Time::new(10, 0, 6, 8)), stl.first_cue_time());
+        assert_eq!(Some(&Time::new(10, 0, 42, 11)), stl.last_cue_out_time());
+
+        stl.ttis.clear();
+        assert_eq!(None, stl.first_cue_time());
+        assert_eq!(None, stl.duration_frames());
+    }
+
+    #[test]
+    fn test_comments() {
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        assert!(stl.comments().is_empty());
+
+        stl.ttis[4].cf = 1;
+        let comments = stl.comments();
+        assert_eq!(1, comments.len());
+        assert_eq!(5, comments[0].number);
+        assert_eq!(Time::new(10, 0, 15, 20), comments[0].start);
+        assert_eq!(stl.ttis[4].get_text(), comments[0].text);
+    }
+
+    #[test]
+    fn test_chains() {
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        let chains = stl.chains();
+        assert_eq!(13, chains.len());
+        assert!(chains.iter().all(Chain::is_well_formed));
+        assert_eq!(stl.ttis[1].get_text(), chains[1].text());
+
+        // Subtitle 2 split in blocks 00h and 02h, with no last block.
+        let mut second = stl.ttis[1].clone();
+        second.ebn = 0;
+        stl.ttis[1].ebn = 2;
+        stl.ttis.insert(1, second);
+        let chains = stl.chains();
+        assert_eq!((1, 2), (chains[1].index, chains[1].blocks().len()));
+        assert_eq!(
+            vec![
+                (
+                    1,
+                    ChainDefect::ExtensionOrder {
+                        expected: 1,
+                        actual: 2
+                    }
+                ),
+                (1, ChainDefect::Unterminated)
+            ],
+            chains[1].defects()
+        );
+    }
+}
@@ -0,0 +1,388 @@
+use std::ops::Range;
+
+use super::*;
+
+/// How [`Stl::fix_overlaps`] makes room between two subtitles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverlapStrategy {
+    #[doc = "Move the time code out of the earlier subtitle"]
+    TrimEarlier,
+    #[doc = "Move the time code in of the later subtitle"]
+    TrimLater,
+    #[doc = "Move both by half the overlap"]
+    Split,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TimingIssue {
+    #[doc = "Subtitle displayed for less than the minimum duration"]
+    TooShort { sn: u16, frames: u32 },
+    #[doc = "Gap to the next subtitle smaller than the minimum, negative for overlaps"]
+    GapTooSmall { sn: u16, next: u16, frames: i64 },
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TimingReport {
+    pub issues: Vec<TimingIssue>,
+}
+
+impl TimingReport {
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Outcome of [`Stl::fix_overlaps`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OverlapReport {
+    #[doc = "Number of subtitle pairs whose time codes were moved"]
+    pub changed: usize,
+    #[doc = "Pairs still closer than the minimum gap, as `GapTooSmall` issues"]
+    pub unfixed: Vec<TimingIssue>,
+}
+
+impl Stl {
+    /// Subtitles displayed for less than `min_duration` frames or followed by another
+    /// one less than `min_gap` frames later.
+    pub fn check_timing(&self, min_duration: u32, min_gap: u32) -> TimingReport {
+        self.plan_timing(min_duration, min_gap, false).1
+    }
+
+    /// Move the time codes out to fix the issues of [`Stl::check_timing`], extending
+    /// short subtitles as far as the next one allows and shortening the ones ending too
+    /// close to the next. The issues that could not be fixed are returned.
+    pub fn enforce_timing(&mut self, min_duration: u32, min_gap: u32) -> TimingReport {
+        let fps = self.gsi.dfc.get_fps();
+        let (changes, report) = self.plan_timing(min_duration, min_gap, true);
+        for (chain, tco) in changes {
+            let tco = Time::from_frames(tco, fps);
+            for tti in &mut self.ttis[chain] {
+                tti.tco = tco;
+            }
+        }
+        report
+    }
+
+    /// Subtract the time code of start of programme (TCP) from every time code, TCP
+    /// becoming zero. Nothing is changed if a subtitle starts before it.
+    pub fn rebase_to_tcp(&mut self) -> Result<(), EditError> {
+        let fps = self.gsi.dfc.get_fps();
+        let tcp = Time::from_time_code(&self.gsi.tcp).ok_or(EditError::GsiTimeCode("TCP"))?;
+        self.shift_time_codes(-(tcp.to_frames(fps) as i64))?;
+        self.gsi.tcp = "00000000".to_string();
+        Ok(())
+    }
+
+    /// Add `new_tcp` to every time code of a document starting at zero and store it as
+    /// the time code of start of programme.
+    pub fn rebase_from_zero(&mut self, new_tcp: Time) -> Result<(), EditError> {
+        let fps = self.gsi.dfc.get_fps();
+        self.shift_time_codes(new_tcp.to_frames(fps) as i64)?;
+        self.gsi.tcp = new_tcp.to_time_code();
+        Ok(())
+    }
+
+    // Move the time codes of the subtitles and TCF by `offset` frames, within a day.
+    fn shift_time_codes(&mut self, offset: i64) -> Result<(), EditError> {
+        let fps = self.gsi.dfc.get_fps();
+        let day = 24 * 3600 * fps as i64;
+        let shift = |time: &Time| {
+            let frames = time.to_frames(fps) as i64 + offset;
+            (0..day)
+                .contains(&frames)
+                .then(|| Time::from_frames(frames as u32, fps))
+        };
+        let tcf = Time::from_time_code(&self.gsi.tcf)
+            .and_then(|tcf| shift(&tcf))
+            .ok_or(EditError::GsiTimeCode("TCF"))?;
+        let mut shifted = vec![];
+        for (index, tti) in self.ttis.iter().enumerate() {
+            if !edit::is_subtitle_block(tti) {
+                continue;
+            }
+            match (shift(&tti.tci), shift(&tti.tco)) {
+                (Some(tci), Some(tco)) => shifted.push((index, tci, tco)),
+                _ => return Err(EditError::TimeCodeOutOfRange(tti.sn)),
+            }
+        }
+        for (index, tci, tco) in shifted {
+            self.ttis[index].tci = tci;
+            self.ttis[index].tco = tco;
+        }
+        self.gsi.tcf = tcf.to_time_code();
+        Ok(())
+    }
+
+    // New time codes out of the chains, in frames, and the issues left.
+    fn plan_timing(
+        &self,
+        min_duration: u32,
+        min_gap: u32,
+        fix: bool,
+    ) -> (Vec<(Range<usize>, u32)>, TimingReport) {
+        let fps = self.gsi.dfc.get_fps();
+        let cues = self.cues();
+        let mut changes = vec![];
+        let mut report = TimingReport::default();
+        for (index, cue) in cues.iter().enumerate() {
+            let first = &self.ttis[cue.start];
+            let tci = first.tci.to_frames(fps);
+            let mut tco = first.tco.to_frames(fps);
+            let next = cues
+                .get(index + 1)
+                .map(|next| &self.ttis[next.start])
+                .filter(|next| !is_in_set_continuation(next));
+            if fix {
+                let limit = next.map_or(u32::MAX, |next| {
+                    next.tci.to_frames(fps).saturating_sub(min_gap)
+                });
+                let target = tco.max(tci + min_duration).min(limit);
+                if target > tci && target != tco {
+                    tco = target;
+                    changes.push((cue.clone(), tco));
+                }
+            }
+            if tco.saturating_sub(tci) < min_duration {
+                report.issues.push(TimingIssue::TooShort {
+                    sn: first.sn,
+                    frames: tco.saturating_sub(tci),
+                });
+            }
+            if let Some(next) = next {
+                let gap = next.tci.to_frames(fps) as i64 - tco as i64;
+                if gap < min_gap as i64 {
+                    report.issues.push(TimingIssue::GapTooSmall {
+                        sn: first.sn,
+                        next: next.sn,
+                        frames: gap,
+                    });
+                }
+            }
+        }
+        (changes, report)
+    }
+
+    /// Consecutive subtitles displayed at the same time, as the numbers of the earlier
+    /// and later subtitles and the overlap in frames. Subtitles of a cumulative set are
+    /// meant to overlap and are not reported.
+    pub fn find_overlaps(&self) -> Vec<(u16, u16, i64)> {
+        let fps = self.gsi.dfc.get_fps();
+        self.cue_pairs()
+            .into_iter()
+            .filter_map(|(earlier, later)| {
+                let earlier = &self.ttis[earlier.start];
+                let later = &self.ttis[later.start];
+                let overlap = earlier.tco.to_frames(fps) as i64 - later.tci.to_frames(fps) as i64;
+                (overlap > 0).then_some((earlier.sn, later.sn, overlap))
+            })
+            .collect()
+    }
+
+    /// Remove the overlaps reported by [`Stl::find_overlaps`], leaving at least
+    /// `min_gap` frames between consecutive subtitles. A subtitle is never shortened
+    /// below one frame, the pairs that cannot be separated that way are reported with
+    /// the gap left between them.
+    pub fn fix_overlaps(&mut self, strategy: OverlapStrategy, min_gap: u32) -> OverlapReport {
+        let fps = self.gsi.dfc.get_fps();
+        let mut report = OverlapReport::default();
+        for (earlier, later) in self.cue_pairs() {
+            let tco = self.ttis[earlier.start].tco.to_frames(fps);
+            let tci = self.ttis[later.start].tci.to_frames(fps);
+            let excess = (tco + min_gap).saturating_sub(tci);
+            if excess == 0 {
+                continue;
+            }
+            // Frames each subtitle can lose while lasting at least one frame.
+            let earlier_room = tco
+                .saturating_sub(self.ttis[earlier.start].tci.to_frames(fps))
+                .saturating_sub(1);
+            let later_room = self.ttis[later.start]
+                .tco
+                .to_frames(fps)
+                .saturating_sub(tci)
+                .saturating_sub(1);
+            let (earlier_shift, later_shift) = match strategy {
+                OverlapStrategy::TrimEarlier => (excess.min(earlier_room), 0),
+                OverlapStrategy::TrimLater => (0, excess.min(later_room)),
+                OverlapStrategy::Split => {
+                    let earlier_shift = (excess / 2).min(earlier_room);
+                    let later_shift = (excess - earlier_shift).min(later_room);
+                    ((excess - later_shift).min(earlier_room), later_shift)
+                }
+            };
+            if earlier_shift + later_shift < excess {
+                report.unfixed.push(TimingIssue::GapTooSmall {
+                    sn: self.ttis[earlier.start].sn,
+                    next: self.ttis[later.start].sn,
+                    frames: (tci + later_shift) as i64 - (tco - earlier_shift) as i64,
+                });
+            }
+            if earlier_shift + later_shift == 0 {
+                continue;
+            }
+            let tco = Time::from_frames(tco - earlier_shift, fps);
+            for tti in &mut self.ttis[earlier] {
+                tti.tco = tco;
+            }
+            let tci = Time::from_frames(tci + later_shift, fps);
+            for tti in &mut self.ttis[later] {
+                tti.tci = tci;
+            }
+            report.changed += 1;
+        }
+        report
+    }
+
+    // Chains of consecutive displayed subtitles, except the ones following each other
+    // in a cumulative set.
+    pub(crate) fn cue_pairs(&self) -> Vec<(Range<usize>, Range<usize>)> {
+        self.cues()
+            .windows(2)
+            .filter(|pair| !is_in_set_continuation(&self.ttis[pair[1].start]))
+            .map(|pair| (pair[0].clone(), pair[1].clone()))
+            .collect()
+    }
+
+    // Chains of the displayed subtitles.
+    pub(crate) fn cues(&self) -> Vec<Range<usize>> {
+        edit::chains(&self.ttis)
+            .into_iter()
+            .filter(|chain| {
+                let first = &self.ttis[chain.start];
+                first.sn != 0
+                    && first.cf == 0
+                    && matches!(
+                        first.get_extension_block_kind(),
+                        ExtensionBlockKind::Extension(_) | ExtensionBlockKind::Last
+                    )
+            })
+            .collect()
+    }
+}
+
+// Subtitles of a cumulative set are displayed along with the previous ones.
+fn is_in_set_continuation(tti: &TtiBlock) -> bool {
+    matches!(
+        tti.cs,
+        CumulativeStatus::IntermediateInSet | CumulativeStatus::LastInSet
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_overlaps() {
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        assert!(stl.find_overlaps().is_empty());
+
+        stl.ttis[1].tco = Time::new(10, 0, 10, 0);
+        stl.ttis[5].tco = Time::new(10, 0, 21, 0);
+        stl.ttis[6].cs = CumulativeStatus::FirstInSet;
+        stl.ttis[7].cs = CumulativeStatus::LastInSet;
+        stl.ttis[6].tco = Time::new(10, 0, 23, 0);
+        assert_eq!(vec![(2, 3, 11), (6, 7, 7)], stl.find_overlaps());
+    }
+
+    #[test]
+    fn test_fix_overlaps() {
+        for (strategy, tco, tci) in [
+            (OverlapStrategy::TrimEarlier, (9, 12), (9, 14)),
+            (OverlapStrategy::TrimLater, (10, 0), (10, 2)),
+            (OverlapStrategy::Split, (9, 19), (9, 21)),
+        ] {
+            let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+            stl.ttis[1].tco = Time::new(10, 0, 10, 0);
+            let report = stl.fix_overlaps(strategy, 2);
+            assert_eq!(1, report.changed);
+            assert!(report.unfixed.is_empty());
+            assert_eq!(Time::new(10, 0, tco.0, tco.1), stl.ttis[1].tco);
+            assert_eq!(Time::new(10, 0, tci.0, tci.1), stl.ttis[2].tci);
+            assert!(stl.find_overlaps().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_fix_overlaps_short_cue() {
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        // Subtitle 3 lasts 2 frames and starts 11 frames before subtitle 2 ends.
+        stl.ttis[1].tco = Time::new(10, 0, 10, 0);
+        stl.ttis[2].tco = Time::new(10, 0, 9, 16);
+        let report = stl.fix_overlaps(OverlapStrategy::TrimLater, 2);
+        assert_eq!(1, report.changed);
+        assert_eq!(
+            vec![TimingIssue::GapTooSmall {
+                sn: 2,
+                next: 3,
+                frames: -10
+            }],
+            report.unfixed
+        );
+        assert_eq!(Time::new(10, 0, 9, 15), stl.ttis[2].tci);
+        assert_eq!(Time::new(10, 0, 9, 16), stl.ttis[2].tco);
+
+        // Split takes from subtitle 2 what subtitle 3 cannot give.
+        stl.ttis[2].tci = Time::new(10, 0, 9, 14);
+        let report = stl.fix_overlaps(OverlapStrategy::Split, 2);
+        assert!(report.unfixed.is_empty());
+        assert_eq!(Time::new(10, 0, 9, 13), stl.ttis[1].tco);
+        assert_eq!(Time::new(10, 0, 9, 15), stl.ttis[2].tci);
+        assert!(stl.find_overlaps().is_empty());
+    }
+
+    #[test]
+    fn test_timing() {
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        // Subtitle 1 lasts 5 frames, subtitle 3 ends 5 frames before subtitle 4.
+        assert_eq!(
+            vec![TimingIssue::TooShort { sn: 1, frames: 5 }],
+            stl.check_timing(20, 5).issues
+        );
+        assert!(stl
+            .check_timing(20, 6)
+            .issues
+            .contains(&TimingIssue::GapTooSmall {
+                sn: 3,
+                next: 4,
+                frames: 5
+            }));
+        // Subtitle 4 cannot last 80 frames without getting too close to subtitle 5.
+        stl.ttis[3].tco = Time::new(10, 0, 14, 0);
+        let report = stl.enforce_timing(80, 5);
+        assert_eq!(Time::new(10, 0, 3, 5), stl.ttis[0].tco);
+        assert_eq!(Time::new(10, 0, 15, 15), stl.ttis[3].tco);
+        assert!(report
+            .issues
+            .contains(&TimingIssue::TooShort { sn: 4, frames: 77 }));
+        assert_eq!(report, stl.check_timing(80, 5));
+    }
+
+    #[test]
+    fn test_rebase() {
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        stl.gsi.tcp = "10000000".to_string();
+        stl.gsi.tcf = "10000000".to_string();
+        stl.rebase_to_tcp().unwrap();
+        assert_eq!("00000000", stl.gsi.tcp);
+        assert_eq!("00000000", stl.gsi.tcf);
+        assert_eq!(Time::new(0, 0, 6, 8), stl.ttis[1].tci);
+        assert_eq!(Time::new(0, 0, 48, 2), stl.ttis[12].tco);
+
+        stl.rebase_from_zero(Time::new(1, 0, 0, 0)).unwrap();
+        assert_eq!("01000000", stl.gsi.tcp);
+        assert_eq!("01000000", stl.gsi.tcf);
+        assert_eq!(Time::new(1, 0, 6, 8), stl.ttis[1].tci);
+
+        // Subtitle 1 starts before the programme.
+        stl.gsi.tcp = "01000001".to_string();
+        stl.gsi.tcf = "01000001".to_string();
+        assert_eq!(Err(EditError::TimeCodeOutOfRange(1)), stl.rebase_to_tcp());
+        assert_eq!("01000001", stl.gsi.tcp);
+        assert_eq!(Time::new(1, 0, 0, 0), stl.ttis[0].tci);
+        assert_eq!(
+            Err(EditError::GsiTimeCode("TCF")),
+            stl.rebase_from_zero(Time::new(23, 0, 0, 0))
+        );
+    }
+}
@@ -0,0 +1,111 @@
+//! Plain text transcript of the subtitles.
+
+use super::*;
+
+/// How the subtitles are laid out in a transcript.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TranscriptLayout {
+    #[doc = "A line per subtitle, its rows joined with spaces"]
+    #[default]
+    CuePerLine,
+    #[doc = "Running text, a new paragraph after pauses of at least `min_pause_frames`"]
+    Paragraphs { min_pause_frames: u32 },
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TranscriptOptions {
+    pub layout: TranscriptLayout,
+    #[doc = "Prefix lines or paragraphs with the Time Code In of their first subtitle"]
+    pub time_codes: bool,
+    #[doc = "Leave out rows repeated from the previous subtitle, as found in caption captures"]
+    pub collapse_repeats: bool,
+}
+
+impl Stl {
+    /// Text of the subtitles in time order, without control codes, comments or
+    /// subtitle zero.
+    pub fn to_transcript(&self, options: &TranscriptOptions) -> String {
+        let fps = self.gsi.dfc.get_fps();
+        let mut subtitles = self.subtitles().collect::<Vec<_>>();
+        subtitles.sort_by_key(|subtitle| subtitle.start.to_frames(fps));
+
+        let mut transcript = String::new();
+        let mut previous_rows: &[String] = &[];
+        let mut previous_end: Option<u32> = None;
+        for subtitle in &subtitles {
+            let rows = subtitle
+                .lines
+                .iter()
+                .filter(|row| !options.collapse_repeats || !previous_rows.contains(row))
+                .map(String::as_str)
+                .collect::<Vec<_>>();
+            previous_rows = &subtitle.lines;
+            if rows.is_empty() {
+                continue;
+            }
+            let start = subtitle.start.to_frames(fps);
+            let new_line = match (options.layout, previous_end) {
+                (_, None) | (TranscriptLayout::CuePerLine, _) => true,
+                (TranscriptLayout::Paragraphs { min_pause_frames }, Some(end)) => {
+                    start.saturating_sub(end) >= min_pause_frames
+                }
+            };
+            previous_end = Some(subtitle.end.to_frames(fps));
+            if new_line {
+                if !transcript.is_empty() {
+                    transcript.push('\n');
+                    if options.layout != TranscriptLayout::CuePerLine {
+                        transcript.push('\n');
+                    }
+                }
+                if options.time_codes {
+                    transcript.push_str(&format!("[{}] ", subtitle.start.to_smpte()));
+                }
+            } else {
+                transcript.push(' ');
+            }
+            transcript.push_str(&rows.join(" "));
+        }
+        if !transcript.is_empty() {
+            transcript.push('\n');
+        }
+        transcript
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_transcript() {
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        let transcript = stl.to_transcript(&TranscriptOptions::default());
+        let lines = transcript.lines().collect::<Vec<_>>();
+        assert_eq!(12, lines.len());
+        assert_eq!("-Ellis Island, îlot de larmes et d'exil,", lines[0]);
+        assert_eq!("au pied de la statue de la Liberté.", lines[1]);
+
+        let options = TranscriptOptions {
+            layout: TranscriptLayout::Paragraphs {
+                min_pause_frames: 25,
+            },
+            time_codes: true,
+            collapse_repeats: false,
+        };
+        let transcript = stl.to_transcript(&options);
+        assert!(transcript.starts_with(
+            "[10:00:06:08] -Ellis Island, îlot de larmes et d'exil, \
+             au pied de la statue de la Liberté."
+        ));
+        assert!(transcript.contains("\n\n[10:00:"));
+
+        stl.ttis[2].tf = stl.ttis[1].tf.clone();
+        let options = TranscriptOptions {
+            collapse_repeats: true,
+            ..TranscriptOptions::default()
+        };
+        let transcript = stl.to_transcript(&options);
+        assert_eq!(11, transcript.lines().count());
+    }
+}
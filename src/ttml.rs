@@ -0,0 +1,637 @@
+//! TTML import, including the IMSC1 and EBU-TT profiles.
+
+use std::collections::HashMap;
+
+use roxmltree::{Document, Node};
+use thiserror::Error;
+
+use super::*;
+
+const TT: &str = "http://www.w3.org/ns/ttml";
+const TTP: &str = "http://www.w3.org/ns/ttml#parameter";
+const TTS: &str = "http://www.w3.org/ns/ttml#styling";
+const XML: &str = "http://www.w3.org/XML/1998/namespace";
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum TtmlError {
+    #[error("Invalid XML: {0}")]
+    Xml(String),
+    #[error("Not a TTML document")]
+    NotTtml,
+    #[error("Invalid cues: {0:?}")]
+    Cues(Vec<CueError>),
+}
+
+/// Problem found while importing a TTML document, with the line of the element.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TtmlWarning {
+    #[doc = "Paragraph skipped, its timing is missing or malformed or it does not end after it starts"]
+    Timing(usize),
+    #[doc = "Paragraph starting with the previous one, in another region, merged into it"]
+    Merged(usize),
+    #[doc = "Paragraph starting before the end of the previous one, which is cut short"]
+    Overlap(usize),
+    #[doc = "Feature that cannot be represented in STL, left out"]
+    Unsupported { line: usize, feature: String },
+}
+
+// Time parameters of the document.
+struct Timing {
+    smpte: bool,
+    frame_rate: f64,
+    #[doc = "Frame rate after the multiplier, for media times"]
+    effective_frame_rate: f64,
+    tick_rate: f64,
+}
+
+impl Timing {
+    fn new(root: Node) -> Timing {
+        let frame_rate = root
+            .attribute((TTP, "frameRate"))
+            .and_then(|rate| rate.parse::<f64>().ok())
+            .unwrap_or(30.0);
+        let multiplier = root
+            .attribute((TTP, "frameRateMultiplier"))
+            .and_then(|multiplier| {
+                let (numerator, denominator) = multiplier.split_once(' ')?;
+                Some(numerator.parse::<f64>().ok()? / denominator.trim().parse::<f64>().ok()?)
+            })
+            .unwrap_or(1.0);
+        let tick_rate = root
+            .attribute((TTP, "tickRate"))
+            .and_then(|rate| rate.parse::<f64>().ok())
+            .unwrap_or(match root.attribute((TTP, "frameRate")) {
+                Some(_) => frame_rate * multiplier,
+                None => 1.0,
+            });
+        Timing {
+            smpte: root.attribute((TTP, "timeBase")) == Some("smpte"),
+            frame_rate,
+            effective_frame_rate: frame_rate * multiplier,
+            tick_rate,
+        }
+    }
+
+    // Milliseconds of a clock time (HH:MM:SS.fraction or HH:MM:SS:FF) or of an offset
+    // time (a number followed by h, m, s, ms, f or t).
+    fn millis(&self, expression: &str) -> Option<f64> {
+        let expression = expression.trim();
+        if expression.contains(':') {
+            let fields = expression.split(':').collect::<Vec<_>>();
+            let hours = fields.first()?.parse::<u32>().ok()? as f64;
+            let minutes = fields.get(1)?.parse::<u32>().ok()? as f64;
+            let seconds = fields.get(2)?.parse::<f64>().ok()?;
+            let frames = match fields.get(3) {
+                None => 0.0,
+                Some(frames) => {
+                    // Time codes count frames at the nominal rate.
+                    let rate = match self.smpte {
+                        true => self.frame_rate,
+                        false => self.effective_frame_rate,
+                    };
+                    frames.parse::<f64>().ok()? / rate
+                }
+            };
+            if fields.len() > 4 || minutes >= 60.0 || seconds >= 60.0 {
+                return None;
+            }
+            return Some(((hours * 60.0 + minutes) * 60.0 + seconds + frames) * 1000.0);
+        }
+        let split = expression.find(|c: char| c.is_ascii_alphabetic())?;
+        let value = expression[..split].parse::<f64>().ok()?;
+        let millis = match &expression[split..] {
+            "h" => value * 3_600_000.0,
+            "m" => value * 60_000.0,
+            "s" => value * 1000.0,
+            "ms" => value,
+            "f" => value * 1000.0 / self.effective_frame_rate,
+            "t" => value * 1000.0 / self.tick_rate,
+            _ => return None,
+        };
+        Some(millis)
+    }
+}
+
+// Style of a run of text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct RunStyle {
+    italic: bool,
+    underline: bool,
+    colour: u8,
+}
+
+impl Default for RunStyle {
+    fn default() -> RunStyle {
+        RunStyle {
+            italic: false,
+            underline: false,
+            colour: 7,
+        }
+    }
+}
+
+// Paragraph of the body, times in milliseconds.
+struct Paragraph {
+    start: u64,
+    end: u64,
+    text: String,
+    format: TtiFormat,
+    line: usize,
+}
+
+struct Importer<'a, 'input> {
+    document: &'a Document<'input>,
+    styles: HashMap<&'a str, Node<'a, 'input>>,
+    regions: HashMap<&'a str, Node<'a, 'input>>,
+    timing: Timing,
+    cell_rows: f64,
+    extent_height: Option<f64>,
+    open_subtitling: bool,
+    format: TtiFormat,
+    warnings: Vec<TtmlWarning>,
+}
+
+impl<'a, 'input> Importer<'a, 'input> {
+    fn line(&self, node: Node) -> usize {
+        self.document.text_pos_at(node.range().start).row as usize
+    }
+
+    fn unsupported(&mut self, node: Node, feature: &str) {
+        let line = self.line(node);
+        self.warnings.push(TtmlWarning::Unsupported {
+            line,
+            feature: feature.to_string(),
+        });
+    }
+
+    // Styling attributes of `node`, from the styles it references then its own.
+    fn style_attributes(&self, node: Node<'a, 'input>, attributes: &mut HashMap<String, String>) {
+        for id in node
+            .attribute("style")
+            .unwrap_or_default()
+            .split_whitespace()
+        {
+            if let Some(&style) = self.styles.get(id) {
+                if style != node {
+                    self.style_attributes(style, attributes);
+                }
+            }
+        }
+        for attribute in node.attributes() {
+            if attribute.namespace() == Some(TTS) {
+                attributes.insert(attribute.name().to_string(), attribute.value().to_string());
+            }
+        }
+    }
+
+    // Region of `node` or of its closest ancestor having one.
+    fn region(&self, node: Node<'a, 'input>) -> Option<Node<'a, 'input>> {
+        node.ancestors()
+            .find_map(|node| node.attribute("region"))
+            .and_then(|id| self.regions.get(id).copied())
+    }
+
+    // Percentage of the screen height of a length in %, px or c.
+    fn height_percentage(&self, length: &str) -> Option<f64> {
+        let length = length.trim();
+        if let Some(value) = length.strip_suffix('%') {
+            return value.parse::<f64>().ok();
+        }
+        if let Some(value) = length.strip_suffix("px") {
+            return Some(value.parse::<f64>().ok()? * 100.0 / self.extent_height?);
+        }
+        let value = length.strip_suffix('c')?.parse::<f64>().ok()?;
+        Some(value * 100.0 / self.cell_rows)
+    }
+
+    // Vertical position of the first of `rows` rows shown in `region`.
+    fn vertical_position(&mut self, region: Node<'a, 'input>, rows: usize) -> Option<u8> {
+        let mut attributes = HashMap::new();
+        self.style_attributes(region, &mut attributes);
+        let origin = attributes.get("origin")?;
+        let top = self.height_percentage(origin.split_whitespace().nth(1)?);
+        let height = attributes
+            .get("extent")
+            .and_then(|extent| extent.split_whitespace().nth(1))
+            .and_then(|height| self.height_percentage(height));
+        let (Some(top), Some(height)) = (top, height) else {
+            self.unsupported(region, "region position");
+            return None;
+        };
+        let step = if self.format.dh && !self.open_subtitling {
+            2
+        } else {
+            1
+        };
+        let span = (rows.max(1) - 1) as u8 * step;
+        Some(match attributes.get("displayAlign").map(String::as_str) {
+            Some("after") => {
+                let bottom = (top + height).clamp(0.0, 100.0);
+                let last = vtt::vertical_position(bottom, self.open_subtitling).saturating_sub(1);
+                last.saturating_sub(span).max(1)
+            }
+            Some("center") => {
+                let middle = (top + height / 2.0).clamp(0.0, 100.0);
+                let middle = vtt::vertical_position(middle, self.open_subtitling);
+                middle.saturating_sub(span / 2).max(1)
+            }
+            _ => vtt::vertical_position(top.clamp(0.0, 100.0), self.open_subtitling),
+        })
+    }
+
+    fn paragraph(&mut self, p: Node<'a, 'input>) -> Option<Paragraph> {
+        let line = self.line(p);
+        let Some((start, end)) = self.interval(p) else {
+            self.warnings.push(TtmlWarning::Timing(line));
+            return None;
+        };
+
+        let mut attributes = HashMap::new();
+        if let Some(region) = self.region(p) {
+            self.style_attributes(region, &mut attributes);
+        }
+        for node in p.ancestors().collect::<Vec<_>>().into_iter().rev() {
+            if node.has_tag_name((TT, "body")) || node.has_tag_name((TT, "div")) || node == p {
+                self.style_attributes(node, &mut attributes);
+            }
+        }
+        let mut format = self.format;
+        match attributes.get("textAlign").map(String::as_str) {
+            Some("left" | "start") => format.jc = 1,
+            Some("center") => format.jc = 2,
+            Some("right" | "end") => format.jc = 3,
+            Some("justify") => self.unsupported(p, "justified text"),
+            _ => {}
+        }
+        if attributes
+            .get("writingMode")
+            .is_some_and(|mode| mode.starts_with("tb"))
+        {
+            self.unsupported(p, "vertical text");
+        }
+
+        let mut text = String::new();
+        let mut current = RunStyle::default();
+        let style = self.run_style(p, &attributes, RunStyle::default());
+        self.content(p, &attributes, style, &mut current, &mut text);
+        let text = text
+            .lines()
+            .map(str::trim)
+            .filter(|row| !row.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n");
+        if let Some(region) = self.region(p) {
+            if let Some(vp) = self.vertical_position(region, text.lines().count()) {
+                format.vp = vp;
+            }
+        }
+        Some(Paragraph {
+            start: start.round() as u64,
+            end: end.round() as u64,
+            text,
+            format,
+            line,
+        })
+    }
+
+    // Begin and end of `p`, its ancestors' begin times added.
+    fn interval(&self, p: Node) -> Option<(f64, f64)> {
+        let time = |node: Node, name: &str| match node.attribute(name) {
+            Some(expression) => self.timing.millis(expression).map(Some),
+            None => Some(None),
+        };
+        let mut offset = 0.0;
+        let mut inherited_end = None;
+        for node in p.ancestors().skip(1).collect::<Vec<_>>().into_iter().rev() {
+            let begin = time(node, "begin")?;
+            let end = time(node, "end")?.map(|end| offset + end);
+            offset += begin.unwrap_or(0.0);
+            inherited_end = end.or(inherited_end);
+        }
+        let start = offset + time(p, "begin")?.unwrap_or(0.0);
+        let end = match (time(p, "end")?, time(p, "dur")?) {
+            (Some(end), _) => offset + end,
+            (None, Some(duration)) => start + duration,
+            (None, None) => inherited_end?,
+        };
+        Some((start, end)).filter(|(start, end)| end > start)
+    }
+
+    // Style of the runs of `node` given the attributes in effect.
+    fn run_style(
+        &mut self,
+        node: Node,
+        attributes: &HashMap<String, String>,
+        inherited: RunStyle,
+    ) -> RunStyle {
+        let mut style = inherited;
+        match attributes.get("fontStyle").map(String::as_str) {
+            Some("italic" | "oblique") => style.italic = true,
+            Some("normal") => style.italic = false,
+            _ => {}
+        }
+        if let Some(decoration) = attributes.get("textDecoration") {
+            if decoration.contains("noUnderline") || decoration == "none" {
+                style.underline = false;
+            } else if decoration.contains("underline") {
+                style.underline = true;
+            }
+        }
+        if let Some(colour) = attributes.get("color") {
+            match styled::teletext_colour(colour) {
+                Some(colour) => style.colour = colour,
+                None => self.unsupported(node, &format!("colour {}", colour)),
+            }
+        }
+        style
+    }
+
+    fn content(
+        &mut self,
+        node: Node<'a, 'input>,
+        attributes: &HashMap<String, String>,
+        style: RunStyle,
+        current: &mut RunStyle,
+        text: &mut String,
+    ) {
+        for child in node.children() {
+            if child.is_text() {
+                let run = collapse_whitespace(child.text().unwrap_or_default(), text);
+                if !run.trim().is_empty() {
+                    self.switch_style(child, style, current, text);
+                }
+                text.push_str(&run);
+            } else if child.has_tag_name((TT, "br")) {
+                text.push('\n');
+                // Teletext colours only last until the end of the row.
+                current.colour = 7;
+            } else if child.has_tag_name((TT, "span")) {
+                let mut span_attributes = attributes.clone();
+                self.style_attributes(child, &mut span_attributes);
+                match span_attributes.get("ruby").map(String::as_str) {
+                    Some("text" | "textContainer") => {
+                        self.unsupported(child, "ruby");
+                        continue;
+                    }
+                    Some(_) => {}
+                    None if span_attributes.contains_key("textEmphasis") => {
+                        self.unsupported(child, "text emphasis")
+                    }
+                    None => {}
+                }
+                if child.has_attribute("begin") || child.has_attribute("end") {
+                    self.unsupported(child, "timed span");
+                }
+                let span_style = self.run_style(child, &span_attributes, style);
+                self.content(child, &span_attributes, span_style, current, text);
+            }
+        }
+    }
+
+    // Codes switching from the `current` style of the text to `style`.
+    fn switch_style(
+        &mut self,
+        node: Node,
+        style: RunStyle,
+        current: &mut RunStyle,
+        text: &mut String,
+    ) {
+        if style.italic != current.italic || style.underline != current.underline {
+            if self.open_subtitling {
+                if style.italic != current.italic {
+                    text.push(match style.italic {
+                        true => cues::ITALICS_ON,
+                        false => cues::ITALICS_OFF,
+                    });
+                }
+                if style.underline != current.underline {
+                    text.push(match style.underline {
+                        true => cues::UNDERLINE_ON,
+                        false => cues::UNDERLINE_OFF,
+                    });
+                }
+            } else if style.italic || style.underline {
+                self.unsupported(node, "italics and underline in teletext");
+            }
+            (current.italic, current.underline) = (style.italic, style.underline);
+        }
+        if style.colour != current.colour {
+            if self.open_subtitling {
+                self.unsupported(node, "colours in open subtitling");
+            } else {
+                // The colour code takes the place of the space before the run.
+                if text.ends_with(' ') {
+                    text.pop();
+                }
+                text.push(cues::colour(style.colour));
+            }
+            current.colour = style.colour;
+        }
+    }
+}
+
+// Text of a text node with XML whitespace collapsed, following `text`.
+fn collapse_whitespace(run: &str, text: &str) -> String {
+    let mut collapsed = String::new();
+    let mut space = text.is_empty() || text.ends_with([' ', '\n']);
+    for c in run.chars() {
+        if c.is_whitespace() {
+            if !space {
+                collapsed.push(' ');
+            }
+            space = true;
+        } else {
+            collapsed.push(c);
+            space = false;
+        }
+    }
+    collapsed
+}
+
+impl Stl {
+    /// Document made of the paragraphs of a TTML document (IMSC1, EBU-TT or EBU-TT-D),
+    /// see [`Stl::from_cues`]. Media and SMPTE time bases are accepted, times are
+    /// rounded to the nearest frame at the frame rate of `gsi`.
+    ///
+    /// Regions set the vertical position and `tts:textAlign` the justification of
+    /// `format`. Colours become teletext colour codes and `<br/>` row breaks.
+    /// Italics and underline are kept for open subtitling. Paragraphs shown together in
+    /// several regions are merged, overlapping ones cut short, and features STL cannot
+    /// represent (ruby, vertical text, ...) are left out, all with a warning.
+    pub fn from_ttml(
+        ttml: &str,
+        gsi: GsiBlock,
+        format: TtiFormat,
+    ) -> Result<(Stl, Vec<TtmlWarning>), TtmlError> {
+        let document = Document::parse(ttml).map_err(|err| TtmlError::Xml(err.to_string()))?;
+        let root = document.root_element();
+        if !root.has_tag_name((TT, "tt")) {
+            return Err(TtmlError::NotTtml);
+        }
+        let by_id = |name: &str| {
+            document
+                .descendants()
+                .filter(|node| node.has_tag_name((TT, name)))
+                .filter_map(|node| Some((node.attribute((XML, "id"))?, node)))
+                .collect::<HashMap<_, _>>()
+        };
+        let cell_rows = root
+            .attribute((TTP, "cellResolution"))
+            .and_then(|resolution| resolution.split_whitespace().nth(1)?.parse::<f64>().ok())
+            .unwrap_or(15.0);
+        let extent_height = root
+            .attribute((TTS, "extent"))
+            .and_then(|extent| extent.split_whitespace().nth(1)?.strip_suffix("px"))
+            .and_then(|height| height.parse::<f64>().ok());
+        let mut importer = Importer {
+            document: &document,
+            styles: by_id("style"),
+            regions: by_id("region"),
+            timing: Timing::new(root),
+            cell_rows,
+            extent_height,
+            open_subtitling: gsi.dsc == DisplayStandardCode::OpenSubtitling,
+            format,
+            warnings: vec![],
+        };
+
+        let mut paragraphs = document
+            .descendants()
+            .filter(|node| node.has_tag_name((TT, "p")))
+            .filter_map(|p| importer.paragraph(p))
+            .filter(|paragraph| !paragraph.text.is_empty())
+            .collect::<Vec<_>>();
+        paragraphs.sort_by_key(|paragraph| paragraph.start);
+        let mut warnings = importer.warnings;
+        let mut merged: Vec<Paragraph> = vec![];
+        for paragraph in paragraphs {
+            match merged.last_mut() {
+                Some(previous) if previous.start == paragraph.start => {
+                    warnings.push(TtmlWarning::Merged(paragraph.line));
+                    previous.end = previous.end.max(paragraph.end);
+                    previous.text = match paragraph.format.vp < previous.format.vp {
+                        true => format!("{}\n{}", paragraph.text, previous.text),
+                        false => format!("{}\n{}", previous.text, paragraph.text),
+                    };
+                    previous.format.vp = previous.format.vp.min(paragraph.format.vp);
+                }
+                Some(previous) if previous.end > paragraph.start => {
+                    warnings.push(TtmlWarning::Overlap(paragraph.line));
+                    previous.end = paragraph.start;
+                    merged.push(paragraph);
+                }
+                _ => merged.push(paragraph),
+            }
+        }
+
+        let fps = gsi.dfc.get_fps();
+        let cues = merged.into_iter().map(|paragraph| Cue {
+            start: Time::from_millis(paragraph.start, fps),
+            end: Time::from_millis(paragraph.end, fps),
+            text: paragraph.text,
+            format: paragraph.format,
+        });
+        let stl = Stl::from_cues(gsi, cues).map_err(TtmlError::Cues)?;
+        Ok((stl, warnings))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FORMAT: TtiFormat = TtiFormat {
+        jc: 2,
+        vp: 20,
+        dh: false,
+    };
+
+    #[test]
+    fn test_from_ttml_imsc() {
+        let ttml = r##"<?xml version="1.0" encoding="UTF-8"?>
+<tt xmlns="http://www.w3.org/ns/ttml" xmlns:tts="http://www.w3.org/ns/ttml#styling"
+    xmlns:ttp="http://www.w3.org/ns/ttml#parameter" ttp:timeBase="media" xml:lang="en">
+  <head>
+    <styling>
+      <style xml:id="base" tts:textAlign="center"/>
+      <style xml:id="yellow" style="base" tts:color="#ffff00"/>
+    </styling>
+    <layout>
+      <region xml:id="top" tts:origin="10% 0%" tts:extent="80% 20%"/>
+      <region xml:id="bottom" tts:origin="10% 80%" tts:extent="80% 20%"
+              tts:displayAlign="after"/>
+    </layout>
+  </head>
+  <body region="bottom" style="base">
+    <div begin="10s">
+      <p begin="00:00:01.000" end="00:00:02.500">Hello,
+        <span style="yellow">world</span><br/>second   row</p>
+      <p begin="3s" dur="1s" tts:textAlign="left">Left <span tts:fontStyle="italic">it</span></p>
+      <p begin="5s" end="6s" region="top">Top<span tts:ruby="text">ruby</span></p>
+      <p begin="5s" end="6s">Bottom</p>
+      <p begin="bad" end="7s">Skipped</p>
+    </div>
+  </body>
+</tt>"##;
+        let (stl, warnings) = Stl::from_ttml(ttml, GsiBlock::new(), FORMAT).unwrap();
+        let subtitles = stl.subtitles().collect::<Vec<_>>();
+        assert_eq!(3, subtitles.len());
+
+        assert_eq!(&Time::new(0, 0, 11, 0), subtitles[0].start);
+        assert_eq!(&Time::new(0, 0, 12, 13), subtitles[0].end);
+        assert_eq!(vec!["Hello, world", "second row"], subtitles[0].lines);
+        assert_eq!((2, 21), (stl.ttis[0].jc, stl.ttis[0].vp));
+
+        assert_eq!(&Time::new(0, 0, 14, 0), subtitles[1].end);
+        assert_eq!(Justification::Left, subtitles[1].justification);
+        assert_eq!(vec!["Top", "Bottom"], subtitles[2].lines);
+        assert_eq!(1, subtitles[2].vertical_position);
+
+        // The colour code takes the place of the space between the words.
+        let rows = styled::styled_rows(&stl.ttis[0..1]);
+        assert_eq!(("world", 3), (rows[0][1].text.as_str(), rows[0][1].colour));
+
+        assert_eq!(
+            vec![
+                TtmlWarning::Unsupported {
+                    line: 19,
+                    feature: "italics and underline in teletext".to_string()
+                },
+                TtmlWarning::Unsupported {
+                    line: 20,
+                    feature: "ruby".to_string()
+                },
+                TtmlWarning::Timing(22),
+                TtmlWarning::Merged(21),
+            ],
+            warnings
+        );
+    }
+
+    #[test]
+    fn test_from_ttml_smpte() {
+        let ttml = r#"<tt:tt xmlns:tt="http://www.w3.org/ns/ttml"
+    xmlns:ttp="http://www.w3.org/ns/ttml#parameter" ttp:timeBase="smpte"
+    ttp:frameRate="25">
+  <tt:body><tt:div>
+    <tt:p begin="10:00:01:12" end="10:00:03:00">First</tt:p>
+    <tt:p begin="10:00:02:00" end="10:00:04:00">Second</tt:p>
+  </tt:div></tt:body>
+</tt:tt>"#;
+        let mut gsi = GsiBlock::new();
+        gsi.dsc = DisplayStandardCode::OpenSubtitling;
+        let (stl, warnings) = Stl::from_ttml(ttml, gsi, FORMAT).unwrap();
+        assert_eq!(Time::new(10, 0, 1, 12), stl.ttis[0].tci);
+        assert_eq!(Time::new(10, 0, 2, 0), stl.ttis[0].tco);
+        assert_eq!(vec![TtmlWarning::Overlap(6)], warnings);
+
+        assert_eq!(
+            Err(TtmlError::NotTtml),
+            Stl::from_ttml("<html/>", GsiBlock::new(), FORMAT).map(|_| ())
+        );
+        assert!(matches!(
+            Stl::from_ttml("<tt", GsiBlock::new(), FORMAT),
+            Err(TtmlError::Xml(_))
+        ));
+    }
+}
@@ -0,0 +1,596 @@
+use std::fmt;
+
+use thiserror::Error;
+
+use super::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// Where an issue was found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Location {
+    #[doc = "GSI field, named as in the specification"]
+    Gsi(&'static str),
+    #[doc = "TTI block, `index` being its position in `Stl::ttis`"]
+    Tti { index: usize, sn: u16 },
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Location::Gsi(field) => write!(f, "GSI {}", field),
+            Location::Tti { index, sn } => write!(f, "TTI block {} (subtitle {})", index, sn),
+        }
+    }
+}
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum IssueCode {
+    #[error("Value does not fit in {width} bytes")]
+    FieldOverflow { width: usize },
+    #[error("Block is {actual} bytes long")]
+    BlockSize { actual: usize },
+    #[error("Disk {dsn} of a set of {tnd} disks")]
+    DiskSequence { tnd: u8, dsn: u8 },
+    #[error("Declared {declared}, found {actual}")]
+    TotalMismatch { declared: u16, actual: u16 },
+    #[error("{blocks} TTI blocks, more than the 65535 of a disk")]
+    CapacityExceeded { blocks: usize },
+    #[error("Spare bytes are not spaces, they are written as spaces")]
+    SpareNotBlank,
+    #[error("Characters {0:?} are missing from the code page, they are written as '?'")]
+    UnmappableCharacters(Vec<char>),
+    #[error("Time code out is not after time code in")]
+    OutNotAfterIn,
+    #[error("Time code in is before the one of the previous subtitle of its group")]
+    CueOrder,
+    #[error("Text field has no unused space terminator (8Fh)")]
+    MissingTerminator,
+    #[error("Text field has data after the unused space terminator (8Fh)")]
+    TextAfterTerminator,
+    #[error("Subtitle number is lower than the one of the previous block of its group")]
+    SubtitleNumberOrder,
+    #[error("Time code {0} is not a valid time at the frame rate of the file")]
+    InvalidTimeCode(String),
+    #[error("Vertical position {0} is out of range for the display standard")]
+    VerticalPosition(u8),
+    #[error("Justification code {0} is not defined")]
+    JustificationCode(u8),
+    #[error("Extension block number {actual} found where {expected} was expected")]
+    ExtensionOrder { expected: u8, actual: u8 },
+    #[error("Extension chain does not end with a last block (FFh)")]
+    UnterminatedChain,
+    #[error("Extension block number is reserved")]
+    ReservedBlock,
+    #[error("Cumulative status {0:?} out of a set")]
+    CumulativeSet(CumulativeStatus),
+    #[error("Row of {chars} characters, at most {max} declared")]
+    RowTooLong { chars: usize, max: u16 },
+    #[error("{rows} rows, at most {max} declared")]
+    TooManyRows { rows: usize, max: u16 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationIssue {
+    pub severity: Severity,
+    pub location: Location,
+    pub code: IssueCode,
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}: {}: {}", self.severity, self.location, self.code)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    pub fn has_errors(&self) -> bool {
+        self.errors().next().is_some()
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &ValidationIssue> {
+        self.issues
+            .iter()
+            .filter(|issue| issue.severity == Severity::Error)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &ValidationIssue> {
+        self.issues
+            .iter()
+            .filter(|issue| issue.severity == Severity::Warning)
+    }
+
+    fn push(&mut self, severity: Severity, location: Location, code: IssueCode) {
+        self.issues.push(ValidationIssue {
+            severity,
+            location,
+            code,
+        });
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for issue in &self.issues {
+            writeln!(f, "{}", issue)?;
+        }
+        Ok(())
+    }
+}
+
+impl Stl {
+    /// Check the document against the rules a conforming file has to follow.
+    pub fn validate(&self) -> ValidationReport {
+        validate(&self.gsi, &self.ttis)
+    }
+}
+
+pub(crate) fn validate(gsi: &GsiBlock, ttis: &[TtiBlock]) -> ValidationReport {
+    let mut report = ValidationReport::default();
+    validate_gsi(gsi, ttis, &mut report);
+    validate_ttis(gsi, ttis, &mut report);
+    validate_chains(gsi, ttis, &mut report);
+    validate_sets(ttis, &mut report);
+    report
+}
+
+fn validate_gsi(gsi: &GsiBlock, ttis: &[TtiBlock], report: &mut ValidationReport) {
+    if let Err(SerializeError::FieldOverflow { field, width }) = gsi.serialize() {
+        report.push(
+            Severity::Error,
+            Location::Gsi(field),
+            IssueCode::FieldOverflow { width },
+        );
+    }
+    if gsi.spare.iter().any(|&c| c != 0x20) {
+        report.push(
+            Severity::Warning,
+            Location::Gsi("SB"),
+            IssueCode::SpareNotBlank,
+        );
+    }
+    for (field, chars) in gsi.unmappable_fields() {
+        report.push(
+            Severity::Warning,
+            Location::Gsi(field),
+            IssueCode::UnmappableCharacters(chars),
+        );
+    }
+    if gsi.tnd == 0 || gsi.dsn == 0 || gsi.dsn > gsi.tnd {
+        report.push(
+            Severity::Error,
+            Location::Gsi("DSN"),
+            IssueCode::DiskSequence {
+                tnd: gsi.tnd,
+                dsn: gsi.dsn,
+            },
+        );
+    }
+    let fps = gsi.dfc.get_fps();
+    for (field, time_code) in [("TCP", &gsi.tcp), ("TCF", &gsi.tcf)] {
+        if !is_valid_time_code(time_code, fps) {
+            report.push(
+                Severity::Error,
+                Location::Gsi(field),
+                IssueCode::InvalidTimeCode(time_code.clone()),
+            );
+        }
+    }
+    if ttis.len() > u16::MAX as usize {
+        report.push(
+            Severity::Error,
+            Location::Gsi("TNB"),
+            IssueCode::CapacityExceeded { blocks: ttis.len() },
+        );
+    }
+    let (tnb, tns, tng) = totals(ttis);
+    for (field, declared, actual) in [
+        ("TNB", gsi.tnb, tnb),
+        ("TNS", gsi.tns, tns),
+        ("TNG", gsi.tng, tng),
+    ] {
+        if declared != actual {
+            report.push(
+                Severity::Error,
+                Location::Gsi(field),
+                IssueCode::TotalMismatch { declared, actual },
+            );
+        }
+    }
+}
+
+fn validate_ttis(gsi: &GsiBlock, ttis: &[TtiBlock], report: &mut ValidationReport) {
+    let fps = gsi.dfc.get_fps();
+    // Subtitle numbers and times only follow each other within a subtitle group.
+    let mut previous_sn = std::collections::BTreeMap::new();
+    let mut previous_tci = std::collections::BTreeMap::new();
+    for (index, tti) in ttis.iter().enumerate() {
+        let location = Location::Tti { index, sn: tti.sn };
+        let size = tti.serialize().len();
+        if size != 128 {
+            report.push(
+                Severity::Error,
+                location.clone(),
+                IssueCode::BlockSize { actual: size },
+            );
+        }
+        if previous_sn
+            .insert(tti.sgn, tti.sn)
+            .is_some_and(|sn| tti.sn < sn)
+        {
+            report.push(
+                Severity::Error,
+                location.clone(),
+                IssueCode::SubtitleNumberOrder,
+            );
+        }
+        if let ExtensionBlockKind::Reserved(_) = tti.get_extension_block_kind() {
+            report.push(
+                Severity::Warning,
+                location.clone(),
+                IssueCode::ReservedBlock,
+            );
+        }
+        let is_subtitle = tti.cf == 0
+            && matches!(
+                tti.get_extension_block_kind(),
+                ExtensionBlockKind::Extension(_) | ExtensionBlockKind::Last
+            );
+        if !is_subtitle {
+            continue;
+        }
+        for time in [&tti.tci, &tti.tco] {
+            if !time.is_valid(fps) {
+                report.push(
+                    Severity::Error,
+                    location.clone(),
+                    IssueCode::InvalidTimeCode(time.to_string()),
+                );
+            }
+        }
+        if tti.tco <= tti.tci {
+            report.push(Severity::Error, location.clone(), IssueCode::OutNotAfterIn);
+        }
+        let vp_range = match gsi.dsc {
+            DisplayStandardCode::Level1Teletext | DisplayStandardCode::Level2Teletext => 1..=23,
+            DisplayStandardCode::OpenSubtitling => 0..=99,
+            DisplayStandardCode::Blank => 0..=u8::MAX,
+        };
+        if !vp_range.contains(&tti.vp) {
+            report.push(
+                Severity::Error,
+                location.clone(),
+                IssueCode::VerticalPosition(tti.vp),
+            );
+        }
+        if tti.jc > 3 {
+            report.push(
+                Severity::Error,
+                location.clone(),
+                IssueCode::JustificationCode(tti.jc),
+            );
+        }
+        if previous_tci
+            .insert(tti.sgn, &tti.tci)
+            .is_some_and(|previous| tti.tci < *previous)
+        {
+            report.push(Severity::Error, location.clone(), IssueCode::CueOrder);
+        }
+        match tti.tf.iter().position(|&c| c == 0x8f) {
+            Some(end) if tti.tf[end..].iter().any(|&c| c != 0x8f) => {
+                report.push(Severity::Error, location, IssueCode::TextAfterTerminator)
+            }
+            None if tti.get_extension_block_kind() == ExtensionBlockKind::Last => {
+                report.push(Severity::Warning, location, IssueCode::MissingTerminator)
+            }
+            _ => {}
+        }
+    }
+}
+
+// Extension block numbers counting from 0 up to the last block, and rows of the whole
+// chain within the declared maximums.
+fn validate_chains(gsi: &GsiBlock, ttis: &[TtiBlock], report: &mut ValidationReport) {
+    for chain in subtitles::chains(ttis) {
+        for (index, defect) in chain.defects() {
+            let location = Location::Tti {
+                index: chain.index + index,
+                sn: chain.blocks()[index].sn,
+            };
+            let code = match defect {
+                ChainDefect::ExtensionOrder { expected, actual } => {
+                    IssueCode::ExtensionOrder { expected, actual }
+                }
+                ChainDefect::Unterminated => IssueCode::UnterminatedChain,
+            };
+            report.push(Severity::Error, location, code);
+        }
+        let first = &chain.blocks()[0];
+        if first.cf != 0 || first.sn == 0 {
+            continue;
+        }
+        let location = Location::Tti {
+            index: chain.index,
+            sn: first.sn,
+        };
+        let text = chain.text();
+        let rows = text
+            .split("\r\n")
+            .map(str::trim)
+            .filter(|row| !row.is_empty())
+            .collect::<Vec<_>>();
+        if rows.len() > gsi.mnr as usize {
+            report.push(
+                Severity::Error,
+                location.clone(),
+                IssueCode::TooManyRows {
+                    rows: rows.len(),
+                    max: gsi.mnr,
+                },
+            );
+        }
+        if let Some(chars) = rows
+            .iter()
+            .map(|row| row.chars().count())
+            .filter(|&chars| chars > gsi.mnc as usize)
+            .max()
+        {
+            report.push(
+                Severity::Error,
+                location,
+                IssueCode::RowTooLong {
+                    chars,
+                    max: gsi.mnc,
+                },
+            );
+        }
+    }
+}
+
+// Cumulative sets opened by a first subtitle and closed by a last one.
+fn validate_sets(ttis: &[TtiBlock], report: &mut ValidationReport) {
+    let mut misplaced = vec![];
+    let mut open: Option<usize> = None;
+    for chain in edit::chains(ttis) {
+        let index = chain.start;
+        let tti = &ttis[index];
+        if !edit::is_subtitle_block(tti) || tti.cf != 0 {
+            continue;
+        }
+        open = match (tti.cs, open) {
+            (CumulativeStatus::FirstInSet, open) => {
+                misplaced.extend(open);
+                Some(index)
+            }
+            (CumulativeStatus::IntermediateInSet, None) | (CumulativeStatus::LastInSet, None) => {
+                misplaced.push(index);
+                None
+            }
+            (CumulativeStatus::IntermediateInSet, open) => open,
+            (CumulativeStatus::LastInSet, Some(_)) => None,
+            (CumulativeStatus::NotPartOfASet, open) => {
+                misplaced.extend(open);
+                None
+            }
+        };
+    }
+    misplaced.extend(open);
+    for index in misplaced {
+        report.push(
+            Severity::Error,
+            Location::Tti {
+                index,
+                sn: ttis[index].sn,
+            },
+            IssueCode::CumulativeSet(ttis[index].cs),
+        );
+    }
+}
+
+fn is_valid_time_code(time_code: &str, fps: usize) -> bool {
+    Time::from_time_code(time_code).is_some_and(|time| time.is_valid(fps))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        assert_eq!(ValidationReport::default(), stl.validate());
+
+        stl.gsi.dsn = 2;
+        stl.gsi.spare[10] = b'x';
+        stl.gsi.tn = "Łukasz".to_string();
+        stl.ttis.truncate(12);
+        stl.ttis[3].tco = Time::new(10, 0, 12, 0);
+        stl.ttis[5].tci = Time::new(9, 0, 0, 0);
+        stl.ttis[7].tf[100] = b'a';
+        stl.ttis[8].tf = vec![b' '; 112];
+        let codes = stl
+            .validate()
+            .issues
+            .into_iter()
+            .map(|issue| (issue.severity, issue.location, issue.code))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (
+                    Severity::Warning,
+                    Location::Gsi("SB"),
+                    IssueCode::SpareNotBlank
+                ),
+                (
+                    Severity::Warning,
+                    Location::Gsi("TN"),
+                    IssueCode::UnmappableCharacters(vec!['Ł'])
+                ),
+                (
+                    Severity::Error,
+                    Location::Gsi("DSN"),
+                    IssueCode::DiskSequence { tnd: 1, dsn: 2 }
+                ),
+                (
+                    Severity::Error,
+                    Location::Gsi("TNB"),
+                    IssueCode::TotalMismatch {
+                        declared: 13,
+                        actual: 12
+                    }
+                ),
+                (
+                    Severity::Error,
+                    Location::Gsi("TNS"),
+                    IssueCode::TotalMismatch {
+                        declared: 13,
+                        actual: 12
+                    }
+                ),
+                (
+                    Severity::Error,
+                    Location::Tti { index: 3, sn: 4 },
+                    IssueCode::OutNotAfterIn
+                ),
+                (
+                    Severity::Error,
+                    Location::Tti { index: 5, sn: 6 },
+                    IssueCode::CueOrder
+                ),
+                (
+                    Severity::Error,
+                    Location::Tti { index: 7, sn: 8 },
+                    IssueCode::TextAfterTerminator
+                ),
+                (
+                    Severity::Warning,
+                    Location::Tti { index: 8, sn: 9 },
+                    IssueCode::MissingTerminator
+                ),
+            ],
+            codes
+        );
+    }
+
+    #[test]
+    fn test_validate_groups() {
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        // Subtitles 8 to 13 numbered again from 1 in group 1, displayed 20 seconds
+        // earlier, in between the subtitles of group 0.
+        for tti in &mut stl.ttis[7..] {
+            tti.sgn = 1;
+            tti.sn -= 7;
+            tti.tci.seconds -= 20;
+            tti.tco.seconds -= 20;
+        }
+        stl.recompute_totals();
+        assert_eq!(ValidationReport::default(), stl.validate());
+
+        stl.ttis[10].tci.seconds -= 10;
+        stl.ttis[10].sn = 1;
+        let codes = stl
+            .validate()
+            .issues
+            .into_iter()
+            .map(|issue| (issue.location, issue.code))
+            .collect::<Vec<_>>();
+        assert!(codes.contains(&(
+            Location::Tti { index: 10, sn: 1 },
+            IssueCode::SubtitleNumberOrder
+        )));
+        assert!(codes.contains(&(Location::Tti { index: 10, sn: 1 }, IssueCode::CueOrder)));
+    }
+
+    #[test]
+    fn test_validate_structure() {
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        stl.gsi.tcp = "10000025".to_string();
+        stl.ttis[0].tci.frames = 25;
+        stl.ttis[1].vp = 0;
+        stl.ttis[1].jc = 5;
+        stl.ttis[2].ebn = 1;
+        stl.ttis[3].sn = 3;
+        stl.ttis[5].ebn = 0xf0;
+        stl.ttis[7].sn = 5;
+        stl.ttis[8].cs = CumulativeStatus::LastInSet;
+        stl.ttis[10].cs = CumulativeStatus::FirstInSet;
+        stl.ttis[12].ebn = 0;
+        let issues = stl
+            .validate()
+            .issues
+            .into_iter()
+            .map(|issue| (issue.location, issue.code))
+            .collect::<Vec<_>>();
+        for issue in [
+            (
+                Location::Gsi("TCP"),
+                IssueCode::InvalidTimeCode("10000025".to_string()),
+            ),
+            (
+                Location::Tti { index: 0, sn: 1 },
+                IssueCode::InvalidTimeCode(stl.ttis[0].tci.to_string()),
+            ),
+            (
+                Location::Tti { index: 1, sn: 2 },
+                IssueCode::VerticalPosition(0),
+            ),
+            (
+                Location::Tti { index: 1, sn: 2 },
+                IssueCode::JustificationCode(5),
+            ),
+            (
+                Location::Tti { index: 2, sn: 3 },
+                IssueCode::ExtensionOrder {
+                    expected: 0,
+                    actual: 1,
+                },
+            ),
+            (Location::Tti { index: 5, sn: 6 }, IssueCode::ReservedBlock),
+            (
+                Location::Tti { index: 7, sn: 5 },
+                IssueCode::SubtitleNumberOrder,
+            ),
+            (
+                Location::Tti { index: 8, sn: 9 },
+                IssueCode::CumulativeSet(CumulativeStatus::LastInSet),
+            ),
+            (
+                Location::Tti { index: 10, sn: 11 },
+                IssueCode::CumulativeSet(CumulativeStatus::FirstInSet),
+            ),
+            (
+                Location::Tti { index: 12, sn: 13 },
+                IssueCode::UnterminatedChain,
+            ),
+        ] {
+            assert!(issues.contains(&issue), "{:?} not found", issue);
+        }
+
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        stl.gsi.mnc = 20;
+        stl.gsi.mnr = 1;
+        let issues = stl
+            .validate()
+            .issues
+            .into_iter()
+            .filter(|issue| issue.location == Location::Tti { index: 1, sn: 2 })
+            .map(|issue| issue.code)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                IssueCode::TooManyRows { rows: 2, max: 1 },
+                IssueCode::RowTooLong { chars: 25, max: 20 }
+            ],
+            issues
+        );
+    }
+}
@@ -0,0 +1,403 @@
+//! WebVTT import and export.
+
+use std::collections::BTreeSet;
+
+use thiserror::Error;
+
+use super::*;
+use styled::{Span, COLOURS};
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum VttError {
+    #[error("Missing WEBVTT header")]
+    Header,
+}
+
+/// Problem found while importing a WebVTT document, with the line it was found on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VttWarning {
+    #[doc = "Cue skipped, its timing line is malformed or it does not end after it starts"]
+    Timing(usize),
+    #[doc = "Cue setting that cannot be represented in STL, ignored"]
+    Setting { line: usize, setting: String },
+    #[doc = "Cue skipped as [`Stl::from_cues`] rejects it, the cues numbered in document order"]
+    Cue { line: usize, error: CueError },
+}
+
+// Cue of a WebVTT document, times in milliseconds.
+struct VttCue {
+    start: u64,
+    end: u64,
+    settings: Vec<String>,
+    text: String,
+    line: usize,
+}
+
+// Cues of a WebVTT document, comments, style and region blocks left out.
+fn parse_vtt(vtt: &str) -> Result<(Vec<VttCue>, Vec<VttWarning>), VttError> {
+    let vtt = vtt.strip_prefix('\u{feff}').unwrap_or(vtt);
+    let mut lines = vtt
+        .lines()
+        .map(|line| line.trim_end_matches('\r'))
+        .enumerate()
+        .map(|(number, line)| (number + 1, line));
+    let header = lines.next().map(|(_, line)| line).unwrap_or_default();
+    if header != "WEBVTT" && !header.starts_with("WEBVTT ") && !header.starts_with("WEBVTT\t") {
+        return Err(VttError::Header);
+    }
+    let mut cues = vec![];
+    let mut warnings = vec![];
+    // Header lines up to the first blank line.
+    lines
+        .by_ref()
+        .take_while(|(_, line)| !line.trim().is_empty())
+        .count();
+    while let Some((number, line)) = lines.next() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut block = vec![(number, line)];
+        block.extend(
+            lines
+                .by_ref()
+                .take_while(|(_, line)| !line.trim().is_empty()),
+        );
+        if ["NOTE", "STYLE", "REGION"]
+            .iter()
+            .any(|keyword| line == *keyword || line.starts_with(&format!("{} ", keyword)))
+        {
+            continue;
+        }
+        // An optional cue identifier comes before the timing line.
+        let Some(timing) = block.iter().position(|(_, line)| line.contains("-->")) else {
+            warnings.push(VttWarning::Timing(number));
+            continue;
+        };
+        let (number, line) = block[timing];
+        let Some((start, end, settings)) = parse_timing(line).filter(|(start, end, _)| end > start)
+        else {
+            warnings.push(VttWarning::Timing(number));
+            continue;
+        };
+        let text = block[timing + 1..]
+            .iter()
+            .map(|(_, line)| *line)
+            .collect::<Vec<_>>()
+            .join("\n");
+        cues.push(VttCue {
+            start,
+            end,
+            settings,
+            text,
+            line: number,
+        });
+    }
+    Ok((cues, warnings))
+}
+
+fn parse_timing(line: &str) -> Option<(u64, u64, Vec<String>)> {
+    let (start, rest) = line.split_once("-->")?;
+    let mut rest = rest.split_whitespace();
+    let end = rest.next()?;
+    Some((
+        parse_time(start.trim())?,
+        parse_time(end)?,
+        rest.map(String::from).collect(),
+    ))
+}
+
+// Milliseconds of [HH:]MM:SS.mmm.
+fn parse_time(time: &str) -> Option<u64> {
+    let (hms, millis) = time.split_once('.')?;
+    let fields = hms
+        .split(':')
+        .map(|field| field.parse::<u64>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    let (h, m, s) = match fields[..] {
+        [h, m, s] => (h, m, s),
+        [m, s] => (0, m, s),
+        _ => return None,
+    };
+    if m >= 60 || s >= 60 || millis.len() != 3 {
+        return None;
+    }
+    Some(((h * 60 + m) * 60 + s) * 1000 + millis.parse::<u64>().ok()?)
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&nbsp;", "\u{a0}")
+        .replace("&lrm;", "")
+        .replace("&rlm;", "")
+        .replace("&amp;", "&")
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct VttExportOptions {
+    #[doc = "Same as SrtExportOptions::rebase_to_tcp"]
+    pub rebase_to_tcp: bool,
+}
+
+impl Stl {
+    /// Document made of the cues of a WebVTT document, see [`Stl::from_cues`]. Times
+    /// are rounded to the nearest frame at the frame rate of `gsi`, which
+    /// [`GsiBlock::from_profile`] sets up. `align` and percentage `line` settings
+    /// replace the justification and vertical position of `format`, other settings are
+    /// ignored with a warning. Italics and underline are kept for open subtitling, other
+    /// markup is removed. Cues with malformed timings or that cannot be converted, such
+    /// as overlapping ones, are skipped with a warning.
+    pub fn from_vtt(
+        vtt: &str,
+        gsi: GsiBlock,
+        format: TtiFormat,
+    ) -> Result<(Stl, Vec<VttWarning>), VttError> {
+        let fps = gsi.dfc.get_fps();
+        let open_subtitling = gsi.dsc == DisplayStandardCode::OpenSubtitling;
+        let (vtt_cues, mut warnings) = parse_vtt(vtt)?;
+        let mut cues = vec![];
+        let mut lines = vec![];
+        for cue in vtt_cues {
+            let mut format = format;
+            for setting in &cue.settings {
+                let applied = match setting.split_once(':') {
+                    Some(("align", align)) => match align {
+                        "left" | "start" => Some((1, format.vp)),
+                        "center" | "middle" => Some((2, format.vp)),
+                        "right" | "end" => Some((3, format.vp)),
+                        _ => None,
+                    },
+                    Some(("line", line)) => line
+                        .strip_suffix('%')
+                        .or_else(|| line.strip_suffix("%,start"))
+                        .and_then(|line| line.parse::<f64>().ok())
+                        .filter(|line| (0.0..=100.0).contains(line))
+                        .map(|line| (format.jc, vertical_position(line, open_subtitling))),
+                    _ => None,
+                };
+                match applied {
+                    Some((jc, vp)) => (format.jc, format.vp) = (jc, vp),
+                    None => warnings.push(VttWarning::Setting {
+                        line: cue.line,
+                        setting: setting.clone(),
+                    }),
+                }
+            }
+            cues.push(Cue {
+                start: Time::from_millis(cue.start, fps),
+                end: Time::from_millis(cue.end, fps),
+                text: unescape(&srt::cue_text(&cue.text, SrtStyles::Map, open_subtitling)),
+                format,
+            });
+            lines.push(cue.line);
+        }
+        // Positions in the document of the cues left.
+        let mut kept = (0..cues.len()).collect::<Vec<_>>();
+        loop {
+            let errors = match Stl::from_cues(gsi.clone(), cues.clone()) {
+                Ok(stl) => return Ok((stl, warnings)),
+                Err(errors) => errors,
+            };
+            let skipped = errors.iter().map(CueError::cue).collect::<BTreeSet<_>>();
+            for error in errors {
+                warnings.push(VttWarning::Cue {
+                    line: lines[kept[error.cue()]],
+                    error: error.renumber(|cue| kept[cue]),
+                });
+            }
+            (cues, kept) = cues
+                .into_iter()
+                .zip(kept)
+                .enumerate()
+                .filter(|(index, _)| !skipped.contains(index))
+                .map(|(_, cue)| cue)
+                .unzip();
+        }
+    }
+
+    /// WebVTT document of the displayed subtitles, identified by their subtitle
+    /// numbers. Vertical positions and justification become `line` and `align` cue
+    /// settings, italics, underline and colours become `<i>`, `<u>` and `<c>` spans.
+    pub fn to_vtt(&self, options: &VttExportOptions) -> String {
+        let fps = self.gsi.dfc.get_fps();
+        let origin = self.time_origin(options.rebase_to_tcp);
+        let mut vtt = String::from("WEBVTT\n");
+        for chain in self.cues() {
+            let first = &self.ttis[chain.start];
+            vtt.push_str(&format!(
+                "\n{}\n{} --> {}",
+                first.sn,
+                styled::clock_time(styled::millis(&first.tci, &origin, fps), '.'),
+                styled::clock_time(styled::millis(&first.tco, &origin, fps), '.'),
+            ));
+            if let Some(line) = self.line_percentage(first.vp) {
+                vtt.push_str(&format!(" line:{}%", line));
+            }
+            match Justification::from_jc(first.jc) {
+                Justification::Left => vtt.push_str(" align:left"),
+                Justification::Centered => vtt.push_str(" align:center"),
+                Justification::Right => vtt.push_str(" align:right"),
+                _ => {}
+            }
+            vtt.push('\n');
+            for row in styled::styled_rows(&self.ttis[chain]) {
+                vtt.push_str(&row.iter().map(styled_text).collect::<String>());
+                vtt.push('\n');
+            }
+        }
+        vtt
+    }
+
+    // Distance of the row from the top of the screen, teletext rows being numbered from
+    // 1 to 23 and open subtitling lines from 0 to 99.
+    pub(crate) fn line_percentage(&self, vp: u8) -> Option<u32> {
+        match self.gsi.dsc {
+            DisplayStandardCode::OpenSubtitling => Some(vp.min(99) as u32),
+            _ if vp == 0 => None,
+            _ => Some((vp.min(23) as u32 - 1) * 100 / 23),
+        }
+    }
+}
+
+// Row at `line` percent from the top of the screen, the inverse of
+// `Stl::line_percentage`.
+pub(crate) fn vertical_position(line: f64, open_subtitling: bool) -> u8 {
+    match open_subtitling {
+        true => (line.round() as u8).min(99),
+        false => (1 + (line * 23.0 / 100.0).round() as u8).min(23),
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn styled_text(span: &Span) -> String {
+    let mut text = escape(&span.text);
+    if span.underline {
+        text = format!("<u>{}</u>", text);
+    }
+    if span.italic {
+        text = format!("<i>{}</i>", text);
+    }
+    if !span.is_white() {
+        text = format!("<c.{}>{}</c>", COLOURS[span.colour as usize].0, text);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_vtt() {
+        let format = TtiFormat {
+            jc: 2,
+            vp: 20,
+            dh: false,
+        };
+        let vtt = "\u{feff}WEBVTT - translated\nKind: captions\n\n\
+                   NOTE reviewed\nby vendor\n\n\
+                   STYLE\n::cue { color: yellow }\n\n\
+                   intro\n00:01.000 --> 00:02.500 align:left line:0% size:50%\n\
+                   <v Tom><i>Hello</i> <b>world</b> &amp; <c.yellow>you</c>\n\n\
+                   01:00:03.000 --> 01:00:04.00\nMalformed\n\n\
+                   00:00:05.000 --> 00:00:04.000\nBackwards\n\n\
+                   00:00:06.000 --> 00:00:07.000 line:3\nBye\n";
+        let (stl, warnings) = Stl::from_vtt(vtt, GsiBlock::new(), format).unwrap();
+        assert_eq!(2, stl.gsi.tns);
+        assert_eq!(Time::new(0, 0, 2, 13), stl.ttis[0].tco);
+        assert_eq!("Hello world & you", stl.ttis[0].get_text().trim());
+        assert_eq!((1, 1), (stl.ttis[0].jc, stl.ttis[0].vp));
+        assert_eq!((2, 20), (stl.ttis[1].jc, stl.ttis[1].vp));
+        assert_eq!(
+            vec![
+                VttWarning::Timing(14),
+                VttWarning::Timing(17),
+                VttWarning::Setting {
+                    line: 11,
+                    setting: "size:50%".to_string()
+                },
+                VttWarning::Setting {
+                    line: 20,
+                    setting: "line:3".to_string()
+                },
+            ],
+            warnings
+        );
+
+        // The cue overlapping the first one and the one the Latin table cannot encode
+        // are left out.
+        let vtt = "WEBVTT\n\n00:01.000 --> 00:03.000\nOne\n\n\
+                   00:02.000 --> 00:04.000\nTwo\n\n\
+                   00:05.000 --> 00:06.000\nТри\n\n\
+                   00:07.000 --> 00:08.000\nFour\n";
+        let (stl, warnings) = Stl::from_vtt(vtt, GsiBlock::new(), format).unwrap();
+        let texts = stl
+            .ttis
+            .iter()
+            .map(|tti| tti.get_text().trim().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["One", "Four"], texts);
+        assert_eq!(
+            vec![
+                VttWarning::Cue {
+                    line: 6,
+                    error: CueError::Overlap(1, 0)
+                },
+                VttWarning::Cue {
+                    line: 9,
+                    error: CueError::Unmappable {
+                        cue: 2,
+                        chars: vec!['Т', 'р', 'и']
+                    }
+                },
+            ],
+            warnings
+        );
+
+        assert_eq!(
+            Err(VttError::Header),
+            Stl::from_vtt("00:01.000 --> 00:02.000\nHi\n", GsiBlock::new(), format)
+        );
+    }
+
+    #[test]
+    fn test_vtt_round_trip() {
+        let stl = parse_stl_from_file("stls/test.stl").unwrap();
+        let vtt = stl.to_vtt(&VttExportOptions::default());
+        let format = TtiFormat {
+            jc: 0,
+            vp: 0,
+            dh: false,
+        };
+        let (imported, warnings) = Stl::from_vtt(&vtt, GsiBlock::new(), format).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(stl.ttis[1].vp, imported.ttis[1].vp);
+        assert_eq!(stl.ttis[1].tci, imported.ttis[1].tci);
+    }
+
+    #[test]
+    fn test_to_vtt() {
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        let mut tf = b"\x0b\x0bTom & \x80Jerry\x81 <3\x0a\x0a".to_vec();
+        tf.resize(112, 0x8f);
+        stl.ttis[2].tf = tf;
+        stl.ttis[2].jc = 1;
+        let vtt = stl.to_vtt(&VttExportOptions {
+            rebase_to_tcp: true,
+        });
+        assert!(
+            vtt.starts_with("WEBVTT\n\n1\n00:00:00.000 --> 00:00:00.200 line:73% align:center\n\n")
+        );
+        assert!(vtt.contains(
+            "\n2\n00:00:06.320 --> 00:00:09.360 line:82% align:center\n\
+             <c.cyan>-Ellis Island,</c>\n<c.cyan>îlot de larmes et d'exil,</c>\n"
+        ));
+        assert!(vtt.contains(" align:left\nTom &amp; <i>Jerry</i> &lt;3\n"));
+        assert_eq!(13, vtt.matches(" --> ").count());
+    }
+}
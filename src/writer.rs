@@ -0,0 +1,924 @@
+use std::borrow::Cow;
+#[cfg(feature = "fs")]
+use std::fs;
+#[cfg(feature = "fs")]
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+#[cfg(feature = "fs")]
+use std::io::BufWriter;
+#[cfg(feature = "fs")]
+use std::path::{Path, PathBuf};
+#[cfg(feature = "fs")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use thiserror::Error;
+
+use super::*;
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum SerializeError {
+    #[error("IoError: {0}")]
+    IoError(String),
+    #[error("GSI field {field} does not fit in {width} bytes")]
+    FieldOverflow { field: &'static str, width: usize },
+    #[error("Block {index} is {actual} bytes long")]
+    BlockSize {
+        #[doc = "Position of the block in the file, the GSI block being 0"]
+        index: usize,
+        actual: usize,
+    },
+    #[error("Block {index} starts a chain of {subtitles} subtitles, more than the {max} allowed per disk")]
+    DiskOverflow {
+        index: usize,
+        subtitles: usize,
+        max: usize,
+    },
+    #[error("{blocks} TTI blocks, more than the 65535 of a disk, see Stl::write_multi_disk")]
+    CapacityExceeded { blocks: usize },
+    #[error("Document is not valid:\n{0}")]
+    Invalid(ValidationReport),
+    #[error("{0}")]
+    Parse(#[from] ParseError),
+}
+
+impl From<io::Error> for SerializeError {
+    fn from(err: io::Error) -> Self {
+        Self::IoError(err.to_string())
+    }
+}
+
+/// Adjustments applied to the GSI block when writing, the document itself is left
+/// untouched.
+#[derive(Debug, Clone, Default)]
+pub struct WriteOptions {
+    #[doc = "Recompute tnb/tns/tng from the TTI blocks, recommended after editing `ttis`"]
+    pub fix_totals: bool,
+    #[doc = "Set the revision date to `revision_date`, or to today"]
+    #[cfg(feature = "chrono")]
+    pub update_revision_date: bool,
+    #[doc = "Revision date written with `update_revision_date`, today if not set"]
+    #[cfg(feature = "chrono")]
+    pub revision_date: Option<chrono::NaiveDate>,
+}
+
+impl Stl {
+    pub fn write_to<W: Write>(&self, w: &mut W) -> Result<(), SerializeError> {
+        self.write_to_with_options(w, &WriteOptions::default())
+    }
+
+    pub fn write_to_with_options<W: Write>(
+        &self,
+        w: &mut W,
+        options: &WriteOptions,
+    ) -> Result<(), SerializeError> {
+        write_blocks(w, &self.output_gsi(options).serialize()?, &self.ttis)
+    }
+
+    /// Validate the document as it would be written and write it only if no error was
+    /// found, the warnings are returned.
+    pub fn write_validated<W: Write>(
+        &self,
+        w: &mut W,
+        options: &WriteOptions,
+    ) -> Result<ValidationReport, SerializeError> {
+        let gsi = self.output_gsi(options);
+        let report = validate::validate(&gsi, &self.ttis);
+        if report.has_errors() {
+            return Err(SerializeError::Invalid(report));
+        }
+        write_blocks(w, &gsi.serialize()?, &self.ttis)?;
+        Ok(report)
+    }
+
+    fn output_gsi(&self, options: &WriteOptions) -> Cow<'_, GsiBlock> {
+        #[cfg(feature = "chrono")]
+        let update_revision_date = options.update_revision_date;
+        #[cfg(not(feature = "chrono"))]
+        let update_revision_date = false;
+        if !options.fix_totals && !update_revision_date {
+            return Cow::Borrowed(&self.gsi);
+        }
+        let mut gsi = self.gsi.clone();
+        if options.fix_totals {
+            gsi.set_totals(self.totals());
+        }
+        #[cfg(feature = "chrono")]
+        if update_revision_date {
+            let date = options.revision_date.unwrap_or_else(today);
+            gsi.rd = date.format("%y%m%d").to_string();
+        }
+        Cow::Owned(gsi)
+    }
+
+    #[cfg(feature = "fs")]
+    pub fn write_to_file(&self, filename: impl AsRef<Path>) -> Result<(), SerializeError> {
+        self.write_to_file_with_options(filename, &WriteOptions::default())
+    }
+
+    #[cfg(feature = "fs")]
+    pub fn write_to_file_with_options(
+        &self,
+        filename: impl AsRef<Path>,
+        options: &WriteOptions,
+    ) -> Result<(), SerializeError> {
+        let filename = filename.as_ref();
+        let write = || -> Result<(), SerializeError> {
+            let mut f = BufWriter::new(File::create(filename)?);
+            self.write_to_with_options(&mut f, options)?;
+            Ok(f.flush()?)
+        };
+        write().map_err(|err| match err {
+            SerializeError::IoError(err) => {
+                SerializeError::IoError(format!("{}: {}", filename.display(), err))
+            }
+            err => err,
+        })
+    }
+
+    #[cfg(feature = "fs")]
+    /// Write the file through a temporary file renamed over `filename` once complete,
+    /// so that readers never see a partially written file.
+    pub fn write_to_file_atomic(&self, filename: impl AsRef<Path>) -> Result<(), SerializeError> {
+        write_file_atomic(filename.as_ref(), |w| self.write_to(w))
+    }
+
+    #[cfg(feature = "fs")]
+    /// Split the document in files holding at most `max_subtitles_per_disk` subtitles,
+    /// numbered from 1 with the Disk Sequence Number before the extension of
+    /// `base_path` (`prog.stl` gives `prog.1.stl`, `prog.2.stl`...).
+    ///
+    /// Extension block chains and cumulative sets are never split across disks. Each
+    /// part gets a copy of the GSI block with its own totals, tnd and dsn.
+    ///
+    /// Every block is checked before the first file is created. If writing a file still
+    /// fails, the files created so far are removed.
+    pub fn write_multi_disk(
+        &self,
+        base_path: impl AsRef<Path>,
+        max_subtitles_per_disk: usize,
+    ) -> Result<Vec<PathBuf>, SerializeError> {
+        let base_path = base_path.as_ref();
+        let disks = self.split_disks(max_subtitles_per_disk)?;
+        let tnd = u8::try_from(disks.len()).map_err(|_| SerializeError::FieldOverflow {
+            field: "TND",
+            width: 1,
+        })?;
+        // Serialize every GSI first so that no file is created if one of them fails.
+        let gsis = disks
+            .iter()
+            .zip(1..=tnd)
+            .map(|(ttis, dsn)| {
+                let mut gsi = self.gsi.clone();
+                gsi.set_totals(totals(ttis));
+                gsi.tnd = tnd;
+                gsi.dsn = dsn;
+                gsi.serialize()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut paths = Vec::with_capacity(disks.len());
+        for ((ttis, gsi), dsn) in disks.iter().zip(gsis).zip(1..=tnd) {
+            let path = disk_path(base_path, dsn);
+            let written = File::create(&path)
+                .map_err(SerializeError::from)
+                .and_then(|f| {
+                    paths.push(path.clone());
+                    let mut f = BufWriter::new(f);
+                    write_blocks(&mut f, &gsi, ttis)?;
+                    Ok(f.flush()?)
+                });
+            if let Err(err) = written {
+                // An incomplete set of disks is of no use, remove the files created.
+                for path in &paths {
+                    let _ = fs::remove_file(path);
+                }
+                return Err(match err {
+                    SerializeError::IoError(err) => {
+                        SerializeError::IoError(format!("{}: {}", path.display(), err))
+                    }
+                    err => err,
+                });
+            }
+        }
+        Ok(paths)
+    }
+
+    #[cfg(feature = "fs")]
+    // Consecutive TTI blocks grouped in disks, a disk always holds whole chains and
+    // cumulative sets.
+    fn split_disks(&self, max_subtitles: usize) -> Result<Vec<&[TtiBlock]>, SerializeError> {
+        for unit in edit::units(&self.ttis) {
+            let subtitles = count_subtitles(&self.ttis[unit.clone()]) as usize;
+            if subtitles > max_subtitles {
+                return Err(SerializeError::DiskOverflow {
+                    index: unit.start + 1,
+                    subtitles,
+                    max: max_subtitles,
+                });
+            }
+        }
+        Ok(edit::split_by_count(&self.ttis, max_subtitles)
+            .into_iter()
+            .map(|part| &self.ttis[part])
+            .collect())
+    }
+
+    /// Serialized document: the GSI block followed by every TTI block.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, SerializeError> {
+        let mut bytes = Vec::with_capacity(1024 + 128 * self.ttis.len());
+        self.write_to(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+/// Writes a document block by block, for subtitles produced over time.
+///
+/// A GSI block is written up front and patched once all the blocks are pushed with
+/// the totals (tnb, tns, tng) and the first in-cue (tcf). The patch is also done,
+/// ignoring errors, when the writer is dropped without calling
+/// [`StlWriter::finalize`] so that an interrupted recording remains readable.
+pub struct StlWriter<W: Write + Seek> {
+    inner: Option<W>,
+    start: u64,
+    gsi: GsiBlock,
+    blocks: usize,
+    #[doc = "Group and number of the subtitles, counted once each as in the TNS of `Stl`"]
+    numbers: std::collections::BTreeSet<(u8, u16)>,
+    groups: std::collections::BTreeSet<u8>,
+    first_in_cue: Option<String>,
+}
+
+impl<W: Write + Seek> StlWriter<W> {
+    /// Write `gsi` at the current position of `w`, its totals are updated later.
+    pub fn new(mut w: W, gsi: GsiBlock) -> Result<StlWriter<W>, SerializeError> {
+        let start = w.stream_position()?;
+        w.write_all(check_block_size(&gsi.serialize()?, 0, 1024)?)?;
+        Ok(StlWriter {
+            inner: Some(w),
+            start,
+            gsi,
+            blocks: 0,
+            numbers: Default::default(),
+            groups: Default::default(),
+            first_in_cue: None,
+        })
+    }
+
+    pub fn push(&mut self, tti: &TtiBlock) -> Result<(), SerializeError> {
+        if self.blocks == u16::MAX as usize {
+            return Err(SerializeError::CapacityExceeded {
+                blocks: self.blocks + 1,
+            });
+        }
+        let w = self.inner.as_mut().expect("writer not finalized");
+        w.write_all(check_block_size(&tti.serialize(), self.blocks + 1, 128)?)?;
+        self.blocks += 1;
+        self.groups.insert(tti.sgn);
+        if matches!(
+            tti.get_extension_block_kind(),
+            ExtensionBlockKind::Extension(_) | ExtensionBlockKind::Last
+        ) {
+            self.numbers.insert((tti.sgn, tti.sn));
+            if self.first_in_cue.is_none() {
+                self.first_in_cue = Some(tti.tci.to_time_code());
+            }
+        }
+        Ok(())
+    }
+
+    /// Patch the GSI block and give back the underlying writer, positioned after the
+    /// last block.
+    pub fn finalize(mut self) -> Result<W, SerializeError> {
+        self.patch_gsi()?;
+        Ok(self.inner.take().expect("writer not finalized"))
+    }
+
+    fn patch_gsi(&mut self) -> Result<(), SerializeError> {
+        self.gsi.set_totals((
+            self.blocks.min(u16::MAX as usize) as u16,
+            self.numbers.len().min(u16::MAX as usize) as u16,
+            self.groups.len().max(1) as u16,
+        ));
+        if let Some(tcf) = &self.first_in_cue {
+            self.gsi.tcf = tcf.clone();
+        }
+        let gsi = self.gsi.serialize()?;
+        let w = self.inner.as_mut().expect("writer not finalized");
+        let end = w.stream_position()?;
+        w.seek(io::SeekFrom::Start(self.start))?;
+        w.write_all(check_block_size(&gsi, 0, 1024)?)?;
+        w.seek(io::SeekFrom::Start(end))?;
+        Ok(w.flush()?)
+    }
+}
+
+impl<W: Write + Seek> Drop for StlWriter<W> {
+    fn drop(&mut self) {
+        if self.inner.is_some() {
+            let _ = self.patch_gsi();
+        }
+    }
+}
+
+/// Write a document from a stream of TTI blocks, only one block being held in memory
+/// at a time. The totals and first in-cue of `gsi` are patched as with [`StlWriter`].
+pub fn write_stl<W, I>(w: W, gsi: GsiBlock, blocks: I) -> Result<W, SerializeError>
+where
+    W: Write + Seek,
+    I: IntoIterator<Item = TtiBlock>,
+{
+    let mut writer = StlWriter::new(w, gsi)?;
+    for tti in blocks {
+        writer.push(&tti)?;
+    }
+    writer.finalize()
+}
+
+#[cfg(feature = "fs")]
+/// Modify the GSI block of an existing file, the TTI blocks are left untouched.
+///
+/// With `atomic` set, the file is copied and the copy renamed over the original as
+/// with [`Stl::write_to_file_atomic`], otherwise the GSI block is overwritten in place.
+pub fn patch_gsi_in_file<F>(
+    filename: impl AsRef<Path>,
+    atomic: bool,
+    patch: F,
+) -> Result<(), SerializeError>
+where
+    F: FnOnce(&mut GsiBlock),
+{
+    let filename = filename.as_ref();
+    let with_path = |err| match err {
+        SerializeError::IoError(err) => {
+            SerializeError::IoError(format!("{}: {}", filename.display(), err))
+        }
+        err => err,
+    };
+    let mut f = fs::OpenOptions::new()
+        .read(true)
+        .write(!atomic)
+        .open(filename)
+        .map_err(|err| with_path(err.into()))?;
+    let mut data = [0u8; 1024];
+    f.read_exact(&mut data)
+        .map_err(|err| with_path(err.into()))?;
+    let mut gsi = GsiBlock::from_bytes(&data)?;
+    patch(&mut gsi);
+    let gsi = gsi.to_bytes()?;
+
+    if atomic {
+        write_file_atomic(filename, |w| {
+            w.write_all(&gsi)?;
+            io::copy(&mut f, w)?;
+            Ok(())
+        })
+    } else {
+        let mut write = || -> Result<(), io::Error> {
+            f.seek(io::SeekFrom::Start(0))?;
+            f.write_all(&gsi)?;
+            f.sync_all()
+        };
+        write().map_err(|err| with_path(err.into()))
+    }
+}
+
+// Number of the next temporary file of the process, so that concurrent atomic writes
+// to the same destination from several threads do not share one.
+#[cfg(feature = "fs")]
+static TMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(feature = "fs")]
+// The temporary file is created next to the destination so that the final rename
+// stays on the same file system, with the permissions of the destination if it
+// exists, and it is removed if anything fails.
+pub(crate) fn write_file_atomic<F>(filename: &Path, write: F) -> Result<(), SerializeError>
+where
+    F: FnOnce(&mut BufWriter<File>) -> Result<(), SerializeError>,
+{
+    let name = filename
+        .file_name()
+        .ok_or_else(|| SerializeError::IoError(format!("{}: not a file", filename.display())))?;
+    let mut tmp_name = std::ffi::OsString::from(".");
+    tmp_name.push(name);
+    tmp_name.push(format!(
+        ".{}.{}.tmp",
+        std::process::id(),
+        TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let tmp_filename = filename.with_file_name(tmp_name);
+
+    let write_tmp = || -> Result<(), SerializeError> {
+        let f = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&tmp_filename)?;
+        if let Ok(metadata) = fs::metadata(filename) {
+            f.set_permissions(metadata.permissions())?;
+        }
+        let mut f = BufWriter::new(f);
+        write(&mut f)?;
+        f.into_inner()
+            .map_err(|err| SerializeError::from(err.into_error()))?
+            .sync_all()?;
+        rename(&tmp_filename, filename)?;
+        Ok(())
+    };
+    write_tmp().map_err(|err| {
+        let _ = fs::remove_file(&tmp_filename);
+        match err {
+            SerializeError::IoError(err) => {
+                SerializeError::IoError(format!("{}: {}", filename.display(), err))
+            }
+            err => err,
+        }
+    })
+}
+
+#[cfg(all(feature = "fs", not(windows)))]
+fn rename(from: &Path, to: &Path) -> Result<(), io::Error> {
+    fs::rename(from, to)?;
+    // Persist the rename itself, best effort as not all platforms allow it.
+    if let Some(dir) = to.parent() {
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        let _ = File::open(dir).and_then(|dir| dir.sync_all());
+    }
+    Ok(())
+}
+
+// Renaming over a file fails on Windows while another process (an antivirus, the
+// playout server...) has it open, retry for a short while before giving up.
+#[cfg(all(feature = "fs", windows))]
+fn rename(from: &Path, to: &Path) -> Result<(), io::Error> {
+    let mut attempts = 0;
+    loop {
+        match fs::rename(from, to) {
+            Err(err) if err.kind() == io::ErrorKind::PermissionDenied && attempts < 10 => {
+                attempts += 1;
+                std::thread::sleep(std::time::Duration::from_millis(50));
+            }
+            res => return res,
+        }
+    }
+}
+
+// Every block is serialized and checked before the first byte is written so that a
+// bad block leaves nothing behind.
+fn write_blocks<W: Write>(w: &mut W, gsi: &[u8], ttis: &[TtiBlock]) -> Result<(), SerializeError> {
+    if ttis.len() > u16::MAX as usize {
+        return Err(SerializeError::CapacityExceeded { blocks: ttis.len() });
+    }
+    check_block_size(gsi, 0, 1024)?;
+    let ttis = ttis
+        .iter()
+        .enumerate()
+        .map(|(index, tti)| {
+            let block = tti.serialize();
+            check_block_size(&block, index + 1, 128)?;
+            Ok(block)
+        })
+        .collect::<Result<Vec<_>, SerializeError>>()?;
+    w.write_all(gsi)?;
+    for tti in &ttis {
+        w.write_all(tti)?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "fs")]
+fn disk_path(base_path: &Path, dsn: u8) -> PathBuf {
+    let mut name = base_path.file_stem().unwrap_or_default().to_os_string();
+    name.push(format!(".{}", dsn));
+    if let Some(extension) = base_path.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    base_path.with_file_name(name)
+}
+
+fn check_block_size(block: &[u8], index: usize, size: usize) -> Result<&[u8], SerializeError> {
+    if block.len() != size {
+        return Err(SerializeError::BlockSize {
+            index,
+            actual: block.len(),
+        });
+    }
+    Ok(block)
+}
+
+#[cfg(test)]
+// Without the chrono feature WriteOptions has fewer fields to leave to the defaults.
+#[cfg_attr(not(feature = "chrono"), allow(clippy::needless_update))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_to() {
+        let data = std::fs::read("stls/test.stl").unwrap();
+        let stl = parse_stl_from_slice(&data).unwrap();
+        let mut buffer = vec![];
+        stl.write_to(&mut buffer).unwrap();
+        assert_eq!(data, buffer);
+    }
+
+    #[test]
+    fn test_to_bytes() {
+        let data = std::fs::read("stls/test.stl").unwrap();
+        let stl = parse_stl_from_slice(&data).unwrap();
+        let bytes = stl.to_bytes().unwrap();
+        assert_eq!(1024 + 128 * 13, bytes.capacity());
+        assert_eq!(data, bytes);
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn test_write_to_file_path_buf() {
+        let stl = parse_stl_from_file(std::path::PathBuf::from("stls/test.stl")).unwrap();
+        let path = std::env::temp_dir().join(format!(
+            "ebustl_test_write_to_file_path_buf_{}.stl",
+            std::process::id()
+        ));
+        stl.write_to_file(&path).unwrap();
+        assert_eq!(
+            std::fs::read("stls/test.stl").unwrap(),
+            std::fs::read(&path).unwrap()
+        );
+        std::fs::remove_file(&path).unwrap();
+
+        let missing = std::path::PathBuf::from("stls/missing.stl");
+        let err = parse_stl_from_file(&missing).unwrap_err();
+        assert!(err.to_string().contains("stls/missing.stl"));
+        let err = stl.write_to_file("stls/missing/out.stl").unwrap_err();
+        assert!(err.to_string().contains("stls/missing/out.stl"));
+    }
+
+    // Variations of the test file covering the code pages, display standards, frame
+    // rates and character tables.
+    fn fixtures() -> Vec<Vec<u8>> {
+        let data = std::fs::read("stls/test.stl").unwrap();
+        let mut fixtures = vec![data.clone()];
+        for cpn in [b"437", b"850", b"860", b"863", b"865"] {
+            let mut fixture = data.clone();
+            fixture[..3].copy_from_slice(cpn);
+            // Accented letters and symbols from the upper half of the code page.
+            fixture[16..26]
+                .copy_from_slice(&[0x90, 0x82, 0x85, 0x89, 0x9b, 0x9c, 0xa4, 0xe1, 0xfd, 0x80]);
+            fixture[448..452].copy_from_slice(&[0xb0, 0xc5, 0xdb, 0xfe]);
+            fixtures.push(fixture);
+        }
+        let mut fixture = data.clone();
+        fixture[3..14].copy_from_slice(b"STL30.01001");
+        fixture[255] = b'0';
+        fixtures.push(fixture);
+        let mut fixture = data;
+        fixture[11] = b'2';
+        fixture[13] = b'3';
+        fixture[1024 + 20..1024 + 24].copy_from_slice(&[0xc1, 0xe0, 0xe1, 0xe2]);
+        fixtures.push(fixture);
+        fixtures
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for fixture in fixtures() {
+            let stl = parse_stl_from_slice(&fixture).unwrap();
+            assert_eq!(fixture, stl.to_bytes().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_write_fix_totals() {
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        stl.ttis.truncate(10);
+        let bytes = stl.to_bytes().unwrap();
+        assert_eq!(b"0001300013001", &bytes[238..251]);
+
+        let options = WriteOptions {
+            fix_totals: true,
+            ..Default::default()
+        };
+        let mut bytes = vec![];
+        stl.write_to_with_options(&mut bytes, &options).unwrap();
+        assert_eq!(b"0001000010001", &bytes[238..251]);
+        assert_eq!(13, stl.gsi.tnb);
+
+        stl.recompute_totals();
+        assert_eq!(bytes, stl.to_bytes().unwrap());
+    }
+
+    #[test]
+    #[cfg(feature = "chrono")]
+    fn test_write_revision_date() {
+        let stl = parse_stl_from_file("stls/test.stl").unwrap();
+        let options = WriteOptions {
+            update_revision_date: true,
+            revision_date: chrono::NaiveDate::from_ymd_opt(2024, 2, 29),
+            ..Default::default()
+        };
+        let mut bytes = vec![];
+        stl.write_to_with_options(&mut bytes, &options).unwrap();
+        assert_eq!(b"131010240229", &bytes[224..236]);
+        assert_eq!("131017", stl.gsi.rd);
+    }
+
+    #[test]
+    fn test_numeric_field_overflow() {
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        stl.gsi.tng = 1000;
+        assert_eq!(
+            Err(SerializeError::FieldOverflow {
+                field: "TNG",
+                width: 3
+            }),
+            stl.to_bytes()
+        );
+        stl.gsi.tng = 999;
+        stl.gsi.dsn = 10;
+        let mut buffer = vec![];
+        assert_eq!(
+            Err(SerializeError::FieldOverflow {
+                field: "DSN",
+                width: 1
+            }),
+            stl.write_to(&mut buffer)
+        );
+    }
+
+    #[test]
+    fn test_block_size() {
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        stl.ttis[2].tf.push(0x8f);
+        assert_eq!(
+            Err(SerializeError::BlockSize {
+                index: 3,
+                actual: 129
+            }),
+            stl.to_bytes()
+        );
+        let mut buffer = vec![];
+        assert!(stl.write_to(&mut buffer).is_err());
+        assert!(buffer.is_empty());
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn test_write_to_file_atomic() {
+        let stl = parse_stl_from_file("stls/test.stl").unwrap();
+        let dir = std::env::temp_dir().join(format!(
+            "ebustl_test_write_to_file_atomic_{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+        let path = dir.join("out.stl");
+        fs::write(&path, b"previous content").unwrap();
+
+        stl.write_to_file_atomic(&path).unwrap();
+        assert_eq!(fs::read("stls/test.stl").unwrap(), fs::read(&path).unwrap());
+        assert_eq!(1, fs::read_dir(&dir).unwrap().count());
+
+        let mut broken = parse_stl_from_file("stls/test.stl").unwrap();
+        broken.gsi.tng = 1000;
+        assert!(broken.write_to_file_atomic(&path).is_err());
+        assert_eq!(fs::read("stls/test.stl").unwrap(), fs::read(&path).unwrap());
+        assert_eq!(1, fs::read_dir(&dir).unwrap().count());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+            stl.write_to_file_atomic(&path).unwrap();
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(0o640, mode & 0o777);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn test_write_multi_disk() {
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        // Subtitle 3 continued in an extension block, subtitles 5 to 7 in a cumulative set.
+        stl.ttis[2].ebn = 0;
+        stl.ttis[3].sn = 3;
+        stl.ttis[4].cs = CumulativeStatus::FirstInSet;
+        stl.ttis[5].cs = CumulativeStatus::IntermediateInSet;
+        stl.ttis[6].cs = CumulativeStatus::LastInSet;
+        let dir = std::env::temp_dir().join(format!(
+            "ebustl_test_write_multi_disk_{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+
+        let paths = stl.write_multi_disk(dir.join("prog.stl"), 3).unwrap();
+        assert_eq!(
+            vec![
+                dir.join("prog.1.stl"),
+                dir.join("prog.2.stl"),
+                dir.join("prog.3.stl"),
+                dir.join("prog.4.stl"),
+            ],
+            paths
+        );
+        let parts = paths
+            .iter()
+            .map(|path| parse_stl_from_file(path).unwrap())
+            .collect::<Vec<_>>();
+        let sizes = parts.iter().map(|part| part.ttis.len()).collect::<Vec<_>>();
+        assert_eq!(vec![4, 3, 3, 3], sizes);
+        for (dsn, part) in parts.iter().enumerate() {
+            assert_eq!(4, part.gsi.tnd);
+            assert_eq!(dsn as u8 + 1, part.gsi.dsn);
+            assert_eq!(part.totals(), (part.gsi.tnb, part.gsi.tns, part.gsi.tng));
+            assert_eq!(stl.gsi.opt, part.gsi.opt);
+        }
+
+        assert_eq!(
+            Err(SerializeError::DiskOverflow {
+                index: 5,
+                subtitles: 3,
+                max: 2
+            }),
+            stl.write_multi_disk(dir.join("prog.stl"), 2)
+        );
+
+        // The third disk cannot be created, the first two are removed.
+        fs::remove_dir_all(&dir).unwrap();
+        fs::create_dir_all(dir.join("prog.3.stl")).unwrap();
+        let err = stl.write_multi_disk(dir.join("prog.stl"), 3).unwrap_err();
+        assert!(err.to_string().contains("prog.3.stl"));
+        assert!(!dir.join("prog.1.stl").exists());
+        assert!(!dir.join("prog.2.stl").exists());
+        assert!(dir.join("prog.3.stl").is_dir());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_stl_writer() {
+        let data = std::fs::read("stls/test.stl").unwrap();
+        let stl = parse_stl_from_slice(&data).unwrap();
+        let mut gsi = stl.gsi.clone();
+        gsi.set_totals((0, 0, 0));
+        gsi.tcf = "00000000".to_string();
+
+        let mut writer = StlWriter::new(io::Cursor::new(vec![]), gsi.clone()).unwrap();
+        for tti in &stl.ttis {
+            writer.push(tti).unwrap();
+        }
+        assert_eq!(data, writer.finalize().unwrap().into_inner());
+
+        // Dropping the writer patches the GSI block as well.
+        let mut buffer = io::Cursor::new(vec![]);
+        let mut writer = StlWriter::new(&mut buffer, gsi).unwrap();
+        for tti in &stl.ttis[..5] {
+            writer.push(tti).unwrap();
+        }
+        drop(writer);
+        let partial = parse_stl_from_slice(buffer.get_ref()).unwrap();
+        assert_eq!(
+            (5, 5, 1),
+            (partial.gsi.tnb, partial.gsi.tns, partial.gsi.tng)
+        );
+        assert_eq!("10000000", partial.gsi.tcf);
+    }
+
+    #[test]
+    fn test_stl_writer_groups() {
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        // Subtitles 8 to 13 numbered again from 1 in a second group.
+        for tti in &mut stl.ttis[7..] {
+            tti.sgn = 1;
+            tti.sn -= 7;
+        }
+        stl.recompute_totals();
+        let bytes = stl.to_bytes().unwrap();
+        let mut gsi = stl.gsi.clone();
+        gsi.set_totals((0, 0, 0));
+        let w = write_stl(io::Cursor::new(vec![]), gsi, stl.ttis.clone()).unwrap();
+        assert_eq!(bytes, w.into_inner());
+        let written = parse_stl_from_slice(&bytes).unwrap();
+        assert_eq!(
+            (13, 13, 2),
+            (written.gsi.tnb, written.gsi.tns, written.gsi.tng)
+        );
+    }
+
+    #[test]
+    fn test_write_validated() {
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        stl.ttis[12].tf = vec![b' '; 112];
+        let mut buffer = vec![];
+        let report = stl
+            .write_validated(&mut buffer, &WriteOptions::default())
+            .unwrap();
+        assert_eq!(1, report.warnings().count());
+        assert_eq!(1024 + 128 * 13, buffer.len());
+
+        stl.ttis.truncate(12);
+        let mut buffer = vec![];
+        let err = stl
+            .write_validated(&mut buffer, &WriteOptions::default())
+            .unwrap_err();
+        assert!(matches!(err, SerializeError::Invalid(report) if report.errors().count() == 2));
+        assert!(buffer.is_empty());
+
+        let options = WriteOptions {
+            fix_totals: true,
+            ..Default::default()
+        };
+        stl.write_validated(&mut buffer, &options).unwrap();
+        assert_eq!(1024 + 128 * 12, buffer.len());
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn test_patch_gsi_in_file() {
+        let data = fs::read("stls/test.stl").unwrap();
+        for atomic in [false, true] {
+            let path = std::env::temp_dir().join(format!(
+                "ebustl_test_patch_gsi_{}_{}.stl",
+                atomic,
+                std::process::id()
+            ));
+            fs::write(&path, &data).unwrap();
+            patch_gsi_in_file(&path, atomic, |gsi| {
+                gsi.set_language_code("09").unwrap();
+                assert!(gsi.set_language_code("009").is_err());
+                gsi.set_publisher("PUBLISHER").unwrap();
+            })
+            .unwrap();
+            let patched = fs::read(&path).unwrap();
+            assert_eq!(data[1024..], patched[1024..]);
+            let gsi = GsiBlock::from_bytes(patched[..1024].try_into().unwrap()).unwrap();
+            assert_eq!("09", gsi.get_language_code());
+            assert_eq!("PUBLISHER", gsi.get_publisher().trim_end());
+            assert_eq!(data[16..277], patched[16..277]);
+
+            let err = patch_gsi_in_file(&path, atomic, |gsi| gsi.tnd = 10).unwrap_err();
+            assert_eq!(
+                SerializeError::FieldOverflow {
+                    field: "TND",
+                    width: 1
+                },
+                err
+            );
+            assert_eq!(patched, fs::read(&path).unwrap());
+            fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_write_stl() {
+        let data = std::fs::read("stls/test.stl").unwrap();
+        let stl = parse_stl_from_slice(&data).unwrap();
+        let blocks = (0..stl.ttis.len()).map(|index| {
+            TtiBlock::from_bytes(
+                data[1024 + 128 * index..][..128].try_into().unwrap(),
+                CharacterCodeTable::Latin,
+            )
+            .unwrap()
+        });
+        let mut gsi = stl.gsi.clone();
+        gsi.set_totals((0, 0, 0));
+        let w = write_stl(io::Cursor::new(vec![]), gsi, blocks).unwrap();
+        assert_eq!(data, w.into_inner());
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn test_capacity_exceeded() {
+        let mut stl = parse_stl_from_file("stls/test.stl").unwrap();
+        let block = stl.ttis[1].clone();
+        stl.ttis = (0..=u16::MAX as usize)
+            .map(|index| {
+                let mut tti = block.clone();
+                tti.sn = (index / 2) as u16 + 1;
+                tti
+            })
+            .collect();
+        assert_eq!(
+            Err(SerializeError::CapacityExceeded { blocks: 65536 }),
+            stl.write_to(&mut vec![])
+        );
+        assert!(stl
+            .validate()
+            .issues
+            .iter()
+            .any(|issue| issue.code == validate::IssueCode::CapacityExceeded { blocks: 65536 }));
+
+        let dir = std::env::temp_dir().join(format!(
+            "ebustl_test_capacity_exceeded_{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+        let paths = stl.write_multi_disk(dir.join("prog.stl"), 20000).unwrap();
+        assert!(paths.len() > 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,182 @@
+//! Conversion of every SRT file of a directory.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use ebustl::GsiBlock;
+
+use super::{convert_srt, Options};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Outcome {
+    #[doc = "Written, with the warnings about the cues"]
+    Converted(Vec<String>),
+    Skipped(String),
+    Failed(String),
+}
+
+impl Outcome {
+    pub fn is_failure(&self) -> bool {
+        matches!(self, Outcome::Failed(_))
+    }
+}
+
+// SRT files of `dir` by path relative to it, in name order.
+fn srt_files(dir: &Path, recursive: bool) -> io::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    let mut dirs = vec![PathBuf::new()];
+    while let Some(relative) = dirs.pop() {
+        for entry in fs::read_dir(dir.join(&relative))? {
+            let entry = entry?;
+            let path = relative.join(entry.file_name());
+            if entry.file_type()?.is_dir() {
+                if recursive {
+                    dirs.push(path);
+                }
+            } else if path
+                .extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("srt"))
+            {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Convert the SRT files of the input directory to STL files of the output directory
+/// with the same relative path, on `options.jobs` threads. A file failing does not
+/// stop the others.
+pub fn run(options: &Options, gsi: &GsiBlock) -> Result<Vec<(PathBuf, Outcome)>, String> {
+    let input = Path::new(&options.input);
+    let files =
+        srt_files(input, options.recursive).map_err(|err| format!("{}: {}", options.input, err))?;
+    let next = AtomicUsize::new(0);
+    let outcomes = Mutex::new((0..files.len()).map(|_| None).collect::<Vec<_>>());
+    thread::scope(|scope| {
+        for _ in 0..options.jobs.min(files.len()) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(file) = files.get(index) else {
+                    break;
+                };
+                let outcome = convert_file(file, options, gsi);
+                outcomes.lock().unwrap()[index] = Some(outcome);
+            });
+        }
+    });
+    let outcomes = outcomes.into_inner().unwrap();
+    Ok(files
+        .into_iter()
+        .zip(outcomes.into_iter().map(Option::unwrap))
+        .collect())
+}
+
+fn convert_file(file: &Path, options: &Options, gsi: &GsiBlock) -> Outcome {
+    let input = Path::new(&options.input).join(file);
+    let output = Path::new(&options.output).join(file.with_extension("stl"));
+    if output.exists() && !options.overwrite {
+        return Outcome::Skipped(format!("{} exists", output.display()));
+    }
+    let result = fs::read_to_string(&input)
+        .map_err(|err| err.to_string())
+        .and_then(|srt| convert_srt(&srt, gsi.clone(), options))
+        .and_then(|(stl, warnings)| {
+            if let Some(dir) = output.parent() {
+                fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+            }
+            stl.write_to_file(&output).map_err(|err| err.to_string())?;
+            Ok(warnings)
+        });
+    match result {
+        Ok(warnings) => Outcome::Converted(warnings),
+        Err(err) => Outcome::Failed(err.replace('\n', "; ")),
+    }
+}
+
+/// Table of the outcome of every file followed by the totals.
+pub fn summary(results: &[(PathBuf, Outcome)]) -> String {
+    let width = results
+        .iter()
+        .map(|(file, _)| file.display().to_string().chars().count())
+        .max()
+        .unwrap_or(0);
+    let mut summary = String::new();
+    let mut totals = [0; 3];
+    for (file, outcome) in results {
+        let (status, detail) = match outcome {
+            Outcome::Converted(warnings) if warnings.is_empty() => (0, String::new()),
+            Outcome::Converted(warnings) => (0, warnings.join("; ")),
+            Outcome::Skipped(reason) => (1, reason.clone()),
+            Outcome::Failed(reason) => (2, reason.clone()),
+        };
+        totals[status] += 1;
+        let line = format!(
+            "{:<9}  {:<width$}  {}",
+            ["converted", "skipped", "failed"][status],
+            file.display(),
+            detail,
+            width = width
+        );
+        summary.push_str(line.trim_end());
+        summary.push('\n');
+    }
+    summary.push_str(&format!(
+        "{} converted, {} skipped, {} failed\n",
+        totals[0], totals[1], totals[2]
+    ));
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_args;
+
+    #[test]
+    fn test_run() {
+        let dir = std::env::temp_dir().join(format!("srt2stl-batch-{}", std::process::id()));
+        let (input, output) = (dir.join("in"), dir.join("out"));
+        fs::create_dir_all(input.join("season 2")).unwrap();
+        fs::create_dir_all(&output).unwrap();
+        let srt = "1\n00:00:01,000 --> 00:00:02,000\nHello\n";
+        fs::write(input.join("ep01.srt"), srt).unwrap();
+        fs::write(input.join("ep02.SRT"), srt.replace("-->", "->")).unwrap();
+        fs::write(input.join("ep03.srt"), srt).unwrap();
+        fs::write(input.join("notes.txt"), "").unwrap();
+        fs::write(input.join("season 2").join("ep01.srt"), srt).unwrap();
+        fs::write(output.join("ep03.stl"), "").unwrap();
+
+        let run_with = |flags: &[&str]| {
+            let dirs = [input.to_str().unwrap(), output.to_str().unwrap()];
+            let args = ["--batch"].iter().chain(flags).chain(&dirs);
+            let options = parse_args(args.map(|arg| arg.to_string())).unwrap();
+            run(&options, &GsiBlock::new()).unwrap()
+        };
+        let results = run_with(&["--jobs", "2"]);
+        let files = results
+            .iter()
+            .map(|(file, _)| file.to_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["ep01.srt", "ep02.SRT", "ep03.srt"], files);
+        assert_eq!(Outcome::Converted(vec![]), results[0].1);
+        assert!(results[1].1.is_failure());
+        assert!(matches!(results[2].1, Outcome::Skipped(_)));
+        assert!(ebustl::parse_stl_from_file(output.join("ep01.stl")).is_ok());
+
+        let summary = summary(&results);
+        assert!(summary.starts_with("converted  ep01.srt\nfailed     ep02.SRT  Line 2:"));
+        assert!(summary.ends_with("1 converted, 1 skipped, 1 failed\n"));
+
+        let results = run_with(&["--recursive", "--overwrite"]);
+        assert_eq!(4, results.len());
+        assert!(results[2].1 == Outcome::Converted(vec![]));
+        assert!(output.join("season 2").join("ep01.stl").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
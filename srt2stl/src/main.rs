@@ -1,60 +1,1098 @@
-extern crate srt;
-extern crate ebustl;
-
-use std::env;
+use std::fmt;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
 use std::process;
-use std::error::Error;
-use srt::{Srt, parse_srt_from_file};
-use ebustl::{Stl, Time, TtiFormat};
-
-fn print_usage() {
-    println!("sub-converter input.srt output.stl\n");
-}
-
-fn convert_srt_to_stl(srt: Srt) -> Result<Stl, String> {
-    let mut stl = Stl::new();
-    let fps = 25; // Assume 25 fps video
-    for sub in srt.subs {
-        stl.add_sub(Time {
-                        hours: sub.start_time.hours,
-                        minutes: sub.start_time.minutes,
-                        seconds: sub.start_time.seconds,
-                        frames: (sub.start_time.milliseconds * fps / 1000) as u8,
-                    },
-                    Time {
-                        hours: sub.end_time.hours,
-                        minutes: sub.end_time.minutes,
-                        seconds: sub.end_time.seconds,
-                        frames: (sub.end_time.milliseconds * fps / 1000) as u8,
-                    },
-                    &sub.text,
-                    TtiFormat {
-                        jc: 2,
-                        vp: 19,
-                        dh: true,
-                    });
-    }
-    Ok(stl)
+
+mod batch;
+
+use clap::error::ErrorKind;
+use clap::{ArgGroup, CommandFactory, Parser, ValueEnum};
+use ebustl::{
+    CharacterCodeTable, CueError, DiskFormatCode, GsiBlock, SrtCue, SrtError, SrtImportOptions,
+    SrtSplitText, SrtStyles, SrtUnderflow, Stl, Time, TtiFormat,
+};
+
+// Teletext rows, double height text takes two.
+const ROWS: u8 = 23;
+const TOP_ROW: u8 = 1;
+const BOTTOM_ROW: u8 = 19;
+
+// File name standing for stdin or stdout.
+const STDIO: &str = "-";
+
+// Exit codes, clap exits with 2 for the arguments it rejects.
+const EXIT_FAILED_FILES: i32 = 1;
+const EXIT_ARGUMENTS: i32 = 2;
+const EXIT_INPUT: i32 = 3;
+const EXIT_WRITE: i32 = 4;
+
+/// Convert SubRip (SRT) subtitles to EBU STL.
+#[derive(Parser, Debug)]
+#[command(
+    name = "srt2stl",
+    version,
+    group(ArgGroup::new("position").args(["row", "top", "bottom"])),
+    after_help = "Use - as input or output to read the SRT from stdin or write the STL to \
+                  stdout.\n\n\
+                  Exit codes: 1 when files of a batch failed, 2 for invalid arguments, 3 when \
+                  an input cannot be read or converted, 4 when the output cannot be written."
+)]
+struct Cli {
+    /// SRT file, or directory with --batch
+    input: String,
+    /// STL file, or directory with --batch
+    output: String,
+
+    /// Frame rate of the time codes
+    #[arg(long, value_enum)]
+    fps: Option<Fps>,
+    #[arg(long, value_enum, default_value_t = Justify::Center)]
+    justify: Justify,
+    /// Teletext row the subtitles start on
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=ROWS as i64))]
+    row: Option<u8>,
+    /// Start on the first row
+    #[arg(long)]
+    top: bool,
+    /// Start on row 19, the default
+    #[arg(long)]
+    bottom: bool,
+    /// Double height text, the default
+    #[arg(long, conflicts_with = "single_height")]
+    double_height: bool,
+    #[arg(long)]
+    single_height: bool,
+    /// What is done with the italic, underline and font tags
+    #[arg(long, value_enum, default_value_t = Styles::Map)]
+    styles: Styles,
+
+    /// Character code table of the text
+    #[arg(long, value_enum)]
+    cct: Option<Cct>,
+    /// Language code of EBU Tech 3264, two hexadecimal digits
+    #[arg(long, value_parser = language)]
+    language: Option<String>,
+    #[arg(long, value_name = "TEXT")]
+    title: Option<String>,
+    #[arg(long, value_name = "TEXT")]
+    episode: Option<String>,
+    #[arg(long, value_name = "TEXT")]
+    publisher: Option<String>,
+    /// Country of origin, three letters
+    #[arg(long, value_name = "CODE")]
+    country: Option<String>,
+    #[arg(long, value_name = "TEXT")]
+    editor: Option<String>,
+    #[arg(long, value_name = "TEXT")]
+    contact: Option<String>,
+    /// Time code of start of programme
+    #[arg(long, value_name = "HH:MM:SS:FF", value_parser = time_code)]
+    tcp: Option<String>,
+    /// STL file whose GSI block is used instead of the level 1 teletext defaults
+    #[arg(long, value_name = "FILE")]
+    gsi_from: Option<String>,
+
+    /// Time code added to every cue, subtracted when starting with a minus
+    #[arg(long, value_name = "[-]HH:MM:SS:FF", allow_hyphen_values = true, value_parser = offset)]
+    offset: Option<(String, bool)>,
+    /// What is done with the cues starting before 00:00:00:00 once offset
+    #[arg(long, value_enum, default_value_t = OnUnderflow::Fail)]
+    on_underflow: OnUnderflow,
+    /// Characters of a row, the maximum of the GSI block by default
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u16).range(1..=99))]
+    max_chars: Option<u16>,
+    /// Rows of a subtitle, the maximum of the GSI block by default
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u16).range(1..=ROWS as i64))]
+    max_rows: Option<u16>,
+    /// What is done with the cues having more rows than the maximum
+    #[arg(long, value_enum, default_value_t = OnTooManyRows::Fail)]
+    on_too_many_rows: OnTooManyRows,
+    /// Longest a subtitle is shown, longer cues are split in consecutive subtitles
+    #[arg(long, value_name = "SECONDS", value_parser = seconds)]
+    max_duration: Option<f64>,
+    /// Text of the subtitles a cue is split in at the maximum duration
+    #[arg(long, value_enum, default_value_t = SplitText::Lines)]
+    split_text: SplitText,
+    /// Frames between the subtitles a cue is split in at the maximum duration
+    #[arg(long, value_name = "FRAMES", default_value_t = 0)]
+    split_gap: u32,
+    /// What is done with the characters missing from the character code table
+    #[arg(long, value_enum, default_value_t = OnUnmappable::Error)]
+    on_unmappable: OnUnmappable,
+
+    /// Write the STL to stdout even if it is a terminal
+    #[arg(long)]
+    force: bool,
+    /// Convert every .srt file of the input directory to the output directory
+    #[arg(long)]
+    batch: bool,
+    /// Convert the files of the subdirectories as well
+    #[arg(long, requires = "batch")]
+    recursive: bool,
+    /// Files converted at the same time
+    #[arg(long, value_name = "N", requires = "batch", default_value_t = 1,
+          value_parser = clap::value_parser!(u16).range(1..))]
+    jobs: u16,
+    /// Replace the STL files already there instead of skipping them
+    #[arg(long, requires = "batch")]
+    overwrite: bool,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Fps {
+    #[value(name = "25")]
+    Fps25,
+    #[value(name = "30")]
+    Fps30,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Justify {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Styles {
+    Keep,
+    Strip,
+    Map,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Cct {
+    Latin,
+    Cyrillic,
+    Arabic,
+    Greek,
+    Hebrew,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum OnUnderflow {
+    Fail,
+    Drop,
+    Clamp,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum SplitText {
+    #[doc = "Share the rows between the subtitles"]
+    Lines,
+    #[doc = "Show the whole text in every subtitle"]
+    Repeat,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum OnTooManyRows {
+    Fail,
+    Split,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum OnUnmappable {
+    #[doc = "List them and write nothing"]
+    Error,
+    #[doc = "Write '?' instead"]
+    Replace,
+    #[doc = "Write the closest characters of the table instead"]
+    Transliterate,
+}
+
+struct Options {
+    input: String,
+    output: String,
+    #[doc = "Write the STL to stdout even if it is a terminal"]
+    force: bool,
+    #[doc = "Convert the SRT files of the input directory to the output directory"]
+    batch: bool,
+    recursive: bool,
+    #[doc = "Files converted at the same time in batch mode"]
+    jobs: usize,
+    overwrite: bool,
+    format: TtiFormat,
+    styles: SrtStyles,
+    #[doc = "Header to start from instead of the level 1 teletext defaults"]
+    template: Option<String>,
+    dfc: Option<DiskFormatCode>,
+    cct: Option<CharacterCodeTable>,
+    #[doc = "Language Code of EBU Tech 3264, two hexadecimal digits"]
+    language: Option<String>,
+    #[doc = "Time Code: Start-of-Programme written HHMMSSFF"]
+    tcp: Option<String>,
+    #[doc = "Text fields of the GSI block, by flag"]
+    fields: Vec<(String, String)>,
+    #[doc = "Time code added to every cue written HHMMSSFF, and whether it is subtracted"]
+    offset: Option<(String, bool)>,
+    on_underflow: SrtUnderflow,
+    #[doc = "Characters and rows of a subtitle instead of the maximums of the GSI block"]
+    max_chars: Option<u16>,
+    max_rows: Option<u16>,
+    split_rows: bool,
+    #[doc = "Longest a subtitle is shown in seconds"]
+    max_duration: Option<f64>,
+    split_text: SrtSplitText,
+    split_gap: u32,
+    on_unmappable: OnUnmappable,
+}
+
+impl TryFrom<Cli> for Options {
+    type Error = String;
+
+    fn try_from(cli: Cli) -> Result<Options, String> {
+        let vp = match (cli.row, cli.top) {
+            (Some(row), _) => row,
+            (None, true) => TOP_ROW,
+            (None, false) => BOTTOM_ROW,
+        };
+        let format = TtiFormat {
+            jc: match cli.justify {
+                Justify::Left => 1,
+                Justify::Center => 2,
+                Justify::Right => 3,
+            },
+            vp,
+            dh: !cli.single_height,
+        };
+        if format.dh && format.vp == ROWS {
+            return Err(format!(
+                "Double height text takes two rows, it cannot start on row {}",
+                ROWS
+            ));
+        }
+        if cli.batch && [&cli.input, &cli.output].contains(&&STDIO.to_string()) {
+            return Err("--batch converts directories, not stdin or stdout".to_string());
+        }
+        let fields = [
+            ("--title", cli.title),
+            ("--episode", cli.episode),
+            ("--publisher", cli.publisher),
+            ("--country", cli.country),
+            ("--editor", cli.editor),
+            ("--contact", cli.contact),
+        ]
+        .into_iter()
+        .filter_map(|(flag, value)| Some((flag.to_string(), value?)))
+        .collect();
+        Ok(Options {
+            input: cli.input,
+            output: cli.output,
+            force: cli.force,
+            batch: cli.batch,
+            recursive: cli.recursive,
+            jobs: cli.jobs as usize,
+            overwrite: cli.overwrite,
+            format,
+            styles: match cli.styles {
+                Styles::Keep => SrtStyles::Keep,
+                Styles::Strip => SrtStyles::Strip,
+                Styles::Map => SrtStyles::Map,
+            },
+            template: cli.gsi_from,
+            dfc: cli.fps.map(|fps| match fps {
+                Fps::Fps25 => DiskFormatCode::STL25_01,
+                Fps::Fps30 => DiskFormatCode::STL30_01,
+            }),
+            cct: cli.cct.map(|cct| match cct {
+                Cct::Latin => CharacterCodeTable::Latin,
+                Cct::Cyrillic => CharacterCodeTable::LatinCyrillic,
+                Cct::Arabic => CharacterCodeTable::LatinArabic,
+                Cct::Greek => CharacterCodeTable::LatinGreek,
+                Cct::Hebrew => CharacterCodeTable::LatinHebrew,
+            }),
+            language: cli.language,
+            tcp: cli.tcp,
+            fields,
+            offset: cli.offset,
+            on_underflow: match cli.on_underflow {
+                OnUnderflow::Fail => SrtUnderflow::Fail,
+                OnUnderflow::Drop => SrtUnderflow::Drop,
+                OnUnderflow::Clamp => SrtUnderflow::Clamp,
+            },
+            max_chars: cli.max_chars,
+            max_rows: cli.max_rows,
+            split_rows: matches!(cli.on_too_many_rows, OnTooManyRows::Split),
+            max_duration: cli.max_duration,
+            split_text: match cli.split_text {
+                SplitText::Lines => SrtSplitText::Lines,
+                SplitText::Repeat => SrtSplitText::Repeat,
+            },
+            split_gap: cli.split_gap,
+            on_unmappable: cli.on_unmappable,
+        })
+    }
+}
+
+/// Failure of a conversion, by what went wrong.
+#[derive(Debug, PartialEq, Eq)]
+enum Error {
+    #[doc = "Flags that cannot be applied, found once the files are read"]
+    Arguments(String),
+    #[doc = "Input that cannot be read or converted, prefixed with the file name"]
+    Input(String),
+    Write(String),
+}
+
+impl Error {
+    fn exit_code(&self) -> i32 {
+        match self {
+            Error::Arguments(_) => EXIT_ARGUMENTS,
+            Error::Input(_) => EXIT_INPUT,
+            Error::Write(_) => EXIT_WRITE,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Arguments(err) | Error::Input(err) | Error::Write(err) => f.write_str(err),
+        }
+    }
+}
+
+// Options of the arguments following the program name.
+#[cfg(test)]
+fn parse_args(args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let cli = Cli::try_parse_from(std::iter::once("srt2stl".to_string()).chain(args))
+        .map_err(|err| err.to_string())?;
+    Options::try_from(cli)
+}
+
+fn language(arg: &str) -> Result<String, String> {
+    match arg.len() == 2 && arg.bytes().all(|c| c.is_ascii_hexdigit()) {
+        true => Ok(arg.to_uppercase()),
+        false => Err("expected two hexadecimal digits".to_string()),
+    }
+}
+
+// Time code HH:MM:SS:FF written HHMMSSFF.
+fn time_code(arg: &str) -> Result<String, String> {
+    let parts = arg.split(':').collect::<Vec<_>>();
+    let valid = parts.len() == 4
+        && parts
+            .iter()
+            .all(|part| part.len() == 2 && part.bytes().all(|c| c.is_ascii_digit()));
+    match valid {
+        true => Ok(parts.concat()),
+        false => Err("expected a time code HH:MM:SS:FF".to_string()),
+    }
+}
+
+fn seconds(arg: &str) -> Result<f64, String> {
+    match arg.parse::<f64>() {
+        Ok(seconds) if seconds > 0.0 && seconds.is_finite() => Ok(seconds),
+        _ => Err("expected a positive number of seconds".to_string()),
+    }
+}
+
+// Time code of an offset and whether it is subtracted.
+fn offset(arg: &str) -> Result<(String, bool), String> {
+    match arg.strip_prefix('-') {
+        Some(arg) => Ok((time_code(arg)?, true)),
+        None => Ok((time_code(arg)?, false)),
+    }
+}
+
+// GSI block of the output, from `template` if given, with the values of the flags.
+fn gsi(options: &Options, template: Option<GsiBlock>) -> Result<GsiBlock, String> {
+    let mut gsi = template.unwrap_or_default();
+    if let Some(dfc) = options.dfc {
+        gsi.set_disk_format_code(dfc);
+    }
+    if let Some(cct) = options.cct {
+        gsi.set_character_code_table(cct);
+    }
+    if let Some(language) = &options.language {
+        gsi.set_language_code(language)
+            .map_err(|err| err.to_string())?;
+    }
+    let fps = gsi.get_disk_format_code().get_fps();
+    // A programme moved to start later starts at the offset, unless set otherwise.
+    let tcp = match &options.offset {
+        Some((offset, false)) if options.tcp.is_none() => Some(offset),
+        _ => options.tcp.as_ref(),
+    };
+    if let Some(tcp) = tcp {
+        if !Time::from_time_code(tcp).is_some_and(|time| time.is_valid(fps)) {
+            return Err(format!("--tcp is not a valid time code at {} fps", fps));
+        }
+        gsi.set_timecode_start_of_program(tcp)
+            .map_err(|err| err.to_string())?;
+    }
+    if let Some((offset, _)) = &options.offset {
+        if !Time::from_time_code(offset).is_some_and(|time| time.is_valid(fps)) {
+            return Err(format!("--offset is not a valid time code at {} fps", fps));
+        }
+    }
+    if let Some(chars) = options.max_chars {
+        gsi.set_max_number_of_chars_in_row(chars)
+            .map_err(|err| err.to_string())?;
+    }
+    if let Some(rows) = options.max_rows {
+        gsi.set_max_number_of_rows(rows)
+            .map_err(|err| err.to_string())?;
+    }
+    for (flag, value) in &options.fields {
+        match flag.as_str() {
+            "--title" => gsi.set_original_program_title(value),
+            "--episode" => gsi.set_original_episode_title(value),
+            "--publisher" => gsi.set_publisher(value),
+            "--country" => gsi.set_country_of_origin(value),
+            "--editor" => gsi.set_editors_name(value),
+            _ => gsi.set_editors_contact_details(value),
+        }
+        .map_err(|err| format!("{}: {}", flag, err))?;
+    }
+    Ok(gsi)
+}
+
+// Document of `srt` and the warnings about the cues dropped or clamped.
+fn convert_srt(srt: &str, gsi: GsiBlock, options: &Options) -> Result<(Stl, Vec<String>), String> {
+    let cct = *gsi.get_character_code_table();
+    let fps = gsi.get_disk_format_code().get_fps();
+    let offset = options.offset.as_ref().map_or(0, |(offset, negative)| {
+        let frames = Time::from_time_code(offset).map_or(0, |time| time.to_frames(fps) as i64);
+        if *negative {
+            -frames
+        } else {
+            frames
+        }
+    });
+    let on_unmappable = options.on_unmappable;
+    let options = SrtImportOptions {
+        gsi,
+        format: options.format,
+        styles: options.styles,
+        offset,
+        on_underflow: options.on_underflow,
+        split_rows: options.split_rows,
+        max_duration: options
+            .max_duration
+            .map(|seconds| (seconds * fps as f64).round().max(1.0) as u32),
+        split_text: options.split_text,
+        split_gap: options.split_gap,
+        ..SrtImportOptions::default()
+    };
+    let mut cues = ebustl::parse_srt(srt).map_err(|err| err.to_string())?;
+    let mut warnings = unmappable(&mut cues, cct, on_unmappable)?;
+    let mut split = vec![];
+    for (index, cue) in cues.iter().enumerate() {
+        let (start, end) = options.frames(cue);
+        if options
+            .max_duration
+            .is_some_and(|max| end > 0 && end - start.max(0) > max as i64)
+        {
+            split.push((index + 1).to_string());
+        }
+        let action = match options.on_underflow {
+            _ if start >= 0 => continue,
+            SrtUnderflow::Fail => continue,
+            SrtUnderflow::Clamp if end > 0 => "clamped to 00:00:00:00",
+            _ => "dropped",
+        };
+        warnings.push(format!(
+            "Cue {} starts before 00:00:00:00 once offset, {}",
+            index + 1,
+            action
+        ));
+    }
+    if !split.is_empty() {
+        warnings.push(format!(
+            "Cues {} split at the maximum duration",
+            split.join(", ")
+        ));
+    }
+    let mut stl = Stl::from_srt_cues(cues, &options).map_err(|err| match err {
+        SrtError::Cues(errors) => errors
+            .iter()
+            .map(|error| describe(error, cct))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        SrtError::Underflow(cues) => format!(
+            "Cues {} start before 00:00:00:00 once offset, see --on-underflow",
+            cues.iter()
+                .map(|cue| (cue + 1).to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        err => err.to_string(),
+    })?;
+    if offset != 0 {
+        if let Some(first) = stl.ttis.first() {
+            let tcf = first.get_time_code_in().to_time_code();
+            stl.gsi
+                .set_timecode_first_in_cue(&tcf)
+                .map_err(|err| err.to_string())?;
+        }
+    }
+    Ok((stl, warnings))
+}
+
+// Warnings about the characters of `cues` missing from `cct` once replaced, all of them
+// listed in the error instead with `OnUnmappable::Error`.
+fn unmappable(
+    cues: &mut [SrtCue],
+    cct: CharacterCodeTable,
+    on_unmappable: OnUnmappable,
+) -> Result<Vec<String>, String> {
+    let mut messages = vec![];
+    for (index, cue) in cues.iter_mut().enumerate() {
+        let chars = cct.unmappable(&cue.text);
+        if chars.is_empty() {
+            continue;
+        }
+        let mut replacements = vec![];
+        for c in chars {
+            let closest = cct.transliterate(c);
+            let suggestion = match closest.as_str() {
+                "" => "suggested removing it".to_string(),
+                text => format!("suggested '{}'", text),
+            };
+            let (replacement, action) = match on_unmappable {
+                OnUnmappable::Error => (String::new(), suggestion),
+                OnUnmappable::Replace => (
+                    "?".to_string(),
+                    format!("replaced with '?', {}", suggestion),
+                ),
+                OnUnmappable::Transliterate if closest.is_empty() => {
+                    (closest, "removed".to_string())
+                }
+                OnUnmappable::Transliterate => {
+                    let action = format!("replaced with '{}'", closest);
+                    (closest, action)
+                }
+            };
+            messages.push(format!(
+                "Cue {}: '{}' (U+{:04X}) is missing from the {:?} character table, {}",
+                index + 1,
+                c,
+                c as u32,
+                cct,
+                action
+            ));
+            replacements.push((c, replacement));
+        }
+        cue.text = cue
+            .text
+            .chars()
+            .map(
+                |c| match replacements.iter().find(|(missing, _)| *missing == c) {
+                    Some((_, replacement)) => replacement.clone(),
+                    None => c.to_string(),
+                },
+            )
+            .collect();
+    }
+    match on_unmappable {
+        OnUnmappable::Error if !messages.is_empty() => Err(messages.join("\n")),
+        _ => Ok(messages),
+    }
+}
+
+// Message for a cue numbered by its position in the SRT file, from 1.
+fn describe(error: &CueError, cct: CharacterCodeTable) -> String {
+    match error {
+        CueError::Unmappable { cue, chars } => format!(
+            "Cue {}: characters {} cannot be written with the {:?} character table",
+            cue + 1,
+            chars
+                .iter()
+                .map(|c| format!("'{}' (U+{:04X})", c, *c as u32))
+                .collect::<Vec<_>>()
+                .join(", "),
+            cct
+        ),
+        CueError::TooManyRows(cue) => format!(
+            "Cue {}: more rows than the maximum, see --max-rows and --on-too-many-rows",
+            cue + 1
+        ),
+        error => error.to_string(),
+    }
+}
+
+// GSI block of the file given with --gsi-from.
+fn template(options: &Options) -> Result<Option<GsiBlock>, Error> {
+    match &options.template {
+        Some(template) => Ok(Some(
+            ebustl::parse_stl_from_file(template)
+                .map_err(|err| Error::Input(format!("{}: {}", template, err)))?
+                .gsi,
+        )),
+        None => Ok(None),
+    }
+}
+
+// GSI block of the output, an error about the flags if they cannot be applied to it.
+fn output_gsi(options: &Options) -> Result<GsiBlock, Error> {
+    gsi(options, template(options)?).map_err(Error::Arguments)
+}
+
+fn convert(options: &Options) -> Result<(), Error> {
+    let gsi = output_gsi(options)?;
+    let input = match options.input.as_str() {
+        STDIO => "stdin",
+        input => input,
+    };
+    let srt = match options.input.as_str() {
+        STDIO => io::read_to_string(io::stdin()),
+        input => fs::read_to_string(input),
+    }
+    .map_err(|err| Error::Input(format!("{}: {}", input, err)))?;
+    let (stl, warnings) = convert_srt(&srt, gsi, options).map_err(|err| {
+        let lines = err.lines().map(|line| format!("{}: {}", input, line));
+        Error::Input(lines.collect::<Vec<_>>().join("\n"))
+    })?;
+    for warning in warnings {
+        eprintln!("warning: {}: {}", input, warning);
+    }
+    if options.output != STDIO {
+        return stl
+            .write_to_file(&options.output)
+            .map_err(|err| Error::Write(format!("{}: {}", options.output, err)));
+    }
+    check_stdout(io::stdout().is_terminal(), options.force)?;
+    let mut stdout = io::stdout().lock();
+    stl.write_to(&mut stdout)
+        .map_err(|err| err.to_string())
+        .and_then(|_| stdout.flush().map_err(|err| err.to_string()))
+        .map_err(|err| Error::Write(format!("stdout: {}", err)))
+}
+
+// Conversion of a directory printing the summary, false if a file failed.
+fn convert_batch(options: &Options) -> Result<bool, Error> {
+    let gsi = output_gsi(options)?;
+    let results = batch::run(options, &gsi).map_err(Error::Input)?;
+    print!("{}", batch::summary(&results));
+    Ok(!results.iter().any(|(_, outcome)| outcome.is_failure()))
+}
+
+// Binary STL is only written to a terminal when forced to.
+fn check_stdout(is_terminal: bool, force: bool) -> Result<(), Error> {
+    match is_terminal && !force {
+        true => Err(Error::Arguments(
+            "Not writing binary STL to a terminal, redirect stdout or use --force".to_string(),
+        )),
+        false => Ok(()),
+    }
 }
 
 fn main() {
-    if env::args().count() != 3 {
-        print_usage();
-        process::exit(1);
-    }
-    let input_filename = env::args().nth(1).unwrap();
-    let output_filename = env::args().nth(2).unwrap();
-    let res = parse_srt_from_file(&input_filename)
-        .map_err(|err| err.description().to_string())
-        .and_then(convert_srt_to_stl)
-        .and_then(|stl| {
-                      stl.write_to_file(&output_filename).map_err(|err| {
-                                                                      err.description().to_string()
-                                                                  })
-                  });
-    if res.is_err() {
-        print_usage();
-        println!("Error: {}\n", res.err().unwrap());
-        process::exit(1);
+    let options = match Options::try_from(Cli::parse()) {
+        Ok(options) => options,
+        Err(err) => Cli::command()
+            .error(ErrorKind::ArgumentConflict, err)
+            .exit(),
+    };
+    let result = match options.batch {
+        true => convert_batch(&options),
+        false => convert(&options).map(|_| true),
+    };
+    match result {
+        Ok(true) => {}
+        Ok(false) => process::exit(EXIT_FAILED_FILES),
+        Err(err) => {
+            eprintln!("error: {}", err);
+            process::exit(err.exit_code());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn args(args: &[&str]) -> impl Iterator<Item = String> {
+        args.iter()
+            .map(|arg| arg.to_string())
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    fn convert_with(flags: &[&str], srt: &str) -> Result<Stl, String> {
+        let flags = [flags, &["in.srt", "out.stl"]].concat();
+        let options = parse_args(args(&flags))?;
+        convert_srt(srt, gsi(&options, None)?, &options).map(|(stl, _)| stl)
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let options = parse_args(args(&["in.srt", "out.stl"])).unwrap();
+        assert_eq!(None, options.dfc);
+        let options = parse_args(args(&["--fps", "30", "in.srt", "out.stl"])).unwrap();
+        assert_eq!(Some(DiskFormatCode::STL30_01), options.dfc);
+        assert_eq!(("in.srt", "out.stl"), (&*options.input, &*options.output));
+        assert!(parse_args(args(&["--fps", "24", "in.srt", "out.stl"])).is_err());
+        assert!(parse_args(args(&["in.srt"])).is_err());
+    }
+
+    #[test]
+    fn test_cli() {
+        Cli::command().debug_assert();
+        assert!(parse_args(args(&["--top", "--row", "3", "in.srt", "out.stl"])).is_err());
+        assert!(parse_args(args(&["--recursive", "in", "out"])).is_err());
+        assert!(parse_args(args(&["--batch", "-", "out"])).is_err());
+    }
+
+    #[test]
+    fn test_errors() {
+        let dir = std::env::temp_dir().join(format!("srt2stl-errors-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.srt");
+        fs::write(&input, "1\n00:00:01,000 -> 00:00:02,000\nHello\n").unwrap();
+        let convert_to = |input: &Path, output: &Path| {
+            let files = [input.to_str().unwrap(), output.to_str().unwrap()];
+            convert(&parse_args(args(&files)).unwrap())
+        };
+
+        let missing = dir.join("missing.srt");
+        let err = convert_to(&missing, &dir.join("out.stl")).unwrap_err();
+        assert_eq!(EXIT_INPUT, err.exit_code());
+        assert!(err.to_string().starts_with(missing.to_str().unwrap()));
+
+        let err = convert_to(&input, &dir.join("out.stl")).unwrap_err();
+        assert_eq!(
+            Error::Input(format!(
+                "{}: Line 2: expected a timing line such as 00:00:01,000 --> 00:00:02,500",
+                input.display()
+            )),
+            err
+        );
+
+        fs::write(&input, "1\n00:00:01,000 --> 00:00:02,000\nHello\n").unwrap();
+        let output = dir.join("missing").join("out.stl");
+        let err = convert_to(&input, &output).unwrap_err();
+        assert_eq!(EXIT_WRITE, err.exit_code());
+        assert!(err.to_string().starts_with(output.to_str().unwrap()));
+        assert_eq!(Ok(()), convert_to(&input, &dir.join("out.stl")));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_stdio() {
+        let options = parse_args(args(&["-", "-"])).unwrap();
+        assert_eq!((STDIO, STDIO), (&*options.input, &*options.output));
+        assert!(!options.force);
+        assert!(check_stdout(true, options.force).is_err());
+        assert!(check_stdout(false, options.force).is_ok());
+        let options = parse_args(args(&["--force", "-", "-"])).unwrap();
+        assert!(check_stdout(true, options.force).is_ok());
+    }
+
+    #[test]
+    fn test_parse_format_args() {
+        let format = |flags: &[&str]| {
+            let flags = [flags, &["in.srt", "out.stl"]].concat();
+            parse_args(args(&flags)).map(|options| options.format)
+        };
+        let default = TtiFormat {
+            jc: 2,
+            vp: 19,
+            dh: true,
+        };
+        assert_eq!(Ok(default), format(&[]));
+        assert_eq!(Ok(default), format(&["--bottom"]));
+        assert_eq!(
+            Ok(TtiFormat {
+                jc: 1,
+                vp: 1,
+                dh: false
+            }),
+            format(&["--justify", "left", "--top", "--single-height"])
+        );
+        assert_eq!(
+            Ok(TtiFormat {
+                jc: 3,
+                vp: 23,
+                dh: false
+            }),
+            format(&["--justify", "right", "--row", "23", "--single-height"])
+        );
+        assert!(format(&["--row", "23"]).is_err());
+        assert!(format(&["--row", "0"]).is_err());
+        assert!(format(&["--row", "24"]).is_err());
+        assert!(format(&["--justify", "middle"]).is_err());
+    }
+
+    #[test]
+    fn test_convert_styles() {
+        let srt = "1\n00:00:01,000 --> 00:00:02,000\n<i>Hello</i> <font color=\"red\">world\n\n\
+                   2\n00:00:03,000 --> 00:00:04,000\n<b>Bye</b>\n";
+        let convert =
+            |styles: &str| convert_with(&["--single-height", "--styles", styles], srt).unwrap();
+        let text = |styles: &str| {
+            let stl = convert(styles);
+            stl.ttis
+                .iter()
+                .map(|tti| tti.get_text().trim().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec!["<i>Hello</i> <font color=\"red\">world", "<b>Bye</b>"],
+            text("keep")
+        );
+        assert_eq!(vec!["Hello world", "Bye"], text("strip"));
+        assert_eq!(vec!["Hello world", "Bye"], text("map"));
+        let stl = convert("map");
+        let tf = stl.ttis[0].get_text_field();
+        assert!(tf.windows(11).any(|window| window == b"Hello\x01world"));
+        assert!(parse_args(args(&["--styles", "bold", "in.srt", "out.stl"])).is_err());
+    }
+
+    #[test]
+    fn test_convert_offset() {
+        let srt = "1\n00:00:00,000 --> 00:00:01,000\nGone\n\n\
+                   2\n00:00:01,000 --> 00:00:03,000\nCut\n\n\
+                   3\n00:00:04,000 --> 00:00:05,000\nKept\n";
+        let stl = convert_with(&["--offset", "10:00:00:00"], srt).unwrap();
+        assert_eq!("10000000", stl.gsi.get_timecode_start_of_program());
+        assert_eq!("10000000", stl.gsi.get_timecode_first_in_cue());
+        assert_eq!(
+            Time::from_time_code("10000400"),
+            Some(*stl.ttis[2].get_time_code_in())
+        );
+
+        let convert = |flags: &[&str]| {
+            let flags = [flags, &["in.srt", "out.stl"]].concat();
+            let options = parse_args(args(&flags))?;
+            convert_srt(srt, gsi(&options, None)?, &options)
+        };
+        assert_eq!(
+            Err("Cues 1, 2 start before 00:00:00:00 once offset, see --on-underflow".to_string()),
+            convert(&["--offset", "-00:00:02:00"]).map(|_| ())
+        );
+        let (stl, warnings) =
+            convert(&["--offset", "-00:00:02:00", "--on-underflow", "drop"]).unwrap();
+        assert_eq!(1, stl.ttis.len());
+        assert_eq!("00000000", stl.gsi.get_timecode_start_of_program());
+        assert_eq!("00000200", stl.gsi.get_timecode_first_in_cue());
+        assert_eq!(
+            vec![
+                "Cue 1 starts before 00:00:00:00 once offset, dropped",
+                "Cue 2 starts before 00:00:00:00 once offset, dropped"
+            ],
+            warnings
+        );
+        let (stl, warnings) =
+            convert(&["--offset", "-00:00:02:00", "--on-underflow", "clamp"]).unwrap();
+        assert_eq!(2, stl.ttis.len());
+        assert_eq!(
+            "Cue 2 starts before 00:00:00:00 once offset, clamped to 00:00:00:00",
+            warnings[1]
+        );
+
+        assert!(convert(&["--offset", "00:00:00:25"]).is_err());
+        assert!(convert(&["--offset", "+00:00:01:00"]).is_err());
+        assert!(convert(&["--on-underflow", "wrap"]).is_err());
+    }
+
+    #[test]
+    fn test_convert_row_limits() {
+        let srt = "1\n00:00:01,000 --> 00:00:04,000\n\
+                   Une ve\u{301}rite\u{301} bien trop longue pour une seule ligne\n";
+        let lines = |flags: &[&str]| {
+            let stl = convert_with(flags, srt)?;
+            Ok::<_, String>(
+                stl.subtitles()
+                    .map(|subtitle| subtitle.lines)
+                    .collect::<Vec<_>>(),
+            )
+        };
+        assert_eq!(
+            Ok(vec![vec![
+                "Une vérité bien trop".to_string(),
+                "longue pour une seule".to_string(),
+                "ligne".to_string()
+            ]]),
+            lines(&["--max-chars", "21"])
+        );
+        let err = lines(&["--max-chars", "21", "--max-rows", "2"]).unwrap_err();
+        assert!(err.starts_with("Cue 1: more rows than the maximum"));
+        let stl = convert_with(
+            &[
+                "--max-chars",
+                "21",
+                "--max-rows",
+                "2",
+                "--on-too-many-rows",
+                "split",
+            ],
+            srt,
+        )
+        .unwrap();
+        let subtitles = stl.subtitles().collect::<Vec<_>>();
+        assert_eq!(2, subtitles.len());
+        assert_eq!(vec!["ligne"], subtitles[1].lines);
+        assert_eq!(
+            Time::from_time_code("00000300"),
+            Some(*stl.ttis[1].get_time_code_in())
+        );
+
+        assert!(lines(&["--max-chars", "0"]).is_err());
+        assert!(lines(&["--max-rows", "24"]).is_err());
+        assert!(lines(&["--on-too-many-rows", "drop"]).is_err());
+    }
+
+    #[test]
+    fn test_convert_max_duration() {
+        let srt = "1\n00:00:01,000 --> 00:00:21,000\nLa la la\nLa la la la\n\n\
+                   2\n00:00:22,000 --> 00:00:24,000\nShort\n\n\
+                   3\n00:00:25,000 --> 00:00:35,000\nLong\n";
+        let flags = ["--max-duration", "7.5", "--split-gap", "2"];
+        let options = parse_args(args(&[&flags[..], &["in.srt", "out.stl"]].concat())).unwrap();
+        let (stl, warnings) = convert_srt(srt, gsi(&options, None).unwrap(), &options).unwrap();
+        assert_eq!(vec!["Cues 1, 3 split at the maximum duration"], warnings);
+        let subtitles = stl.subtitles().collect::<Vec<_>>();
+        assert_eq!(6, subtitles.len());
+        assert_eq!(vec!["La la la"], subtitles[0].lines);
+        assert_eq!(vec!["La la la la"], subtitles[2].lines);
+        assert!(stl.ttis.iter().all(|tti| {
+            tti.get_time_code_out().to_frames(25) - tti.get_time_code_in().to_frames(25)
+                <= 7 * 25 + 13
+        }));
+
+        let stl = convert_with(&["--max-duration", "7.5", "--split-text", "repeat"], srt).unwrap();
+        let subtitles = stl.subtitles().collect::<Vec<_>>();
+        assert_eq!(subtitles[0].lines, subtitles[2].lines);
+        assert!(convert_with(&["--max-duration", "0"], srt).is_err());
+    }
+
+    #[test]
+    fn test_convert_unmappable() {
+        let srt = "1\n00:00:01,000 --> 00:00:02,000\n“Wait” — 😀\n\n\
+                   2\n00:00:03,000 --> 00:00:04,000\nOK — bye\n";
+        let err = convert_with(&[], srt).err().unwrap();
+        assert_eq!(
+            "Cue 1: '—' (U+2014) is missing from the Latin character table, suggested '-'\n\
+             Cue 1: '😀' (U+1F600) is missing from the Latin character table, \
+             suggested removing it\n\
+             Cue 2: '—' (U+2014) is missing from the Latin character table, suggested '-'",
+            err
+        );
+
+        let options =
+            parse_args(args(&["--on-unmappable", "replace", "in.srt", "out.stl"])).unwrap();
+        let (stl, warnings) = convert_srt(srt, gsi(&options, None).unwrap(), &options).unwrap();
+        assert_eq!(3, warnings.len());
+        assert!(warnings[0].ends_with("replaced with '?', suggested '-'"));
+        assert_eq!(vec!["“Wait” ? ?"], stl.subtitles().next().unwrap().lines);
+
+        let options = parse_args(args(&["--on-unmappable", "transliterate", "-", "-"])).unwrap();
+        let (stl, warnings) = convert_srt(srt, gsi(&options, None).unwrap(), &options).unwrap();
+        assert!(warnings[0].ends_with("character table, replaced with '-'"));
+        assert!(warnings[1].ends_with("character table, removed"));
+        let subtitles = stl.subtitles().collect::<Vec<_>>();
+        assert_eq!(vec!["“Wait” -"], subtitles[0].lines);
+        assert_eq!(vec!["OK - bye"], subtitles[1].lines);
+    }
+
+    #[test]
+    fn test_convert_character_tables() {
+        let srt = "\u{feff}1\n00:00:01,000 --> 00:00:02,000\nΚαλημέρα\n\n\
+                   2\n00:00:03,000 --> 00:00:04,000\nДобрый день — Ж\n";
+        let flags = ["--cct", "greek", "--language", "1f"];
+        let err = convert_with(&flags, srt).err().unwrap();
+        assert!(err.starts_with(
+            "Cue 2: 'Д' (U+0414) is missing from the LatinGreek character table, \
+             suggested removing it\nCue 2: 'о' (U+043E)"
+        ));
+
+        let srt = srt.replace("Добрый день — Ж", "Ευχαριστώ");
+        let stl = convert_with(&flags, &srt).unwrap();
+        let subtitles = stl.subtitles().collect::<Vec<_>>();
+        assert_eq!(vec!["Καλημέρα"], subtitles[0].lines);
+        assert_eq!("1F", stl.gsi.get_language_code());
+        assert_eq!(
+            CharacterCodeTable::LatinGreek,
+            *stl.gsi.get_character_code_table()
+        );
+
+        assert!(parse_args(args(&["--cct", "thai", "in.srt", "out.stl"])).is_err());
+        assert!(parse_args(args(&["--language", "English", "in.srt", "out.stl"])).is_err());
+    }
+
+    #[test]
+    fn test_convert_frame_rates() {
+        let srt = "1\n00:00:01,000 --> 00:00:02,500\nHello\n\n\
+                   2\n00:00:03,020 --> 00:00:04,040\nWorld\n";
+        let frames = |fps: &str| {
+            let stl = convert_with(&["--fps", fps], srt).unwrap();
+            let bytes = stl.to_bytes().unwrap();
+            let dfc = String::from_utf8(bytes[3..11].to_vec()).unwrap();
+            let frames = stl
+                .ttis
+                .iter()
+                .map(|tti| {
+                    (
+                        tti.get_time_code_in().frames,
+                        tti.get_time_code_out().frames,
+                    )
+                })
+                .collect::<Vec<_>>();
+            (dfc, frames)
+        };
+        assert_eq!(
+            ("STL25.01".to_string(), vec![(0, 13), (1, 1)]),
+            frames("25")
+        );
+        assert_eq!(
+            ("STL30.01".to_string(), vec![(0, 15), (1, 1)]),
+            frames("30")
+        );
+    }
+
+    #[test]
+    fn test_gsi_flags() {
+        let srt = "1\n00:00:01,000 --> 00:00:02,000\nHello\n";
+        let stl = convert_with(
+            &[
+                "--title",
+                "Programme",
+                "--episode",
+                "Episode 1",
+                "--publisher",
+                "Publisher",
+                "--country",
+                "FRA",
+                "--editor",
+                "Editor",
+                "--contact",
+                "editor@example.com",
+                "--tcp",
+                "10:00:00:00",
+            ],
+            srt,
+        )
+        .unwrap();
+        assert_eq!("Programme", stl.gsi.get_original_program_title());
+        assert_eq!("Episode 1", stl.gsi.get_original_episode_title());
+        assert_eq!("Publisher", stl.gsi.get_publisher());
+        assert_eq!("FRA", stl.gsi.get_country_of_origin());
+        assert_eq!("Editor", stl.gsi.get_editors_name());
+        assert_eq!("editor@example.com", stl.gsi.get_editors_contact_details());
+        assert_eq!("10000000", stl.gsi.get_timecode_start_of_program());
+
+        assert_eq!(
+            Err("--country: GSI field CO does not fit in 3 bytes".to_string()),
+            convert_with(&["--country", "France"], srt).map(|_| ())
+        );
+        assert!(convert_with(&["--tcp", "10:00:00:25"], srt).is_err());
+        assert!(convert_with(&["--fps", "30", "--tcp", "10:00:00:25"], srt).is_ok());
+        assert!(convert_with(&["--tcp", "10:00:00"], srt).is_err());
+    }
+
+    #[test]
+    fn test_gsi_template() {
+        let template = ebustl::parse_stl_from_file("../stls/test.stl").unwrap().gsi;
+        let options = parse_args(args(&["--fps", "30", "in.srt", "out.stl"])).unwrap();
+        let gsi = gsi(&options, Some(template.clone())).unwrap();
+        assert_eq!("TEST", gsi.get_original_program_title().trim());
+        assert_eq!(
+            template.get_timecode_start_of_program(),
+            gsi.get_timecode_start_of_program()
+        );
+        assert_eq!(DiskFormatCode::STL30_01, *gsi.get_disk_format_code());
     }
 }
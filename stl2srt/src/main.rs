@@ -0,0 +1,189 @@
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::process;
+
+use clap::{Parser, ValueEnum};
+use ebustl::parser::parse_stl_with_options;
+use ebustl::{ParseOptions, ParseWarning, SrtExportOptions, Stl};
+
+// File name standing for stdin or stdout.
+const STDIO: &str = "-";
+
+// Exit codes, clap exits with 2 for the arguments it rejects.
+const EXIT_PARSE: i32 = 3;
+const EXIT_WRITE: i32 = 4;
+
+/// Convert EBU STL subtitles to SubRip (SRT).
+#[derive(Parser, Debug)]
+#[command(
+    name = "stl2srt",
+    version,
+    after_help = "Use - as input or output to read the STL from stdin or write the SRT to \
+                  stdout.\n\n\
+                  Exit codes: 2 for invalid arguments, 3 when the input cannot be read or \
+                  parsed, 4 when the output cannot be written."
+)]
+struct Cli {
+    /// STL file
+    input: String,
+    /// SRT file, written in UTF-8
+    output: String,
+
+    /// Count time from the Time Code: Start-of-Programme instead of midnight
+    #[arg(long)]
+    rebase: bool,
+    /// What is done with the italics, underline and colours
+    #[arg(long, value_enum, default_value_t = Styles::Map)]
+    styles: Styles,
+    /// Accept files deviating from the specification, reporting what was fixed
+    #[arg(long)]
+    lenient: bool,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Styles {
+    #[doc = "Write them as <i>, <u> and <font> tags"]
+    Map,
+    #[doc = "Leave them out"]
+    Strip,
+}
+
+/// Failure of a conversion, by what went wrong.
+#[derive(Debug, PartialEq, Eq)]
+enum Error {
+    #[doc = "Input that cannot be read or parsed, prefixed with the file name"]
+    Parse(String),
+    Write(String),
+}
+
+impl Error {
+    fn exit_code(&self) -> i32 {
+        match self {
+            Error::Parse(_) => EXIT_PARSE,
+            Error::Write(_) => EXIT_WRITE,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Parse(err) | Error::Write(err) => f.write_str(err),
+        }
+    }
+}
+
+// Name of a file in the messages.
+fn name<'a>(file: &'a str, stdio: &'a str) -> &'a str {
+    match file {
+        STDIO => stdio,
+        file => file,
+    }
+}
+
+// Document of the input with the warnings of lenient parsing.
+fn read(cli: &Cli) -> Result<(Stl, Vec<ParseWarning>), Error> {
+    let input = name(&cli.input, "stdin");
+    let data = match cli.input.as_str() {
+        STDIO => {
+            let mut data = vec![];
+            io::stdin().read_to_end(&mut data).map(|_| data)
+        }
+        input => fs::read(input),
+    }
+    .map_err(|err| Error::Parse(format!("{}: {}", input, err)))?;
+    let options = ParseOptions {
+        lenient: cli.lenient,
+        ..ParseOptions::default()
+    };
+    parse_stl_with_options(&data, &options)
+        .map_err(|err| Error::Parse(format!("{}: {}", input, err)))
+}
+
+fn convert(cli: &Cli) -> Result<(), Error> {
+    let (stl, warnings) = read(cli)?;
+    for warning in warnings {
+        eprintln!("warning: {}: {}", name(&cli.input, "stdin"), warning);
+    }
+    let srt = stl.to_srt(&SrtExportOptions {
+        rebase_to_tcp: cli.rebase,
+        styling: matches!(cli.styles, Styles::Map),
+    });
+    match cli.output.as_str() {
+        STDIO => {
+            let mut stdout = io::stdout().lock();
+            stdout
+                .write_all(srt.as_bytes())
+                .and_then(|_| stdout.flush())
+        }
+        output => fs::write(output, srt),
+    }
+    .map_err(|err| Error::Write(format!("{}: {}", name(&cli.output, "stdout"), err)))
+}
+
+fn main() {
+    if let Err(err) = convert(&Cli::parse()) {
+        eprintln!("error: {}", err);
+        process::exit(err.exit_code());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    fn cli(args: &[&str]) -> Result<Cli, clap::Error> {
+        Cli::try_parse_from(std::iter::once(&"stl2srt").chain(args))
+    }
+
+    #[test]
+    fn test_cli() {
+        Cli::command().debug_assert();
+        assert!(cli(&["in.stl", "out.srt"]).is_ok());
+        assert!(cli(&["--styles", "keep", "in.stl", "out.srt"]).is_err());
+        assert!(cli(&["in.stl"]).is_err());
+    }
+
+    #[test]
+    fn test_convert() {
+        let dir = std::env::temp_dir().join(format!("stl2srt-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("out.srt");
+        let convert_with = |flags: &[&str]| {
+            let files = ["../stls/test.stl", output.to_str().unwrap()];
+            convert(&cli(&[flags, &files].concat()).unwrap()).unwrap();
+            fs::read_to_string(&output).unwrap()
+        };
+
+        let srt = convert_with(&[]);
+        assert!(srt.starts_with("1\n10:00:06,320 --> 10:00:09,360\n<font"));
+        assert_eq!(12, srt.matches(" --> ").count());
+        let srt = convert_with(&["--rebase", "--styles", "strip"]);
+        assert!(srt.starts_with("1\n00:00:06,320 --> 00:00:09,360\n-Ellis Island,\n"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_errors() {
+        let dir = std::env::temp_dir().join(format!("stl2srt-errors-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.stl");
+        let convert_to = |input: &str, output: &str| convert(&cli(&[input, output]).unwrap());
+
+        let err = convert_to(input.to_str().unwrap(), "out.srt").unwrap_err();
+        assert_eq!(EXIT_PARSE, err.exit_code());
+        assert!(err.to_string().starts_with(input.to_str().unwrap()));
+
+        fs::write(&input, [0x20; 1024]).unwrap();
+        let err = convert_to(input.to_str().unwrap(), "out.srt").unwrap_err();
+        assert_eq!(EXIT_PARSE, err.exit_code());
+
+        let output = dir.join("missing").join("out.srt");
+        let err = convert_to("../stls/test.stl", output.to_str().unwrap()).unwrap_err();
+        assert_eq!(EXIT_WRITE, err.exit_code());
+        assert!(err.to_string().starts_with(output.to_str().unwrap()));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
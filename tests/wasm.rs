@@ -0,0 +1,35 @@
+//! Parsing and writing on wasm32-unknown-unknown, run with `cargo test-wasm`.
+#![cfg(target_arch = "wasm32")]
+
+use ebustl::parser::parse_stl_from_slice;
+use ebustl::{GsiBlock, Time, TtiFormat};
+use wasm_bindgen_test::wasm_bindgen_test;
+
+const TEST_STL: &[u8] = include_bytes!("../stls/test.stl");
+
+#[wasm_bindgen_test]
+fn parse_and_write() {
+    let stl = parse_stl_from_slice(TEST_STL).unwrap();
+    assert_eq!(13, stl.ttis.len());
+    assert_eq!("    dans la baie de New York.\r\n", stl.ttis[11].get_text());
+    assert_eq!(TEST_STL, &stl.to_bytes().unwrap()[..]);
+}
+
+#[wasm_bindgen_test]
+fn build_document() {
+    let gsi = GsiBlock::new_with_date_codes("261016", "261016").unwrap();
+    let mut stl = ebustl::Stl::new();
+    stl.gsi = gsi;
+    let format = TtiFormat {
+        jc: 2,
+        vp: 20,
+        dh: false,
+    };
+    let (tci, tco) = (Time::from_frames(25, 25), Time::from_frames(50, 25));
+    stl.try_add_sub(tci, tco, "Hello", format).unwrap();
+    let bytes = stl.to_bytes().unwrap();
+    assert_eq!(1024 + 128, bytes.len());
+    let parsed = parse_stl_from_slice(&bytes).unwrap();
+    assert_eq!("261016", parsed.gsi.get_creation_date());
+    assert_eq!("Hello", parsed.ttis[0].get_text().trim());
+}